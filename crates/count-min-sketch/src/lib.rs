@@ -13,6 +13,8 @@ use std::hash::SipHasher;
 
 use serde::{Deserialize, Serialize};
 
+pub mod topn;
+
 /// The CountMinHashFn is a data structure used to hash items that are being
 /// added to a Count-Min Sketch.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -48,6 +50,25 @@ impl CountMinHashFn {
     }
 }
 
+/// How a Count-Min Sketch is updated and queried.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CountMinMode {
+    /// Every add increments one counter in each row, and estimates are the
+    /// minimum of the item's counters.
+    Standard,
+    /// Conservative update: an add only raises the item's counters that are
+    /// below its new estimate.  Estimates are never larger than with
+    /// `Standard`, but values can no longer be subtracted.
+    Conservative,
+    /// Count-Mean-Min: updated like `Standard`, but each row's counter has the
+    /// noise expected from the other items in the row subtracted before taking
+    /// the median across the rows.  Estimates can undercount.
+    ///
+    /// Based on: F. Deng and D. Rafiei, "New Estimation Algorithms for
+    /// Streaming Data: Count-min Can Do More"
+    CountMeanMin,
+}
+
 /// The Count-Min Sketch is a compact summary data structure capable of
 /// representing a high-dimensional vector and answering queries on this vector,
 /// in particular point queries and dot product queries, with strong accuracy
@@ -68,6 +89,7 @@ pub struct CountMinSketch {
     hashfuncs: Vec<CountMinHashFn>,
     // The outer and inner `Vec`s must be `depth` and `width` long, respectively
     counters: Vec<Vec<i64>>,
+    mode: CountMinMode,
 }
 
 impl CountMinSketch {
@@ -88,6 +110,7 @@ impl CountMinSketch {
             depth,
             hashfuncs,
             counters,
+            mode: CountMinMode::Standard,
         }
     }
 
@@ -103,6 +126,7 @@ impl CountMinSketch {
                 .map(|key| CountMinHashFn::with_key(*key))
                 .collect(),
             counters: vec![vec![0; width]; depth],
+            mode: CountMinMode::Standard,
         }
     }
 
@@ -135,6 +159,19 @@ impl CountMinSketch {
         CountMinSketch::with_dim(width, depth)
     }
 
+    /// Returns the sketch using `mode` to add values and estimate counts.
+    ///
+    /// The mode should be chosen before any values are added.
+    pub fn with_mode(mut self, mode: CountMinMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Returns the mode of the sketch.
+    pub fn mode(&self) -> CountMinMode {
+        self.mode
+    }
+
     /// Returns the width of the sketch.
     pub fn width(&self) -> usize {
         self.width
@@ -162,10 +199,18 @@ impl CountMinSketch {
     /// Returns an estimate of the number of times `item` has been seen by the
     /// sketch.
     pub fn estimate<T: Hash>(&self, item: T) -> i64 {
+        let min = self.estimate_min(&item);
+        match self.mode {
+            CountMinMode::Standard | CountMinMode::Conservative => min,
+            CountMinMode::CountMeanMin => self.estimate_count_mean_min(&item).min(min),
+        }
+    }
+
+    fn estimate_min<T: Hash>(&self, item: &T) -> i64 {
         let buckets = self
             .hashfuncs
             .iter()
-            .map(|h| h.hash_into_buckets(&item, self.width));
+            .map(|h| h.hash_into_buckets(item, self.width));
 
         self.counters
             .iter()
@@ -175,6 +220,31 @@ impl CountMinSketch {
             .unwrap()
     }
 
+    fn estimate_count_mean_min<T: Hash>(&self, item: &T) -> i64 {
+        if self.width < 2 {
+            // every item shares the one counter, there's no noise to estimate
+            return self.estimate_min(item);
+        }
+
+        let mut estimates: Vec<f64> = self
+            .counters
+            .iter()
+            .zip(self.get_bucket_indices(item))
+            .map(|(counter, bucket)| {
+                let total: i64 = counter.iter().sum();
+                let count = counter[bucket];
+                let noise = (total - count) as f64 / (self.width - 1) as f64;
+                count as f64 - noise
+            })
+            .collect();
+        estimates.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        // for an odd number of rows both indices are the middle one
+        let len = estimates.len();
+        let median = (estimates[(len - 1) / 2] + estimates[len / 2]) / 2.0;
+        median.max(0.0).round() as i64
+    }
+
     /// Returns a vector of the indices for the buckets into which `item` hashes.
     ///
     /// The vector will have `self.depth` elements, each in the range
@@ -188,6 +258,15 @@ impl CountMinSketch {
 
     /// Adds the given `item` to the sketch.
     pub fn add_value<T: Hash>(&mut self, item: T) {
+        if self.mode == CountMinMode::Conservative {
+            let buckets = self.get_bucket_indices(&item);
+            let estimate = self.estimate_min(&item) + 1;
+            for (counter, bucket) in self.counters.iter_mut().zip(buckets) {
+                counter[bucket] = counter[bucket].max(estimate);
+            }
+            return;
+        }
+
         for i in 0..self.depth {
             let bucket = self.hashfuncs[i].hash_into_buckets(&item, self.width);
             self.counters[i][bucket] += 1;
@@ -195,7 +274,13 @@ impl CountMinSketch {
     }
 
     /// Subtract the given `item` from the sketch.
+    ///
+    /// Not supported by `CountMinMode::Conservative` sketches.
     pub fn subtract_value<T: Hash>(&mut self, item: T) {
+        assert!(
+            self.mode != CountMinMode::Conservative,
+            "cannot subtract from a conservative update sketch"
+        );
         for i in 0..self.depth {
             let bucket = self.hashfuncs[i].hash_into_buckets(&item, self.width);
             self.counters[i][bucket] -= 1;
@@ -205,11 +290,13 @@ impl CountMinSketch {
     /// Includes the counts from `other` into `self` via elementwise addition of
     /// the counter vectors.
     ///
-    /// The underlying `CountMinHashFn`s in each sketch must have the same keys.
+    /// The underlying `CountMinHashFn`s in each sketch must have the same keys,
+    /// and the sketches must use the same mode.
     pub fn combine(&mut self, other: CountMinSketch) {
         assert_eq!(self.width, other.width);
         assert_eq!(self.depth, other.depth);
         assert_eq!(self.hashfuncs, other.hashfuncs);
+        assert_eq!(self.mode, other.mode);
         for (counter1, counter2) in self.counters.iter_mut().zip(other.counters) {
             for (val1, val2) in counter1.iter_mut().zip(counter2) {
                 *val1 += val2;
@@ -0,0 +1,143 @@
+//! Heavy hitter tracking on top of a Count-Min Sketch.
+//!
+//! Based on the "Count-Min Sketch + heap" approach from section 5.1 of:
+//! <http://dimacs.rutgers.edu/~graham/pubs/papers/cm-full.pdf>
+//!
+//! Every item is added to the sketch, and the `n` items with the largest
+//! estimates seen so far are kept as candidates alongside it.
+
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+use crate::CountMinSketch;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TopNCountMinSketch<T> {
+    sketch: CountMinSketch,
+    n: usize,
+    // total number of items added to the sketch, the `N` in the error bound
+    total: u64,
+    // sorted by estimated count, largest first; at most `n` long
+    candidates: Vec<(T, i64)>,
+}
+
+impl<T: Hash + Eq + Clone> TopNCountMinSketch<T> {
+    /// Constructs a new, empty sketch that tracks the `n` most frequent items,
+    /// see `CountMinSketch::with_prob` for the meaning of `epsilon` and `delta`.
+    pub fn with_prob(n: usize, epsilon: f64, delta: f64) -> Self {
+        Self::new(n, 0, CountMinSketch::with_prob(epsilon, delta), vec![])
+    }
+
+    /// Recreates a sketch from its component parts.
+    pub fn new(n: usize, total: u64, sketch: CountMinSketch, candidates: Vec<(T, i64)>) -> Self {
+        assert!(n > 0);
+        let mut sketch = Self {
+            sketch,
+            n,
+            total,
+            candidates,
+        };
+        sketch.sort_candidates();
+        sketch
+    }
+
+    /// Returns the number of heavy hitters tracked.
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    /// Returns the total number of items added to the sketch.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns the underlying Count-Min Sketch.
+    pub fn sketch(&self) -> &CountMinSketch {
+        &self.sketch
+    }
+
+    /// Returns the candidate heavy hitters along with their estimated counts,
+    /// largest first.
+    pub fn candidates(&self) -> &[(T, i64)] {
+        &self.candidates
+    }
+
+    /// Adds the given `item` to the sketch, updating the candidates if its
+    /// estimated count now places it among the top `n`.
+    pub fn add_value(&mut self, item: T) {
+        self.sketch.add_value(&item);
+        self.total += 1;
+        let estimate = self.sketch.estimate(&item);
+
+        match self.candidates.iter().position(|(c, _)| *c == item) {
+            Some(i) => {
+                self.candidates[i].1 = estimate;
+                self.move_left(i);
+            }
+            None if self.candidates.len() < self.n => {
+                self.candidates.push((item, estimate));
+                self.move_left(self.candidates.len() - 1);
+            }
+            None => {
+                let last = self.candidates.len() - 1;
+                if estimate > self.candidates[last].1 {
+                    self.candidates[last] = (item, estimate);
+                    self.move_left(last);
+                }
+            }
+        }
+    }
+
+    /// Includes the counts from `other` into `self`.  The candidates of the
+    /// result are the top `n` of both sets of candidates, re-estimated against
+    /// the combined sketch.
+    ///
+    /// Both sketches must have been created with the same parameters.
+    pub fn combine(&mut self, other: &TopNCountMinSketch<T>) {
+        assert_eq!(self.n, other.n);
+        self.sketch.combine(other.sketch.clone());
+        self.total += other.total;
+
+        for (item, _) in &other.candidates {
+            if !self.candidates.iter().any(|(c, _)| c == item) {
+                self.candidates.push((item.clone(), 0));
+            }
+        }
+        for (item, estimate) in &mut self.candidates {
+            *estimate = self.sketch.estimate(&*item);
+        }
+        self.sort_candidates();
+    }
+
+    /// Returns the maximum amount any estimate exceeds the true count by, with
+    /// probability 1-δ: εN, where ε = e/width.
+    pub fn error_bound(&self) -> i64 {
+        let epsilon = 1f64.exp() / self.sketch.width() as f64;
+        (epsilon * self.total as f64).ceil() as i64
+    }
+
+    /// Returns the candidates as `(item, estimated_count, error_bound)`,
+    /// largest first.  An item's true count is within
+    /// `[estimated_count - error_bound, estimated_count]` with probability 1-δ.
+    pub fn topn(&self) -> impl Iterator<Item = (&T, i64, i64)> + '_ {
+        let error_bound = self.error_bound();
+        self.candidates
+            .iter()
+            .map(move |(item, estimate)| (item, *estimate, error_bound.min(*estimate)))
+    }
+
+    // estimates only ever grow, so moving the updated entry left restores the order
+    fn move_left(&mut self, mut i: usize) {
+        while i > 0 && self.candidates[i - 1].1 < self.candidates[i].1 {
+            self.candidates.swap(i - 1, i);
+            i -= 1;
+        }
+    }
+
+    fn sort_candidates(&mut self) {
+        // stable, so ties keep the order they were first seen in
+        self.candidates.sort_by(|(_, a), (_, b)| b.cmp(a));
+        self.candidates.truncate(self.n);
+    }
+}
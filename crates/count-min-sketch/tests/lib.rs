@@ -1,4 +1,4 @@
-use countminsketch::CountMinSketch;
+use countminsketch::{topn::TopNCountMinSketch, CountMinMode, CountMinSketch};
 
 #[test]
 fn empty_sketch() {
@@ -58,3 +58,136 @@ fn add_repeated_with_collisions() {
     assert!(1_000 <= bar_est && bar_est < (1_000 + err_margin));
     assert!(1_000_000 <= baz_est && baz_est < (1_000_000 + err_margin));
 }
+
+// a skewed stream: item i is seen 1000/i times
+fn add_skewed(cms: &mut CountMinSketch) {
+    for i in 1..=1_000_i64 {
+        for _ in 0..1_000 / i {
+            cms.add_value(i);
+        }
+    }
+}
+
+#[test]
+fn conservative_update_never_overcounts_more() {
+    let mut standard = CountMinSketch::with_dim(50, 3);
+    let mut conservative = CountMinSketch::with_dim(50, 3).with_mode(CountMinMode::Conservative);
+    add_skewed(&mut standard);
+    add_skewed(&mut conservative);
+
+    let (mut standard_error, mut conservative_error) = (0, 0);
+    for i in 1..=1_000 {
+        let expected = 1_000 / i;
+        let standard_est = standard.estimate(i);
+        let conservative_est = conservative.estimate(i);
+        assert!(expected <= conservative_est && conservative_est <= standard_est);
+        standard_error += standard_est - expected;
+        conservative_error += conservative_est - expected;
+    }
+    assert!(conservative_error < standard_error);
+}
+
+#[test]
+#[should_panic(expected = "cannot subtract from a conservative update sketch")]
+fn conservative_update_cannot_subtract() {
+    let mut cms = CountMinSketch::with_dim(2, 2).with_mode(CountMinMode::Conservative);
+    cms.add_value("foo");
+    cms.subtract_value("foo");
+}
+
+#[test]
+fn count_mean_min_removes_noise() {
+    let mut standard = CountMinSketch::with_dim(50, 5);
+    let mut cmm = CountMinSketch::with_dim(50, 5).with_mode(CountMinMode::CountMeanMin);
+    add_skewed(&mut standard);
+    add_skewed(&mut cmm);
+
+    // the same counters are kept, only the estimates differ
+    assert_eq!(standard.counters(), cmm.counters());
+
+    let (mut standard_error, mut cmm_error) = (0, 0);
+    for i in 1..=1_000 {
+        let expected = 1_000 / i;
+        let cmm_est = cmm.estimate(i);
+        assert!(cmm_est <= standard.estimate(i));
+        standard_error += (standard.estimate(i) - expected).abs();
+        cmm_error += (cmm_est - expected).abs();
+    }
+    assert!(cmm_error < standard_error / 2);
+}
+
+#[test]
+#[should_panic]
+fn combine_requires_matching_modes() {
+    let mut standard = CountMinSketch::with_dim(2, 2);
+    let cmm = CountMinSketch::with_dim(2, 2).with_mode(CountMinMode::CountMeanMin);
+    standard.combine(cmm);
+}
+
+#[test]
+fn topn_finds_heavy_hitters() {
+    let mut topn = TopNCountMinSketch::with_prob(3, 0.01, 0.01);
+    // a long tail of items seen once, interleaved with three heavy hitters
+    for i in 0..10_000 {
+        topn.add_value(format!("tail {}", i));
+        if i % 10 == 0 {
+            topn.add_value("foo".to_string());
+        }
+        if i % 20 == 0 {
+            topn.add_value("bar".to_string());
+        }
+        if i % 40 == 0 {
+            topn.add_value("baz".to_string());
+        }
+    }
+
+    let expected = [("foo", 1_000), ("bar", 500), ("baz", 250)];
+    let found: Vec<_> = topn.topn().collect();
+    assert_eq!(found.len(), 3);
+    for ((item, estimate, error_bound), (expected_item, expected_count)) in
+        found.into_iter().zip(expected)
+    {
+        assert_eq!(item, expected_item);
+        assert!(expected_count <= estimate);
+        assert!(estimate - error_bound <= expected_count);
+    }
+    assert_eq!(topn.total(), 10_000 + 1_000 + 500 + 250);
+    assert_eq!(
+        topn.error_bound(),
+        (1f64.exp() / 272.0 * 11_750.0).ceil() as i64
+    );
+}
+
+#[test]
+fn topn_error_bound_never_exceeds_estimate() {
+    let mut topn = TopNCountMinSketch::with_prob(5, 0.5, 0.5);
+    for i in 0..100 {
+        topn.add_value(i);
+    }
+    for (_, estimate, error_bound) in topn.topn() {
+        assert!(error_bound <= estimate);
+    }
+}
+
+#[test]
+fn topn_combine() {
+    let mut early = TopNCountMinSketch::with_prob(2, 0.01, 0.01);
+    let mut late = TopNCountMinSketch::with_prob(2, 0.01, 0.01);
+    // "foo" is only the second most common item in each half, but the most common overall
+    for _ in 0..30 {
+        early.add_value("foo");
+        late.add_value("foo");
+    }
+    for _ in 0..40 {
+        early.add_value("bar");
+        late.add_value("baz");
+    }
+
+    early.combine(&late);
+    let found: Vec<_> = early
+        .topn()
+        .map(|(item, estimate, _)| (*item, estimate))
+        .collect();
+    assert_eq!(found, vec![("foo", 60), ("bar", 40)]);
+    assert_eq!(early.total(), 140);
+}
@@ -0,0 +1,304 @@
+use serde::{Deserialize, Serialize};
+use tspoint::TSPoint;
+
+use crate::{range, CounterError, CounterSummaryBuilder, MetricSummary};
+
+/// HistogramSummary tracks a Prometheus-style cumulative histogram, ie a set of `_bucket` counters each
+/// labeled with the upper bound (`le`) of the values it counts. Every bucket is an independent, reset
+/// corrected counter.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HistogramSummary {
+    // sorted by `le`, with at most one summary per `le`
+    pub buckets: Vec<HistogramBucket>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HistogramBucket {
+    pub le: f64,
+    pub counter: MetricSummary,
+}
+
+impl HistogramSummary {
+    /// Builds a summary from `(le, point)` pairs in any order.
+    pub fn from_points(
+        points: &mut [(f64, TSPoint)],
+        bounds: Option<range::I64Range>,
+    ) -> Result<HistogramSummary, CounterError> {
+        points.sort_unstable_by(|(le1, p1), (le2, p2)| le1.total_cmp(le2).then(p1.ts.cmp(&p2.ts)));
+        let mut buckets: Vec<HistogramBucket> = vec![];
+        let mut current: Option<(f64, CounterSummaryBuilder)> = None;
+        for (le, p) in points.iter() {
+            match &mut current {
+                Some((current_le, counter)) if current_le == le => counter.add_point(p)?,
+                _ => {
+                    if let Some((le, counter)) = current.take() {
+                        buckets.push(HistogramBucket {
+                            le,
+                            counter: counter.build(),
+                        });
+                    }
+                    current = Some((*le, CounterSummaryBuilder::new(p, bounds)));
+                }
+            }
+        }
+        if let Some((le, counter)) = current {
+            buckets.push(HistogramBucket {
+                le,
+                counter: counter.build(),
+            });
+        }
+        Ok(HistogramSummary { buckets })
+    }
+
+    /// Combines summaries covering disjoint time ranges, the order they are passed in doesn't matter.
+    pub fn combine_all(
+        summaries: impl IntoIterator<Item = HistogramSummary>,
+    ) -> Result<HistogramSummary, CounterError> {
+        let mut all: Vec<HistogramBucket> = summaries.into_iter().flat_map(|s| s.buckets).collect();
+        all.sort_unstable_by(|b1, b2| {
+            b1.le
+                .total_cmp(&b2.le)
+                .then(b1.counter.first.ts.cmp(&b2.counter.first.ts))
+        });
+        let mut buckets: Vec<HistogramBucket> = vec![];
+        for bucket in all {
+            match buckets.last_mut() {
+                Some(last) if last.le == bucket.le => {
+                    let mut counter = CounterSummaryBuilder::from(last.counter.clone());
+                    counter.combine(&bucket.counter)?;
+                    last.counter = counter.build();
+                }
+                _ => buckets.push(bucket),
+            }
+        }
+        Ok(HistogramSummary { buckets })
+    }
+
+    pub fn bounds_valid(&self) -> bool {
+        self.buckets.iter().all(|b| b.counter.bounds_valid())
+    }
+
+    /// The reset-corrected increase of each bucket.
+    pub fn deltas(&self) -> Vec<(f64, f64)> {
+        self.buckets
+            .iter()
+            .map(|b| (b.le, b.counter.delta()))
+            .collect()
+    }
+
+    /// The increase of each bucket, extrapolated to the bounds following the same rules as
+    /// `MetricSummary::prometheus_delta`.  Buckets with fewer than two points are left out.
+    pub fn prometheus_deltas(&self) -> Result<Vec<(f64, f64)>, CounterError> {
+        let mut deltas = vec![];
+        for b in &self.buckets {
+            if let Some(delta) = b.counter.prometheus_delta()? {
+                deltas.push((b.le, delta));
+            }
+        }
+        Ok(deltas)
+    }
+
+    /// The per-second rate of each bucket, see `MetricSummary::prometheus_rate`.
+    /// Buckets with fewer than two points are left out.
+    pub fn prometheus_rates(&self) -> Result<Vec<(f64, f64)>, CounterError> {
+        let mut rates = vec![];
+        for b in &self.buckets {
+            if let Some(rate) = b.counter.prometheus_rate()? {
+                rates.push((b.le, rate));
+            }
+        }
+        Ok(rates)
+    }
+
+    /// Estimates the `quantile` of the observations counted by this histogram over its time range.
+    /// Uses the extrapolated increases if the summary has bounds and the raw increases otherwise.
+    pub fn quantile(&self, quantile: f64) -> Result<Option<f64>, CounterError> {
+        let counts = match self.buckets.first().and_then(|b| b.counter.bounds) {
+            Some(_) => self.prometheus_deltas()?,
+            None => self.deltas(),
+        };
+        Ok(bucket_quantile(quantile, counts))
+    }
+}
+
+// based on: https://github.com/prometheus/prometheus/blob/e5ffa8c9a08a5ee4185271c8c26051ddc1388b7a/promql/quantile.go#L72
+// Returns None where Prometheus would return NaN: when there is no +Inf bucket, fewer than two buckets,
+// or no observations at all.
+pub fn bucket_quantile(quantile: f64, mut buckets: Vec<(f64, f64)>) -> Option<f64> {
+    if quantile.is_nan() {
+        return None;
+    }
+    if quantile < 0.0 {
+        return Some(f64::NEG_INFINITY);
+    }
+    if quantile > 1.0 {
+        return Some(f64::INFINITY);
+    }
+    buckets.sort_unstable_by(|(le1, _), (le2, _)| le1.total_cmp(le2));
+    match buckets.last() {
+        Some((le, _)) if *le == f64::INFINITY => (),
+        _ => return None,
+    }
+    if buckets.len() < 2 {
+        return None;
+    }
+
+    // extrapolation can break the monotonicity of the cumulative counts, repair it as Prometheus does
+    let mut max = f64::NEG_INFINITY;
+    for (_, count) in buckets.iter_mut() {
+        if *count > max {
+            max = *count;
+        } else {
+            *count = max;
+        }
+    }
+
+    let observations = buckets.last().unwrap().1;
+    if observations == 0.0 {
+        return None;
+    }
+    let mut rank = quantile * observations;
+    let b = buckets[..buckets.len() - 1]
+        .iter()
+        .position(|(_, count)| *count >= rank)
+        .unwrap_or(buckets.len() - 1);
+
+    if b == buckets.len() - 1 {
+        return Some(buckets[buckets.len() - 2].0);
+    }
+    if b == 0 && buckets[0].0 <= 0.0 {
+        return Some(buckets[0].0);
+    }
+    let bucket_end = buckets[b].0;
+    let mut bucket_start = 0.0;
+    let mut count = buckets[b].1;
+    if b > 0 {
+        bucket_start = buckets[b - 1].0;
+        count -= buckets[b - 1].1;
+        rank -= buckets[b - 1].1;
+    }
+    Some(bucket_start + (bucket_end - bucket_start) * (rank / count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::range::I64Range;
+    use approx::assert_relative_eq;
+
+    fn pt(secs: i64, val: f64) -> TSPoint {
+        TSPoint {
+            ts: secs * 1_000_000,
+            val,
+        }
+    }
+
+    fn sample_points() -> Vec<(f64, TSPoint)> {
+        // three scrapes of a histogram with buckets 0.1, 0.5, 1 and +Inf, the 0.5 bucket resets in the middle
+        vec![
+            (0.1, pt(0, 1.0)),
+            (0.5, pt(0, 5.0)),
+            (1.0, pt(0, 8.0)),
+            (f64::INFINITY, pt(0, 10.0)),
+            (0.1, pt(10, 3.0)),
+            (0.5, pt(10, 2.0)),
+            (1.0, pt(10, 20.0)),
+            (f64::INFINITY, pt(10, 25.0)),
+            (0.1, pt(20, 5.0)),
+            (0.5, pt(20, 9.0)),
+            (1.0, pt(20, 28.0)),
+            (f64::INFINITY, pt(20, 40.0)),
+        ]
+    }
+
+    #[test]
+    fn reset_corrected_deltas() {
+        let mut points = sample_points();
+        let summary = HistogramSummary::from_points(&mut points, None).unwrap();
+        assert_eq!(
+            summary.deltas(),
+            vec![(0.1, 4.0), (0.5, 9.0), (1.0, 20.0), (f64::INFINITY, 30.0)]
+        );
+        assert_eq!(summary.buckets[1].counter.num_resets, 1);
+    }
+
+    #[test]
+    fn combine_in_any_order() {
+        let mut points = sample_points();
+        let expected = HistogramSummary::from_points(&mut points, None).unwrap();
+
+        let (mut early, mut late): (Vec<_>, Vec<_>) =
+            points.into_iter().partition(|(_, p)| p.ts < 15_000_000);
+        let early = HistogramSummary::from_points(&mut early, None).unwrap();
+        let late = HistogramSummary::from_points(&mut late, None).unwrap();
+
+        let combined = HistogramSummary::combine_all(vec![late, early]).unwrap();
+        assert_eq!(combined.deltas(), expected.deltas());
+        for (c, e) in combined.buckets.iter().zip(&expected.buckets) {
+            crate::tests::assert_close_enough(&c.counter, &e.counter);
+        }
+    }
+
+    #[test]
+    fn extrapolated_rates() {
+        let mut points = sample_points();
+        let bounds = Some(I64Range {
+            left: Some(0),
+            right: Some(20_001_000),
+        });
+        let summary = HistogramSummary::from_points(&mut points, bounds).unwrap();
+
+        // the points cover the entire range so there is no extrapolation
+        let rates = summary.prometheus_rates().unwrap();
+        assert_eq!(rates.len(), 4);
+        for ((le, rate), (delta_le, delta)) in rates.iter().zip(summary.deltas()) {
+            assert_eq!(*le, delta_le);
+            assert_relative_eq!(*rate, delta / 20.0);
+        }
+
+        let summary = HistogramSummary::from_points(&mut sample_points(), None).unwrap();
+        assert_eq!(summary.prometheus_rates(), Err(CounterError::BoundsInvalid));
+    }
+
+    #[test]
+    fn quantiles() {
+        let mut points = sample_points();
+        let summary = HistogramSummary::from_points(&mut points, None).unwrap();
+        // increases: 4 below 0.1, 9 below 0.5, 20 below 1, 30 total
+        assert_relative_eq!(summary.quantile(0.1).unwrap().unwrap(), 0.075);
+        assert_relative_eq!(
+            summary.quantile(0.5).unwrap().unwrap(),
+            0.5 + 0.5 * 6.0 / 11.0
+        );
+        // anything in the +Inf bucket is reported as the highest finite bound
+        assert_eq!(summary.quantile(0.9).unwrap(), Some(1.0));
+        assert_eq!(summary.quantile(1.5).unwrap(), Some(f64::INFINITY));
+        assert_eq!(summary.quantile(-0.5).unwrap(), Some(f64::NEG_INFINITY));
+    }
+
+    #[test]
+    fn quantile_edge_cases() {
+        // no +Inf bucket
+        assert_eq!(bucket_quantile(0.5, vec![(1.0, 2.0), (2.0, 4.0)]), None);
+        // too few buckets
+        assert_eq!(bucket_quantile(0.5, vec![(f64::INFINITY, 4.0)]), None);
+        // no observations
+        assert_eq!(
+            bucket_quantile(0.5, vec![(1.0, 0.0), (f64::INFINITY, 0.0)]),
+            None
+        );
+        // negative lowest bucket
+        assert_eq!(
+            bucket_quantile(0.1, vec![(-1.0, 5.0), (f64::INFINITY, 10.0)]),
+            Some(-1.0)
+        );
+        // non-monotonic counts are repaired
+        assert_eq!(
+            bucket_quantile(
+                0.5,
+                vec![(1.0, 6.0), (2.0, 4.0), (4.0, 10.0), (f64::INFINITY, 10.0)]
+            ),
+            Some(5.0 / 6.0)
+        );
+    }
+}
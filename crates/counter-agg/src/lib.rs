@@ -3,6 +3,8 @@ use stats_agg::{stats2d::StatsSummary2D, XYPair};
 use std::fmt;
 use tspoint::TSPoint;
 
+pub mod histogram;
+pub mod model;
 pub mod range;
 
 #[cfg(test)]
@@ -12,6 +14,8 @@ mod tests;
 pub enum CounterError {
     OrderError,
     BoundsInvalid,
+    InvalidModel,
+    ModelMismatch,
 }
 
 // TODO Intent is for this to be immutable with mutations going through (and
@@ -304,6 +308,13 @@ impl fmt::Display for CounterError {
                 "out of order points: points must be submitted in time-order"
             ),
             CounterError::BoundsInvalid => write!(f, "cannot calculate delta without valid bounds"),
+            CounterError::InvalidModel => write!(
+                f,
+                "invalid counter model: wrap_bits must be 32 or 64 and reset_threshold must not be negative"
+            ),
+            CounterError::ModelMismatch => {
+                write!(f, "cannot combine counters with different counter models")
+            }
         }
     }
 }
@@ -0,0 +1,379 @@
+use serde::{Deserialize, Serialize};
+use tspoint::TSPoint;
+
+use crate::{range, CounterError, MetricSummary};
+
+/// How the decreases of a counter are interpreted.  By default, as with
+/// [`CounterSummaryBuilder`](crate::CounterSummaryBuilder), every decrease is
+/// a reset to zero.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CounterModel {
+    /// Counters of this many bits wrap around to zero when they overflow, so
+    /// a decrease from near the top of the range to near zero adds
+    /// `2^wrap_bits - prev` instead of being a reset.  Any other decrease,
+    /// such as a reboot, is still a reset.
+    pub wrap_bits: Option<u8>,
+    /// Decreases by at most this much are jitter, neither resets nor wraps.
+    pub reset_threshold: f64,
+}
+
+// what happened to the counter between two consecutive points
+enum Change {
+    None,
+    Reset,
+    Wrap,
+    // a decrease that is neither a reset nor a wrap
+    Ignored,
+}
+
+impl CounterModel {
+    pub fn new(wrap_bits: Option<u8>, reset_threshold: f64) -> Result<Self, CounterError> {
+        if !matches!(wrap_bits, None | Some(32) | Some(64))
+            || reset_threshold.is_nan()
+            || reset_threshold < 0.0
+        {
+            return Err(CounterError::InvalidModel);
+        }
+        Ok(CounterModel {
+            wrap_bits,
+            reset_threshold,
+        })
+    }
+
+    fn wrap_size(&self) -> f64 {
+        2f64.powi(self.wrap_bits.unwrap_or(0) as i32)
+    }
+
+    // A counter only wraps if it was close enough to overflowing that it could
+    // have counted past the top of the range and on to `next` in one step.
+    // Taking that to be less than a quarter of the range, `prev` is in the top
+    // quarter of it and `next` in the bottom one; a counter that drops from
+    // anywhere else was restarted.
+    fn plausible_wrap(&self, prev: f64, next: f64) -> bool {
+        let size = self.wrap_size();
+        size - prev + next < size / 4.0
+    }
+
+    // Created timestamps, like the OpenMetrics `_created` series, are
+    // authoritative when known for both points: the counter was reset if and
+    // only if it changed.  Otherwise only the values are known to go by.
+    fn classify(
+        &self,
+        prev: &TSPoint,
+        prev_created: Option<i64>,
+        next: &TSPoint,
+        next_created: Option<i64>,
+    ) -> Change {
+        let same_counter = match (prev_created, next_created) {
+            (Some(prev_created), Some(next_created)) if prev_created != next_created => {
+                return Change::Reset
+            }
+            (Some(_), Some(_)) => true,
+            _ => false,
+        };
+        // These values are not rounded, so direct comparison is valid.
+        if next.val >= prev.val {
+            return Change::None;
+        }
+        if prev.val - next.val <= self.reset_threshold {
+            return Change::Ignored;
+        }
+        match self.wrap_bits {
+            Some(_) if self.plausible_wrap(prev.val, next.val) => Change::Wrap,
+            _ if same_counter => Change::Ignored,
+            _ => Change::Reset,
+        }
+    }
+}
+
+/// A counter summary following a [`CounterModel`], which may also contain
+/// points from different incarnations of a counter told apart by their
+/// created timestamps.  Wraps add to the `reset_sum` of the summary, but are
+/// counted in `num_wraps` rather than its `num_resets`.  The decreases that
+/// are neither, jitter and drops within one created timestamp, are counted in
+/// `num_ignored`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ModeledCounterSummary {
+    pub summary: MetricSummary,
+    pub model: CounterModel,
+    pub num_wraps: u64,
+    pub num_ignored: u64,
+    pub first_created: Option<i64>,
+    pub last_created: Option<i64>,
+}
+
+impl ModeledCounterSummary {
+    pub fn new(
+        pt: &TSPoint,
+        created: Option<i64>,
+        model: CounterModel,
+        bounds: Option<range::I64Range>,
+    ) -> Self {
+        ModeledCounterSummary {
+            summary: MetricSummary::new(pt, bounds),
+            model,
+            num_wraps: 0,
+            num_ignored: 0,
+            first_created: created,
+            last_created: created,
+        }
+    }
+
+    /// expects time-ordered input
+    pub fn add_point(
+        &mut self,
+        incoming: &TSPoint,
+        created: Option<i64>,
+    ) -> Result<(), CounterError> {
+        if incoming.ts < self.summary.last.ts {
+            return Err(CounterError::OrderError);
+        }
+        if incoming.ts == self.summary.last.ts {
+            // ignored like in `MetricSummary::add_point`
+            return Ok(());
+        }
+        let last = self.summary.last;
+        self.change(&last, self.last_created, incoming, created);
+        self.summary.add_point(incoming)?;
+        self.last_created = created;
+        Ok(())
+    }
+
+    /// combining can only happen for disjoint time ranges
+    pub fn combine(&mut self, incoming: &ModeledCounterSummary) -> Result<(), CounterError> {
+        if self.model != incoming.model {
+            return Err(CounterError::ModelMismatch);
+        }
+        if self.summary.last.ts >= incoming.summary.first.ts {
+            return Err(CounterError::OrderError);
+        }
+        let last = self.summary.last;
+        self.change(
+            &last,
+            self.last_created,
+            &incoming.summary.first,
+            incoming.first_created,
+        );
+        self.summary.combine(&incoming.summary)?;
+        self.num_wraps += incoming.num_wraps;
+        self.num_ignored += incoming.num_ignored;
+        self.last_created = incoming.last_created;
+        Ok(())
+    }
+
+    fn change(
+        &mut self,
+        prev: &TSPoint,
+        prev_created: Option<i64>,
+        next: &TSPoint,
+        next_created: Option<i64>,
+    ) {
+        match self.model.classify(prev, prev_created, next, next_created) {
+            Change::None => {}
+            Change::Reset => {
+                self.summary.reset_sum += prev.val;
+                self.summary.num_resets += 1;
+            }
+            Change::Wrap => {
+                self.summary.reset_sum += self.model.wrap_size();
+                self.num_wraps += 1;
+            }
+            Change::Ignored => self.num_ignored += 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CounterSummaryBuilder;
+    use approx::assert_relative_eq;
+
+    const U32: f64 = 4_294_967_296.0;
+
+    fn summary(
+        points: &[(i64, f64, Option<i64>)],
+        model: CounterModel,
+    ) -> Result<ModeledCounterSummary, CounterError> {
+        let (ts, val, created) = points[0];
+        let mut summary = ModeledCounterSummary::new(&TSPoint { ts, val }, created, model, None);
+        for &(ts, val, created) in &points[1..] {
+            summary.add_point(&TSPoint { ts, val }, created)?;
+        }
+        Ok(summary)
+    }
+
+    #[test]
+    fn default_model() {
+        // without any options it's the same as a plain counter summary
+        let model = CounterModel::new(None, 0.0).unwrap();
+        let points = [(0, 10.0), (1, 20.0), (2, 5.0), (3, 15.0), (4, 15.0)];
+        let modeled = summary(&points.map(|(ts, val)| (ts, val, None)), model).unwrap();
+        let mut plain = CounterSummaryBuilder::new(&TSPoint { ts: 0, val: 10.0 }, None);
+        for (ts, val) in &points[1..] {
+            plain.add_point(&TSPoint { ts: *ts, val: *val }).unwrap();
+        }
+        assert_eq!(modeled.summary, plain.build());
+        assert_eq!(modeled.num_wraps, 0);
+        assert_eq!(modeled.num_ignored, 0);
+    }
+
+    #[test]
+    fn wraps() {
+        let model = CounterModel::new(Some(32), 0.0).unwrap();
+        let test = summary(
+            &[
+                (0, U32 - 100.0, None),
+                (1, U32 - 10.0, None),
+                (2, 40.0, None),
+                (3, 90.0, None),
+            ],
+            model,
+        )
+        .unwrap();
+        assert_eq!(test.summary.delta(), 190.0);
+        assert_eq!(test.num_wraps, 1);
+        assert_eq!(test.summary.num_resets, 0);
+
+        let model = CounterModel::new(Some(64), 0.0).unwrap();
+        // f64s that big are only precise to thousands
+        let test = summary(
+            &[(0, 2f64.powi(64) - 1048576.0, None), (1, 1048576.0, None)],
+            model,
+        )
+        .unwrap();
+        assert_eq!(test.summary.delta(), 2097152.0);
+        assert_eq!(test.num_wraps, 1);
+
+        assert_eq!(
+            CounterModel::new(Some(16), 0.0),
+            Err(CounterError::InvalidModel)
+        );
+    }
+
+    #[test]
+    fn reboot_of_wrapping_counter() {
+        let model = CounterModel::new(Some(32), 0.0).unwrap();
+        let test = summary(
+            &[
+                (0, 1_000_000.0, None),
+                (1, 2_000_000.0, None),
+                // rebooted, rather than counting 2^32 values in a step
+                (2, 500.0, None),
+                (3, 1_500.0, None),
+                // the top of the range, but too far from zero after the drop
+                (4, U32 - 10.0, None),
+                (5, U32 / 2.0, None),
+            ],
+            model,
+        )
+        .unwrap();
+        assert_eq!(test.summary.num_resets, 2);
+        assert_eq!(test.num_wraps, 0);
+        assert_eq!(
+            test.summary.delta(),
+            1_000_000.0 + 1_500.0 + (U32 - 10.0 - 1_500.0) + U32 / 2.0
+        );
+
+        // the same jump backwards within created timestamps of one counter is
+        // neither a wrap nor a reset
+        let test = summary(&[(0, 2_000_000.0, Some(0)), (1, 500.0, Some(0))], model).unwrap();
+        assert_eq!(test.summary.num_resets, 0);
+        assert_eq!(test.num_wraps, 0);
+        assert_eq!(test.num_ignored, 1);
+    }
+
+    #[test]
+    fn created_resets() {
+        let model = CounterModel::new(Some(32), 0.0).unwrap();
+        let test = summary(
+            &[
+                (0, 100.0, Some(-10)),
+                // restarted and already past the old value
+                (1, 150.0, Some(1)),
+                // a reset without a created timestamp, too far from the top
+                // of the range to be a wrap
+                (2, 50.0, None),
+                (3, 60.0, Some(1)),
+                // a real reset
+                (4, 20.0, Some(4)),
+            ],
+            model,
+        )
+        .unwrap();
+        assert_eq!(test.summary.num_resets, 3);
+        assert_eq!(test.num_wraps, 0);
+        assert_eq!(test.summary.delta(), 150.0 + 60.0 + 20.0);
+        assert_eq!(
+            (test.first_created, test.last_created),
+            (Some(-10), Some(4))
+        );
+
+        // with the same created timestamp a decrease isn't a reset
+        let model = CounterModel::new(None, 0.0).unwrap();
+        let test = summary(&[(0, 100.0, Some(0)), (1, 90.0, Some(0))], model).unwrap();
+        assert_eq!(test.summary.num_resets, 0);
+        assert_eq!(test.summary.delta(), -10.0);
+    }
+
+    #[test]
+    fn reset_threshold() {
+        let model = CounterModel::new(None, 0.5).unwrap();
+        let test = summary(
+            &[
+                (0, 100.0, None),
+                (1, 99.5, None),
+                (2, 101.0, None),
+                (3, 1.0, None),
+            ],
+            model,
+        )
+        .unwrap();
+        assert_eq!(test.summary.num_resets, 1);
+        assert_eq!(test.num_ignored, 1);
+        assert_eq!(test.summary.delta(), 2.0);
+
+        assert_eq!(
+            CounterModel::new(None, -1.0),
+            Err(CounterError::InvalidModel)
+        );
+    }
+
+    #[test]
+    fn combine() {
+        let model = CounterModel::new(Some(32), 1.0).unwrap();
+        let points = [
+            (0, U32 - 100.0, Some(0)),
+            (1, U32 - 10.0, Some(0)),
+            (2, 40.0, Some(0)),
+            (3, 39.5, Some(0)),
+            (4, 10.0, Some(3)),
+            (5, 5.0, None),
+        ];
+        let expected = summary(&points, model).unwrap();
+        for split in 1..points.len() {
+            let mut first = summary(&points[..split], model).unwrap();
+            let second = summary(&points[split..], model).unwrap();
+            first.combine(&second).unwrap();
+            // the adjusted values are all close to 2^32, so the sums of
+            // squares differ more than `assert_close_enough` allows
+            let (actual, whole) = (&first.summary, &expected.summary);
+            assert_eq!(
+                (actual.first, actual.last, actual.num_resets),
+                (whole.first, whole.last, whole.num_resets)
+            );
+            assert_eq!(actual.stats.n, whole.stats.n);
+            assert_relative_eq!(actual.stats.sy, whole.stats.sy, max_relative = 1e-6);
+            assert_relative_eq!(actual.stats.sy2, whole.stats.sy2, max_relative = 1e-6);
+            assert_relative_eq!(actual.stats.sxy, whole.stats.sxy, max_relative = 1e-6);
+            assert_eq!(actual.reset_sum, whole.reset_sum);
+            assert_eq!(first.num_wraps, 1);
+            assert_eq!(first.num_ignored, 1);
+            assert_eq!(first.last_created, expected.last_created);
+        }
+
+        let mut first = summary(&points[..2], model).unwrap();
+        let second = summary(&points[2..], CounterModel::new(Some(64), 1.0).unwrap()).unwrap();
+        assert_eq!(first.combine(&second), Err(CounterError::ModelMismatch));
+    }
+}
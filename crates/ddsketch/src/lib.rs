@@ -0,0 +1,355 @@
+//! DDSketch implementation in rust.
+//! Based on the paper: https://arxiv.org/abs/1908.10693
+//!
+//! This uses the same logarithmic bucket mapping as UDDSketch, but handles
+//! running out of buckets differently.  Where UDDSketch merges every pair of
+//! adjacent buckets (doubling the error for the whole distribution), this
+//! sketch collapses the buckets at one end of the distribution into their
+//! neighbor.  Quantiles away from the collapsed end keep the initial relative
+//! error guarantee no matter how many values are added.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use uddsketch::SketchHashKey;
+
+// Which end of the distribution loses resolution once `max_buckets` is exceeded.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CollapseMode {
+    Lowest,
+    Highest,
+}
+
+// SketchHashKey only implements PartialOrd, but its ordering is total over the
+// keys we can generate, so wrap it for use in the BTreeMap.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+struct OrderedKey(SketchHashKey);
+
+impl Ord for OrderedKey {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0
+            .partial_cmp(&other.0)
+            .expect("sketch keys are totally ordered")
+    }
+}
+
+impl PartialOrd for OrderedKey {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DDSketch {
+    buckets: BTreeMap<OrderedKey, u64>,
+    alpha: f64,
+    gamma: f64,
+    max_buckets: u64,
+    collapse: CollapseMode,
+    num_values: u64,
+    values_sum: f64,
+}
+
+impl DDSketch {
+    pub fn new(max_buckets: u64, max_error: f64, collapse: CollapseMode) -> Self {
+        assert!((1e-12..1.0).contains(&max_error));
+        assert!(max_buckets > 0);
+        DDSketch {
+            buckets: BTreeMap::new(),
+            alpha: max_error,
+            gamma: uddsketch::gamma(max_error),
+            max_buckets,
+            collapse,
+            num_values: 0,
+            values_sum: 0.0,
+        }
+    }
+
+    // This constructor is used to recreate a DDSketch from it's component data
+    pub fn new_from_data(
+        max_buckets: u64,
+        max_error: f64,
+        collapse: CollapseMode,
+        values: u64,
+        sum: f64,
+        keys: impl Iterator<Item = SketchHashKey>,
+        counts: impl Iterator<Item = u64>,
+    ) -> Self {
+        let mut sketch = DDSketch::new(max_buckets, max_error, collapse);
+        sketch.num_values = values;
+        sketch.values_sum = sum;
+        for (key, count) in keys.zip(counts) {
+            sketch.buckets.insert(OrderedKey(key), count);
+        }
+        sketch
+    }
+}
+
+impl DDSketch {
+    // For a given value return the index of it's bucket in the sketch.
+    fn key(&self, value: f64) -> SketchHashKey {
+        uddsketch::key(value, self.gamma)
+    }
+
+    // Fold the buckets at the collapsing end of the sketch into their neighbor
+    // until we're back within `max_buckets`.
+    fn collapse_buckets(&mut self) {
+        while self.buckets.len() > self.max_buckets as usize {
+            let folded = match self.collapse {
+                CollapseMode::Lowest => *self.buckets.keys().next().unwrap(),
+                CollapseMode::Highest => *self.buckets.keys().next_back().unwrap(),
+            };
+            let count = self.buckets.remove(&folded).unwrap();
+            let neighbor = match self.collapse {
+                CollapseMode::Lowest => self.buckets.values_mut().next(),
+                CollapseMode::Highest => self.buckets.values_mut().next_back(),
+            };
+            *neighbor.unwrap() += count;
+        }
+    }
+
+    pub fn bucket_iter(&self) -> impl Iterator<Item = (SketchHashKey, u64)> + '_ {
+        self.buckets.iter().map(|(key, count)| (key.0, *count))
+    }
+}
+
+impl DDSketch {
+    pub fn add_value(&mut self, value: f64) {
+        *self.buckets.entry(OrderedKey(self.key(value))).or_insert(0) += 1;
+        self.collapse_buckets();
+
+        self.num_values += 1;
+        self.values_sum += value;
+    }
+
+    /// Whether `other` was built with the same parameters, which merging requires.
+    pub fn compatible(&self, other: &DDSketch) -> bool {
+        (self.alpha - other.alpha).abs() < 1e-9
+            && self.max_buckets == other.max_buckets
+            && self.collapse == other.collapse
+    }
+
+    pub fn merge_sketch(&mut self, other: &DDSketch) {
+        assert!(self.compatible(other));
+
+        for (key, count) in other.buckets.iter() {
+            *self.buckets.entry(*key).or_insert(0) += count;
+        }
+        self.collapse_buckets();
+
+        self.num_values += other.num_values;
+        self.values_sum += other.values_sum;
+    }
+
+    pub fn max_allowed_buckets(&self) -> u64 {
+        self.max_buckets
+    }
+
+    pub fn collapse_mode(&self) -> CollapseMode {
+        self.collapse
+    }
+
+    pub fn current_buckets_count(&self) -> usize {
+        self.buckets.len()
+    }
+}
+
+impl DDSketch {
+    #[inline]
+    pub fn mean(&self) -> f64 {
+        if self.num_values == 0 {
+            0.0
+        } else {
+            self.values_sum / self.num_values as f64
+        }
+    }
+
+    #[inline]
+    pub fn sum(&self) -> f64 {
+        self.values_sum
+    }
+
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.num_values
+    }
+
+    /// The relative error guaranteed for quantiles that don't fall into the
+    /// collapsed end of the sketch.
+    #[inline]
+    pub fn max_error(&self) -> f64 {
+        self.alpha
+    }
+
+    pub fn estimate_quantile(&self, quantile: f64) -> f64 {
+        uddsketch::estimate_quantile(
+            quantile,
+            self.alpha,
+            self.gamma,
+            self.num_values,
+            self.bucket_iter(),
+        )
+    }
+
+    pub fn estimate_quantile_at_value(&self, value: f64) -> f64 {
+        uddsketch::estimate_quantile_at_value(
+            value,
+            self.gamma,
+            self.num_values,
+            self.bucket_iter(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn build_and_add_values() {
+        let mut sketch = DDSketch::new(20, 0.1, CollapseMode::Lowest);
+        sketch.add_value(1.0);
+        sketch.add_value(3.0);
+        sketch.add_value(0.5);
+
+        assert_eq!(sketch.count(), 3);
+        assert_eq!(sketch.mean(), 1.5);
+        assert_eq!(sketch.max_error(), 0.1);
+        assert_eq!(sketch.current_buckets_count(), 3);
+    }
+
+    #[test]
+    fn collapse_lowest() {
+        let mut sketch = DDSketch::new(20, 0.1, CollapseMode::Lowest);
+        for i in 0..30 {
+            sketch.add_value(1.23_f64.powi(i));
+        }
+
+        assert_eq!(sketch.count(), 30);
+        assert_eq!(sketch.current_buckets_count(), 20);
+        assert_eq!(sketch.max_error(), 0.1);
+
+        // the first 11 values now all live in the lowest remaining bucket
+        let (lowest, count) = sketch.bucket_iter().next().unwrap();
+        assert_eq!(lowest, sketch.key(1.23_f64.powi(10)));
+        assert_eq!(count, 11);
+
+        // the top of the distribution is unaffected
+        let max = 1.23_f64.powi(29);
+        assert!((sketch.estimate_quantile(1.0) - max).abs() / max <= 0.1);
+    }
+
+    #[test]
+    fn collapse_highest() {
+        let mut sketch = DDSketch::new(20, 0.1, CollapseMode::Highest);
+        for i in 0..30 {
+            sketch.add_value(1.23_f64.powi(i));
+        }
+
+        assert_eq!(sketch.current_buckets_count(), 20);
+        let (highest, count) = sketch.bucket_iter().last().unwrap();
+        assert_eq!(highest, sketch.key(1.23_f64.powi(19)));
+        assert_eq!(count, 11);
+
+        assert!((sketch.estimate_quantile(0.0) - 1.0).abs() <= 0.1);
+    }
+
+    #[test]
+    fn collapse_across_zero_and_negatives() {
+        let mut sketch = DDSketch::new(3, 0.1, CollapseMode::Lowest);
+        sketch.add_value(-100.0);
+        sketch.add_value(-1.0);
+        sketch.add_value(0.0);
+        sketch.add_value(1.0);
+        sketch.add_value(100.0);
+
+        let buckets: Vec<_> = sketch.bucket_iter().collect();
+        assert_eq!(
+            buckets,
+            vec![
+                (SketchHashKey::Zero, 3),
+                (sketch.key(1.0), 1),
+                (sketch.key(100.0), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_sketches() {
+        let mut sketch1 = DDSketch::new(20, 0.1, CollapseMode::Lowest);
+        let mut sketch2 = DDSketch::new(20, 0.1, CollapseMode::Lowest);
+        let mut expected = DDSketch::new(20, 0.1, CollapseMode::Lowest);
+        for i in 0..30 {
+            let value = 1.23_f64.powi(i);
+            if i % 2 == 0 {
+                sketch1.add_value(value);
+            } else {
+                sketch2.add_value(value);
+            }
+        }
+        // values are added in increasing order so no collapsed bucket ever
+        // needs to be split when building the expected sketch
+        for i in 0..30 {
+            expected.add_value(1.23_f64.powi(i));
+        }
+
+        sketch1.merge_sketch(&sketch2);
+        assert_eq!(sketch1.count(), 30);
+        assert_eq!(sketch1.current_buckets_count(), 20);
+        assert_eq!(
+            sketch1.bucket_iter().collect::<Vec<_>>(),
+            expected.bucket_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn rebuild_from_data() {
+        let mut sketch = DDSketch::new(20, 0.05, CollapseMode::Highest);
+        for i in -20..20 {
+            sketch.add_value(i as f64 * 3.7);
+        }
+
+        let (keys, counts): (Vec<_>, Vec<_>) = sketch.bucket_iter().unzip();
+        let rebuilt = DDSketch::new_from_data(
+            20,
+            0.05,
+            CollapseMode::Highest,
+            sketch.count(),
+            sketch.sum(),
+            keys.into_iter(),
+            counts.into_iter(),
+        );
+        assert_eq!(rebuilt, sketch);
+    }
+
+    #[test]
+    fn random_stress_high_quantiles() {
+        let mut sketch = DDSketch::new(100, 0.01, CollapseMode::Lowest);
+        let seed = rand::thread_rng().gen();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        let mut values = Vec::new();
+        for _ in 0..100_000 {
+            // log-uniform over ten orders of magnitude, far more than 100 buckets can cover
+            let v = 10.0_f64.powf(rng.gen_range(-5.0..5.0));
+            sketch.add_value(v);
+            values.push(v);
+        }
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        for quantile in [0.99, 0.995, 0.999] {
+            let idx = (quantile * values.len() as f64) as usize;
+            let target = values[idx];
+            let estimate = sketch.estimate_quantile(quantile);
+            assert!(
+                (estimate - target).abs() / target <= sketch.max_error(),
+                "Failed to match {} quantile with seed {}. Received: {}, Expected: {}",
+                quantile,
+                seed,
+                estimate,
+                target
+            );
+        }
+    }
+}
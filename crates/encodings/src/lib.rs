@@ -323,3 +323,292 @@ pub mod prefix_varint {
         }
     }
 }
+
+pub mod gorilla {
+    //! The XOR float encoding from Facebook's Gorilla,
+    //! https://www.vldb.org/pvldb/vol8/p1816-teller.pdf
+    //!
+    //! The first value is stored as-is, every later value is XORed with the
+    //! previous one:
+    //! ```python,ignore,no_run
+    //! 0                                     same as the previous value
+    //! 10 <meaningful bits>                  the XOR fits in the previous window
+    //! 11 <5 bits leading zeros>
+    //!    <6 bits meaningful bits - 1>
+    //!    <meaningful bits>                  the XOR starts a new window
+    //! ```
+    //! A new window with more than 64 bits marks the end of the stream, the
+    //! rest of the last byte is padding.  Values are compared bit-for-bit, so
+    //! NaN payloads, signed zeros and subnormals all round-trip exactly.
+
+    // 11, 31 leading zeros, 64 meaningful bits
+    const END_MARKER: (u64, u32) = ((0b11 << 11) | (31 << 6) | 63, 13);
+
+    pub struct F64Compressor {
+        bits: BitWriter,
+        prev: Option<u64>,
+        // leading and trailing zeros of the current window
+        window: Option<(u32, u32)>,
+    }
+
+    impl F64Compressor {
+        pub fn new() -> Self {
+            Self {
+                bits: BitWriter::default(),
+                prev: None,
+                window: None,
+            }
+        }
+
+        pub fn push(&mut self, value: f64) {
+            let value = value.to_bits();
+            let prev = match self.prev.replace(value) {
+                None => return self.bits.write(value, 64),
+                Some(prev) => prev,
+            };
+
+            let xor = value ^ prev;
+            if xor == 0 {
+                return self.bits.write(0b0, 1);
+            }
+
+            // the leading zeros are stored in 5 bits
+            let leading = xor.leading_zeros().min(31);
+            let trailing = xor.trailing_zeros();
+            match self.window {
+                Some((l, t)) if leading >= l && trailing >= t => {
+                    self.bits.write(0b10, 2);
+                    self.bits.write(xor >> t, 64 - l - t);
+                }
+                _ => {
+                    let meaningful = 64 - leading - trailing;
+                    self.bits.write(0b11, 2);
+                    self.bits.write(leading as u64, 5);
+                    self.bits.write(meaningful as u64 - 1, 6);
+                    self.bits.write(xor >> trailing, meaningful);
+                    self.window = Some((leading, trailing));
+                }
+            }
+        }
+
+        pub fn finish(mut self) -> Vec<u8> {
+            if self.prev.is_some() {
+                let (marker, len) = END_MARKER;
+                self.bits.write(marker, len);
+            }
+            self.bits.finish()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.prev.is_none()
+        }
+    }
+
+    impl Default for F64Compressor {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    pub fn compress_f64s_to_vec<I: Iterator<Item = f64>>(bytes: &mut Vec<u8>, values: I) {
+        let mut compressor = F64Compressor::new();
+        values.for_each(|v| compressor.push(v));
+        bytes.extend_from_slice(&compressor.finish());
+    }
+
+    pub fn f64_decompressor(bytes: &[u8]) -> F64Decompressor<'_> {
+        F64Decompressor {
+            bits: BitReader {
+                bytes,
+                pending: 0,
+                pending_bits: 0,
+            },
+            prev: None,
+            window: (0, 0),
+        }
+    }
+
+    pub struct F64Decompressor<'a> {
+        bits: BitReader<'a>,
+        prev: Option<u64>,
+        window: (u32, u32),
+    }
+
+    impl<'a> Iterator for F64Decompressor<'a> {
+        type Item = f64;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let value = match self.prev {
+                None => self.bits.read(64)?,
+                Some(prev) if self.bits.read(1)? == 0 => prev,
+                Some(prev) => {
+                    if self.bits.read(1)? == 1 {
+                        let leading = self.bits.read(5)? as u32;
+                        let meaningful = self.bits.read(6)? as u32 + 1;
+                        if leading + meaningful > 64 {
+                            // the end marker, stay at the end
+                            self.bits.bytes = &[];
+                            self.bits.pending_bits = 0;
+                            return None;
+                        }
+                        self.window = (leading, 64 - leading - meaningful);
+                    }
+                    let (leading, trailing) = self.window;
+                    prev ^ (self.bits.read(64 - leading - trailing)? << trailing)
+                }
+            };
+            self.prev = Some(value);
+            Some(f64::from_bits(value))
+        }
+    }
+
+    #[inline]
+    fn mask(num_bits: u32) -> u64 {
+        u64::MAX.checked_shr(64 - num_bits).unwrap_or(0)
+    }
+
+    // writes bits most-significant first
+    #[derive(Default)]
+    struct BitWriter {
+        bytes: Vec<u8>,
+        // always fewer than 8 bits pending between writes
+        pending: u128,
+        pending_bits: u32,
+    }
+
+    impl BitWriter {
+        fn write(&mut self, value: u64, num_bits: u32) {
+            debug_assert!(num_bits <= 64);
+            self.pending = (self.pending << num_bits) | (value & mask(num_bits)) as u128;
+            self.pending_bits += num_bits;
+            while self.pending_bits >= 8 {
+                self.pending_bits -= 8;
+                self.bytes.push((self.pending >> self.pending_bits) as u8);
+            }
+            self.pending &= (1 << self.pending_bits) - 1;
+        }
+
+        fn finish(mut self) -> Vec<u8> {
+            if self.pending_bits > 0 {
+                self.bytes
+                    .push((self.pending << (8 - self.pending_bits)) as u8);
+            }
+            self.bytes
+        }
+    }
+
+    struct BitReader<'a> {
+        bytes: &'a [u8],
+        pending: u128,
+        pending_bits: u32,
+    }
+
+    impl<'a> BitReader<'a> {
+        fn read(&mut self, num_bits: u32) -> Option<u64> {
+            debug_assert!(num_bits <= 64);
+            while self.pending_bits < num_bits {
+                let (byte, rest) = self.bytes.split_first()?;
+                self.bytes = rest;
+                self.pending = (self.pending << 8) | *byte as u128;
+                self.pending_bits += 8;
+            }
+            self.pending_bits -= num_bits;
+            let value = (self.pending >> self.pending_bits) as u64 & mask(num_bits);
+            self.pending &= (1 << self.pending_bits) - 1;
+            Some(value)
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use quickcheck_macros::quickcheck;
+
+        use super::*;
+
+        fn roundtrip(values: &[f64]) {
+            let mut bytes = vec![];
+            compress_f64s_to_vec(&mut bytes, values.iter().cloned());
+
+            let output: Vec<u64> = f64_decompressor(&bytes).map(f64::to_bits).collect();
+            let expected: Vec<u64> = values.iter().map(|v| v.to_bits()).collect();
+            assert_eq!(expected, output);
+        }
+
+        #[quickcheck]
+        fn quick_test_roundtrip_f64(values: Vec<f64>) -> bool {
+            roundtrip(&values);
+            true
+        }
+
+        // every bit pattern, including all the NaN payloads and subnormals
+        #[quickcheck]
+        fn quick_test_roundtrip_f64_bits(values: Vec<u64>) -> bool {
+            let values: Vec<f64> = values.into_iter().map(f64::from_bits).collect();
+            roundtrip(&values);
+            true
+        }
+
+        #[quickcheck]
+        fn quick_test_roundtrip_subnormals(values: Vec<(bool, u64)>) -> bool {
+            let values: Vec<f64> = values
+                .into_iter()
+                .map(|(negative, mantissa)| {
+                    let sign = if negative { 1 << 63 } else { 0 };
+                    f64::from_bits(sign | (mantissa & ((1 << 52) - 1)))
+                })
+                .collect();
+            assert!(values.iter().all(|v| !v.is_normal()));
+            roundtrip(&values);
+            true
+        }
+
+        #[test]
+        fn test_special_values() {
+            let specials = [
+                0.0,
+                -0.0,
+                f64::NAN,
+                -f64::NAN,
+                f64::from_bits(0x7ff0_0000_0000_0001), // signaling NaN
+                f64::from_bits(0x7fff_ffff_ffff_ffff),
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::MIN_POSITIVE,
+                f64::MIN_POSITIVE / 2.0,
+                f64::from_bits(1), // smallest subnormal
+                -f64::from_bits(1),
+                f64::MAX,
+                f64::MIN,
+                f64::EPSILON,
+                1.0,
+            ];
+            roundtrip(&specials);
+            // every pair, in both orders, so each value is XORed with each
+            let pairs: Vec<f64> = specials
+                .iter()
+                .flat_map(|a| specials.iter().flat_map(move |b| [*a, *b]))
+                .collect();
+            roundtrip(&pairs);
+        }
+
+        #[test]
+        fn test_streaming() {
+            let compressor = F64Compressor::new();
+            assert!(compressor.is_empty());
+            assert!(compressor.finish().is_empty());
+            assert_eq!(f64_decompressor(&[]).next(), None);
+
+            let mut compressor = F64Compressor::default();
+            for i in 0..100 {
+                compressor.push((i / 10) as f64);
+            }
+            assert!(!compressor.is_empty());
+            let bytes = compressor.finish();
+            // runs of equal values take a single bit each
+            assert!(bytes.len() < 100, "{} bytes", bytes.len());
+            let output: Vec<f64> = f64_decompressor(&bytes).collect();
+            let expected: Vec<f64> = (0..100).map(|i| (i / 10) as f64).collect();
+            assert_eq!(output, expected);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+//! Holt-Winters (triple exponential smoothing) forecasting.
+//!
+//! The additive method tracks a level, a trend and a seasonal adjustment for
+//! each of the `period` positions of a season, updating them after every
+//! observation:
+//!
+//! ```text
+//! level    = α (y - seasonal[t % period]) + (1 - α) (level + trend)
+//! trend    = β (level - previous level) + (1 - β) trend
+//! seasonal = γ (y - level) + (1 - γ) seasonal[t % period]
+//! ```
+//!
+//! With a `period` of 0 there is no seasonal component and this is Holt's
+//! linear trend method.  The values are assumed to be evenly spaced.
+//!
+//! Prediction intervals use the variance of the one step ahead errors made
+//! while fitting, propagated `h` steps ahead with the formula for the
+//! equivalent ETS(A,A,A) state space model from Hyndman et al., "Forecasting
+//! with Exponential Smoothing" (2008), table 6.1.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HoltWinters {
+    pub alpha: f64,
+    pub beta: f64,
+    pub gamma: f64,
+    pub level: f64,
+    pub trend: f64,
+    /// Seasonal adjustments, `seasonal[0]` applies to the step after the last
+    /// value.  Empty without seasonality.
+    pub seasonal: Vec<f64>,
+    /// Sum of the squared one step ahead errors.
+    pub sse: f64,
+    pub num_errors: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FitError {
+    /// A smoothing parameter isn't within `[0, 1]`.
+    InvalidParameter,
+    /// `period` is 1, a season needs at least two values.
+    InvalidPeriod,
+    /// Fewer than two values, or two seasons of values when seasonal.
+    TooFewValues,
+}
+
+impl HoltWinters {
+    /// The number of values `fit` needs for a given `period`.
+    pub fn min_values(period: usize) -> usize {
+        if period == 0 {
+            2
+        } else {
+            2 * period
+        }
+    }
+
+    /// Fits the model to evenly spaced `values`.
+    ///
+    /// The initial trend is the difference between the first two values, or
+    /// the difference between the means of the first two seasons divided by
+    /// `period`.  The initial level is the first value, or the mean of the
+    /// first season moved along the trend to its last value, and the initial
+    /// seasonal adjustments are the deviations of the first season from that
+    /// trend line.  The values used for initialization aren't included in the
+    /// errors.
+    pub fn fit(
+        values: &[f64],
+        period: usize,
+        alpha: f64,
+        beta: f64,
+        gamma: f64,
+    ) -> Result<Self, FitError> {
+        let valid = |p: f64| (0.0..=1.0).contains(&p);
+        if !valid(alpha) || !valid(beta) || !valid(gamma) {
+            return Err(FitError::InvalidParameter);
+        }
+        if period == 1 {
+            return Err(FitError::InvalidPeriod);
+        }
+        if values.len() < Self::min_values(period) {
+            return Err(FitError::TooFewValues);
+        }
+
+        let (level, trend, seasonal, start) = if period == 0 {
+            (values[0], values[1] - values[0], vec![], 1)
+        } else {
+            let mean = |season: &[f64]| season.iter().sum::<f64>() / period as f64;
+            let first = mean(&values[..period]);
+            let second = mean(&values[period..2 * period]);
+            let trend = (second - first) / period as f64;
+            // the mean is the level in the middle of the season
+            let middle = (period - 1) as f64 / 2.0;
+            let seasonal = values[..period]
+                .iter()
+                .enumerate()
+                .map(|(i, v)| v - (first + (i as f64 - middle) * trend))
+                .collect();
+            (first + middle * trend, trend, seasonal, period)
+        };
+        let mut model = HoltWinters {
+            alpha,
+            beta,
+            gamma,
+            level,
+            trend,
+            seasonal,
+            sse: 0.0,
+            num_errors: 0,
+        };
+        for &value in &values[start..] {
+            model.update(value);
+        }
+        Ok(model)
+    }
+
+    fn update(&mut self, value: f64) {
+        let season = self.seasonal.first().copied().unwrap_or(0.0);
+        let error = value - (self.level + self.trend + season);
+        self.sse += error * error;
+        self.num_errors += 1;
+
+        let previous = self.level;
+        self.level =
+            self.alpha * (value - season) + (1.0 - self.alpha) * (self.level + self.trend);
+        self.trend = self.beta * (self.level - previous) + (1.0 - self.beta) * self.trend;
+        if !self.seasonal.is_empty() {
+            self.seasonal[0] =
+                self.gamma * (value - self.level) + (1.0 - self.gamma) * self.seasonal[0];
+            self.seasonal.rotate_left(1);
+        }
+    }
+
+    /// The forecast `steps` steps after the last value.  Fractional steps
+    /// extrapolate the trend linearly and use the seasonal adjustment of the
+    /// nearest whole step.
+    pub fn forecast(&self, steps: f64) -> f64 {
+        let season = match self.seasonal.len() {
+            0 => 0.0,
+            period => {
+                let step = (steps.round() as i64 - 1).rem_euclid(period as i64);
+                self.seasonal[step as usize]
+            }
+        };
+        self.level + steps * self.trend + season
+    }
+
+    /// The variance of the one step ahead errors, `None` if there were none.
+    pub fn error_variance(&self) -> Option<f64> {
+        if self.num_errors == 0 {
+            return None;
+        }
+        Some(self.sse / self.num_errors as f64)
+    }
+
+    /// The variance of the forecast `steps` steps after the last value,
+    /// fractional steps are rounded up.
+    pub fn forecast_variance(&self, steps: f64) -> Option<f64> {
+        let variance = self.error_variance()?;
+        let steps = steps.ceil().max(1.0) as u64;
+        // the ETS form updates the seasonal adjustment by γ (1 - α) times the
+        // error rather than γ times the deviation from the new level
+        let gamma = self.gamma * (1.0 - self.alpha);
+        let period = self.seasonal.len() as u64;
+        let sum: f64 = (1..steps)
+            .map(|j| {
+                let seasonal = if period > 0 && j % period == 0 {
+                    gamma
+                } else {
+                    0.0
+                };
+                let c = self.alpha * (1.0 + j as f64 * self.beta) + seasonal;
+                c * c
+            })
+            .sum();
+        Some(variance * (1.0 + sum))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn linear_trend() {
+        let values: Vec<f64> = (0..20).map(|i| 3.0 + 2.0 * i as f64).collect();
+        let model = HoltWinters::fit(&values, 0, 0.5, 0.5, 0.0).unwrap();
+        assert_relative_eq!(model.level, 41.0);
+        assert_relative_eq!(model.trend, 2.0);
+        assert_relative_eq!(model.forecast(1.0), 43.0);
+        assert_relative_eq!(model.forecast(10.0), 61.0);
+        assert_relative_eq!(model.forecast(0.5), 42.0);
+        assert_relative_eq!(model.error_variance().unwrap(), 0.0);
+        assert_eq!(model.num_errors, 19);
+    }
+
+    #[test]
+    fn seasonal_pattern() {
+        let pattern = [5.0, -1.0, -3.0, -1.0];
+        let values: Vec<f64> = (0..40)
+            .map(|i| 10.0 + 0.5 * i as f64 + pattern[i % 4])
+            .collect();
+        let model = HoltWinters::fit(&values, 4, 0.3, 0.1, 0.2).unwrap();
+        for steps in 1..=8 {
+            let i = 39 + steps;
+            let expected = 10.0 + 0.5 * i as f64 + pattern[i % 4];
+            assert_relative_eq!(model.forecast(steps as f64), expected, epsilon = 1e-9);
+        }
+        assert_eq!(model.num_errors, 36);
+    }
+
+    #[test]
+    fn smoothing_follows_level_shift() {
+        let mut values = vec![10.0; 10];
+        values.extend([20.0; 30]);
+        let model = HoltWinters::fit(&values, 0, 0.5, 0.0, 0.0).unwrap();
+        assert_relative_eq!(model.trend, 0.0);
+        assert_relative_eq!(model.forecast(5.0), 20.0, epsilon = 1e-6);
+        // the one big miss at the shift dominates the errors
+        assert!(model.error_variance().unwrap() > 2.0);
+    }
+
+    #[test]
+    fn forecast_variance_grows() {
+        let values: Vec<f64> = (0..50)
+            .map(|i| i as f64 + if i % 2 == 0 { 1.0 } else { -1.0 })
+            .collect();
+        let model = HoltWinters::fit(&values, 0, 0.5, 0.2, 0.0).unwrap();
+        let variance = model.error_variance().unwrap();
+        assert_relative_eq!(model.forecast_variance(1.0).unwrap(), variance);
+        // 1 + (0.5 * 1.2)^2 + (0.5 * 1.4)^2
+        assert_relative_eq!(
+            model.forecast_variance(3.0).unwrap(),
+            variance * (1.0 + 0.36 + 0.49)
+        );
+        assert_relative_eq!(
+            model.forecast_variance(2.5).unwrap(),
+            model.forecast_variance(3.0).unwrap()
+        );
+
+        let seasonal: Vec<f64> = (0..16).map(|i| (i % 4) as f64 + (i % 3) as f64).collect();
+        let model = HoltWinters::fit(&seasonal, 4, 0.5, 0.0, 0.5).unwrap();
+        let variance = model.error_variance().unwrap();
+        // steps 1 to 3 only see the level, step 4 is a full season away
+        let expected = variance * (1.0 + 3.0 * 0.25 + (0.5 + 0.25) * (0.5 + 0.25));
+        assert_relative_eq!(model.forecast_variance(5.0).unwrap(), expected);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            HoltWinters::fit(&[1.0, 2.0], 0, 1.5, 0.5, 0.5),
+            Err(FitError::InvalidParameter)
+        );
+        assert_eq!(
+            HoltWinters::fit(&[1.0, 2.0], 0, 0.5, f64::NAN, 0.5),
+            Err(FitError::InvalidParameter)
+        );
+        assert_eq!(
+            HoltWinters::fit(&[1.0, 2.0], 1, 0.5, 0.5, 0.5),
+            Err(FitError::InvalidPeriod)
+        );
+        assert_eq!(
+            HoltWinters::fit(&[1.0], 0, 0.5, 0.5, 0.5),
+            Err(FitError::TooFewValues)
+        );
+        assert_eq!(
+            HoltWinters::fit(&[1.0, 2.0, 3.0, 4.0, 5.0], 3, 0.5, 0.5, 0.5),
+            Err(FitError::TooFewValues)
+        );
+        assert!(HoltWinters::fit(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 3, 0.5, 0.5, 0.5).is_ok());
+    }
+}
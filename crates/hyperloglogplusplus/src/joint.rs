@@ -0,0 +1,259 @@
+//! Joint estimation of the cardinalities of `A \ B`, `B \ A` and `A ∩ B` from
+//! the registers of two sketches, after Otmar Ertl, "New cardinality
+//! estimation algorithms for HyperLogLog sketches" (2017), section 4.
+//!
+//! The values of each set are modeled as Poisson distributed over the
+//! registers, so the pair of registers at each index is the maximum of
+//! independent geometric values from the three disjoint parts of the sets.
+//! The rates of those parts are found by maximizing the likelihood of all of
+//! the register pairs together.  Unlike inclusion–exclusion, which subtracts
+//! the union from the sum of the two counts and so has an error on the order
+//! of the union, this uses which of the two registers of each pair is larger,
+//! and stays accurate for intersections that are small compared to the sets.
+
+use std::collections::BTreeMap;
+
+use crate::registers::Registers;
+
+// The rates are optimized as their logarithms, within these bounds.  A rate
+// that ends up on the lower bound is taken to be zero: the registers are
+// explained without that part of the sets.
+const MIN_LOG_RATE: f64 = -30.0;
+const MAX_LOG_RATE: f64 = 50.0;
+
+const MAX_ITERATIONS: usize = 500;
+
+/// The estimated rates of `A \ B`, `B \ A` and `A ∩ B` per register.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct JointRates {
+    pub only_a: f64,
+    pub only_b: f64,
+    pub both: f64,
+}
+
+impl JointRates {
+    pub fn total(&self) -> f64 {
+        self.only_a + self.only_b + self.both
+    }
+}
+
+/// `initial` is a starting guess of the rates, such as from inclusion–exclusion.
+pub(crate) fn estimate_rates(
+    a: &Registers<'_>,
+    b: &Registers<'_>,
+    precision: u8,
+    initial: [f64; 3],
+) -> JointRates {
+    let mut pairs = BTreeMap::new();
+    for pair in a.iter().zip(b.iter()) {
+        *pairs.entry(pair).or_insert(0u64) += 1;
+    }
+    let likelihood = Likelihood {
+        pairs: pairs.into_iter().collect(),
+        max_register: 64 - precision,
+    };
+
+    let num_registers = (1u64 << precision) as f64;
+    let mut t = initial.map(|count| {
+        (count.max(0.5) / num_registers)
+            .ln()
+            .clamp(MIN_LOG_RATE, MAX_LOG_RATE)
+    });
+    let [only_a, only_b, both] = likelihood.maximize(&mut t);
+    JointRates {
+        only_a,
+        only_b,
+        both,
+    }
+}
+
+struct Likelihood {
+    // how many registers have each pair of values
+    pairs: Vec<((u8, u8), u64)>,
+    // `q` in the paper, registers range from 0 to `max_register + 1`
+    max_register: u8,
+}
+
+impl Likelihood {
+    // `2^-k`, the probability that a value is in a register greater than `k`,
+    // scaled by the rate.
+    fn weight(&self, k: u8) -> f64 {
+        if k > self.max_register {
+            0.0
+        } else {
+            2f64.powi(-(k as i32))
+        }
+    }
+
+    // the log probability that the maximum of the values of a rate `rate` in
+    // a register is at most `k`, and its derivative with respect to the rate
+    fn log_at_most(&self, k: u8, rate: f64) -> (f64, f64) {
+        let w = self.weight(k);
+        (-rate * w, -w)
+    }
+
+    // the log probability that the maximum of the values of a rate `rate` in
+    // a register is exactly `k`, and its derivative with respect to the rate
+    fn log_exactly(&self, k: u8, rate: f64) -> (f64, f64) {
+        if k == 0 {
+            return (-rate, -1.0);
+        }
+        let w = self.weight(k);
+        let d = self.weight(k - 1) - w;
+        // computed as a product rather than a difference of probabilities,
+        // which would lose all precision for the larger registers
+        let log = -rate * w + (-(-rate * d).exp_m1()).ln();
+        (log, -w + d / (rate * d).exp_m1())
+    }
+
+    // the log likelihood and its gradient with respect to the log rates
+    fn evaluate(&self, t: &[f64; 3]) -> (f64, [f64; 3]) {
+        let [ra, rb, rx] = t.map(f64::exp);
+        let mut total = 0.0;
+        let mut gradient = [0.0; 3];
+        for &((i, j), count) in &self.pairs {
+            let count = count as f64;
+            let (log, [ga, gb, gx]) = match i.cmp(&j) {
+                // the larger register can only have come from its own part,
+                // the smaller from the rest of its set
+                std::cmp::Ordering::Less => {
+                    let (log_b, db) = self.log_exactly(j, rb);
+                    let (log_ax, dax) = self.log_exactly(i, ra + rx);
+                    (log_b + log_ax, [dax, db, dax])
+                }
+                std::cmp::Ordering::Greater => {
+                    let (log_a, da) = self.log_exactly(i, ra);
+                    let (log_bx, dbx) = self.log_exactly(j, rb + rx);
+                    (log_a + log_bx, [da, dbx, dbx])
+                }
+                // either the intersection set both registers, or each was set
+                // by its own part with the intersection below them
+                std::cmp::Ordering::Equal => {
+                    let k = i;
+                    let (log_x, dx) = self.log_exactly(k, rx);
+                    let (log_a, da) = self.log_at_most(k, ra);
+                    let (log_b, db) = self.log_at_most(k, rb);
+                    let shared = log_x + log_a + log_b;
+                    if k == 0 {
+                        (shared, [da, db, dx])
+                    } else {
+                        let (log_x2, dx2) = self.log_at_most(k - 1, rx);
+                        let (log_a2, da2) = self.log_exactly(k, ra);
+                        let (log_b2, db2) = self.log_exactly(k, rb);
+                        let separate = log_x2 + log_a2 + log_b2;
+                        let max = shared.max(separate);
+                        let log = max + ((shared - max).exp() + (separate - max).exp()).ln();
+                        let (w1, w2) = ((shared - log).exp(), (separate - log).exp());
+                        (
+                            log,
+                            [w1 * da + w2 * da2, w1 * db + w2 * db2, w1 * dx + w2 * dx2],
+                        )
+                    }
+                }
+            };
+            total += count * log;
+            // chain rule for the log rates
+            gradient[0] += count * ga * ra;
+            gradient[1] += count * gb * rb;
+            gradient[2] += count * gx * rx;
+        }
+        (total, gradient)
+    }
+
+    // Levenberg-Marquardt damped Newton iterations over the log rates, using
+    // a Hessian estimated from the gradient.  Returns the rates.
+    fn maximize(&self, t: &mut [f64; 3]) -> [f64; 3] {
+        const STEP: f64 = 1e-5;
+        let (mut value, mut gradient) = self.evaluate(t);
+        let mut damping = 1e-3;
+        for _ in 0..MAX_ITERATIONS {
+            let mut hessian = [[0.0; 3]; 3];
+            for k in 0..3 {
+                let (mut above, mut below) = (*t, *t);
+                above[k] += STEP;
+                below[k] -= STEP;
+                let (_, ga) = self.evaluate(&above);
+                let (_, gb) = self.evaluate(&below);
+                for l in 0..3 {
+                    hessian[l][k] = (ga[l] - gb[l]) / (2.0 * STEP);
+                }
+            }
+            for k in 0..3 {
+                for l in 0..k {
+                    let mean = (hessian[k][l] + hessian[l][k]) / 2.0;
+                    hessian[k][l] = mean;
+                    hessian[l][k] = mean;
+                }
+            }
+
+            let mut improved = false;
+            while damping < 1e12 {
+                // solve (-H + damping * diag(|H|)) step = gradient
+                let mut system = [[0.0; 3]; 3];
+                for k in 0..3 {
+                    for l in 0..3 {
+                        system[k][l] = -hessian[k][l];
+                    }
+                    system[k][k] += damping * hessian[k][k].abs().max(1e-12);
+                }
+                let step = match solve(system, gradient) {
+                    Some(step) => step,
+                    None => {
+                        damping *= 10.0;
+                        continue;
+                    }
+                };
+                let mut next = *t;
+                for k in 0..3 {
+                    next[k] = (t[k] + step[k]).clamp(MIN_LOG_RATE, MAX_LOG_RATE);
+                }
+                let (next_value, next_gradient) = self.evaluate(&next);
+                if next_value.is_finite() && next_value >= value {
+                    let moved = (0..3).map(|k| (next[k] - t[k]).abs()).fold(0.0, f64::max);
+                    *t = next;
+                    value = next_value;
+                    gradient = next_gradient;
+                    damping = (damping / 10.0).max(1e-12);
+                    improved = moved > 1e-9;
+                    break;
+                }
+                damping *= 10.0;
+            }
+            if !improved {
+                break;
+            }
+        }
+        t.map(|t| {
+            if t <= MIN_LOG_RATE + 1e-6 {
+                0.0
+            } else {
+                t.exp()
+            }
+        })
+    }
+}
+
+// Gaussian elimination with partial pivoting, `None` if `a` is singular.
+fn solve(mut a: [[f64; 3]; 3], mut b: [f64; 3]) -> Option<[f64; 3]> {
+    for col in 0..3 {
+        let pivot = (col..3).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-300 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in col + 1..3 {
+            let factor = a[row][col] / a[col][col];
+            for k in col..3 {
+                a[row][k] -= factor * a[col][k];
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = [0.0; 3];
+    for row in (0..3).rev() {
+        let sum: f64 = (row + 1..3).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    x.iter().all(|x| x.is_finite()).then_some(x)
+}
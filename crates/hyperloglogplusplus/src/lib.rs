@@ -11,6 +11,7 @@ use std::{
 
 pub mod dense;
 mod hyperloglog_data;
+mod joint;
 pub mod registers;
 pub mod sparse;
 
@@ -167,6 +168,110 @@ where
     }
 }
 
+// Set operations between two sketches of the same precision. While the union
+// is still sparse its estimate is close to exact, and so is inclusion–exclusion.
+// Otherwise the share of the union that each part of the two sets makes up is
+// estimated jointly from the pairs of registers (see the `joint` module), then
+// scaled by the estimate of the union. The error of an intersection is then
+// relative to its own size rather than that of the union, as it would be with
+// inclusion–exclusion.
+impl<'s, T, B> HyperLogLog<'s, T, B>
+where
+    T: Hash,
+    B: BuildHasher + Clone,
+{
+    pub fn estimate_union_count<'o>(&self, other: &HyperLogLog<'o, T, B>) -> u64 {
+        self.set_counts(other).2
+    }
+
+    // The counts of both sketches and their union, and whether the union is
+    // still sparse.
+    fn set_counts<'o>(&self, other: &HyperLogLog<'o, T, B>) -> (u64, u64, u64, bool) {
+        let mut union = self.into_owned();
+        let mut other = other.into_owned();
+        union.merge_all();
+        other.merge_all();
+        let a = union.estimate_count();
+        let b = other.estimate_count();
+        union.merge_in(&other);
+        let sparse = union.is_sparse();
+        (a, b, union.estimate_count().max(a).max(b), sparse)
+    }
+
+    fn dense_storage(&self) -> dense::Storage<'static> {
+        use HyperLogLogStorage::*;
+
+        match &self.storage {
+            Sparse(s) => {
+                let mut s = s.into_owned();
+                s.merge_buffers();
+                s.immutable_to_dense()
+            }
+            Dense(s) => s.into_owned(),
+        }
+    }
+
+    // The estimated sizes of `self \ other`, `other \ self` and their
+    // intersection, which add up to the estimated union.
+    fn joint_counts<'o>(&self, other: &HyperLogLog<'o, T, B>) -> (f64, f64, f64) {
+        let (a, b, union, sparse) = self.set_counts(other);
+        let (a, b, union) = (a as f64, b as f64, union as f64);
+        // inclusion–exclusion is also the starting point for the joint estimation
+        let both = (a + b - union).clamp(0.0, a.min(b));
+        if sparse {
+            return (a - both, b - both, both);
+        }
+        let (dense, other_dense) = (self.dense_storage(), other.dense_storage());
+        // the sketches are passed in a fixed order so that the rounding in the
+        // optimization can't make the estimates asymmetric
+        let swap = dense.registers.bytes() > other_dense.registers.bytes();
+        let (first, second, initial) = if swap {
+            (&other_dense, &dense, [b - both, a - both, both])
+        } else {
+            (&dense, &other_dense, [a - both, b - both, both])
+        };
+        let mut rates = joint::estimate_rates(
+            &first.registers,
+            &second.registers,
+            dense.precision,
+            initial,
+        );
+        if swap {
+            std::mem::swap(&mut rates.only_a, &mut rates.only_b);
+        }
+        let total = rates.total();
+        if total == 0.0 {
+            return (0.0, 0.0, 0.0);
+        }
+        (
+            union * rates.only_a / total,
+            union * rates.only_b / total,
+            union * rates.both / total,
+        )
+    }
+
+    /// Estimates the number of values seen by both `self` and `other`.
+    pub fn estimate_intersection_count<'o>(&self, other: &HyperLogLog<'o, T, B>) -> u64 {
+        self.joint_counts(other).2.round() as u64
+    }
+
+    /// Estimates the number of values seen by `self` but not by `other`.
+    pub fn estimate_difference_count<'o>(&self, other: &HyperLogLog<'o, T, B>) -> u64 {
+        self.joint_counts(other).0.round() as u64
+    }
+
+    /// Estimates the Jaccard index, `|self ∩ other| / |self ∪ other|`, of the
+    /// two sets. Two empty sets have an index of 0.
+    pub fn estimate_jaccard<'o>(&self, other: &HyperLogLog<'o, T, B>) -> f64 {
+        let (only_a, only_b, both) = self.joint_counts(other);
+        let union = only_a + only_b + both;
+        if union == 0.0 {
+            return 0.0;
+        }
+        both / union
+    }
+}
+
 pub(crate) trait Extractable:
     Sized + Copy + std::ops::Shl<u8, Output = Self> + std::ops::Shr<u8, Output = Self>
 {
@@ -420,6 +525,122 @@ mod tests {
         assert_eq!(hll_b.estimate_count(), baseline.estimate_count())
     }
 
+    // FNV clusters sequential integers too much for the dense estimates to be
+    // useful here, so use SipHash with fixed keys instead
+    type SipBuildHasher = std::hash::BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+
+    fn overlapping(
+        precision: u8,
+    ) -> (
+        HyperLogLog<'static, u64, SipBuildHasher>,
+        HyperLogLog<'static, u64, SipBuildHasher>,
+    ) {
+        let mut hll_a = HyperLogLog::new(precision, SipBuildHasher::default());
+        let mut hll_b = HyperLogLog::new(precision, SipBuildHasher::default());
+        for i in 0..10_000u64 {
+            hll_a.add(&i);
+        }
+        for i in 5_000..15_000u64 {
+            hll_b.add(&i);
+        }
+        (hll_a, hll_b)
+    }
+
+    #[test]
+    fn intersection_and_difference_12() {
+        let (hll_a, hll_b) = overlapping(12);
+        let union = hll_a.estimate_union_count(&hll_b) as f64;
+        let error = 3.0 * error_for_precision(12) * union;
+
+        let intersection = hll_a.estimate_intersection_count(&hll_b) as f64;
+        assert!((intersection - 5_000.0).abs() <= error, "{}", intersection);
+        let difference = hll_a.estimate_difference_count(&hll_b) as f64;
+        assert!((difference - 5_000.0).abs() <= error, "{}", difference);
+        let jaccard = hll_a.estimate_jaccard(&hll_b);
+        assert!((jaccard - 1.0 / 3.0).abs() <= 0.05, "{}", jaccard);
+
+        // the estimates are symmetric where they should be
+        assert_eq!(
+            hll_a.estimate_intersection_count(&hll_b),
+            hll_b.estimate_intersection_count(&hll_a)
+        );
+        assert_eq!(
+            hll_a.estimate_jaccard(&hll_b),
+            hll_b.estimate_jaccard(&hll_a)
+        );
+    }
+
+    #[test]
+    fn small_intersection_of_large_sets() {
+        // An intersection of 1000 in a union of 199_000: inclusion–exclusion
+        // has an error on the order of 1.6% of the union, over 3000.
+        let (mut joint_error, mut inclusion_exclusion_error) = (0.0, 0.0);
+        for trial in 0..10u64 {
+            let base = trial * 1_000_000;
+            let mut hll_a = HyperLogLog::new(12, SipBuildHasher::default());
+            let mut hll_b = HyperLogLog::new(12, SipBuildHasher::default());
+            for i in base..base + 100_000 {
+                hll_a.add(&i);
+            }
+            for i in base + 99_000..base + 199_000 {
+                hll_b.add(&i);
+            }
+            let joint = hll_a.estimate_intersection_count(&hll_b) as f64;
+            let (a, b, union, _) = hll_a.set_counts(&hll_b);
+            let inclusion_exclusion = a as f64 + b as f64 - union as f64;
+            joint_error += (joint - 1000.0).powi(2);
+            inclusion_exclusion_error += (inclusion_exclusion - 1000.0).powi(2);
+        }
+        let joint_error = (joint_error / 10.0).sqrt();
+        let inclusion_exclusion_error = (inclusion_exclusion_error / 10.0).sqrt();
+        assert!(joint_error < 600.0, "{}", joint_error);
+        assert!(
+            joint_error * 3.0 < inclusion_exclusion_error,
+            "{} vs {}",
+            joint_error,
+            inclusion_exclusion_error
+        );
+    }
+
+    #[test]
+    fn set_operations_edge_cases() {
+        let (mut hll_a, _) = overlapping(8);
+        hll_a.merge_all();
+        let empty = HyperLogLog::new(8, SipBuildHasher::default());
+        let a = hll_a.estimate_count();
+
+        assert_eq!(hll_a.estimate_intersection_count(&hll_a), a);
+        assert_eq!(hll_a.estimate_difference_count(&hll_a), 0);
+        assert_eq!(hll_a.estimate_jaccard(&hll_a), 1.0);
+
+        assert_eq!(hll_a.estimate_intersection_count(&empty), 0);
+        assert_eq!(hll_a.estimate_difference_count(&empty), a);
+        assert_eq!(empty.estimate_difference_count(&hll_a), 0);
+        assert_eq!(empty.estimate_jaccard(&empty), 0.0);
+    }
+
+    #[quickcheck]
+    fn quick_disjoint_sparse_16(values: HashSet<u64>) -> TestResult {
+        // small sets stay sparse, where the estimates are close to exact
+        let mut hll_a = HyperLogLog::new(16, FnvBuildHasher::default());
+        let mut hll_b = HyperLogLog::new(16, FnvBuildHasher::default());
+        for value in &values {
+            if value % 2 == 0 {
+                hll_a.add(value);
+            } else {
+                hll_b.add(value);
+            }
+        }
+        hll_a.merge_all();
+        hll_b.merge_all();
+        let intersection = hll_a.estimate_intersection_count(&hll_b);
+        if intersection as f64 <= 10.0_f64.max(0.001 * values.len() as f64) {
+            return TestResult::passed();
+        }
+        println!("got {} for disjoint sets of {}", intersection, values.len());
+        TestResult::failed()
+    }
+
     #[test]
     fn precision_for_error() {
         for precision in 4..=18 {
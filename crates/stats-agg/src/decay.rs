@@ -0,0 +1,449 @@
+// Exponentially decaying (half-life) summaries.
+//
+// Every point is weighted by 2^(-age/half_life), where the age is measured relative to `ref_time`, the
+// latest timestamp the summary has seen. Weighted means and second moments are tracked with the weighted
+// form of Welford's algorithm, so points can arrive in any order: a point older than `ref_time` simply gets
+// a smaller weight, while a newer point moves `ref_time` forward and scales down everything accumulated so
+// far. Combining two partials works the same way, the one with the older reference time is re-weighted by
+// the offset between the two before they are merged.
+//
+// Times and the half-life are in whatever unit the caller chooses, the extension uses microseconds.
+
+use crate::{StatsError, XYPair};
+use serde::{Deserialize, Serialize};
+
+fn decay_factor(offset: i64, half_life: i64) -> f64 {
+    (-(offset as f64) / half_life as f64).exp2()
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[repr(C)]
+pub struct DecayingSummary1D {
+    pub half_life: i64,
+    pub ref_time: i64,
+    pub n: u64,
+    pub w: f64,
+    pub mean: f64,
+    pub m2: f64,
+}
+
+impl DecayingSummary1D {
+    pub fn new(half_life: i64) -> Self {
+        assert!(half_life > 0, "half-life must be positive");
+        DecayingSummary1D {
+            half_life,
+            ref_time: 0,
+            n: 0,
+            w: 0.0,
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    // move the reference time forward to `ts`, scaling down the accumulated weight accordingly
+    // the mean is a ratio of weights, so it is unaffected
+    fn decay_to(&mut self, ts: i64) {
+        if self.n == 0 {
+            self.ref_time = ts;
+            return;
+        }
+        if ts <= self.ref_time {
+            return;
+        }
+        let factor = decay_factor(ts - self.ref_time, self.half_life);
+        self.w *= factor;
+        self.m2 *= factor;
+        self.ref_time = ts;
+    }
+
+    pub fn accum(&mut self, ts: i64, x: f64) {
+        self.decay_to(ts);
+        let wi = decay_factor(self.ref_time - ts, self.half_life);
+        self.n += 1;
+        let w = self.w + wi;
+        if w == 0.0 {
+            // every point so far has decayed to nothing
+            return;
+        }
+        let delta = x - self.mean;
+        self.mean += delta * wi / w;
+        self.m2 += wi * delta * (x - self.mean);
+        self.w = w;
+    }
+
+    pub fn combine(&self, other: &Self) -> Result<Self, StatsError> {
+        if self.half_life != other.half_life {
+            return Err(StatsError::MismatchedHalfLife);
+        }
+        if other.n == 0 {
+            return Ok(*self);
+        }
+        if self.n == 0 {
+            return Ok(*other);
+        }
+        let (mut a, mut b) = (*self, *other);
+        let ref_time = a.ref_time.max(b.ref_time);
+        a.decay_to(ref_time);
+        b.decay_to(ref_time);
+
+        let w = a.w + b.w;
+        if w == 0.0 {
+            a.n += b.n;
+            return Ok(a);
+        }
+        let delta = b.mean - a.mean;
+        Ok(DecayingSummary1D {
+            half_life: a.half_life,
+            ref_time,
+            n: a.n + b.n,
+            w,
+            mean: a.mean + delta * b.w / w,
+            m2: a.m2 + b.m2 + delta * delta * a.w * b.w / w,
+        })
+    }
+
+    pub fn count(&self) -> i64 {
+        self.n as i64
+    }
+
+    pub fn ewma(&self) -> Option<f64> {
+        if self.n == 0 || self.w == 0.0 {
+            return None;
+        }
+        Some(self.mean)
+    }
+
+    pub fn ewm_variance(&self) -> Option<f64> {
+        if self.n == 0 || self.w == 0.0 {
+            return None;
+        }
+        Some(self.m2 / self.w)
+    }
+
+    pub fn ewm_stddev(&self) -> Option<f64> {
+        Some(self.ewm_variance()?.sqrt())
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[repr(C)]
+pub struct DecayingSummary2D {
+    pub half_life: i64,
+    pub ref_time: i64,
+    pub n: u64,
+    pub w: f64,
+    pub mean_x: f64,
+    pub mean_y: f64,
+    pub m2x: f64,
+    pub m2y: f64,
+    pub cxy: f64,
+}
+
+impl DecayingSummary2D {
+    pub fn new(half_life: i64) -> Self {
+        assert!(half_life > 0, "half-life must be positive");
+        DecayingSummary2D {
+            half_life,
+            ref_time: 0,
+            n: 0,
+            w: 0.0,
+            mean_x: 0.0,
+            mean_y: 0.0,
+            m2x: 0.0,
+            m2y: 0.0,
+            cxy: 0.0,
+        }
+    }
+
+    fn decay_to(&mut self, ts: i64) {
+        if self.n == 0 {
+            self.ref_time = ts;
+            return;
+        }
+        if ts <= self.ref_time {
+            return;
+        }
+        let factor = decay_factor(ts - self.ref_time, self.half_life);
+        self.w *= factor;
+        self.m2x *= factor;
+        self.m2y *= factor;
+        self.cxy *= factor;
+        self.ref_time = ts;
+    }
+
+    pub fn accum(&mut self, ts: i64, p: XYPair<f64>) {
+        self.decay_to(ts);
+        let wi = decay_factor(self.ref_time - ts, self.half_life);
+        self.n += 1;
+        let w = self.w + wi;
+        if w == 0.0 {
+            return;
+        }
+        let dx = p.x - self.mean_x;
+        let dy = p.y - self.mean_y;
+        self.mean_x += dx * wi / w;
+        self.mean_y += dy * wi / w;
+        self.m2x += wi * dx * (p.x - self.mean_x);
+        self.m2y += wi * dy * (p.y - self.mean_y);
+        self.cxy += wi * dx * (p.y - self.mean_y);
+        self.w = w;
+    }
+
+    pub fn combine(&self, other: &Self) -> Result<Self, StatsError> {
+        if self.half_life != other.half_life {
+            return Err(StatsError::MismatchedHalfLife);
+        }
+        if other.n == 0 {
+            return Ok(*self);
+        }
+        if self.n == 0 {
+            return Ok(*other);
+        }
+        let (mut a, mut b) = (*self, *other);
+        let ref_time = a.ref_time.max(b.ref_time);
+        a.decay_to(ref_time);
+        b.decay_to(ref_time);
+
+        let w = a.w + b.w;
+        if w == 0.0 {
+            a.n += b.n;
+            return Ok(a);
+        }
+        let dx = b.mean_x - a.mean_x;
+        let dy = b.mean_y - a.mean_y;
+        let scale = a.w * b.w / w;
+        Ok(DecayingSummary2D {
+            half_life: a.half_life,
+            ref_time,
+            n: a.n + b.n,
+            w,
+            mean_x: a.mean_x + dx * b.w / w,
+            mean_y: a.mean_y + dy * b.w / w,
+            m2x: a.m2x + b.m2x + dx * dx * scale,
+            m2y: a.m2y + b.m2y + dy * dy * scale,
+            cxy: a.cxy + b.cxy + dx * dy * scale,
+        })
+    }
+
+    pub fn count(&self) -> i64 {
+        self.n as i64
+    }
+
+    pub fn ewma(&self) -> Option<XYPair<f64>> {
+        if self.n == 0 || self.w == 0.0 {
+            return None;
+        }
+        Some(XYPair {
+            x: self.mean_x,
+            y: self.mean_y,
+        })
+    }
+
+    pub fn ewm_variance(&self) -> Option<XYPair<f64>> {
+        if self.n == 0 || self.w == 0.0 {
+            return None;
+        }
+        Some(XYPair {
+            x: self.m2x / self.w,
+            y: self.m2y / self.w,
+        })
+    }
+
+    pub fn ewm_covariance(&self) -> Option<f64> {
+        if self.n == 0 || self.w == 0.0 {
+            return None;
+        }
+        Some(self.cxy / self.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    const HOUR: i64 = 3_600_000_000;
+
+    // direct computation of the weighted statistics
+    fn brute_force(points: &[(i64, f64)], half_life: i64) -> (f64, f64) {
+        let ref_time = points.iter().map(|p| p.0).max().unwrap();
+        let weights: Vec<f64> = points
+            .iter()
+            .map(|(ts, _)| decay_factor(ref_time - ts, half_life))
+            .collect();
+        let w: f64 = weights.iter().sum();
+        let mean = points
+            .iter()
+            .zip(&weights)
+            .map(|((_, x), wi)| x * wi)
+            .sum::<f64>()
+            / w;
+        let var = points
+            .iter()
+            .zip(&weights)
+            .map(|((_, x), wi)| wi * (x - mean) * (x - mean))
+            .sum::<f64>()
+            / w;
+        (mean, var)
+    }
+
+    #[test]
+    fn test_weights() {
+        let mut s = DecayingSummary1D::new(HOUR);
+        s.accum(0, 10.0);
+        s.accum(HOUR, 20.0);
+
+        // the first point is one half-life old so it has half the weight of the second
+        assert_eq!(s.count(), 2);
+        assert_relative_eq!(s.w, 1.5, max_relative = 1e-12);
+        assert_relative_eq!(
+            s.ewma().unwrap(),
+            (10.0 * 0.5 + 20.0) / 1.5,
+            max_relative = 1e-12
+        );
+        assert_relative_eq!(s.ewm_variance().unwrap(), 200.0 / 9.0, max_relative = 1e-12);
+    }
+
+    #[test]
+    fn test_order_independent() {
+        let points = [
+            (0, 7.0),
+            (HOUR / 2, 18.0),
+            (HOUR, -2.0),
+            (3 * HOUR, 5.0),
+            (2 * HOUR, 3.0),
+            (5 * HOUR, 11.0),
+        ];
+        let (mean, var) = brute_force(&points, HOUR);
+
+        let mut forward = DecayingSummary1D::new(HOUR);
+        for (ts, x) in points {
+            forward.accum(ts, x);
+        }
+        let mut backward = DecayingSummary1D::new(HOUR);
+        for (ts, x) in points.iter().rev() {
+            backward.accum(*ts, *x);
+        }
+
+        for s in [forward, backward] {
+            assert_eq!(s.ref_time, 5 * HOUR);
+            assert_relative_eq!(s.ewma().unwrap(), mean, max_relative = 1e-12);
+            assert_relative_eq!(s.ewm_variance().unwrap(), var, max_relative = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_combine() {
+        let points = [
+            (0, 7.0),
+            (HOUR / 2, 18.0),
+            (HOUR, -2.0),
+            (2 * HOUR, 3.0),
+            (3 * HOUR, 5.0),
+            (5 * HOUR, 11.0),
+        ];
+        let (mean, var) = brute_force(&points, HOUR);
+
+        let mut early = DecayingSummary1D::new(HOUR);
+        let mut late = DecayingSummary1D::new(HOUR);
+        for (ts, x) in &points[..3] {
+            early.accum(*ts, *x);
+        }
+        for (ts, x) in &points[3..] {
+            late.accum(*ts, *x);
+        }
+
+        for s in [early.combine(&late).unwrap(), late.combine(&early).unwrap()] {
+            assert_eq!(s.count(), 6);
+            assert_eq!(s.ref_time, 5 * HOUR);
+            assert_relative_eq!(s.ewma().unwrap(), mean, max_relative = 1e-12);
+            assert_relative_eq!(s.ewm_variance().unwrap(), var, max_relative = 1e-12);
+        }
+
+        let empty = DecayingSummary1D::new(HOUR);
+        assert_eq!(early.combine(&empty), Ok(early));
+        assert_eq!(empty.combine(&early), Ok(early));
+    }
+
+    #[test]
+    fn test_empty() {
+        let s = DecayingSummary1D::new(HOUR);
+        assert_eq!(s.ewma(), None);
+        assert_eq!(s.ewm_variance(), None);
+
+        let s = DecayingSummary2D::new(HOUR);
+        assert_eq!(s.ewma(), None);
+        assert_eq!(s.ewm_covariance(), None);
+    }
+
+    #[test]
+    fn test_combine_mismatched_half_life() {
+        let mut a = DecayingSummary1D::new(HOUR);
+        let mut b = DecayingSummary1D::new(2 * HOUR);
+        a.accum(0, 1.0);
+        b.accum(0, 1.0);
+        assert_eq!(a.combine(&b), Err(StatsError::MismatchedHalfLife));
+
+        // even an empty summary can't be combined with a different half-life
+        let empty = DecayingSummary2D::new(2 * HOUR);
+        assert_eq!(
+            DecayingSummary2D::new(HOUR).combine(&empty),
+            Err(StatsError::MismatchedHalfLife)
+        );
+    }
+
+    #[test]
+    fn test_2d_matches_1d() {
+        let points = [
+            (0, 7.0, 1.0),
+            (HOUR, 18.0, 2.0),
+            (2 * HOUR, -2.0, 3.5),
+            (3 * HOUR, 5.0, 3.0),
+            (5 * HOUR, 11.0, 6.0),
+        ];
+        let mut xs = DecayingSummary1D::new(HOUR);
+        let mut ys = DecayingSummary1D::new(HOUR);
+        let mut first = DecayingSummary2D::new(HOUR);
+        let mut second = DecayingSummary2D::new(HOUR);
+        for (i, (ts, y, x)) in points.iter().enumerate() {
+            xs.accum(*ts, *x);
+            ys.accum(*ts, *y);
+            let s = if i < 2 { &mut first } else { &mut second };
+            s.accum(*ts, XYPair { x: *x, y: *y });
+        }
+        let s = second.combine(&first).unwrap();
+
+        assert_eq!(s.count(), 5);
+        assert_relative_eq!(
+            s.ewma().unwrap().x,
+            xs.ewma().unwrap(),
+            max_relative = 1e-12
+        );
+        assert_relative_eq!(
+            s.ewma().unwrap().y,
+            ys.ewma().unwrap(),
+            max_relative = 1e-12
+        );
+        assert_relative_eq!(
+            s.ewm_variance().unwrap().x,
+            xs.ewm_variance().unwrap(),
+            max_relative = 1e-12
+        );
+        assert_relative_eq!(
+            s.ewm_variance().unwrap().y,
+            ys.ewm_variance().unwrap(),
+            max_relative = 1e-12
+        );
+
+        // covariance of a variable with itself is its variance
+        let mut self_cov = DecayingSummary2D::new(HOUR);
+        for (ts, _, x) in points {
+            self_cov.accum(ts, XYPair { x, y: x });
+        }
+        assert_relative_eq!(
+            self_cov.ewm_covariance().unwrap(),
+            xs.ewm_variance().unwrap(),
+            max_relative = 1e-12
+        );
+    }
+}
@@ -28,6 +28,7 @@ impl FloatLike for twofloat::TwoFloat {
 #[derive(Debug, PartialEq, Eq)]
 pub enum StatsError {
     DoubleOverflow,
+    MismatchedHalfLife,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -54,6 +55,7 @@ const INV_FLOATING_ERROR_THRESHOLD: f64 = 0.99;
 #[cfg(any(test, feature = "pg_test"))] // don't have a threshold for tests, to ensure the inverse function is better tested
 const INV_FLOATING_ERROR_THRESHOLD: f64 = f64::INFINITY;
 
+pub mod decay;
 pub mod stats1d;
 pub mod stats2d;
 
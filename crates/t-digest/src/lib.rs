@@ -286,15 +286,32 @@ impl TDigest {
     }
 
     pub fn merge_sorted(&self, sorted_values: Vec<f64>) -> TDigest {
+        let sorted_values = sorted_values
+            .into_iter()
+            .map(|v| Centroid::new(v, 1))
+            .collect();
+        self.merge_sorted_weighted(sorted_values)
+    }
+
+    /// Merges values that have each been seen `weight` times, given as
+    /// centroids with the value as the mean.  Zero-weight values are ignored.
+    pub fn merge_unsorted_weighted(&self, mut unsorted_values: Vec<Centroid>) -> TDigest {
+        unsorted_values.retain(|c| c.weight() > 0);
+        unsorted_values.sort();
+        self.merge_sorted_weighted(unsorted_values)
+    }
+
+    // Same as `merge_sorted`, but every value may carry any (non-zero) weight.
+    fn merge_sorted_weighted(&self, sorted_values: Vec<Centroid>) -> TDigest {
         if sorted_values.is_empty() {
             return self.clone();
         }
 
         let mut result = TDigest::new_with_size(self.max_size());
-        result.count = self.count() + (sorted_values.len() as u64);
+        result.count = self.count() + sorted_values.iter().map(|c| c.weight()).sum::<u64>();
 
-        let maybe_min = OrderedFloat::from(*sorted_values.first().unwrap());
-        let maybe_max = OrderedFloat::from(*sorted_values.last().unwrap());
+        let maybe_min = sorted_values.first().unwrap().mean;
+        let maybe_max = sorted_values.last().unwrap().mean;
 
         if self.count() > 0 {
             result.min = std::cmp::min(self.min, maybe_min);
@@ -315,14 +332,14 @@ impl TDigest {
         let mut iter_sorted_values = sorted_values.iter().peekable();
 
         let mut curr: Centroid = if let Some(c) = iter_centroids.peek() {
-            let curr = **iter_sorted_values.peek().unwrap();
+            let curr = iter_sorted_values.peek().unwrap().mean();
             if c.mean() < curr {
                 iter_centroids.next().unwrap().clone()
             } else {
-                Centroid::new(*iter_sorted_values.next().unwrap(), 1)
+                iter_sorted_values.next().unwrap().clone()
             }
         } else {
-            Centroid::new(*iter_sorted_values.next().unwrap(), 1)
+            iter_sorted_values.next().unwrap().clone()
         };
 
         let mut weight_so_far: u64 = curr.weight();
@@ -333,14 +350,14 @@ impl TDigest {
         while iter_centroids.peek().is_some() || iter_sorted_values.peek().is_some() {
             let next: Centroid = if let Some(c) = iter_centroids.peek() {
                 if iter_sorted_values.peek().is_none()
-                    || c.mean() < **iter_sorted_values.peek().unwrap()
+                    || c.mean() < iter_sorted_values.peek().unwrap().mean()
                 {
                     iter_centroids.next().unwrap().clone()
                 } else {
-                    Centroid::new(*iter_sorted_values.next().unwrap(), 1)
+                    iter_sorted_values.next().unwrap().clone()
                 }
             } else {
-                Centroid::new(*iter_sorted_values.next().unwrap(), 1)
+                iter_sorted_values.next().unwrap().clone()
             };
 
             let next_sum: f64 = next.mean() * next.weight() as f64;
@@ -559,6 +576,75 @@ impl TDigest {
         }
     }
 
+    /// Estimate the mean of the values between the `low` and `high` quantiles, centroids
+    /// straddling either quantile only contribute the part of their weight inside of it
+    pub fn estimate_trimmed_mean(&self, low: f64, high: f64) -> f64 {
+        assert!(0.0 <= low && low < high && high <= 1.0);
+        if self.centroids.is_empty() {
+            return 0.0;
+        }
+        let count = self.count as f64;
+        self.sum_between_ranks(low * count, high * count) / ((high - low) * count)
+    }
+
+    /// Estimate the mean of the values after clamping those outside of the `low` and `high`
+    /// quantiles to the value at that quantile
+    pub fn estimate_winsorized_mean(&self, low: f64, high: f64) -> f64 {
+        assert!(0.0 <= low && low < high && high <= 1.0);
+        if self.centroids.is_empty() {
+            return 0.0;
+        }
+        let count = self.count as f64;
+        let middle = self.sum_between_ranks(low * count, high * count);
+        (low * count * self.estimate_quantile(low)
+            + middle
+            + (1.0 - high) * count * self.estimate_quantile(high))
+            / count
+    }
+
+    /// Estimate the median of the absolute deviations from the median
+    pub fn estimate_median_absolute_deviation(&self) -> f64 {
+        if self.centroids.is_empty() {
+            return 0.0;
+        }
+        let median = self.estimate_quantile(0.5);
+        let mut deviations: Vec<(f64, u64)> = self
+            .centroids
+            .iter()
+            .map(|c| ((c.mean() - median).abs(), c.weight()))
+            .collect();
+        deviations.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        let half = self.count as f64 / 2.0;
+        let mut seen = 0;
+        for (deviation, weight) in &deviations {
+            seen += weight;
+            if seen as f64 >= half {
+                return *deviation;
+            }
+        }
+        deviations.last().unwrap().0
+    }
+
+    // Sum of the values with ranks between `low` and `high`, a centroid of
+    // weight `w` covers `w` consecutive ranks
+    fn sum_between_ranks(&self, low: f64, high: f64) -> f64 {
+        let mut sum = 0.0;
+        let mut seen = 0.0;
+        for centroid in &self.centroids {
+            let start = seen;
+            seen += centroid.weight() as f64;
+            let overlap = seen.min(high) - start.max(low);
+            if overlap > 0.0 {
+                sum += overlap * centroid.mean();
+            }
+            if seen >= high {
+                break;
+            }
+        }
+        sum
+    }
+
     /// To estimate the value located at `q` quantile
     pub fn estimate_quantile(&self, q: f64) -> f64 {
         if self.centroids.is_empty() {
@@ -683,7 +769,7 @@ impl TDigest {
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct Builder {
     #[serde(skip)]
-    buffer: Vec<f64>,
+    buffer: Vec<Centroid>,
     digested: TDigest,
 }
 
@@ -704,7 +790,15 @@ impl Builder {
     // Add a new value, recalculate the digest if we've crossed a threshold.
     // TODO threshold is currently set to number of digest buckets, should this be adjusted
     pub fn push(&mut self, value: f64) {
-        self.buffer.push(value);
+        self.push_weighted(value, 1)
+    }
+
+    // Add a value that was seen `weight` times, zero weights are ignored.
+    pub fn push_weighted(&mut self, value: f64, weight: u64) {
+        if weight == 0 {
+            return;
+        }
+        self.buffer.push(Centroid::new(value, weight));
         if self.buffer.len() >= self.digested.max_size() {
             self.digest()
         }
@@ -716,7 +810,7 @@ impl Builder {
             return;
         }
         let new = std::mem::take(&mut self.buffer);
-        self.digested = self.digested.merge_unsorted(new)
+        self.digested = self.digested.merge_unsorted_weighted(new)
     }
 
     pub fn build(&mut self) -> TDigest {
@@ -728,10 +822,10 @@ impl Builder {
         assert_eq!(self.digested.max_size(), other.digested.max_size());
         let digvec = vec![std::mem::take(&mut self.digested), other.digested];
         if !self.buffer.is_empty() {
-            digvec[0].merge_unsorted(std::mem::take(&mut self.buffer));
+            digvec[0].merge_unsorted_weighted(std::mem::take(&mut self.buffer));
         }
         if !other.buffer.is_empty() {
-            digvec[1].merge_unsorted(other.buffer);
+            digvec[1].merge_unsorted_weighted(other.buffer);
         }
         self.digested = TDigest::merge_digests(digvec);
     }
@@ -992,6 +1086,66 @@ mod tests {
         assert_eq!(estimate, 99.5);
     }
 
+    #[test]
+    fn test_weighted_builder() {
+        // 1..=1000 where each value v is seen v times
+        let mut builder = Builder::with_size(100);
+        for i in (1..=1000).rev() {
+            builder.push_weighted(i as f64, i);
+        }
+        builder.push_weighted(-1.0, 0);
+        let digest = builder.build();
+
+        let total: u64 = (1..=1000).sum();
+        assert_eq!(digest.count(), total);
+        assert_eq!(digest.min(), 1.0);
+        assert_eq!(digest.max(), 1000.0);
+        let sum: u64 = (1..=1000u64).map(|i| i * i).sum();
+        assert!((digest.sum() - sum as f64).abs() < 1e-6 * sum as f64);
+
+        // the q quantile is the v where v(v+1)/2 = q * total
+        for q in [0.1, 0.25, 0.5, 0.75, 0.9, 0.99] {
+            let expected = (2.0 * q * total as f64).sqrt();
+            let ans = digest.estimate_quantile(q);
+            let percentage = (expected - ans).abs() / expected;
+            assert!(
+                percentage < 0.01,
+                "quantile {}: expected {}, got {}",
+                q,
+                expected,
+                ans
+            );
+        }
+    }
+
+    #[test]
+    fn test_robust_statistics() {
+        let t = TDigest::new_with_size(100);
+        let mut values: Vec<f64> = (1..=100_000).map(f64::from).collect();
+        // 1% huge outliers
+        values.resize(values.len() + 1_000, 1e12);
+        let t = t.merge_sorted(values);
+        assert!(t.mean() > 1e9);
+
+        let trimmed = t.estimate_trimmed_mean(0.05, 0.95);
+        let expected = 50_000.0 + 500.0; // ranks 5050..95950 cover values ~5051..95950
+        assert!((trimmed - expected).abs() / expected < 0.01, "{}", trimmed);
+
+        let winsorized = t.estimate_winsorized_mean(0.05, 0.95);
+        assert!(
+            (winsorized - expected).abs() / expected < 0.01,
+            "{}",
+            winsorized
+        );
+
+        let all = t.estimate_trimmed_mean(0.0, 1.0);
+        assert!((all - t.mean()).abs() / t.mean() < 1e-9);
+
+        // half the values are within ~25,250 of the median
+        let mad = t.estimate_median_absolute_deviation();
+        assert!((mad - 25_250.0).abs() / 25_250.0 < 0.05, "{}", mad);
+    }
+
     use quickcheck::*;
 
     #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug)]
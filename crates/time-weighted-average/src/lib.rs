@@ -3,11 +3,23 @@ use tspoint::TSPoint;
 
 use flat_serialize_macro::FlatSerializable;
 
+mod stats;
+
+pub use stats::TimeWeightStats;
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, FlatSerializable)]
 #[repr(u8)]
 pub enum TimeWeightMethod {
     LOCF = 0,
     Linear,
+    /// Next observation carried backward: each value applies to the interval
+    /// before it, as with sensors reporting the average since their last
+    /// sample.
+    NOCB,
+    /// Trapezoidal with a gap threshold: `Linear`, except that summaries
+    /// using it have a `max_gap` and the time between points further apart
+    /// than it is missing data instead of being interpolated across.
+    LinearWithGaps,
 }
 
 #[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
@@ -16,6 +28,10 @@ pub struct TimeWeightSummary {
     pub first: TSPoint,
     pub last: TSPoint,
     pub w_sum: f64,
+    // the time between points further apart than this is missing data,
+    // counting neither towards the integral nor the duration of the average
+    pub max_gap: Option<i64>,
+    pub gap_duration: i64,
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -30,11 +46,17 @@ pub enum TimeWeightError {
 
 impl TimeWeightSummary {
     pub fn new(pt: TSPoint, method: TimeWeightMethod) -> Self {
+        Self::new_with_max_gap(pt, method, None)
+    }
+
+    pub fn new_with_max_gap(pt: TSPoint, method: TimeWeightMethod, max_gap: Option<i64>) -> Self {
         TimeWeightSummary {
             method,
             first: pt,
             last: pt,
             w_sum: 0.0,
+            max_gap,
+            gap_duration: 0,
         }
     }
 
@@ -47,18 +69,35 @@ impl TimeWeightSummary {
             // see discussion at https://github.com/timescale/timescaledb-toolkit/discussions/65
             return Ok(());
         }
-        self.w_sum += self.method.weighted_sum(self.last, pt);
+        self.add_segment(self.last, pt);
         self.last = pt;
         Ok(())
     }
 
+    /// Whether the interval between `first` and `second` is a gap in the data.
+    pub fn is_gap(&self, first: TSPoint, second: TSPoint) -> bool {
+        match self.max_gap {
+            Some(max_gap) => second.ts - first.ts > max_gap,
+            None => false,
+        }
+    }
+
+    fn add_segment(&mut self, first: TSPoint, second: TSPoint) {
+        if self.is_gap(first, second) {
+            self.gap_duration += second.ts - first.ts;
+        } else {
+            self.w_sum += self.method.weighted_sum(first, second);
+        }
+    }
+
     // This combine function is different than some other combine functions as it requires disjoint time ranges in order to work
-    // correctly. The aggregate will never be parallel safe in the Postgres formulation because of this. However in the continuous
-    // aggregate context (and potentially in a multinode context) where we can be sure of disjoint time ranges, this will work.
+    // correctly. Parallel aggregates, where the ranges overlap, have to keep the points and summarize them all at once instead.
+    // However in the continuous aggregate context (and potentially in a multinode context) where we can be sure of disjoint time
+    // ranges, this will work.
     // If there are space partitions, the space partition keys should be included in the group bys in order to be sure of this, otherwise
     // overlapping ranges will be created.
     pub fn combine(&self, next: &TimeWeightSummary) -> Result<TimeWeightSummary, TimeWeightError> {
-        if self.method != next.method {
+        if self.method != next.method || self.max_gap != next.max_gap {
             return Err(TimeWeightError::MethodMismatch);
         }
         if self.last.ts >= next.first.ts {
@@ -67,25 +106,34 @@ impl TimeWeightSummary {
             // always have been sorted into one or another bucket, and it means that the bounds of our buckets were wrong.
             return Err(TimeWeightError::OrderError);
         }
-        let new = TimeWeightSummary {
-            method: self.method,
-            first: self.first,
+        let mut new = TimeWeightSummary {
             last: next.last,
-            w_sum: self.w_sum + next.w_sum + self.method.weighted_sum(self.last, next.first),
+            w_sum: self.w_sum + next.w_sum,
+            gap_duration: self.gap_duration + next.gap_duration,
+            ..*self
         };
+        new.add_segment(self.last, next.first);
         Ok(new)
     }
 
     pub fn new_from_sorted_iter<'a>(
         iter: impl IntoIterator<Item = &'a TSPoint>,
         method: TimeWeightMethod,
+    ) -> Result<TimeWeightSummary, TimeWeightError> {
+        Self::new_from_sorted_iter_with_max_gap(iter, method, None)
+    }
+
+    pub fn new_from_sorted_iter_with_max_gap<'a>(
+        iter: impl IntoIterator<Item = &'a TSPoint>,
+        method: TimeWeightMethod,
+        max_gap: Option<i64>,
     ) -> Result<TimeWeightSummary, TimeWeightError> {
         let mut t = iter.into_iter();
         let mut s = match t.next() {
             None => {
                 return Err(TimeWeightError::EmptyIterator);
             }
-            Some(val) => TimeWeightSummary::new(*val, method),
+            Some(val) => TimeWeightSummary::new_with_max_gap(*val, method, max_gap),
         };
         for p in t {
             s.accum(*p)?;
@@ -114,9 +162,9 @@ impl TimeWeightSummary {
     /// The initial aggregate will only have points within the time bucket, but outside of it, you will either have a point that you select
     /// or a TimeWeightSummary where the first or last point can be used depending on which bound you are extrapolating to.
     /// 1. The start_prev parameter is optional, but if a start is provided a previous point must be
-    /// provided (for both linear and locf weighting methods).
+    /// provided (for all weighting methods).
     /// 2. The end_next parameter is also optional, if an end is provided and the locf weighting
-    /// method is specified, a next parameter isn't needed, with the linear method, the next
+    /// method is specified, a next parameter isn't needed, with the other methods, the next
     /// point is needed and we will error if it is not provided.
     pub fn with_bounds(
         &self,
@@ -146,13 +194,16 @@ impl TimeWeightSummary {
         let new_first = self
             .method
             .interpolate(prev, Some(self.first), target_start)?;
-        let w_sum = self.w_sum + self.method.weighted_sum(new_first, self.first);
-
-        Ok(TimeWeightSummary {
+        let mut calc = TimeWeightSummary {
             first: new_first,
-            w_sum,
             ..*self
-        })
+        };
+        if self.is_gap(prev, self.first) {
+            calc.gap_duration += self.first.ts - new_first.ts;
+        } else {
+            calc.w_sum += self.method.weighted_sum(new_first, self.first);
+        }
+        Ok(calc)
     }
 
     fn with_next(&self, target_end: i64, next: Option<TSPoint>) -> Result<Self, TimeWeightError> {
@@ -172,22 +223,31 @@ impl TimeWeightSummary {
         }
 
         let new_last = self.method.interpolate(self.last, next, target_end)?;
-        let w_sum = self.w_sum + self.method.weighted_sum(self.last, new_last);
-
-        Ok(TimeWeightSummary {
+        let mut calc = TimeWeightSummary {
             last: new_last,
-            w_sum,
             ..*self
-        })
+        };
+        match next {
+            Some(next) if self.is_gap(self.last, next) => {
+                calc.gap_duration += new_last.ts - self.last.ts
+            }
+            _ => calc.w_sum += self.method.weighted_sum(self.last, new_last),
+        }
+        Ok(calc)
+    }
+
+    /// The duration of the summary without its gaps.
+    pub fn covered_duration(&self) -> i64 {
+        self.last.ts - self.first.ts - self.gap_duration
     }
 
     ///Evaluate the time_weighted_average from the summary.
     pub fn time_weighted_average(&self) -> Result<f64, TimeWeightError> {
-        if self.last.ts == self.first.ts {
+        let duration = self.covered_duration();
+        if duration == 0 {
             return Err(TimeWeightError::ZeroDuration);
         }
-        let duration = (self.last.ts - self.first.ts) as f64;
-        Ok(self.w_sum / duration)
+        Ok(self.w_sum / duration as f64)
     }
 
     /// Evaluate the integral in microseconds.
@@ -218,12 +278,18 @@ impl TimeWeightMethod {
             val: match (self, second) {
                 (TimeWeightMethod::LOCF, _) => first.val,
                 // TODO make this a method on TimeWeightMethod?
-                (TimeWeightMethod::Linear, Some(second)) => {
+                (TimeWeightMethod::Linear | TimeWeightMethod::LinearWithGaps, Some(second)) => {
                     first.interpolate_linear(&second, target).unwrap()
                 }
-                (TimeWeightMethod::Linear, None) => {
-                    return Err(TimeWeightError::InterpolateMissingPoint)
-                }
+                // a point still has its own value, the next one only applies after it
+                (TimeWeightMethod::NOCB, _) if target == first.ts => first.val,
+                (TimeWeightMethod::NOCB, Some(second)) => second.val,
+                (
+                    TimeWeightMethod::Linear
+                    | TimeWeightMethod::NOCB
+                    | TimeWeightMethod::LinearWithGaps,
+                    None,
+                ) => return Err(TimeWeightError::InterpolateMissingPoint),
             },
         };
         Ok(pt)
@@ -241,7 +307,10 @@ impl TimeWeightMethod {
             //two / 2 * duration) this is equivalent to the rectangle formed by the
             //midpoint of the two.
             //TODO: Stable midpoint calc? http://www.open-std.org/jtc1/sc22/wg21/docs/papers/2018/p0811r2.html
-            TimeWeightMethod::Linear => (first.val + second.val) / 2.0 * duration,
+            TimeWeightMethod::Linear | TimeWeightMethod::LinearWithGaps => {
+                (first.val + second.val) / 2.0 * duration
+            }
+            TimeWeightMethod::NOCB => second.val * duration,
         }
     }
 }
@@ -324,6 +393,7 @@ mod tests {
     fn test_new_from_sorted_iter() {
         new_from_sorted_iter_test(TimeWeightMethod::LOCF);
         new_from_sorted_iter_test(TimeWeightMethod::Linear);
+        new_from_sorted_iter_test(TimeWeightMethod::NOCB);
     }
 
     fn combine_test(t: TimeWeightMethod) {
@@ -358,6 +428,7 @@ mod tests {
     fn test_combine() {
         combine_test(TimeWeightMethod::LOCF);
         combine_test(TimeWeightMethod::Linear);
+        combine_test(TimeWeightMethod::NOCB);
     }
 
     fn order_accum_test(t: TimeWeightMethod) {
@@ -418,6 +489,7 @@ mod tests {
     fn test_order_accum() {
         order_accum_test(TimeWeightMethod::LOCF);
         order_accum_test(TimeWeightMethod::Linear);
+        order_accum_test(TimeWeightMethod::NOCB);
     }
 
     fn order_combine_test(t: TimeWeightMethod) {
@@ -445,6 +517,7 @@ mod tests {
     fn test_order_combine() {
         order_combine_test(TimeWeightMethod::LOCF);
         order_combine_test(TimeWeightMethod::Linear);
+        order_combine_test(TimeWeightMethod::NOCB);
     }
 
     fn combine_sorted_iter_test(t: TimeWeightMethod) {
@@ -505,6 +578,7 @@ mod tests {
     fn test_combine_sorted_iter() {
         combine_sorted_iter_test(TimeWeightMethod::LOCF);
         combine_sorted_iter_test(TimeWeightMethod::Linear);
+        combine_sorted_iter_test(TimeWeightMethod::NOCB);
     }
 
     #[test]
@@ -552,6 +626,17 @@ mod tests {
 
         let linear = TimeWeightMethod::Linear.weighted_sum(pt1, pt2);
         assert_eq!(linear, -100.0);
+
+        let nocb = TimeWeightMethod::NOCB.weighted_sum(pt1, pt2);
+        assert_eq!(nocb, -400.0);
+
+        // the gaps are left out by the summary, a segment is linear
+        let with_gaps = TimeWeightMethod::LinearWithGaps.weighted_sum(pt1, pt2);
+        assert_eq!(with_gaps, -100.0);
+        assert_eq!(
+            TimeWeightMethod::LinearWithGaps.interpolate(pt1, Some(pt2), 15),
+            Ok(TSPoint { ts: 15, val: -10.0 })
+        );
     }
 
     fn with_prev_common_test(t: TimeWeightMethod) {
@@ -648,6 +733,7 @@ mod tests {
 
         // now some common tests:
         with_prev_common_test(TimeWeightMethod::Linear);
+        with_prev_common_test(TimeWeightMethod::NOCB);
         with_prev_common_test(TimeWeightMethod::LOCF);
     }
 
@@ -755,6 +841,7 @@ mod tests {
 
         // now some common tests:
         with_next_common_test(TimeWeightMethod::Linear);
+        with_next_common_test(TimeWeightMethod::NOCB);
         with_next_common_test(TimeWeightMethod::LOCF);
     }
 
@@ -769,6 +856,7 @@ mod tests {
     #[test]
     fn test_average() {
         average_common_tests(TimeWeightMethod::Linear);
+        average_common_tests(TimeWeightMethod::NOCB);
         average_common_tests(TimeWeightMethod::LOCF);
 
         let test = TimeWeightSummary::new_from_sorted_iter(
@@ -794,4 +882,142 @@ mod tests {
         let expected = (10.0 * 1.5 + 10.0 * 2.5) / (30.0 - 10.0);
         assert_eq!(test.time_weighted_average().unwrap(), expected);
     }
+
+    #[test]
+    fn test_nocb() {
+        let test = TimeWeightSummary::new_from_sorted_iter(
+            vec![
+                &TSPoint { ts: 10, val: 1.0 },
+                &TSPoint { ts: 20, val: 2.0 },
+                &TSPoint { ts: 30, val: 3.0 },
+            ],
+            TimeWeightMethod::NOCB,
+        )
+        .unwrap();
+        let expected = (10.0 * 2.0 + 10.0 * 3.0) / (30.0 - 10.0);
+        assert_eq!(test.time_weighted_average().unwrap(), expected);
+
+        // the bounds take the value of the point after them
+        let prev = TSPoint { ts: 0, val: 5.0 };
+        let next = TSPoint { ts: 40, val: 4.0 };
+        let bounded = test
+            .with_prev(5, prev)
+            .unwrap()
+            .with_next(35, Some(next))
+            .unwrap();
+        assert_eq!(bounded.first, TSPoint { ts: 5, val: 1.0 });
+        assert_eq!(bounded.last, TSPoint { ts: 35, val: 4.0 });
+        assert_eq!(bounded.w_sum, 5.0 * 1.0 + 50.0 + 5.0 * 4.0);
+        assert_eq!(
+            test.with_next(35, None).unwrap_err(),
+            TimeWeightError::InterpolateMissingPoint
+        );
+    }
+
+    #[test]
+    fn test_linear_with_gaps() {
+        let points = [
+            TSPoint { ts: 10, val: 1.0 },
+            TSPoint { ts: 20, val: 3.0 },
+            // a gap
+            TSPoint {
+                ts: 100,
+                val: 100.0,
+            },
+            TSPoint { ts: 110, val: 2.0 },
+        ];
+        let test = TimeWeightSummary::new_from_sorted_iter_with_max_gap(
+            &points,
+            TimeWeightMethod::LinearWithGaps,
+            Some(20),
+        )
+        .unwrap();
+        assert_eq!(test.w_sum, 20.0 + 510.0);
+        assert_eq!(test.gap_duration, 80);
+        assert_eq!(test.covered_duration(), 20);
+        assert_eq!(test.time_weighted_average().unwrap(), 530.0 / 20.0);
+
+        // without a max_gap it is linear
+        let linear =
+            TimeWeightSummary::new_from_sorted_iter(&points, TimeWeightMethod::LinearWithGaps)
+                .unwrap();
+        let expected =
+            TimeWeightSummary::new_from_sorted_iter(&points, TimeWeightMethod::Linear).unwrap();
+        assert_eq!(linear.w_sum, expected.w_sum);
+
+        // combining across the gap gives the same result
+        let first = TimeWeightSummary::new_from_sorted_iter_with_max_gap(
+            &points[..2],
+            TimeWeightMethod::LinearWithGaps,
+            Some(20),
+        )
+        .unwrap();
+        let second = TimeWeightSummary::new_from_sorted_iter_with_max_gap(
+            &points[2..],
+            TimeWeightMethod::LinearWithGaps,
+            Some(20),
+        )
+        .unwrap();
+        assert_eq!(first.combine(&second).unwrap(), test);
+        assert_eq!(
+            first.combine(&TimeWeightSummary {
+                max_gap: Some(30),
+                ..second
+            }),
+            Err(TimeWeightError::MethodMismatch)
+        );
+
+        // bounds within a gap are missing as well
+        let bounded = second
+            .with_prev(50, points[1])
+            .unwrap()
+            .with_next(115, Some(TSPoint { ts: 120, val: 4.0 }))
+            .unwrap();
+        assert_eq!(bounded.first.ts, 50);
+        assert_eq!(bounded.last, TSPoint { ts: 115, val: 3.0 });
+        assert_eq!(bounded.gap_duration, 50);
+        assert_eq!(bounded.w_sum, 510.0 + 12.5);
+    }
+
+    #[test]
+    fn test_locf_with_gaps() {
+        let points = [
+            TSPoint { ts: 10, val: 1.0 },
+            TSPoint { ts: 20, val: 3.0 },
+            TSPoint { ts: 100, val: 5.0 },
+            TSPoint { ts: 110, val: 2.0 },
+        ];
+        let test = TimeWeightSummary::new_from_sorted_iter_with_max_gap(
+            &points,
+            TimeWeightMethod::LOCF,
+            Some(20),
+        )
+        .unwrap();
+        assert_eq!(test.w_sum, 10.0 + 50.0);
+        assert_eq!(test.covered_duration(), 20);
+        assert_eq!(test.time_weighted_average().unwrap(), 3.0);
+
+        let test = TimeWeightSummary::new_from_sorted_iter_with_max_gap(
+            &points,
+            TimeWeightMethod::NOCB,
+            Some(20),
+        )
+        .unwrap();
+        assert_eq!(test.w_sum, 30.0 + 20.0);
+        assert_eq!(test.covered_duration(), 20);
+
+        // nothing but gaps
+        let test = TimeWeightSummary::new_from_sorted_iter_with_max_gap(
+            &points,
+            TimeWeightMethod::LOCF,
+            Some(5),
+        )
+        .unwrap();
+        assert_eq!(test.w_sum, 0.0);
+        assert_eq!(test.gap_duration, 100);
+        assert_eq!(
+            test.time_weighted_average(),
+            Err(TimeWeightError::ZeroDuration)
+        );
+    }
 }
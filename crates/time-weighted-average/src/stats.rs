@@ -0,0 +1,432 @@
+use serde::{Deserialize, Serialize};
+use tspoint::TSPoint;
+use uddsketch::UDDSketch;
+
+use crate::{TimeWeightError, TimeWeightMethod, TimeWeightSummary};
+
+/// The number of equally long parts a linearly interpolated segment is split
+/// into when adding it to the sketch, each part is added at its middle value.
+const LINEAR_SKETCH_PARTS: i64 = 16;
+
+/// A [`TimeWeightSummary`] that also tracks the time-weighted variance, the
+/// minimum and maximum values, and optionally a sketch of how long the series
+/// spent at each value.
+///
+/// Like the summary itself, it treats the series as a function of time made
+/// of segments between consecutive points, interpolated according to the
+/// method.  The variance is kept as `m2`, the time-weighted sum of squared
+/// differences from the mean, which is updated per segment with the weighted
+/// form of Welford's algorithm to avoid cancellation for large values.  The
+/// sketch is weighted by the duration of the segments in microseconds.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct TimeWeightStats {
+    pub summary: TimeWeightSummary,
+    pub m2: f64,
+    pub min: f64,
+    pub max: f64,
+    pub sketch: Option<UDDSketch>,
+}
+
+impl TimeWeightStats {
+    /// `sketch` should be empty, it determines the size and error of the
+    /// sketch if one is kept.  `max_gap` is as in
+    /// [`TimeWeightSummary::new_with_max_gap`].
+    pub fn new(
+        pt: TSPoint,
+        method: TimeWeightMethod,
+        max_gap: Option<i64>,
+        sketch: Option<UDDSketch>,
+    ) -> Self {
+        TimeWeightStats {
+            summary: TimeWeightSummary::new_with_max_gap(pt, method, max_gap),
+            m2: 0.0,
+            min: pt.val,
+            max: pt.val,
+            sketch,
+        }
+    }
+
+    pub fn accum(&mut self, pt: TSPoint) -> Result<(), TimeWeightError> {
+        let last = self.summary.last;
+        let (duration, mean) = duration_and_mean(&self.summary);
+        self.summary.accum(pt)?;
+        if pt.ts == last.ts {
+            // ignored like in `TimeWeightSummary::accum`
+            return Ok(());
+        }
+        self.add_segment(duration, mean, last, pt);
+        Ok(())
+    }
+
+    /// Combines with the statistics of a later, disjoint, time range, the
+    /// same way [`TimeWeightSummary::combine`] does.
+    pub fn combine(&self, next: &TimeWeightStats) -> Result<TimeWeightStats, TimeWeightError> {
+        let summary = self.summary.combine(&next.summary)?;
+        let mut combined = self.clone();
+        let (duration, mean) = duration_and_mean(&self.summary);
+        let (last, next_first) = (self.summary.last, next.summary.first);
+        combined.add_segment(duration, mean, last, next_first);
+
+        // everything up to the first point of `next`, then `next` itself
+        let mut through = self.summary;
+        through.accum(next_first)?;
+        let (duration, mean) = duration_and_mean(&through);
+        let (next_duration, next_mean) = duration_and_mean(&next.summary);
+        combined.m2 = merge_m2(
+            duration,
+            mean,
+            combined.m2,
+            next_duration,
+            next_mean,
+            next.m2,
+        );
+        combined.min = combined.min.min(next.min);
+        combined.max = combined.max.max(next.max);
+        if let (Some(sketch), Some(next)) = (&mut combined.sketch, &next.sketch) {
+            sketch.merge_sketch(next);
+        }
+        combined.summary = summary;
+        Ok(combined)
+    }
+
+    pub fn new_from_sorted_iter<'a>(
+        iter: impl IntoIterator<Item = &'a TSPoint>,
+        method: TimeWeightMethod,
+        max_gap: Option<i64>,
+        sketch: Option<UDDSketch>,
+    ) -> Result<TimeWeightStats, TimeWeightError> {
+        let mut t = iter.into_iter();
+        let mut s = match t.next() {
+            None => return Err(TimeWeightError::EmptyIterator),
+            Some(val) => TimeWeightStats::new(*val, method, max_gap, sketch),
+        };
+        for p in t {
+            s.accum(*p)?;
+        }
+        Ok(s)
+    }
+
+    pub fn combine_sorted_iter<'a>(
+        iter: impl IntoIterator<Item = &'a TimeWeightStats>,
+    ) -> Result<TimeWeightStats, TimeWeightError> {
+        let mut t = iter.into_iter();
+        let mut s = match t.next() {
+            None => return Err(TimeWeightError::EmptyIterator),
+            Some(val) => val.clone(),
+        };
+        for p in t {
+            s = s.combine(p)?;
+        }
+        Ok(s)
+    }
+
+    /// The time-weighted population variance.
+    pub fn time_weighted_variance(&self) -> Result<f64, TimeWeightError> {
+        let (duration, _) = duration_and_mean(&self.summary);
+        if duration == 0.0 {
+            return Err(TimeWeightError::ZeroDuration);
+        }
+        Ok(self.m2 / duration)
+    }
+
+    pub fn time_weighted_stddev(&self) -> Result<f64, TimeWeightError> {
+        self.time_weighted_variance().map(f64::sqrt)
+    }
+
+    // Adds the segment from `first` to `second` to everything but the
+    // summary, `first` must be the last point seen so far, and `before` and
+    // `mean` the duration and mean of the series up to it.
+    fn add_segment(&mut self, before: f64, mean: f64, first: TSPoint, second: TSPoint) {
+        debug_assert!(second.ts > first.ts);
+        self.min = self.min.min(second.val);
+        self.max = self.max.max(second.val);
+        if self.summary.is_gap(first, second) {
+            // missing data
+            return;
+        }
+
+        let method = self.summary.method;
+        let duration = second.ts - first.ts;
+        let segment_mean = method.weighted_sum(first, second) / duration as f64;
+        let segment_m2 = match method {
+            TimeWeightMethod::LOCF | TimeWeightMethod::NOCB => 0.0,
+            // the variance of a uniform distribution over the values
+            TimeWeightMethod::Linear | TimeWeightMethod::LinearWithGaps => {
+                duration as f64 * (second.val - first.val).powi(2) / 12.0
+            }
+        };
+        self.m2 = merge_m2(
+            before,
+            mean,
+            self.m2,
+            duration as f64,
+            segment_mean,
+            segment_m2,
+        );
+
+        let sketch = match &mut self.sketch {
+            None => return,
+            Some(sketch) => sketch,
+        };
+        match method {
+            TimeWeightMethod::LOCF => sketch.add_weighted_value(first.val, duration as u64),
+            TimeWeightMethod::NOCB => sketch.add_weighted_value(second.val, duration as u64),
+            TimeWeightMethod::Linear | TimeWeightMethod::LinearWithGaps
+                if first.val == second.val =>
+            {
+                sketch.add_weighted_value(first.val, duration as u64)
+            }
+            TimeWeightMethod::Linear | TimeWeightMethod::LinearWithGaps => {
+                for part in 0..LINEAR_SKETCH_PARTS {
+                    let weight = duration * (part + 1) / LINEAR_SKETCH_PARTS
+                        - duration * part / LINEAR_SKETCH_PARTS;
+                    let middle = (2 * part + 1) as f64 / (2 * LINEAR_SKETCH_PARTS) as f64;
+                    let value = first.val + (second.val - first.val) * middle;
+                    sketch.add_weighted_value(value, weight as u64);
+                }
+            }
+        }
+    }
+}
+
+// The duration, without gaps, and the mean of a summary.
+fn duration_and_mean(summary: &TimeWeightSummary) -> (f64, f64) {
+    let duration = summary.covered_duration() as f64;
+    if duration == 0.0 {
+        return (0.0, 0.0);
+    }
+    (duration, summary.w_sum / duration)
+}
+
+// Combines the time-weighted sums of squared differences from the mean of two
+// disjoint parts of a series.
+fn merge_m2(duration1: f64, mean1: f64, m2_1: f64, duration2: f64, mean2: f64, m2_2: f64) -> f64 {
+    let duration = duration1 + duration2;
+    if duration == 0.0 {
+        return 0.0;
+    }
+    let delta = mean2 - mean1;
+    m2_1 + m2_2 + delta * delta * duration1 * duration2 / duration
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points() -> Vec<TSPoint> {
+        [10.0, 20.0, 10.0, 20.0, 10.0]
+            .iter()
+            .enumerate()
+            .map(|(i, &val)| TSPoint {
+                ts: i as i64 * 60_000_000,
+                val,
+            })
+            .collect()
+    }
+
+    fn sketch() -> Option<UDDSketch> {
+        Some(UDDSketch::new(200, 0.001))
+    }
+
+    #[test]
+    fn test_locf_stats() {
+        let s = TimeWeightStats::new_from_sorted_iter(
+            &points(),
+            TimeWeightMethod::LOCF,
+            None,
+            sketch(),
+        )
+        .unwrap();
+        assert_eq!(s.summary.time_weighted_average().unwrap(), 15.0);
+        assert_eq!(s.time_weighted_variance().unwrap(), 25.0);
+        assert_eq!(s.time_weighted_stddev().unwrap(), 5.0);
+        assert_eq!((s.min, s.max), (10.0, 20.0));
+
+        let sketch = s.sketch.unwrap();
+        assert_eq!(sketch.count(), 4 * 60_000_000);
+        assert!((sketch.estimate_quantile_at_value(15.0) - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_linear_stats() {
+        let s = TimeWeightStats::new_from_sorted_iter(
+            &points(),
+            TimeWeightMethod::Linear,
+            None,
+            sketch(),
+        )
+        .unwrap();
+        assert_eq!(s.summary.time_weighted_average().unwrap(), 15.0);
+        let variance = s.time_weighted_variance().unwrap();
+        assert!((variance - 100.0 / 12.0).abs() < 1e-9, "{}", variance);
+        assert_eq!((s.min, s.max), (10.0, 20.0));
+
+        // the value is below 15 half of the time
+        let sketch = s.sketch.unwrap();
+        assert_eq!(sketch.count(), 4 * 60_000_000);
+        assert!((sketch.estimate_quantile_at_value(15.0) - 0.5).abs() < 1e-9);
+        assert!((sketch.estimate_quantile(0.5) - 15.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_single_point() {
+        let s = TimeWeightStats::new(
+            TSPoint { ts: 1, val: 1.0 },
+            TimeWeightMethod::LOCF,
+            None,
+            None,
+        );
+        assert_eq!(
+            s.time_weighted_variance(),
+            Err(TimeWeightError::ZeroDuration)
+        );
+        assert_eq!((s.min, s.max), (1.0, 1.0));
+    }
+
+    fn combine_test(method: TimeWeightMethod) {
+        let points = points();
+        let expected =
+            TimeWeightStats::new_from_sorted_iter(&points, method, None, sketch()).unwrap();
+        for split in 1..points.len() {
+            let first =
+                TimeWeightStats::new_from_sorted_iter(&points[..split], method, None, sketch())
+                    .unwrap();
+            let second =
+                TimeWeightStats::new_from_sorted_iter(&points[split..], method, None, sketch())
+                    .unwrap();
+            let combined = first.combine(&second).unwrap();
+            assert_eq!(combined.summary, expected.summary);
+            assert!((combined.m2 - expected.m2).abs() < 1e-3 * expected.m2);
+            assert_eq!((combined.min, combined.max), (expected.min, expected.max));
+            assert_eq!(combined.sketch, expected.sketch);
+        }
+
+        let parts: Vec<_> = points
+            .iter()
+            .map(|p| TimeWeightStats::new(*p, method, None, sketch()))
+            .collect();
+        let combined = TimeWeightStats::combine_sorted_iter(&parts).unwrap();
+        assert_eq!(combined.summary, expected.summary);
+        assert!((combined.m2 - expected.m2).abs() < 1e-3 * expected.m2);
+        assert_eq!(combined.sketch, expected.sketch);
+    }
+
+    #[test]
+    fn test_combine() {
+        combine_test(TimeWeightMethod::LOCF);
+        combine_test(TimeWeightMethod::Linear);
+        combine_test(TimeWeightMethod::NOCB);
+    }
+
+    #[test]
+    fn test_order() {
+        let mut s = TimeWeightStats::new(
+            TSPoint { ts: 10, val: 1.0 },
+            TimeWeightMethod::LOCF,
+            None,
+            None,
+        );
+        assert_eq!(
+            s.accum(TSPoint { ts: 5, val: 2.0 }),
+            Err(TimeWeightError::OrderError)
+        );
+        let earlier = TimeWeightStats::new(
+            TSPoint { ts: 5, val: 2.0 },
+            TimeWeightMethod::LOCF,
+            None,
+            None,
+        );
+        assert_eq!(s.combine(&earlier), Err(TimeWeightError::OrderError));
+        let linear = TimeWeightStats::new(
+            TSPoint { ts: 20, val: 2.0 },
+            TimeWeightMethod::Linear,
+            None,
+            None,
+        );
+        assert_eq!(s.combine(&linear), Err(TimeWeightError::MethodMismatch));
+    }
+
+    #[test]
+    fn test_gap_stats() {
+        let points: Vec<TSPoint> = [(0, 10.0), (10, 20.0), (20, 5.0), (40, 20.0), (50, 0.0)]
+            .iter()
+            .map(|&(minute, val)| TSPoint {
+                ts: minute * 60_000_000,
+                val,
+            })
+            .collect();
+        // only the gap from 20 to 40 minutes is missing, so the stats are
+        // those of the two parts around it
+        let max_gap = Some(15 * 60_000_000);
+        let s = TimeWeightStats::new_from_sorted_iter(
+            &points,
+            TimeWeightMethod::LinearWithGaps,
+            max_gap,
+            sketch(),
+        )
+        .unwrap();
+        let before = TimeWeightStats::new_from_sorted_iter(
+            &points[..3],
+            TimeWeightMethod::Linear,
+            None,
+            None,
+        )
+        .unwrap();
+        let after = TimeWeightStats::new_from_sorted_iter(
+            &points[3..],
+            TimeWeightMethod::Linear,
+            None,
+            None,
+        )
+        .unwrap();
+        let (duration, mean) = duration_and_mean(&before.summary);
+        let (next_duration, next_mean) = duration_and_mean(&after.summary);
+        let m2 = merge_m2(
+            duration,
+            mean,
+            before.m2,
+            next_duration,
+            next_mean,
+            after.m2,
+        );
+        assert_eq!(s.summary.covered_duration(), 30 * 60_000_000);
+        assert!((s.m2 - m2).abs() < 1e-6 * m2);
+        assert_eq!((s.min, s.max), (0.0, 20.0));
+        assert_eq!(s.sketch.as_ref().unwrap().count(), 30 * 60_000_000);
+
+        for split in 1..points.len() {
+            let first = TimeWeightStats::new_from_sorted_iter(
+                &points[..split],
+                TimeWeightMethod::LinearWithGaps,
+                max_gap,
+                sketch(),
+            )
+            .unwrap();
+            let second = TimeWeightStats::new_from_sorted_iter(
+                &points[split..],
+                TimeWeightMethod::LinearWithGaps,
+                max_gap,
+                sketch(),
+            )
+            .unwrap();
+            let combined = first.combine(&second).unwrap();
+            assert_eq!(combined.summary, s.summary);
+            assert!((combined.m2 - s.m2).abs() < 1e-6 * s.m2);
+            assert_eq!(combined.sketch, s.sketch);
+        }
+
+        // every segment is a gap
+        let s = TimeWeightStats::new_from_sorted_iter(
+            &points,
+            TimeWeightMethod::LinearWithGaps,
+            Some(60_000_000),
+            sketch(),
+        )
+        .unwrap();
+        assert_eq!(
+            s.time_weighted_variance(),
+            Err(TimeWeightError::ZeroDuration)
+        );
+        assert_eq!(s.sketch.unwrap().count(), 0);
+    }
+}
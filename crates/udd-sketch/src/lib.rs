@@ -114,8 +114,8 @@ impl SketchHashMap {
     }
 
     // Increment the count at a key, creating the entry if needed.
-    fn increment(&mut self, key: SketchHashKey) {
-        self.entry(key).count += 1;
+    fn increment(&mut self, key: SketchHashKey, count: u64) {
+        self.entry(key).count += count;
     }
 
     fn iter(&self) -> SketchHashIterator {
@@ -275,14 +275,75 @@ impl UDDSketch {
 
 impl UDDSketch {
     pub fn add_value(&mut self, value: f64) {
-        self.buckets.increment(self.key(value));
+        self.add_weighted_value(value, 1)
+    }
+
+    /// Adds `value` as if it had been seen `weight` times.
+    pub fn add_weighted_value(&mut self, value: f64, weight: u64) {
+        if weight == 0 {
+            return;
+        }
+        self.buckets.increment(self.key(value), weight);
+
+        while self.buckets.len() > self.max_buckets as usize {
+            self.compact_buckets();
+        }
+
+        self.num_values += weight;
+        self.values_sum += value * weight as f64;
+    }
+
+    /// Adds `count` values that are only known to lie in the range (`lower`, `upper`], as
+    /// found in explicit-bucket histograms.  The sketch is compacted until the whole range
+    /// fits into a single bucket, so the error guarantee also holds for these values.
+    /// The sum is estimated using the midpoint of the range.
+    ///
+    /// Two kinds of range carry too little information for any error guarantee, and are
+    /// given the meaning Prometheus gives them instead:
+    /// - No bucket can hold a range that touches or contains zero.  Its values are counted
+    ///   in the zero bucket, like those of the zero bucket of a native histogram, so their
+    ///   quantiles are estimated as zero.
+    /// - Nothing is known about the values of a range unbounded on one side, such as the
+    ///   `+Inf` bucket of a classic histogram, except that they are beyond its finite bound.
+    ///   They are counted at that bound, as `histogram_quantile` does.
+    pub fn add_bucket(&mut self, lower: f64, upper: f64, count: u64) {
+        assert!(lower < upper && (lower.is_finite() || upper.is_finite()));
+        if count == 0 {
+            return;
+        }
+
+        if lower.is_infinite() {
+            return self.add_weighted_value(upper, count);
+        }
+        if upper.is_infinite() {
+            return self.add_weighted_value(lower, count);
+        }
+
+        let key = if lower <= 0.0 && upper >= 0.0 {
+            SketchHashKey::Zero
+        } else if lower > 0.0 {
+            loop {
+                if let Some(k) = bucket_containing(lower, upper, self.gamma) {
+                    break SketchHashKey::Positive(k);
+                }
+                self.compact_buckets();
+            }
+        } else {
+            loop {
+                if let Some(k) = bucket_containing(-upper, -lower, self.gamma) {
+                    break SketchHashKey::Negative(k);
+                }
+                self.compact_buckets();
+            }
+        };
+        self.buckets.increment(key, count);
 
         while self.buckets.len() > self.max_buckets as usize {
             self.compact_buckets();
         }
 
-        self.num_values += 1;
-        self.values_sum += value;
+        self.num_values += count;
+        self.values_sum += (lower + upper) / 2.0 * count as f64;
     }
 
     pub fn merge_sketch(&mut self, other: &UDDSketch) {
@@ -380,6 +441,37 @@ impl UDDSketch {
     pub fn estimate_quantile_at_value(&self, value: f64) -> f64 {
         estimate_quantile_at_value(value, self.gamma, self.num_values, self.buckets.iter())
     }
+
+    pub fn estimate_trimmed_mean(&self, low: f64, high: f64) -> f64 {
+        estimate_trimmed_mean(
+            low,
+            high,
+            self.alpha,
+            self.gamma,
+            self.num_values,
+            self.buckets.iter(),
+        )
+    }
+
+    pub fn estimate_winsorized_mean(&self, low: f64, high: f64) -> f64 {
+        estimate_winsorized_mean(
+            low,
+            high,
+            self.alpha,
+            self.gamma,
+            self.num_values,
+            self.buckets.iter(),
+        )
+    }
+
+    pub fn estimate_median_absolute_deviation(&self) -> f64 {
+        estimate_median_absolute_deviation(
+            self.alpha,
+            self.gamma,
+            self.num_values,
+            self.buckets.iter(),
+        )
+    }
 }
 
 pub fn estimate_quantile(
@@ -454,7 +546,133 @@ pub fn estimate_quantile_at_value(
     1.0 // Greater than anything in the sketch
 }
 
-fn key(value: f64, gamma: f64) -> SketchHashKey {
+/// The range of values a bucket covers: `(gamma^(i-1), gamma^i]` for `Positive(i)`, the
+/// mirror image of that for `Negative(i)`, and just zero for the zero bucket.
+pub fn bucket_bounds(gamma: f64, bucket: SketchHashKey) -> (f64, f64) {
+    match bucket {
+        SketchHashKey::Zero => (0.0, 0.0),
+        SketchHashKey::Positive(i) => (gamma.powf(i as f64 - 1.0), gamma.powf(i as f64)),
+        SketchHashKey::Negative(i) => (-gamma.powf(i as f64), -gamma.powf(i as f64 - 1.0)),
+        SketchHashKey::Invalid => panic!("Unable to convert invalid bucket id to value"),
+    }
+}
+
+/// Estimates how many values fall into each of the bins (`bounds[i]`, `bounds[i + 1]`], assuming
+/// the values in a bucket are spread evenly across its range.  `bounds` must be sorted.
+pub fn estimate_histogram(
+    bounds: &[f64],
+    gamma: f64,
+    buckets: impl Iterator<Item = (SketchHashKey, u64)>,
+) -> Vec<f64> {
+    let mut counts = vec![0.0; bounds.len().saturating_sub(1)];
+    for (key, count) in buckets {
+        let (lower, upper) = bucket_bounds(gamma, key);
+        for (bin, edges) in counts.iter_mut().zip(bounds.windows(2)) {
+            let fraction = if lower == upper {
+                if edges[0] < lower && lower <= edges[1] {
+                    1.0
+                } else {
+                    0.0
+                }
+            } else {
+                let overlap = upper.min(edges[1]) - lower.max(edges[0]);
+                overlap / (upper - lower)
+            };
+            if fraction > 0.0 {
+                *bin += fraction * count as f64;
+            }
+        }
+    }
+    counts
+}
+
+/// Estimates the mean of the values between the `low` and `high` quantiles.  Buckets straddling
+/// either quantile only contribute the part of their count that lies inside of it.
+pub fn estimate_trimmed_mean(
+    low: f64,
+    high: f64,
+    alpha: f64,
+    gamma: f64,
+    num_values: u64,
+    buckets: impl Iterator<Item = (SketchHashKey, u64)>,
+) -> f64 {
+    assert!(0.0 <= low && low < high && high <= 1.0);
+    let (low, high) = (low * num_values as f64, high * num_values as f64);
+    sum_between_ranks(low, high, alpha, gamma, buckets) / (high - low)
+}
+
+/// Estimates the mean of the values after clamping everything below the `low` quantile to the
+/// `low` quantile, and everything above the `high` quantile to the `high` quantile.
+pub fn estimate_winsorized_mean(
+    low: f64,
+    high: f64,
+    alpha: f64,
+    gamma: f64,
+    num_values: u64,
+    buckets: impl Iterator<Item = (SketchHashKey, u64)>,
+) -> f64 {
+    assert!(0.0 <= low && low < high && high <= 1.0);
+    let buckets: Vec<_> = buckets.collect();
+    let low_value = estimate_quantile(low, alpha, gamma, num_values, buckets.iter().copied());
+    let high_value = estimate_quantile(high, alpha, gamma, num_values, buckets.iter().copied());
+
+    let n = num_values as f64;
+    let middle = sum_between_ranks(low * n, high * n, alpha, gamma, buckets.into_iter());
+    (low * n * low_value + middle + (1.0 - high) * n * high_value) / n
+}
+
+/// Estimates the median of the absolute deviations from the median.
+pub fn estimate_median_absolute_deviation(
+    alpha: f64,
+    gamma: f64,
+    num_values: u64,
+    buckets: impl Iterator<Item = (SketchHashKey, u64)>,
+) -> f64 {
+    let buckets: Vec<_> = buckets.collect();
+    let median = estimate_quantile(0.5, alpha, gamma, num_values, buckets.iter().copied());
+
+    let mut deviations: Vec<(f64, u64)> = buckets
+        .into_iter()
+        .map(|(key, count)| ((bucket_to_value(alpha, gamma, key) - median).abs(), count))
+        .collect();
+    deviations.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+    let mut remaining = (num_values as f64 * 0.5) as u64 + 1;
+    for (deviation, count) in &deviations {
+        if remaining <= *count {
+            return *deviation;
+        }
+        remaining -= count;
+    }
+    deviations.last().map_or(0.0, |(deviation, _)| *deviation)
+}
+
+// Sum of the values whose rank lies between `low` and `high`, where a bucket
+// with count `c` covers `c` consecutive ranks.
+fn sum_between_ranks(
+    low: f64,
+    high: f64,
+    alpha: f64,
+    gamma: f64,
+    buckets: impl Iterator<Item = (SketchHashKey, u64)>,
+) -> f64 {
+    let mut sum = 0.0;
+    let mut seen = 0.0;
+    for (key, count) in buckets {
+        let start = seen;
+        seen += count as f64;
+        let overlap = seen.min(high) - start.max(low);
+        if overlap > 0.0 {
+            sum += overlap * bucket_to_value(alpha, gamma, key);
+        }
+        if seen >= high {
+            break;
+        }
+    }
+    sum
+}
+
+pub fn key(value: f64, gamma: f64) -> SketchHashKey {
     let negative = value < 0.0;
     let value = value.abs();
 
@@ -467,6 +685,15 @@ fn key(value: f64, gamma: f64) -> SketchHashKey {
     }
 }
 
+// Returns the index of the bucket holding every magnitude from `min` to `max`, if there is
+// one.  Magnitudes within rounding error of a boundary are considered part of either bucket,
+// otherwise ranges that line up with the sketch's buckets would never fit.
+fn bucket_containing(min: f64, max: f64, gamma: f64) -> Option<i64> {
+    const EPSILON: f64 = 1e-9;
+    let k = (max.log(gamma) - EPSILON).ceil();
+    (min.log(gamma) >= k - 1.0 - EPSILON).then_some(k as i64)
+}
+
 pub fn gamma(alpha: f64) -> f64 {
     (1.0 + alpha) / (1.0 - alpha)
 }
@@ -626,6 +853,194 @@ mod tests {
         assert!((sketch.mean() - 50.005).abs() < 0.001);
     }
 
+    #[test]
+    fn weighted_values() {
+        let mut weighted = UDDSketch::new(20, 0.1);
+        let mut repeated = UDDSketch::new(20, 0.1);
+        for i in 0..40 {
+            let value = 1.23_f64.powi(i) - 10.0;
+            let weight = (i % 5) as u64;
+            weighted.add_weighted_value(value, weight);
+            for _ in 0..weight {
+                repeated.add_value(value);
+            }
+        }
+
+        assert_eq!(weighted.count(), repeated.count());
+        assert_eq!(weighted.max_error(), repeated.max_error());
+        assert!(weighted.bucket_iter().eq(repeated.bucket_iter()));
+        assert!((weighted.sum() - repeated.sum()).abs() < 1e-6 * repeated.sum().abs());
+        for quantile in [0.01, 0.25, 0.5, 0.75, 0.99] {
+            assert_eq!(
+                weighted.estimate_quantile(quantile),
+                repeated.estimate_quantile(quantile)
+            );
+        }
+    }
+
+    #[test]
+    fn aligned_buckets() {
+        // a Prometheus native histogram with schema 3 has 8 buckets per power of two,
+        // bucket i covering (2^((i-1)/8), 2^(i/8)]
+        let base = 2.0_f64.powf(1.0 / 8.0);
+        let alpha = (base - 1.0) / (base + 1.0);
+        let mut sketch = UDDSketch::new(100, alpha);
+        for i in -20..20 {
+            let lower = 2.0_f64.powf((i - 1) as f64 / 8.0);
+            let upper = 2.0_f64.powf(i as f64 / 8.0);
+            sketch.add_bucket(lower, upper, 2);
+            sketch.add_bucket(-upper, -lower, 1);
+        }
+        sketch.add_bucket(-1e-9, 1e-9, 5);
+
+        // the buckets match up exactly so there is no need to compact
+        assert_eq!(sketch.times_compacted(), 0);
+        assert_eq!(sketch.max_error(), alpha);
+        assert_eq!(sketch.count(), 125);
+        assert_eq!(sketch.current_buckets_count(), 81);
+        // 40 negative values come before the 5 in the zero bucket
+        assert_eq!(sketch.estimate_quantile(0.34), 0.0);
+    }
+
+    #[test]
+    fn unbounded_and_zero_buckets() {
+        let mut sketch = UDDSketch::new(100, 0.01);
+        // a classic Prometheus histogram: le="-1", le="1", le="10", le="+Inf"
+        sketch.add_bucket(f64::NEG_INFINITY, -1.0, 2);
+        sketch.add_bucket(-1.0, 1.0, 4);
+        sketch.add_bucket(1.0, 10.0, 3);
+        sketch.add_bucket(10.0, f64::INFINITY, 1);
+
+        assert_eq!(sketch.count(), 10);
+        // the unbounded buckets are counted at their finite bound, the one
+        // around zero at its midpoint
+        let sum = -2.0 + 4.0 * 0.0 + 3.0 * 5.5 + 10.0;
+        assert_eq!(sketch.sum(), sum);
+        // the bucket up to 10 needs a lot of compaction
+        let error = sketch.max_error();
+        let close = |estimate: f64, value: f64| (estimate - value).abs() <= error * value.abs();
+        assert!(close(sketch.estimate_quantile(0.0), -1.0));
+        assert_eq!(sketch.estimate_quantile(0.3), 0.0);
+        assert_eq!(sketch.estimate_quantile(0.5), 0.0);
+        assert!(close(sketch.estimate_quantile(0.8), 5.5));
+        assert!(close(sketch.estimate_quantile(1.0), 10.0));
+
+        // a bucket touching zero counts as the zero bucket as well
+        let mut sketch = UDDSketch::new(100, 0.01);
+        sketch.add_bucket(-1.0, 3.0, 8);
+        sketch.add_bucket(0.0, 2.0, 2);
+        sketch.add_bucket(-2.0, 0.0, 2);
+        assert_eq!(sketch.current_buckets_count(), 1);
+        assert_eq!(sketch.estimate_quantile(0.5), 0.0);
+        // the midpoints of the three buckets are 1, 1 and -1
+        assert_eq!(sketch.sum(), 8.0 + 2.0 - 2.0);
+    }
+
+    #[test]
+    fn unaligned_buckets_keep_error() {
+        let seed = rand::thread_rng().gen();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+
+        // explicit buckets with arbitrary bounds, each far wider than the sketch's buckets
+        let mut bounds: Vec<f64> = (0..30).map(|_| rng.gen_range(0.1..1000.0)).collect();
+        bounds.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        bounds.dedup();
+
+        let mut values = vec![];
+        let mut sketch = UDDSketch::new(200, 0.001);
+        for edges in bounds.windows(2) {
+            let count = rng.gen_range(0..100);
+            for _ in 0..count {
+                values.push(rng.gen_range(edges[0]..edges[1]));
+            }
+            sketch.add_bucket(edges[0], edges[1], count);
+        }
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        assert_eq!(sketch.count(), values.len() as u64);
+        assert!(sketch.times_compacted() > 0);
+        for i in 0..values.len() {
+            let quantile = i as f64 / values.len() as f64;
+            let estimate = sketch.estimate_quantile(quantile);
+            let target = values[i];
+            assert!(
+                (estimate - target).abs() / target <= sketch.max_error(),
+                "Failed to match {} quantile with seed {}. Received: {}, Expected: {}",
+                quantile,
+                seed,
+                estimate,
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn robust_statistics() {
+        let mut sketch = UDDSketch::new(200, 0.001);
+        for v in 1..=1000 {
+            sketch.add_value(v as f64);
+        }
+        // a few huge outliers
+        for _ in 0..10 {
+            sketch.add_value(1e9);
+        }
+        let error = sketch.max_error();
+
+        // the outliers and a bit more are trimmed away
+        let trimmed = sketch.estimate_trimmed_mean(0.1, 0.9);
+        assert!(
+            (trimmed - 505.0).abs() / 505.0 <= error + 0.01,
+            "{}",
+            trimmed
+        );
+        assert!(sketch.mean() > 1e6);
+
+        // the top 10% is clamped to the 90th percentile, the bottom 10% to the 10th
+        let winsorized = sketch.estimate_winsorized_mean(0.1, 0.9);
+        assert!(
+            (winsorized - 505.0).abs() / 505.0 <= error + 0.01,
+            "{}",
+            winsorized
+        );
+
+        // trimming nothing gives the mean of the bucket values
+        let all = sketch.estimate_trimmed_mean(0.0, 1.0);
+        assert!((all - sketch.mean()).abs() / sketch.mean() <= error);
+
+        // half of the values lie within 252.5 of the median
+        let mad = sketch.estimate_median_absolute_deviation();
+        assert!((mad - 252.5).abs() <= 5.0, "{}", mad);
+    }
+
+    #[test]
+    fn histogram_estimates() {
+        let mut sketch = UDDSketch::new(200, 0.01);
+        for v in -100..=100 {
+            sketch.add_value(v as f64);
+        }
+
+        // the buckets tile the values without gaps
+        let buckets: Vec<_> = sketch
+            .bucket_iter()
+            .map(|(key, count)| (bucket_bounds(sketch.gamma, key), count))
+            .collect();
+        for ((lower, upper), _) in &buckets {
+            assert!(lower <= upper);
+        }
+        assert_eq!(buckets.iter().map(|(_, c)| c).sum::<u64>(), 201);
+        assert!(buckets.contains(&((0.0, 0.0), 1)));
+        assert!(buckets.windows(2).all(|w| (w[0].0).1 <= (w[1].0).0));
+
+        let bins = [f64::NEG_INFINITY, -50.0, 0.0, 50.0, 100.0, 200.0];
+        let histogram = estimate_histogram(&bins, sketch.gamma, sketch.bucket_iter());
+        let expected = [51.0, 50.0, 50.0, 50.0, 0.0];
+        assert_eq!(histogram.len(), expected.len());
+        for (estimate, expected) in histogram.iter().zip(expected) {
+            assert!((estimate - expected).abs() <= 1.0, "{:?}", histogram);
+        }
+        assert!((histogram.iter().sum::<f64>() - 201.0).abs() < 1e-9);
+    }
+
     #[test]
     fn test_extreme_quantile_at_value() {
         let mut sketch = UDDSketch::new(50, 0.1);
@@ -109,6 +109,36 @@ accessor! { state_int_timeline() }
 accessor! { num_live_ranges() }
 accessor! { num_gaps() }
 accessor! { topn() }
+
+// accessors for features that haven't been stabilized yet
+#[pg_schema]
+pub mod toolkit_experimental {
+    use super::*;
+
+    accessor! { ewma() }
+    accessor! { ewma_x() }
+    accessor! { ewma_y() }
+    accessor! { ewm_variance() }
+    accessor! { ewm_variance_x() }
+    accessor! { ewm_variance_y() }
+    accessor! { ewm_covariance() }
+    accessor! { histogram_quantile(
+        quantile: f64,
+    ) }
+    accessor! { histogram_delta() }
+    accessor! { histogram_rate() }
+    accessor! { approx_trimmed_mean(
+        low: f64,
+        high: f64,
+    ) }
+    accessor! { approx_winsorized_mean(
+        low: f64,
+        high: f64,
+    ) }
+    accessor! { approx_iqr() }
+    accessor! { approx_mad() }
+}
+
 // The rest are more complex, with String or other challenges.  Leaving alone for now.
 
 pg_type! {
@@ -124,8 +124,10 @@ fn asap_final_inner(
                     num_points: points.len() as u32,
                     flags: time_vector::FLAG_IS_SORTED,
                     internal_padding: [0; 3],
+                    compressed_len: vec![].into(),
                     points: points.into(),
                     null_val: std::vec::from_elem(0_u8, nulls_len).into(),
+                    compressed: vec![].into(),
                 }
             })
         })
@@ -134,9 +136,10 @@ fn asap_final_inner(
 
 #[pg_extern(name = "asap_smooth", immutable, parallel_safe)]
 pub fn asap_on_timevector(
-    mut series: Timevector_TSTZ_F64<'static>,
+    series: Timevector_TSTZ_F64<'static>,
     resolution: i32,
 ) -> Option<Timevector_TSTZ_F64<'static>> {
+    let mut series = series.decompress();
     // TODO: implement this using zero copy (requires sort, find_downsample_interval, and downsample_and_gapfill on Timevector)
     let needs_sort = series.is_sorted();
 
@@ -172,8 +175,10 @@ pub fn asap_on_timevector(
             num_points: points.len() as u32,
             flags: time_vector::FLAG_IS_SORTED,
             internal_padding: [0; 3],
+            compressed_len: vec![].into(),
             points: points.into(),
             null_val: std::vec::from_elem(0_u8, nulls_len).into(),
+            compressed: vec![].into(),
         }
     })
 }
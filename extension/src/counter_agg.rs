@@ -30,6 +30,8 @@ use crate::raw::tstzrange;
 use crate::raw::bytea;
 
 mod accessors;
+mod histogram;
+mod model;
 
 use accessors::{CounterInterpolatedDeltaAccessor, CounterInterpolatedRateAccessor};
 
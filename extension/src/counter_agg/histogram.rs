@@ -0,0 +1,649 @@
+use std::mem::take;
+
+use pgx::{iter::TableIterator, *};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessors::toolkit_experimental::{
+        AccessorHistogramDelta, AccessorHistogramQuantile, AccessorHistogramRate,
+    },
+    aggregate_utils::in_aggregate_context,
+    flatten,
+    palloc::{Inner, Internal, InternalAsValue, ToInternal},
+    pg_type,
+    range::*,
+    raw::{bytea, tstzrange},
+    ron_inout_funcs,
+};
+
+use tspoint::TSPoint;
+
+use counter_agg::{
+    histogram::{HistogramBucket, HistogramSummary},
+    range::I64Range,
+    MetricSummary,
+};
+
+use super::PgTypeHackStatsSummary2D;
+
+use toolkit_experimental::HistogramCounterSummary;
+
+flat_serialize_macro::flat_serialize! {
+    #[derive(Serialize, Deserialize, Debug, Copy)]
+    struct HistogramCounterBucket {
+        le: f64,
+        stats: PgTypeHackStatsSummary2D,
+        first: TSPoint,
+        second: TSPoint,
+        penultimate: TSPoint,
+        last: TSPoint,
+        reset_sum: f64,
+        num_resets: u64,
+        num_changes: u64,
+    }
+}
+
+#[pg_schema]
+pub mod toolkit_experimental {
+    use super::*;
+
+    // every bucket shares the bounds of the aggregate, so they're only stored once
+    pg_type! {
+        #[derive(Debug)]
+        struct HistogramCounterSummary<'input> {
+            num_buckets: u64,
+            buckets: [HistogramCounterBucket; self.num_buckets],
+            #[flat_serialize::flatten]
+            bounds: I64RangeWrapper,
+        }
+    }
+
+    ron_inout_funcs!(HistogramCounterSummary);
+}
+
+impl<'input> HistogramCounterSummary<'input> {
+    pub fn to_internal_histogram_summary(&self) -> HistogramSummary {
+        let bounds = self.bounds.to_i64range();
+        let buckets = self
+            .buckets
+            .iter()
+            .map(|b| HistogramBucket {
+                le: b.le,
+                counter: MetricSummary {
+                    first: b.first,
+                    second: b.second,
+                    penultimate: b.penultimate,
+                    last: b.last,
+                    reset_sum: b.reset_sum,
+                    num_resets: b.num_resets,
+                    num_changes: b.num_changes,
+                    stats: b.stats,
+                    bounds,
+                },
+            })
+            .collect();
+        HistogramSummary { buckets }
+    }
+
+    pub fn from_internal_histogram_summary(st: HistogramSummary) -> Self {
+        let bounds = st.buckets.first().and_then(|b| b.counter.bounds);
+        let buckets: Vec<_> = st
+            .buckets
+            .into_iter()
+            .map(|b| HistogramCounterBucket {
+                le: b.le,
+                stats: b.counter.stats,
+                first: b.counter.first,
+                second: b.counter.second,
+                penultimate: b.counter.penultimate,
+                last: b.counter.last,
+                reset_sum: b.counter.reset_sum,
+                num_resets: b.counter.num_resets,
+                num_changes: b.counter.num_changes,
+            })
+            .collect();
+        unsafe {
+            flatten!(HistogramCounterSummary {
+                num_buckets: buckets.len() as u64,
+                buckets: (&*buckets).into(),
+                bounds: I64RangeWrapper::from_i64range(bounds),
+            })
+        }
+    }
+}
+
+// Like CounterSummaryTransState, points are buffered until the summaries need to be built,
+// so they can arrive in any order.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HistogramCounterTransState {
+    #[serde(skip)]
+    point_buffer: Vec<(f64, TSPoint)>,
+    #[serde(skip)]
+    bounds: Option<I64Range>,
+    summary_buffer: Vec<HistogramSummary>,
+}
+
+impl HistogramCounterTransState {
+    fn new() -> Self {
+        Self {
+            point_buffer: vec![],
+            bounds: None,
+            summary_buffer: vec![],
+        }
+    }
+
+    fn combine_points(&mut self) {
+        if self.point_buffer.is_empty() {
+            return;
+        }
+        let summary = HistogramSummary::from_points(&mut self.point_buffer, self.bounds)
+            .unwrap_or_else(|e| pgx::error!("{}", e));
+        self.point_buffer.clear();
+        if !summary.bounds_valid() {
+            panic!("counter bounds invalid")
+        }
+        self.summary_buffer.push(summary);
+    }
+
+    fn push_summary(&mut self, other: &HistogramCounterTransState) {
+        self.summary_buffer
+            .extend(other.summary_buffer.iter().cloned());
+    }
+
+    fn combine_summaries(&mut self) {
+        self.combine_points();
+
+        if self.summary_buffer.len() <= 1 {
+            return;
+        }
+        let summary = HistogramSummary::combine_all(take(&mut self.summary_buffer))
+            .unwrap_or_else(|e| pgx::error!("{}", e));
+        self.summary_buffer = vec![summary];
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, strict, schema = "toolkit_experimental")]
+pub fn histogram_counter_trans_serialize(state: Internal) -> bytea {
+    let state: &mut HistogramCounterTransState = unsafe { state.get_mut().unwrap() };
+    state.combine_summaries();
+    crate::do_serialize!(state)
+}
+
+#[pg_extern(strict, immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn histogram_counter_trans_deserialize(bytes: bytea, _internal: Internal) -> Option<Internal> {
+    histogram_counter_trans_deserialize_inner(bytes).internal()
+}
+pub fn histogram_counter_trans_deserialize_inner(
+    bytes: bytea,
+) -> Inner<HistogramCounterTransState> {
+    let c: HistogramCounterTransState = crate::do_deserialize!(bytes, HistogramCounterTransState);
+    c.into()
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn histogram_counter_agg_trans(
+    state: Internal,
+    ts: Option<crate::raw::TimestampTz>,
+    le: Option<f64>,
+    val: Option<f64>,
+    bounds: Option<tstzrange>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    histogram_counter_agg_trans_inner(unsafe { state.to_inner() }, ts, le, val, bounds, fcinfo)
+        .internal()
+}
+pub fn histogram_counter_agg_trans_inner(
+    state: Option<Inner<HistogramCounterTransState>>,
+    ts: Option<crate::raw::TimestampTz>,
+    le: Option<f64>,
+    val: Option<f64>,
+    bounds: Option<tstzrange>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<HistogramCounterTransState>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let (le, p) = match (ts, le, val) {
+                (Some(ts), Some(le), Some(val)) => (le, TSPoint { ts: ts.into(), val }),
+                _ => return state,
+            };
+            match state {
+                None => {
+                    let mut s = HistogramCounterTransState::new();
+                    if let Some(r) = bounds {
+                        s.bounds = get_range(r.0.cast_mut_ptr());
+                    }
+                    s.point_buffer.push((le, p));
+                    Some(s.into())
+                }
+                Some(mut s) => {
+                    s.point_buffer.push((le, p));
+                    Some(s)
+                }
+            }
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn histogram_counter_agg_trans_no_bounds(
+    state: Internal,
+    ts: Option<crate::raw::TimestampTz>,
+    le: Option<f64>,
+    val: Option<f64>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    histogram_counter_agg_trans_inner(unsafe { state.to_inner() }, ts, le, val, None, fcinfo)
+        .internal()
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn histogram_counter_agg_summary_trans<'a>(
+    state: Internal,
+    value: Option<HistogramCounterSummary<'a>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    histogram_counter_agg_summary_trans_inner(unsafe { state.to_inner() }, value, fcinfo).internal()
+}
+pub fn histogram_counter_agg_summary_trans_inner(
+    state: Option<Inner<HistogramCounterTransState>>,
+    value: Option<HistogramCounterSummary>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<HistogramCounterTransState>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || match (state, value) {
+            (state, None) => state,
+            (None, Some(value)) => {
+                let mut state = HistogramCounterTransState::new();
+                state
+                    .summary_buffer
+                    .push(value.to_internal_histogram_summary());
+                Some(state.into())
+            }
+            (Some(mut state), Some(value)) => {
+                state
+                    .summary_buffer
+                    .push(value.to_internal_histogram_summary());
+                Some(state)
+            }
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn histogram_counter_agg_combine(
+    state1: Internal,
+    state2: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    unsafe {
+        histogram_counter_agg_combine_inner(state1.to_inner(), state2.to_inner(), fcinfo).internal()
+    }
+}
+pub fn histogram_counter_agg_combine_inner(
+    state1: Option<Inner<HistogramCounterTransState>>,
+    state2: Option<Inner<HistogramCounterTransState>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<HistogramCounterTransState>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || match (state1, state2) {
+            (None, None) => None,
+            (None, Some(state2)) => {
+                let mut s = state2.clone();
+                s.combine_points();
+                Some(s.into())
+            }
+            (Some(state1), None) => {
+                let mut s = state1.clone();
+                s.combine_points();
+                Some(s.into())
+            }
+            (Some(state1), Some(state2)) => {
+                let mut s1 = state1.clone();
+                s1.combine_points();
+                let mut s2 = state2.clone();
+                s2.combine_points();
+                s2.push_summary(&s1);
+                Some(s2.into())
+            }
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+fn histogram_counter_agg_final(
+    state: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<HistogramCounterSummary<'static>> {
+    histogram_counter_agg_final_inner(unsafe { state.to_inner() }, fcinfo)
+}
+fn histogram_counter_agg_final_inner(
+    state: Option<Inner<HistogramCounterTransState>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<HistogramCounterSummary<'static>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let mut state = match state {
+                None => return None,
+                Some(state) => state.clone(),
+            };
+            state.combine_summaries();
+            debug_assert!(state.summary_buffer.len() <= 1);
+            match state.summary_buffer.pop() {
+                None => None,
+                Some(st) => {
+                    if !st.bounds_valid() {
+                        panic!("counter bounds invalid")
+                    }
+                    Some(HistogramCounterSummary::from_internal_histogram_summary(st))
+                }
+            }
+        })
+    }
+}
+
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.histogram_counter_agg(\n\
+        ts timestamptz, le DOUBLE PRECISION, value DOUBLE PRECISION, bounds tstzrange\n\
+    ) (\n\
+        sfunc = toolkit_experimental.histogram_counter_agg_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.histogram_counter_agg_final,\n\
+        combinefunc = toolkit_experimental.histogram_counter_agg_combine,\n\
+        serialfunc = toolkit_experimental.histogram_counter_trans_serialize,\n\
+        deserialfunc = toolkit_experimental.histogram_counter_trans_deserialize,\n\
+        parallel = restricted\n\
+    );\n",
+    name = "histogram_counter_agg",
+    requires = [
+        histogram_counter_agg_trans,
+        histogram_counter_agg_final,
+        histogram_counter_agg_combine,
+        histogram_counter_trans_serialize,
+        histogram_counter_trans_deserialize
+    ],
+);
+
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.histogram_counter_agg(\n\
+        ts timestamptz, le DOUBLE PRECISION, value DOUBLE PRECISION\n\
+    ) (\n\
+        sfunc = toolkit_experimental.histogram_counter_agg_trans_no_bounds,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.histogram_counter_agg_final,\n\
+        combinefunc = toolkit_experimental.histogram_counter_agg_combine,\n\
+        serialfunc = toolkit_experimental.histogram_counter_trans_serialize,\n\
+        deserialfunc = toolkit_experimental.histogram_counter_trans_deserialize,\n\
+        parallel = restricted\n\
+    );\n",
+    name = "histogram_counter_agg2",
+    requires = [
+        histogram_counter_agg_trans_no_bounds,
+        histogram_counter_agg_final,
+        histogram_counter_agg_combine,
+        histogram_counter_trans_serialize,
+        histogram_counter_trans_deserialize
+    ],
+);
+
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.rollup(hs toolkit_experimental.HistogramCounterSummary)\n\
+    (\n\
+        sfunc = toolkit_experimental.histogram_counter_agg_summary_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.histogram_counter_agg_final,\n\
+        combinefunc = toolkit_experimental.histogram_counter_agg_combine,\n\
+        serialfunc = toolkit_experimental.histogram_counter_trans_serialize,\n\
+        deserialfunc = toolkit_experimental.histogram_counter_trans_deserialize,\n\
+        parallel = restricted\n\
+    );\n",
+    name = "histogram_counter_rollup",
+    requires = [
+        histogram_counter_agg_summary_trans,
+        histogram_counter_agg_final,
+        histogram_counter_agg_combine,
+        histogram_counter_trans_serialize,
+        histogram_counter_trans_deserialize
+    ],
+);
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_histogram_counter_quantile<'a>(
+    summary: HistogramCounterSummary<'a>,
+    accessor: AccessorHistogramQuantile<'a>,
+) -> Option<f64> {
+    histogram_counter_quantile(accessor.quantile, summary)
+}
+
+#[pg_extern(
+    name = "histogram_quantile",
+    strict,
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+fn histogram_counter_quantile<'a>(
+    quantile: f64,
+    summary: HistogramCounterSummary<'a>,
+) -> Option<f64> {
+    summary
+        .to_internal_histogram_summary()
+        .quantile(quantile)
+        .unwrap_or_else(|e| pgx::error!("{}", e))
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_histogram_counter_delta<'a>(
+    summary: HistogramCounterSummary<'a>,
+    _accessor: AccessorHistogramDelta<'a>,
+) -> TableIterator<'static, (name!(le, f64), name!(delta, f64))> {
+    histogram_counter_delta(summary)
+}
+
+#[pg_extern(
+    name = "histogram_delta",
+    strict,
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+fn histogram_counter_delta<'a>(
+    summary: HistogramCounterSummary<'a>,
+) -> TableIterator<'static, (name!(le, f64), name!(delta, f64))> {
+    let deltas = summary
+        .to_internal_histogram_summary()
+        .prometheus_deltas()
+        .unwrap_or_else(|e| pgx::error!("{}", e));
+    TableIterator::new(deltas.into_iter())
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_histogram_counter_rate<'a>(
+    summary: HistogramCounterSummary<'a>,
+    _accessor: AccessorHistogramRate<'a>,
+) -> TableIterator<'static, (name!(le, f64), name!(rate, f64))> {
+    histogram_counter_rate(summary)
+}
+
+#[pg_extern(
+    name = "histogram_rate",
+    strict,
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+fn histogram_counter_rate<'a>(
+    summary: HistogramCounterSummary<'a>,
+) -> TableIterator<'static, (name!(le, f64), name!(rate, f64))> {
+    let rates = summary
+        .to_internal_histogram_summary()
+        .prometheus_rates()
+        .unwrap_or_else(|e| pgx::error!("{}", e));
+    TableIterator::new(rates.into_iter())
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use approx::assert_relative_eq;
+    use pgx::*;
+    use pgx_macros::pg_test;
+
+    // three scrapes of a histogram with buckets 0.1, 0.5, 1 and +Inf, the 0.5 bucket resets in the middle
+    fn make_test_table(client: &mut pgx::spi::SpiClient) {
+        client.update("SET timezone TO 'UTC'", None, None).unwrap();
+        client
+            .update(
+                "CREATE TABLE hist(ts timestamptz, le DOUBLE PRECISION, val DOUBLE PRECISION)",
+                None,
+                None,
+            )
+            .unwrap();
+        client
+            .update(
+                "INSERT INTO hist VALUES \
+                    ('2020-01-01 00:00:00+00', 0.1, 1), ('2020-01-01 00:00:00+00', 0.5, 5), \
+                    ('2020-01-01 00:00:00+00', 1, 8), ('2020-01-01 00:00:00+00', '+Inf', 10), \
+                    ('2020-01-01 00:00:10+00', 0.1, 3), ('2020-01-01 00:00:10+00', 0.5, 2), \
+                    ('2020-01-01 00:00:10+00', 1, 20), ('2020-01-01 00:00:10+00', '+Inf', 25), \
+                    ('2020-01-01 00:00:20+00', 0.1, 5), ('2020-01-01 00:00:20+00', 0.5, 9), \
+                    ('2020-01-01 00:00:20+00', 1, 28), ('2020-01-01 00:00:20+00', '+Inf', 40)",
+                None,
+                None,
+            )
+            .unwrap();
+    }
+
+    #[pg_test]
+    fn histogram_quantile_unbounded() {
+        Spi::connect(|mut client| {
+            make_test_table(&mut client);
+            // increases: 4 below 0.1, 9 below 0.5, 20 below 1, 30 total
+            let (median, arrow) = client
+                .update(
+                    "SELECT toolkit_experimental.histogram_quantile(0.5, h), \
+                        h -> toolkit_experimental.histogram_quantile(0.5) \
+                    FROM (SELECT toolkit_experimental.histogram_counter_agg(ts, le, val) h FROM hist) q",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<f64, f64>()
+                .unwrap();
+            assert_relative_eq!(median.unwrap(), 0.5 + 0.5 * 6.0 / 11.0);
+            assert_eq!(median, arrow);
+
+            let p99 = client
+                .update(
+                    "SELECT toolkit_experimental.histogram_quantile(0.99, \
+                        toolkit_experimental.histogram_counter_agg(ts, le, val)) FROM hist",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<f64>()
+                .unwrap();
+            assert_eq!(p99, Some(1.0));
+
+            // without a +Inf bucket there's nothing to compute
+            let missing = client
+                .update(
+                    "SELECT toolkit_experimental.histogram_quantile(0.5, \
+                        toolkit_experimental.histogram_counter_agg(ts, le, val)) \
+                    FROM hist WHERE le < '+Inf'",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<f64>()
+                .unwrap();
+            assert_eq!(missing, None);
+        });
+    }
+
+    #[pg_test]
+    fn histogram_rate_and_delta() {
+        Spi::connect(|mut client| {
+            make_test_table(&mut client);
+            let agg = "(SELECT toolkit_experimental.histogram_counter_agg(ts, le, val, \
+                '[2020-01-01 00:00:00+00, 2020-01-01 00:00:20.001+00)') h FROM hist) q";
+
+            let mut deltas = client
+                .update(
+                    &format!(
+                        "SELECT le, delta FROM {}, toolkit_experimental.histogram_delta(h)",
+                        agg
+                    ),
+                    None,
+                    None,
+                )
+                .unwrap();
+            for expected in [(0.1, 4.0), (0.5, 9.0), (1.0, 20.0), (f64::INFINITY, 30.0)] {
+                let (le, delta) = deltas.next().unwrap().get_two::<f64, f64>().unwrap();
+                assert_eq!(le.unwrap(), expected.0);
+                assert_relative_eq!(delta.unwrap(), expected.1);
+            }
+            assert!(deltas.next().is_none());
+
+            let mut rates = client
+                .update(
+                    &format!(
+                        "SELECT (h -> toolkit_experimental.histogram_rate()).* FROM {}",
+                        agg
+                    ),
+                    None,
+                    None,
+                )
+                .unwrap();
+            for expected in [(0.1, 0.2), (0.5, 0.45), (1.0, 1.0), (f64::INFINITY, 1.5)] {
+                let (le, rate) = rates.next().unwrap().get_two::<f64, f64>().unwrap();
+                assert_eq!(le.unwrap(), expected.0);
+                assert_relative_eq!(rate.unwrap(), expected.1);
+            }
+            assert!(rates.next().is_none());
+        });
+    }
+
+    #[pg_test(error = "cannot calculate delta without valid bounds")]
+    fn histogram_rate_requires_bounds() {
+        Spi::connect(|mut client| {
+            make_test_table(&mut client);
+            client
+                .update(
+                    "SELECT toolkit_experimental.histogram_rate(\
+                        toolkit_experimental.histogram_counter_agg(ts, le, val)) FROM hist",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test]
+    fn histogram_rollup() {
+        Spi::connect(|mut client| {
+            make_test_table(&mut client);
+            let median = client
+                .update(
+                    "SELECT toolkit_experimental.histogram_quantile(0.5, toolkit_experimental.rollup(h)) \
+                    FROM (SELECT toolkit_experimental.histogram_counter_agg(ts, le, val) h \
+                        FROM hist GROUP BY ts < '2020-01-01 00:00:15+00') q",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<f64>()
+                .unwrap();
+            assert_relative_eq!(median.unwrap(), 0.5 + 0.5 * 6.0 / 11.0);
+        });
+    }
+}
@@ -0,0 +1,710 @@
+use pgx::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aggregate_utils::in_aggregate_context,
+    flatten,
+    palloc::{Inner, Internal, InternalAsValue, ToInternal},
+    pg_type,
+    range::*,
+    raw::{bytea, tstzrange},
+    ron_inout_funcs,
+};
+
+use tspoint::TSPoint;
+
+use counter_agg::{
+    model::{CounterModel, ModeledCounterSummary},
+    range::I64Range,
+    MetricSummary,
+};
+
+use super::{CounterSummary, PgTypeHackStatsSummary2D};
+
+use toolkit_experimental::CounterModelSummary;
+
+#[pg_schema]
+pub mod toolkit_experimental {
+    use super::*;
+
+    pg_type! {
+        #[derive(Debug)]
+        struct CounterModelSummary {
+            stats: PgTypeHackStatsSummary2D,
+            first: TSPoint,
+            second: TSPoint,
+            penultimate: TSPoint,
+            last: TSPoint,
+            reset_sum: f64,
+            num_resets: u64,
+            num_changes: u64,
+            num_wraps: u64,
+            num_ignored: u64,
+            reset_threshold: f64,
+            // i64::MIN when unknown
+            first_created: i64,
+            last_created: i64,
+            // 0 when the counter doesn't wrap
+            wrap_bits: u8,
+            internal_padding: [u8; 7],
+            #[flat_serialize::flatten]
+            bounds: I64RangeWrapper,
+        }
+    }
+
+    ron_inout_funcs!(CounterModelSummary);
+}
+
+fn created_to_internal(created: i64) -> Option<i64> {
+    (created != i64::MIN).then_some(created)
+}
+
+impl CounterModelSummary {
+    pub fn to_internal_modeled_summary(&self) -> ModeledCounterSummary {
+        ModeledCounterSummary {
+            summary: MetricSummary {
+                first: self.first,
+                second: self.second,
+                penultimate: self.penultimate,
+                last: self.last,
+                reset_sum: self.reset_sum,
+                num_resets: self.num_resets,
+                num_changes: self.num_changes,
+                stats: self.stats,
+                bounds: self.bounds.to_i64range(),
+            },
+            model: CounterModel {
+                wrap_bits: (self.wrap_bits != 0).then_some(self.wrap_bits),
+                reset_threshold: self.reset_threshold,
+            },
+            num_wraps: self.num_wraps,
+            num_ignored: self.num_ignored,
+            first_created: created_to_internal(self.first_created),
+            last_created: created_to_internal(self.last_created),
+        }
+    }
+
+    pub fn from_internal_modeled_summary(st: ModeledCounterSummary) -> Self {
+        unsafe {
+            flatten!(CounterModelSummary {
+                stats: st.summary.stats,
+                first: st.summary.first,
+                second: st.summary.second,
+                penultimate: st.summary.penultimate,
+                last: st.summary.last,
+                reset_sum: st.summary.reset_sum,
+                num_resets: st.summary.num_resets,
+                num_changes: st.summary.num_changes,
+                num_wraps: st.num_wraps,
+                num_ignored: st.num_ignored,
+                reset_threshold: st.model.reset_threshold,
+                first_created: st.first_created.unwrap_or(i64::MIN),
+                last_created: st.last_created.unwrap_or(i64::MIN),
+                wrap_bits: st.model.wrap_bits.unwrap_or(0),
+                internal_padding: [0; 7],
+                bounds: I64RangeWrapper::from_i64range(st.summary.bounds)
+            })
+        }
+    }
+}
+
+// Like CounterSummaryTransState, points are buffered until the summaries need to be built,
+// so they can arrive in any order.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CounterModelTransState {
+    #[serde(skip)]
+    point_buffer: Vec<(TSPoint, Option<i64>)>,
+    #[serde(skip)]
+    bounds: Option<I64Range>,
+    model: CounterModel,
+    summary_buffer: Vec<ModeledCounterSummary>,
+}
+
+impl CounterModelTransState {
+    fn new(model: CounterModel) -> Self {
+        Self {
+            point_buffer: vec![],
+            bounds: None,
+            model,
+            summary_buffer: vec![],
+        }
+    }
+
+    fn combine_points(&mut self) {
+        if self.point_buffer.is_empty() {
+            return;
+        }
+        self.point_buffer.sort_unstable_by_key(|(p, _)| p.ts);
+        let mut iter = self.point_buffer.iter();
+        let (first, created) = iter.next().unwrap();
+        let mut summary = ModeledCounterSummary::new(first, *created, self.model, self.bounds);
+        for (p, created) in iter {
+            summary
+                .add_point(p, *created)
+                .unwrap_or_else(|e| pgx::error!("{}", e));
+        }
+        self.point_buffer.clear();
+        if !summary.summary.bounds_valid() {
+            panic!("counter bounds invalid")
+        }
+        self.summary_buffer.push(summary);
+    }
+
+    fn push_summary(&mut self, other: &CounterModelTransState) {
+        self.summary_buffer
+            .extend(other.summary_buffer.iter().cloned());
+    }
+
+    fn combine_summaries(&mut self) {
+        self.combine_points();
+
+        if self.summary_buffer.len() <= 1 {
+            return;
+        }
+        self.summary_buffer
+            .sort_unstable_by_key(|s| s.summary.first.ts);
+        let mut sum_iter = self.summary_buffer.iter();
+        let mut new_summary = sum_iter.next().unwrap().clone();
+        for sum in sum_iter {
+            new_summary
+                .combine(sum)
+                .unwrap_or_else(|e| pgx::error!("{}", e));
+        }
+        self.summary_buffer = vec![new_summary];
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, strict, schema = "toolkit_experimental")]
+pub fn counter_model_trans_serialize(state: Internal) -> bytea {
+    let state: &mut CounterModelTransState = unsafe { state.get_mut().unwrap() };
+    state.combine_summaries();
+    crate::do_serialize!(state)
+}
+
+#[pg_extern(strict, immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn counter_model_trans_deserialize(bytes: bytea, _internal: Internal) -> Option<Internal> {
+    counter_model_trans_deserialize_inner(bytes).internal()
+}
+pub fn counter_model_trans_deserialize_inner(bytes: bytea) -> Inner<CounterModelTransState> {
+    let c: CounterModelTransState = crate::do_deserialize!(bytes, CounterModelTransState);
+    c.into()
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn counter_model_agg_trans(
+    state: Internal,
+    ts: Option<crate::raw::TimestampTz>,
+    val: Option<f64>,
+    bounds: Option<tstzrange>,
+    wrap_bits: Option<i32>,
+    reset_threshold: Option<f64>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    counter_model_agg_trans_inner(
+        unsafe { state.to_inner() },
+        ts,
+        val,
+        None,
+        bounds,
+        wrap_bits,
+        reset_threshold,
+        fcinfo,
+    )
+    .internal()
+}
+
+#[allow(clippy::too_many_arguments)]
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn counter_model_agg_created_trans(
+    state: Internal,
+    ts: Option<crate::raw::TimestampTz>,
+    val: Option<f64>,
+    created: Option<crate::raw::TimestampTz>,
+    bounds: Option<tstzrange>,
+    wrap_bits: Option<i32>,
+    reset_threshold: Option<f64>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    counter_model_agg_trans_inner(
+        unsafe { state.to_inner() },
+        ts,
+        val,
+        created,
+        bounds,
+        wrap_bits,
+        reset_threshold,
+        fcinfo,
+    )
+    .internal()
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn counter_model_agg_trans_inner(
+    state: Option<Inner<CounterModelTransState>>,
+    ts: Option<crate::raw::TimestampTz>,
+    val: Option<f64>,
+    created: Option<crate::raw::TimestampTz>,
+    bounds: Option<tstzrange>,
+    wrap_bits: Option<i32>,
+    reset_threshold: Option<f64>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<CounterModelTransState>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let p = match (ts, val) {
+                (Some(ts), Some(val)) => TSPoint { ts: ts.into(), val },
+                _ => return state,
+            };
+            let created = created.map(i64::from);
+            match state {
+                None => {
+                    // out of range widths are rejected by the model as well
+                    let wrap_bits = wrap_bits.map(|bits| u8::try_from(bits).unwrap_or(0));
+                    let model = CounterModel::new(wrap_bits, reset_threshold.unwrap_or(0.0))
+                        .unwrap_or_else(|e| pgx::error!("{}", e));
+                    let mut s = CounterModelTransState::new(model);
+                    if let Some(r) = bounds {
+                        s.bounds = get_range(r.0.cast_mut_ptr());
+                    }
+                    s.point_buffer.push((p, created));
+                    Some(s.into())
+                }
+                Some(mut s) => {
+                    s.point_buffer.push((p, created));
+                    Some(s)
+                }
+            }
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn counter_model_agg_summary_trans(
+    state: Internal,
+    value: Option<CounterModelSummary>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    counter_model_agg_summary_trans_inner(unsafe { state.to_inner() }, value, fcinfo).internal()
+}
+pub fn counter_model_agg_summary_trans_inner(
+    state: Option<Inner<CounterModelTransState>>,
+    value: Option<CounterModelSummary>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<CounterModelTransState>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || match (state, value) {
+            (state, None) => state,
+            (None, Some(value)) => {
+                let value = value.to_internal_modeled_summary();
+                let mut state = CounterModelTransState::new(value.model);
+                state.summary_buffer.push(value);
+                Some(state.into())
+            }
+            (Some(mut state), Some(value)) => {
+                state
+                    .summary_buffer
+                    .push(value.to_internal_modeled_summary());
+                Some(state)
+            }
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn counter_model_agg_combine(
+    state1: Internal,
+    state2: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    unsafe {
+        counter_model_agg_combine_inner(state1.to_inner(), state2.to_inner(), fcinfo).internal()
+    }
+}
+pub fn counter_model_agg_combine_inner(
+    state1: Option<Inner<CounterModelTransState>>,
+    state2: Option<Inner<CounterModelTransState>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<CounterModelTransState>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || match (state1, state2) {
+            (None, None) => None,
+            (None, Some(state2)) => {
+                let mut s = state2.clone();
+                s.combine_points();
+                Some(s.into())
+            }
+            (Some(state1), None) => {
+                let mut s = state1.clone();
+                s.combine_points();
+                Some(s.into())
+            }
+            (Some(state1), Some(state2)) => {
+                let mut s1 = state1.clone();
+                s1.combine_points();
+                let mut s2 = state2.clone();
+                s2.combine_points();
+                s2.push_summary(&s1);
+                Some(s2.into())
+            }
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+fn counter_model_agg_final(
+    state: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<CounterModelSummary> {
+    counter_model_agg_final_inner(unsafe { state.to_inner() }, fcinfo)
+}
+fn counter_model_agg_final_inner(
+    state: Option<Inner<CounterModelTransState>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<CounterModelSummary> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let mut state = match state {
+                None => return None,
+                Some(state) => state.clone(),
+            };
+            state.combine_summaries();
+            debug_assert!(state.summary_buffer.len() <= 1);
+            match state.summary_buffer.pop() {
+                None => None,
+                Some(st) => {
+                    if !st.summary.bounds_valid() {
+                        panic!("counter bounds invalid")
+                    }
+                    Some(CounterModelSummary::from_internal_modeled_summary(st))
+                }
+            }
+        })
+    }
+}
+
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.counter_agg(\n\
+        ts timestamptz, value DOUBLE PRECISION, bounds tstzrange, wrap_bits INTEGER, reset_threshold DOUBLE PRECISION\n\
+    ) (\n\
+        sfunc = toolkit_experimental.counter_model_agg_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.counter_model_agg_final,\n\
+        combinefunc = toolkit_experimental.counter_model_agg_combine,\n\
+        serialfunc = toolkit_experimental.counter_model_trans_serialize,\n\
+        deserialfunc = toolkit_experimental.counter_model_trans_deserialize,\n\
+        parallel = restricted\n\
+    );\n",
+    name = "counter_model_agg",
+    requires = [
+        counter_model_agg_trans,
+        counter_model_agg_final,
+        counter_model_agg_combine,
+        counter_model_trans_serialize,
+        counter_model_trans_deserialize
+    ],
+);
+
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.counter_agg(\n\
+        ts timestamptz, value DOUBLE PRECISION, created timestamptz, bounds tstzrange, \
+        wrap_bits INTEGER, reset_threshold DOUBLE PRECISION\n\
+    ) (\n\
+        sfunc = toolkit_experimental.counter_model_agg_created_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.counter_model_agg_final,\n\
+        combinefunc = toolkit_experimental.counter_model_agg_combine,\n\
+        serialfunc = toolkit_experimental.counter_model_trans_serialize,\n\
+        deserialfunc = toolkit_experimental.counter_model_trans_deserialize,\n\
+        parallel = restricted\n\
+    );\n",
+    name = "counter_model_agg_created",
+    requires = [
+        counter_model_agg_created_trans,
+        counter_model_agg_final,
+        counter_model_agg_combine,
+        counter_model_trans_serialize,
+        counter_model_trans_deserialize
+    ],
+);
+
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.rollup(cs toolkit_experimental.CounterModelSummary)\n\
+    (\n\
+        sfunc = toolkit_experimental.counter_model_agg_summary_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.counter_model_agg_final,\n\
+        combinefunc = toolkit_experimental.counter_model_agg_combine,\n\
+        serialfunc = toolkit_experimental.counter_model_trans_serialize,\n\
+        deserialfunc = toolkit_experimental.counter_model_trans_deserialize,\n\
+        parallel = restricted\n\
+    );\n",
+    name = "counter_model_rollup",
+    requires = [
+        counter_model_agg_summary_trans,
+        counter_model_agg_final,
+        counter_model_agg_combine,
+        counter_model_trans_serialize,
+        counter_model_trans_deserialize
+    ],
+);
+
+// A plain counter summary takes every decrease for a reset: its instantaneous
+// accessors, such as `irate_right`, and its `rollup` would get any wraps or
+// ignored decreases wrong, so those summaries aren't converted.
+#[pg_extern(
+    name = "counter_agg",
+    strict,
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+fn counter_model_counter_agg(summary: CounterModelSummary) -> CounterSummary<'static> {
+    if summary.num_wraps > 0 || summary.num_ignored > 0 {
+        pgx::error!(
+            "counter_agg can't represent a counter model summary with wraps or ignored decreases"
+        )
+    }
+    CounterSummary::from_internal_counter_summary(summary.to_internal_modeled_summary().summary)
+}
+
+#[pg_extern(
+    name = "delta",
+    strict,
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+fn counter_model_delta(summary: CounterModelSummary) -> f64 {
+    summary.to_internal_modeled_summary().summary.delta()
+}
+
+#[pg_extern(
+    name = "rate",
+    strict,
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+fn counter_model_rate(summary: CounterModelSummary) -> Option<f64> {
+    summary.to_internal_modeled_summary().summary.rate()
+}
+
+#[pg_extern(
+    name = "num_resets",
+    strict,
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+fn counter_model_num_resets(summary: CounterModelSummary) -> i64 {
+    summary.num_resets as i64
+}
+
+#[pg_extern(
+    name = "num_wraps",
+    strict,
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+fn counter_model_num_wraps(summary: CounterModelSummary) -> i64 {
+    summary.num_wraps as i64
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use pgx::*;
+    use pgx_macros::pg_test;
+
+    // an SNMP-style 32 bit counter that wraps once, then is restarted, which
+    // shows in its created timestamp
+    fn make_test_table(client: &mut pgx::spi::SpiClient) {
+        client.update("SET timezone TO 'UTC'", None, None).unwrap();
+        client
+            .update(
+                "CREATE TABLE octets(ts timestamptz, val DOUBLE PRECISION, created timestamptz)",
+                None,
+                None,
+            )
+            .unwrap();
+        client
+            .update(
+                "INSERT INTO octets VALUES \
+                    ('2020-01-01 00:00:00+00', 4294967196, '2019-12-01 00:00:00+00'), \
+                    ('2020-01-01 00:01:00+00', 4294967286, '2019-12-01 00:00:00+00'), \
+                    ('2020-01-01 00:02:00+00', 40, '2019-12-01 00:00:00+00'), \
+                    ('2020-01-01 00:03:00+00', 39.5, '2019-12-01 00:00:00+00'), \
+                    ('2020-01-01 00:04:00+00', 100, '2020-01-01 00:03:30+00'), \
+                    ('2020-01-01 00:05:00+00', 160, '2020-01-01 00:03:30+00')",
+                None,
+                None,
+            )
+            .unwrap();
+    }
+
+    #[pg_test]
+    fn counter_model_wraps() {
+        Spi::connect(|mut client| {
+            make_test_table(&mut client);
+            // the wrap at 00:02 isn't a reset, and the jitter at 00:03 is
+            // ignored, but the restart at 00:04 goes unnoticed
+            let (delta, resets, wraps) = client
+                .update(
+                    "SELECT toolkit_experimental.delta(c), toolkit_experimental.num_resets(c), \
+                        toolkit_experimental.num_wraps(c) \
+                    FROM (SELECT toolkit_experimental.counter_agg(ts, val, NULL, 32, 1) c FROM octets) q",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_three::<f64, i64, i64>()
+                .unwrap();
+            assert_eq!(delta, Some(90.0 + 50.0 - 0.5 + 60.5 + 60.0));
+            assert_eq!(resets, Some(0));
+            assert_eq!(wraps, Some(1));
+
+            // the plain counter_agg takes both decreases for resets
+            let resets = client
+                .update(
+                    "SELECT num_resets(counter_agg(ts, val)) FROM octets",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<i64>()
+                .unwrap();
+            assert_eq!(resets, Some(2));
+        });
+    }
+
+    #[pg_test]
+    fn counter_model_created() {
+        Spi::connect(|mut client| {
+            make_test_table(&mut client);
+            // the restart at 00:04 is a reset, from which the counter counted up to 100
+            let (delta, resets, wraps) = client
+                .update(
+                    "SELECT toolkit_experimental.delta(c), toolkit_experimental.num_resets(c), \
+                        toolkit_experimental.num_wraps(c) \
+                    FROM (\
+                        SELECT toolkit_experimental.counter_agg(ts, val, created, NULL, 32, 1) c \
+                        FROM octets\
+                    ) q",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_three::<f64, i64, i64>()
+                .unwrap();
+            assert_eq!(delta, Some(90.0 + 50.0 - 0.5 + 100.0 + 60.0));
+            assert_eq!(resets, Some(1));
+            assert_eq!(wraps, Some(1));
+
+            // the same across a rollup
+            let (delta, rate) = client
+                .update(
+                    "SELECT toolkit_experimental.delta(c), toolkit_experimental.rate(c) \
+                    FROM (\
+                        SELECT toolkit_experimental.rollup(c ORDER BY random()) c \
+                        FROM (\
+                            SELECT toolkit_experimental.counter_agg(ts, val, created, NULL, 32, 1) c \
+                            FROM octets GROUP BY date_trunc('minute', ts)\
+                        ) q\
+                    ) q",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<f64, f64>()
+                .unwrap();
+            assert_eq!(delta, Some(299.5));
+            assert_eq!(rate, Some(299.5 / 300.0));
+        });
+    }
+
+    #[pg_test]
+    fn counter_model_to_counter_agg() {
+        Spi::connect(|mut client| {
+            make_test_table(&mut client);
+            // only resets, which a plain summary agrees with
+            let (idelta, resets) = client
+                .update(
+                    "SELECT idelta_right(toolkit_experimental.counter_agg(c)), \
+                        num_resets(toolkit_experimental.counter_agg(c)) \
+                    FROM (\
+                        SELECT toolkit_experimental.counter_agg(ts, val, created, NULL, 32, 1) c \
+                        FROM octets WHERE ts >= '2020-01-01 00:03:00+00'\
+                    ) q",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<f64, i64>()
+                .unwrap();
+            assert_eq!(idelta, Some(60.0));
+            assert_eq!(resets, Some(1));
+        });
+    }
+
+    #[pg_test(
+        error = "counter_agg can't represent a counter model summary with wraps or ignored decreases"
+    )]
+    fn counter_model_wraps_to_counter_agg() {
+        Spi::connect(|mut client| {
+            make_test_table(&mut client);
+            client
+                .update(
+                    "SELECT toolkit_experimental.counter_agg(\
+                        toolkit_experimental.counter_agg(ts, val, NULL, 32, 1)\
+                    ) FROM octets",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test(
+        error = "counter_agg can't represent a counter model summary with wraps or ignored decreases"
+    )]
+    fn counter_model_jitter_to_counter_agg() {
+        Spi::connect(|mut client| {
+            make_test_table(&mut client);
+            // 40 to 39.5 is below the reset threshold
+            client
+                .update(
+                    "SELECT toolkit_experimental.counter_agg(\
+                        toolkit_experimental.counter_agg(ts, val, NULL, NULL, 1)\
+                    ) FROM octets WHERE ts BETWEEN '2020-01-01 00:02:00+00' AND '2020-01-01 00:03:00+00'",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test(
+        error = "invalid counter model: wrap_bits must be 32 or 64 and reset_threshold must not be negative"
+    )]
+    fn counter_model_invalid() {
+        Spi::connect(|mut client| {
+            make_test_table(&mut client);
+            client
+                .update(
+                    "SELECT toolkit_experimental.counter_agg(ts, val, NULL, 16, NULL) FROM octets",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+}
@@ -1,10 +1,15 @@
-use pgx::*;
+use pgx::{iter::TableIterator, *};
+use serde::{Deserialize, Serialize};
 
 use aggregate_builder::aggregate;
-use countminsketch::{CountMinHashFn, CountMinSketch as CountMinSketchInternal};
+use countminsketch::{
+    topn::TopNCountMinSketch as TopNCountMinSketchInternal, CountMinHashFn, CountMinMode,
+    CountMinSketch as CountMinSketchInternal,
+};
 
 use crate::{
-    flatten,
+    accessors::AccessorTopn,
+    build, flatten,
     palloc::{Inner, Internal},
     pg_type,
     raw::bytea,
@@ -15,27 +20,41 @@ use crate::{
 pub mod toolkit_experimental {
     use super::*;
 
+    // Sketches with a version greater than 1 store their mode, older ones
+    // predate the modes and are all standard.
     pg_type! {
         #[derive(Debug)]
         struct CountMinSketch<'input> {
             width: u32,
             depth: u32,
+            mode: [u64; if self.version > 1 { 1 } else { 0 }],
             counters: [i64; self.width * self.depth],
         }
     }
 
     impl CountMinSketch<'_> {
-        fn new(width: u32, depth: u32, counters: Vec<i64>) -> Self {
-            let counters_arr = counters.try_into().unwrap();
+        fn new(width: u32, depth: u32, mode: CountMinMode, counters: Vec<i64>) -> Self {
             unsafe {
-                flatten!(CountMinSketch {
+                CountMinSketchData {
+                    header: 0,
+                    version: 2,
+                    padding: [0; 3],
                     width,
                     depth,
-                    counters: counters_arr,
-                })
+                    mode: vec![mode_to_id(mode)].into(),
+                    counters: counters.into(),
+                }
+                .flatten()
             }
         }
 
+        pub fn mode(&self) -> CountMinMode {
+            self.mode
+                .iter()
+                .next()
+                .map_or(CountMinMode::Standard, mode_from_id)
+        }
+
         pub fn to_internal_countminsketch(&self) -> CountMinSketchInternal {
             let depth: u64 = self.depth.into();
             let hashfuncs = (1..=depth).map(CountMinHashFn::with_key).collect();
@@ -59,21 +78,192 @@ pub mod toolkit_experimental {
                 hashfuncs,
                 counters,
             )
+            .with_mode(self.mode())
         }
 
         pub fn from_internal_countminsketch(sketch: &mut CountMinSketchInternal) -> Self {
             CountMinSketch::new(
                 sketch.width().try_into().unwrap(),
                 sketch.depth().try_into().unwrap(),
+                sketch.mode(),
                 sketch.counters().iter().flatten().cloned().collect(),
             )
         }
     }
 
-    ron_inout_funcs!(CountMinSketch);
+    pg_type! {
+        #[derive(Debug)]
+        struct TopNCountMinSketch<'input> {
+            n: u32,
+            width: u32,
+            depth: u32,
+            num_candidates: u32,
+            total: u64,
+            values_len: u64,
+            counters: [i64; self.width * self.depth],
+            counts: [i64; self.num_candidates],
+            // the candidates are stored back-to-back in `values`, ending at these offsets
+            value_ends: [u64; self.num_candidates],
+            values: [u8; self.values_len],
+        }
+    }
+
+    impl TopNCountMinSketch<'_> {
+        pub fn to_internal_topn(&self) -> TopNCountMinSketchInternal<String> {
+            let sketch = build! {
+                CountMinSketch {
+                    width: self.width,
+                    depth: self.depth,
+                    mode: vec![].into(),
+                    counters: self.counters.clone(),
+                }
+            }
+            .to_internal_countminsketch();
+
+            let values = std::str::from_utf8(self.values.as_slice()).unwrap();
+            let mut start = 0;
+            let candidates = self
+                .value_ends
+                .iter()
+                .zip(self.counts.iter())
+                .map(|(end, count)| {
+                    let value = values[start..end as usize].to_string();
+                    start = end as usize;
+                    (value, count)
+                })
+                .collect();
+
+            TopNCountMinSketchInternal::new(self.n as usize, self.total, sketch, candidates)
+        }
+
+        pub fn from_internal_topn(topn: &TopNCountMinSketchInternal<String>) -> Self {
+            let sketch = topn.sketch();
+            let counters: Vec<i64> = sketch.counters().iter().flatten().cloned().collect();
+            let mut values = String::new();
+            let mut value_ends = vec![];
+            let mut counts = vec![];
+            for (value, count) in topn.candidates() {
+                values.push_str(value);
+                value_ends.push(values.len() as u64);
+                counts.push(*count);
+            }
+            unsafe {
+                flatten!(TopNCountMinSketch {
+                    n: topn.n() as u32,
+                    width: sketch.width() as u32,
+                    depth: sketch.depth() as u32,
+                    num_candidates: counts.len() as u32,
+                    total: topn.total(),
+                    values_len: values.len() as u64,
+                    counters: (&*counters).into(),
+                    counts: (&*counts).into(),
+                    value_ends: (&*value_ends).into(),
+                    values: values.as_bytes().into(),
+                })
+            }
+        }
+    }
+
+    ron_inout_funcs!(TopNCountMinSketch);
+}
+
+use toolkit_experimental::{CountMinSketch, TopNCountMinSketch};
+
+// The text format names the mode, sketches without one are standard.
+#[derive(Serialize, Deserialize)]
+struct CountMinSketchText {
+    version: u8,
+    width: u32,
+    depth: u32,
+    #[serde(default = "standard_mode")]
+    mode: CountMinMode,
+    counters: Vec<i64>,
+}
+
+fn standard_mode() -> CountMinMode {
+    CountMinMode::Standard
+}
+
+impl<'input> InOutFuncs for CountMinSketch<'input> {
+    fn output(&self, buffer: &mut StringInfo) {
+        use crate::serialization::{str_to_db_encoding, EncodedStr::*};
+
+        let text = CountMinSketchText {
+            version: self.version,
+            width: self.width,
+            depth: self.depth,
+            mode: self.mode(),
+            counters: self.counters.iter().collect(),
+        };
+        let stringified = ron::to_string(&text).unwrap();
+        match str_to_db_encoding(&stringified) {
+            Utf8(s) => buffer.push_str(s),
+            Other(s) => buffer.push_bytes(s.to_bytes()),
+        }
+    }
+
+    fn input(input: &std::ffi::CStr) -> CountMinSketch<'input>
+    where
+        Self: Sized,
+    {
+        use crate::serialization::str_from_db_encoding;
+
+        let input = str_from_db_encoding(input);
+        let text: CountMinSketchText = ron::from_str(input).unwrap();
+        CountMinSketch::new(text.width, text.depth, text.mode, text.counters)
+    }
 }
 
-use toolkit_experimental::CountMinSketch;
+// the mode is stored in the flattened sketch as a plain integer
+fn mode_to_id(mode: CountMinMode) -> u64 {
+    match mode {
+        CountMinMode::Standard => 0,
+        CountMinMode::Conservative => 1,
+        CountMinMode::CountMeanMin => 2,
+    }
+}
+
+fn mode_from_id(id: u64) -> CountMinMode {
+    match id {
+        0 => CountMinMode::Standard,
+        1 => CountMinMode::Conservative,
+        2 => CountMinMode::CountMeanMin,
+        _ => pgx::error!("invalid count-min sketch mode {}", id),
+    }
+}
+
+fn mode_from_name(name: &str) -> CountMinMode {
+    match name.to_lowercase().as_str() {
+        "standard" => CountMinMode::Standard,
+        "conservative" => CountMinMode::Conservative,
+        "count_mean_min" => CountMinMode::CountMeanMin,
+        _ => pgx::error!(
+            "unknown count-min sketch mode '{}', expected 'standard', 'conservative' or 'count_mean_min'",
+            name
+        ),
+    }
+}
+
+fn count_min_sketch_trans_inner(
+    state: Option<CountMinSketchInternal>,
+    value: Option<String>,
+    error: f64,
+    probability: f64,
+    mode: CountMinMode,
+) -> Option<CountMinSketchInternal> {
+    let value = match value {
+        None => return state,
+        Some(value) => value,
+    };
+
+    let mut state = match state {
+        None => CountMinSketchInternal::with_prob(error, probability).with_mode(mode),
+        Some(state) => state,
+    };
+
+    state.add_value(value);
+    Some(state)
+}
 
 #[aggregate]
 impl toolkit_experimental::count_min_sketch {
@@ -84,6 +274,117 @@ impl toolkit_experimental::count_min_sketch {
         #[sql_type("text")] value: Option<String>,
         #[sql_type("float")] error: f64,
         #[sql_type("float")] probability: f64,
+    ) -> Option<State> {
+        count_min_sketch_trans_inner(state, value, error, probability, CountMinMode::Standard)
+    }
+
+    fn finally(state: Option<&mut State>) -> Option<CountMinSketch<'static>> {
+        state.map(CountMinSketch::from_internal_countminsketch)
+    }
+
+    const PARALLEL_SAFE: bool = true;
+
+    fn serialize(state: &mut State) -> bytea {
+        crate::do_serialize!(state)
+    }
+
+    fn deserialize(bytes: bytea) -> State {
+        crate::do_deserialize!(bytes, State)
+    }
+
+    fn combine(state1: Option<&State>, state2: Option<&State>) -> Option<State> {
+        match (state1, state2) {
+            (None, None) => None,
+            (None, Some(only)) | (Some(only), None) => Some(only.clone()),
+            (Some(a), Some(b)) => {
+                let (mut a, b) = (a.clone(), b.clone());
+                a.combine(b);
+                Some(a)
+            }
+        }
+    }
+}
+
+extension_sql!(
+    "CREATE AGGREGATE toolkit_experimental.count_min_sketch(
+        value text,
+        error float,
+        probability float,
+        mode text
+    ) (
+        stype = internal,
+        sfunc = toolkit_experimental.count_min_sketch_mode_trans,
+        finalfunc = toolkit_experimental.count_min_sketch_finally_fn_outer,
+        parallel = safe,
+        serialfunc = toolkit_experimental.count_min_sketch_serialize_fn_outer,
+        deserialfunc = toolkit_experimental.count_min_sketch_deserialize_fn_outer,
+        combinefunc = toolkit_experimental.count_min_sketch_combine_fn_outer
+    );",
+    name = "count_min_sketch_with_mode",
+    requires = [
+        count_min_sketch_mode_trans,
+        count_min_sketch_finally_fn_outer,
+        count_min_sketch_serialize_fn_outer,
+        count_min_sketch_deserialize_fn_outer,
+        count_min_sketch_combine_fn_outer
+    ],
+);
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+fn count_min_sketch_mode_trans(
+    __inner: pgx::Internal,
+    value: Option<String>,
+    error: f64,
+    probability: f64,
+    mode: String,
+    __fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<pgx::Internal> {
+    // expanded from #[aggregate] transition function
+    use crate::palloc::{Inner, InternalAsValue, ToInternal};
+    type State = CountMinSketchInternal;
+    unsafe {
+        let mut __inner: Option<Inner<Option<State>>> = __inner.to_inner();
+        let inner: Option<State> = match &mut __inner {
+            None => None,
+            Some(inner) => Option::take(&mut **inner),
+        };
+        let state: Option<State> = inner;
+        crate::aggregate_utils::in_aggregate_context(__fcinfo, || {
+            let result = count_min_sketch_trans_inner(
+                state,
+                value,
+                error,
+                probability,
+                mode_from_name(&mode),
+            );
+            let state: Option<State> = result;
+            __inner = match (__inner, state) {
+                (None, None) => None,
+                (None, state @ Some(..)) => Some(state.into()),
+                (Some(mut inner), state) => {
+                    *inner = state;
+                    Some(inner)
+                }
+            };
+            __inner.internal()
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn approx_count<'a>(item: String, aggregate: Option<CountMinSketch<'a>>) -> Option<i64> {
+    aggregate.map(|sketch| CountMinSketch::to_internal_countminsketch(&sketch).estimate(item))
+}
+
+#[aggregate]
+impl toolkit_experimental::topn_count_min_sketch {
+    type State = TopNCountMinSketchInternal<String>;
+
+    fn transition(
+        state: Option<State>,
+        #[sql_type("text")] value: Option<String>,
+        #[sql_type("integer")] n: i32,
+        #[sql_type("float")] error: f64,
+        #[sql_type("float")] probability: f64,
     ) -> Option<State> {
         let value = match value {
             None => return state,
@@ -91,7 +392,12 @@ impl toolkit_experimental::count_min_sketch {
         };
 
         let mut state = match state {
-            None => CountMinSketchInternal::with_prob(error, probability),
+            None => {
+                if n <= 0 {
+                    pgx::error!("topn_count_min_sketch requires a positive N, got {}", n)
+                }
+                TopNCountMinSketchInternal::with_prob(n as usize, error, probability)
+            }
             Some(state) => state,
         };
 
@@ -99,8 +405,8 @@ impl toolkit_experimental::count_min_sketch {
         Some(state)
     }
 
-    fn finally(state: Option<&mut State>) -> Option<CountMinSketch<'static>> {
-        state.map(CountMinSketch::from_internal_countminsketch)
+    fn finally(state: Option<&mut State>) -> Option<TopNCountMinSketch<'static>> {
+        state.map(|s| TopNCountMinSketch::from_internal_topn(s))
     }
 
     const PARALLEL_SAFE: bool = true;
@@ -118,7 +424,7 @@ impl toolkit_experimental::count_min_sketch {
             (None, None) => None,
             (None, Some(only)) | (Some(only), None) => Some(only.clone()),
             (Some(a), Some(b)) => {
-                let (mut a, b) = (a.clone(), b.clone());
+                let mut a = a.clone();
                 a.combine(b);
                 Some(a)
             }
@@ -126,9 +432,118 @@ impl toolkit_experimental::count_min_sketch {
     }
 }
 
+extension_sql!(
+    "CREATE AGGREGATE toolkit_experimental.rollup(
+        sketch toolkit_experimental.TopNCountMinSketch
+    ) (
+        stype = internal,
+        sfunc = toolkit_experimental.topn_count_min_sketch_rollup_trans,
+        finalfunc = toolkit_experimental.topn_count_min_sketch_finally_fn_outer,
+        parallel = safe,
+        serialfunc = toolkit_experimental.topn_count_min_sketch_serialize_fn_outer,
+        deserialfunc = toolkit_experimental.topn_count_min_sketch_deserialize_fn_outer,
+        combinefunc = toolkit_experimental.topn_count_min_sketch_combine_fn_outer
+    );",
+    name = "topn_count_min_sketch_rollup",
+    requires = [
+        topn_count_min_sketch_rollup_trans,
+        topn_count_min_sketch_finally_fn_outer,
+        topn_count_min_sketch_serialize_fn_outer,
+        topn_count_min_sketch_deserialize_fn_outer,
+        topn_count_min_sketch_combine_fn_outer
+    ],
+);
 #[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
-pub fn approx_count<'a>(item: String, aggregate: Option<CountMinSketch<'a>>) -> Option<i64> {
-    aggregate.map(|sketch| CountMinSketch::to_internal_countminsketch(&sketch).estimate(item))
+fn topn_count_min_sketch_rollup_trans<'a>(
+    __inner: pgx::Internal,
+    value: Option<TopNCountMinSketch<'a>>,
+    __fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<pgx::Internal> {
+    // expanded from #[aggregate] transition function
+    use crate::palloc::{Inner, InternalAsValue, ToInternal};
+    type State = TopNCountMinSketchInternal<String>;
+    unsafe {
+        let mut __inner: Option<Inner<Option<State>>> = __inner.to_inner();
+        let inner: Option<State> = match &mut __inner {
+            None => None,
+            Some(inner) => Option::take(&mut **inner),
+        };
+        let state: Option<State> = inner;
+        crate::aggregate_utils::in_aggregate_context(__fcinfo, || {
+            let result = match (state, value) {
+                (state, None) => state,
+                (None, Some(value)) => Some(value.to_internal_topn()),
+                (Some(mut state), Some(value)) => {
+                    state.combine(&value.to_internal_topn());
+                    Some(state)
+                }
+            };
+            let state: Option<State> = result;
+            __inner = match (__inner, state) {
+                (None, None) => None,
+                (None, state @ Some(..)) => Some(state.into()),
+                (Some(mut inner), state) => {
+                    *inner = state;
+                    Some(inner)
+                }
+            };
+            __inner.internal()
+        })
+    }
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    strict,
+    name = "topn",
+    schema = "toolkit_experimental"
+)]
+pub fn topn_count_min_sketch_topn<'a>(
+    agg: TopNCountMinSketch<'a>,
+) -> TableIterator<
+    'static,
+    (
+        name!(value, String),
+        name!(estimated_count, i64),
+        name!(error_bound, i64),
+    ),
+> {
+    let topn = agg.to_internal_topn();
+    let rows: Vec<_> = topn
+        .topn()
+        .map(|(value, count, error_bound)| (value.clone(), count, error_bound))
+        .collect();
+    TableIterator::new(rows.into_iter())
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_topn_count_min_sketch_topn<'a>(
+    agg: TopNCountMinSketch<'a>,
+    _accessor: AccessorTopn<'static>,
+) -> TableIterator<
+    'static,
+    (
+        name!(value, String),
+        name!(estimated_count, i64),
+        name!(error_bound, i64),
+    ),
+> {
+    topn_count_min_sketch_topn(agg)
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "approx_count",
+    schema = "toolkit_experimental"
+)]
+pub fn topn_count_min_sketch_approx_count<'a>(
+    item: String,
+    aggregate: Option<TopNCountMinSketch<'a>>,
+) -> Option<i64> {
+    aggregate.map(|agg| agg.to_internal_topn().sketch().estimate(item))
 }
 
 #[cfg(any(test, feature = "pg_test"))]
@@ -247,9 +662,10 @@ mod tests {
                 .get_one::<String>().unwrap();
 
             let expected = "(\
-                version:1,\
+                version:2,\
                 width:6,\
                 depth:5,\
+                mode:Standard,\
                 counters:[\
                     1,2,2,1,1,1,\
                     0,0,2,3,1,2,\
@@ -263,6 +679,130 @@ mod tests {
         });
     }
 
+    #[pg_test]
+    fn countminsketch_version_1() {
+        // sketches from before the modes were added are standard
+        let old = build! {
+            CountMinSketch {
+                width: 2,
+                depth: 1,
+                mode: vec![].into(),
+                counters: vec![3, 4].into(),
+            }
+        };
+        assert_eq!(old.version, 1);
+        assert_eq!(old.mode(), CountMinMode::Standard);
+        let sketch = old.to_internal_countminsketch();
+        assert_eq!(sketch.mode(), CountMinMode::Standard);
+        assert_eq!(sketch.counters(), &vec![vec![3, 4]]);
+
+        Spi::connect(|mut client| {
+            let text = client
+                .update(
+                    "SELECT '(version:1,width:2,depth:1,counters:[3,4])'\
+                        ::toolkit_experimental.countminsketch::text",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert_eq!(
+                text.as_deref(),
+                Some("(version:2,width:2,depth:1,mode:Standard,counters:[3,4])")
+            );
+        });
+    }
+
+    #[pg_test]
+    fn test_countminsketch_modes() {
+        Spi::connect(|mut client| {
+            // a skewed distribution: value i is seen 200/i times
+            client
+                .update(
+                    "CREATE TABLE skewed AS \
+                    SELECT i::TEXT AS data FROM generate_series(1, 200) i, generate_series(1, 200 / i)",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            let mut errors = vec![];
+            for mode in ["standard", "conservative", "count_mean_min"] {
+                let text = client
+                    .update(
+                        &format!(
+                            "SELECT toolkit_experimental.count_min_sketch(data, 0.05, 0.01, '{}')::TEXT \
+                            FROM skewed",
+                            mode
+                        ),
+                        None,
+                        None,
+                    )
+                    .unwrap()
+                    .first()
+                    .get_one::<String>()
+                    .unwrap()
+                    .unwrap();
+                assert!(text.contains(&format!("mode:{:?},", mode_from_name(mode))));
+
+                let error = client
+                    .update(
+                        &format!(
+                            "SELECT sum(abs(toolkit_experimental.approx_count(i::TEXT, sketch) - 200 / i))::BIGINT \
+                            FROM generate_series(1, 200) i, \
+                                (SELECT toolkit_experimental.count_min_sketch(data, 0.05, 0.01, '{}') sketch \
+                                FROM skewed) s",
+                            mode
+                        ),
+                        None,
+                        None,
+                    )
+                    .unwrap()
+                    .first()
+                    .get_one::<i64>()
+                    .unwrap()
+                    .unwrap();
+                errors.push(error);
+            }
+            // both alternatives should improve on the standard estimates
+            assert!(errors[1] < errors[0], "{:?}", errors);
+            assert!(errors[2] < errors[0], "{:?}", errors);
+
+            // the three argument version is the standard sketch
+            let (default, standard) = client
+                .update(
+                    "SELECT \
+                        toolkit_experimental.count_min_sketch(data, 0.05, 0.01)::TEXT, \
+                        toolkit_experimental.count_min_sketch(data, 0.05, 0.01, 'Standard')::TEXT \
+                    FROM skewed",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<String, String>()
+                .unwrap();
+            assert_eq!(default, standard);
+        });
+    }
+
+    #[pg_test(
+        error = "unknown count-min sketch mode 'fancy', expected 'standard', 'conservative' or 'count_mean_min'"
+    )]
+    fn test_countminsketch_unknown_mode() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.count_min_sketch('a', 0.1, 0.1, 'fancy')",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
     #[pg_test]
     fn test_cms_null_input_yields_null_output() {
         Spi::connect(|mut client| {
@@ -294,4 +834,141 @@ mod tests {
             assert_eq!(output, None)
         })
     }
+
+    #[pg_test]
+    fn test_topn_count_min_sketch() {
+        Spi::connect(|mut client| {
+            client
+                .update("CREATE TABLE heavy (data TEXT)", None, None)
+                .unwrap();
+            // a tail of 1000 values seen once, 'a' seen 100 times, 'b' 50 times and 'c' 25 times
+            client
+                .update(
+                    "INSERT INTO heavy \
+                    SELECT v::TEXT FROM generate_series(1, 1000) v \
+                    UNION ALL SELECT 'a' FROM generate_series(1, 100) \
+                    UNION ALL SELECT 'b' FROM generate_series(1, 50) \
+                    UNION ALL SELECT 'c' FROM generate_series(1, 25)",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            let mut rows = client
+                .update(
+                    "SELECT value, estimated_count, error_bound FROM \
+                    toolkit_experimental.topn(\
+                        (SELECT toolkit_experimental.topn_count_min_sketch(data, 3, 0.01, 0.01) FROM heavy))",
+                    None,
+                    None,
+                )
+                .unwrap();
+            // 0.01 => error param to the sketch, 1175 => number of items added to the sketch
+            let err_margin = (0.01 * 1175.0_f64).ceil() as i64;
+            for (expected_value, expected_count) in [("a", 100), ("b", 50), ("c", 25)] {
+                let (value, count, error_bound) = rows
+                    .next()
+                    .unwrap()
+                    .get_three::<String, i64, i64>()
+                    .unwrap();
+                let (count, error_bound) = (count.unwrap(), error_bound.unwrap());
+                assert_eq!(value.as_deref(), Some(expected_value));
+                assert!(expected_count <= count && count <= expected_count + err_margin);
+                assert!(count - error_bound <= expected_count);
+                assert!(error_bound <= err_margin);
+            }
+            assert!(rows.next().is_none());
+
+            let (count, arrow_count) = client
+                .update(
+                    "SELECT \
+                        (SELECT count(*) FROM toolkit_experimental.topn(sketch)), \
+                        (SELECT count(*) FROM (SELECT (sketch -> topn()).*) t) \
+                    FROM (SELECT toolkit_experimental.topn_count_min_sketch(data, 3, 0.01, 0.01) sketch FROM heavy) s",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<i64, i64>()
+                .unwrap();
+            assert_eq!(count, Some(3));
+            assert_eq!(count, arrow_count);
+
+            let approx_count = client
+                .update(
+                    "SELECT toolkit_experimental.approx_count('a', \
+                        toolkit_experimental.topn_count_min_sketch(data, 3, 0.01, 0.01)) FROM heavy",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<i64>()
+                .unwrap()
+                .unwrap();
+            assert!(100 <= approx_count && approx_count <= 100 + err_margin);
+        });
+    }
+
+    #[pg_test]
+    fn test_topn_count_min_sketch_rollup() {
+        Spi::connect(|mut client| {
+            // 'x' is the second most common value in each half, but the most common overall
+            let mut rows = client
+                .update(
+                    "SELECT value, estimated_count FROM toolkit_experimental.topn(\
+                        (SELECT toolkit_experimental.rollup(sketch) FROM (\
+                            SELECT toolkit_experimental.topn_count_min_sketch(v, 2, 0.01, 0.01) sketch \
+                            FROM (\
+                                SELECT 1, 'x' FROM generate_series(1, 30) \
+                                UNION ALL SELECT 1, 'y' FROM generate_series(1, 40) \
+                                UNION ALL SELECT 2, 'x' FROM generate_series(1, 30) \
+                                UNION ALL SELECT 2, 'z' FROM generate_series(1, 40) \
+                            ) data(half, v) GROUP BY half ORDER BY half) s))",
+                    None,
+                    None,
+                )
+                .unwrap();
+            let (value, count) = rows.next().unwrap().get_two::<String, i64>().unwrap();
+            assert_eq!(value.as_deref(), Some("x"));
+            assert!(count.unwrap() >= 60);
+            let (value, count) = rows.next().unwrap().get_two::<String, i64>().unwrap();
+            assert!(matches!(value.as_deref(), Some("y") | Some("z")));
+            assert!(count.unwrap() >= 40);
+            assert!(rows.next().is_none());
+        });
+    }
+
+    #[pg_test]
+    fn topn_count_min_sketch_io_test() {
+        Spi::connect(|mut client| {
+            let (sketch, roundtrip) = client
+                .update(
+                    "SELECT sketch::TEXT, sketch::TEXT::toolkit_experimental.TopNCountMinSketch::TEXT FROM \
+                        (SELECT toolkit_experimental.topn_count_min_sketch(v, 2, 0.5, 0.5) sketch \
+                        FROM (VALUES ('lorem'), ('ipsum'), ('lorem')) data(v)) s",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<String, String>()
+                .unwrap();
+            assert_eq!(sketch, roundtrip);
+        });
+    }
+
+    #[pg_test(error = "topn_count_min_sketch requires a positive N, got 0")]
+    fn test_topn_count_min_sketch_requires_positive_n() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.topn_count_min_sketch('a', 0, 0.01, 0.01)",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
 }
@@ -63,6 +63,21 @@ pub fn interval_to_ms(ref_time: &crate::raw::TimestampTz, interval: &crate::raw:
     ts_interval_sum_to_ms(ref_time, interval) - ref_time.0.value() as i64
 }
 
+// The timevector pipeline elements and decaying summaries store intervals as
+// microseconds, so only intervals of a fixed length can be used: a month is
+// anything from 28 to 31 days, and treating it as 30 would put the points of
+// e.g. `seasonal_delta('1 month')` on the wrong days.  (`fill_to` predates this
+// and still counts a month as 30 days.)
+pub(crate) fn interval_to_micros(interval: crate::raw::Interval) -> i64 {
+    unsafe {
+        let interval = interval.0.cast_mut_ptr::<pg_sys::Interval>() as *const pg_sys::Interval;
+        if (*interval).month != 0 {
+            pgx::error!("intervals with months or years have no fixed length, use days instead")
+        }
+        (*interval).day as i64 * 24 * 60 * 60 * 1000000 + (*interval).time
+    }
+}
+
 pub struct TextSerializableDatumWriter {
     flinfo: pg_sys::FmgrInfo,
 }
@@ -0,0 +1,1052 @@
+use pgx::*;
+
+use ddsketch::{CollapseMode, DDSketch as DDSketchInternal};
+use uddsketch::SketchHashKey;
+
+use crate::{
+    accessors::{
+        AccessorApproxPercentile, AccessorApproxPercentileRank, AccessorError, AccessorMean,
+        AccessorNumVals, AccessorPercentileArray,
+    },
+    aggregate_utils::in_aggregate_context,
+    flatten,
+    palloc::{Inner, Internal, InternalAsValue, ToInternal},
+    pg_type,
+    raw::bytea,
+    uddsketch::{compress_buckets, decompress_counts, decompress_keys, CompressedBuckets},
+};
+
+#[track_caller]
+pub fn collapse_mode(mode: &str) -> CollapseMode {
+    match mode.trim().to_lowercase().as_str() {
+        "lowest" => CollapseMode::Lowest,
+        "highest" => CollapseMode::Highest,
+        _ => pgx::error!("unknown collapse mode. Valid modes are 'lowest' and 'highest'"),
+    }
+}
+
+// PG function for adding values to a sketch.
+// Null values are ignored.
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn ddsketch_trans(
+    state: Internal,
+    size: i32,
+    max_error: f64,
+    value: Option<f64>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    ddsketch_trans_inner(
+        unsafe { state.to_inner() },
+        size,
+        max_error,
+        CollapseMode::Lowest,
+        value,
+        fcinfo,
+    )
+    .internal()
+}
+
+// transition function for the variant of the aggregate that lets the user
+// choose which end of the distribution gets collapsed
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn ddsketch_collapse_trans(
+    state: Internal,
+    size: i32,
+    max_error: f64,
+    collapse: String,
+    value: Option<f64>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    ddsketch_trans_inner(
+        unsafe { state.to_inner() },
+        size,
+        max_error,
+        collapse_mode(&collapse),
+        value,
+        fcinfo,
+    )
+    .internal()
+}
+
+pub fn ddsketch_trans_inner(
+    state: Option<Inner<DDSketchInternal>>,
+    size: i32,
+    max_error: f64,
+    collapse: CollapseMode,
+    value: Option<f64>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<DDSketchInternal>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let value = match value {
+                None => return state,
+                Some(value) => value,
+            };
+            let mut state = match state {
+                None => DDSketchInternal::new(size as u64, max_error, collapse).into(),
+                Some(state) => state,
+            };
+            state.add_value(value);
+            Some(state)
+        })
+    }
+}
+
+// PG function for merging sketches.
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn ddsketch_combine(
+    state1: Internal,
+    state2: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    unsafe { ddsketch_combine_inner(state1.to_inner(), state2.to_inner(), fcinfo).internal() }
+}
+fn merge_sketch(sketch: &mut DDSketchInternal, other: &DDSketchInternal) {
+    if !sketch.compatible(other) {
+        pgx::error!("cannot merge ddsketches with different sizes, max errors or collapse modes")
+    }
+    sketch.merge_sketch(other);
+}
+
+pub fn ddsketch_combine_inner(
+    state1: Option<Inner<DDSketchInternal>>,
+    state2: Option<Inner<DDSketchInternal>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<DDSketchInternal>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || match (state1, state2) {
+            (None, None) => None,
+            (None, Some(state2)) => Some(state2.clone().into()),
+            (Some(state1), None) => Some(state1.clone().into()),
+            (Some(state1), Some(state2)) => {
+                let mut sketch = state1.clone();
+                merge_sketch(&mut sketch, &state2);
+                Some(sketch.into())
+            }
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, strict, schema = "toolkit_experimental")]
+pub fn ddsketch_serialize(state: Internal) -> bytea {
+    let serializable = &SerializedDDSketch::from(unsafe { state.get().unwrap() });
+    crate::do_serialize!(serializable)
+}
+
+#[pg_extern(strict, immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn ddsketch_deserialize(bytes: bytea, _internal: Internal) -> Option<Internal> {
+    ddsketch_deserialize_inner(bytes).internal()
+}
+pub fn ddsketch_deserialize_inner(bytes: bytea) -> Inner<DDSketchInternal> {
+    let sketch: DDSketchInternal = crate::do_deserialize!(bytes, SerializedDDSketch);
+    sketch.into()
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerializedDDSketch {
+    alpha: f64,
+    max_buckets: u32,
+    num_buckets: u32,
+    collapse: CollapseMode,
+    count: u64,
+    sum: f64,
+    buckets: CompressedBuckets,
+}
+
+impl From<&DDSketchInternal> for SerializedDDSketch {
+    fn from(sketch: &DDSketchInternal) -> Self {
+        let buckets = compress_buckets(sketch.bucket_iter());
+        SerializedDDSketch {
+            alpha: sketch.max_error(),
+            max_buckets: sketch.max_allowed_buckets() as u32,
+            num_buckets: sketch.current_buckets_count() as u32,
+            collapse: sketch.collapse_mode(),
+            count: sketch.count(),
+            sum: sketch.sum(),
+            buckets,
+        }
+    }
+}
+
+impl From<SerializedDDSketch> for DDSketchInternal {
+    fn from(sketch: SerializedDDSketch) -> Self {
+        DDSketchInternal::new_from_data(
+            sketch.max_buckets as u64,
+            sketch.alpha,
+            sketch.collapse,
+            sketch.count,
+            sketch.sum,
+            decompress_keys(
+                &sketch.buckets.negative_indexes,
+                sketch.buckets.zero_bucket_count != 0,
+                &sketch.buckets.positive_indexes,
+            ),
+            decompress_counts(
+                &sketch.buckets.negative_counts,
+                sketch.buckets.zero_bucket_count,
+                &sketch.buckets.positive_counts,
+            ),
+        )
+    }
+}
+
+#[pg_schema]
+pub mod toolkit_experimental {
+    use super::*;
+
+    // PG object for the sketch.
+    pg_type! {
+        #[derive(Debug)]
+        struct DDSketch<'input> {
+            alpha: f64,
+            max_buckets: u32,
+            num_buckets: u32,
+            count: u64,
+            sum: f64,
+            zero_bucket_count: u64,
+            collapse_highest: bool,
+            padding_2: [u8; 3],
+            neg_indexes_bytes: u32,
+            neg_buckets_bytes: u32,
+            pos_indexes_bytes: u32,
+            pos_buckets_bytes: u32,
+            negative_indexes: [u8; self.neg_indexes_bytes],
+            negative_counts: [u8; self.neg_buckets_bytes],
+            positive_indexes: [u8; self.pos_indexes_bytes],
+            positive_counts: [u8; self.pos_buckets_bytes],
+        }
+    }
+}
+
+use toolkit_experimental::DDSketch;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ReadableDDSketch {
+    version: u8,
+    alpha: f64,
+    max_buckets: u32,
+    num_buckets: u32,
+    collapse: CollapseMode,
+    count: u64,
+    sum: f64,
+    buckets: Vec<(SketchHashKey, u64)>,
+}
+
+impl From<&DDSketch<'_>> for ReadableDDSketch {
+    fn from(sketch: &DDSketch<'_>) -> Self {
+        ReadableDDSketch {
+            version: sketch.version,
+            alpha: sketch.alpha,
+            max_buckets: sketch.max_buckets,
+            num_buckets: sketch.num_buckets,
+            collapse: sketch.collapse_mode(),
+            count: sketch.count,
+            sum: sketch.sum,
+            buckets: sketch.keys().zip(sketch.counts()).collect(),
+        }
+    }
+}
+
+impl<'a, 'b> From<&'a ReadableDDSketch> for DDSketch<'b> {
+    fn from(sketch: &'a ReadableDDSketch) -> Self {
+        assert_eq!(sketch.version, 1);
+
+        let CompressedBuckets {
+            negative_indexes,
+            negative_counts,
+            zero_bucket_count,
+            positive_indexes,
+            positive_counts,
+        } = compress_buckets(sketch.buckets.iter().cloned());
+
+        unsafe {
+            flatten! {
+                DDSketch {
+                    alpha: sketch.alpha,
+                    max_buckets: sketch.max_buckets,
+                    num_buckets: sketch.num_buckets,
+                    count: sketch.count,
+                    sum: sketch.sum,
+                    zero_bucket_count,
+                    collapse_highest: sketch.collapse == CollapseMode::Highest,
+                    padding_2: [0; 3],
+                    neg_indexes_bytes: (negative_indexes.len() as u32),
+                    neg_buckets_bytes: (negative_counts.len() as u32),
+                    pos_indexes_bytes: (positive_indexes.len() as u32),
+                    pos_buckets_bytes: (positive_counts.len() as u32),
+                    negative_indexes: (&*negative_indexes).into(),
+                    negative_counts: (&*negative_counts).into(),
+                    positive_indexes: (&*positive_indexes).into(),
+                    positive_counts: (&*positive_counts).into(),
+                }
+            }
+        }
+    }
+}
+
+impl<'input> InOutFuncs for DDSketch<'input> {
+    fn output(&self, buffer: &mut StringInfo) {
+        use crate::serialization::{str_to_db_encoding, EncodedStr::*};
+
+        let stringified = ron::to_string(&ReadableDDSketch::from(self)).unwrap();
+        match str_to_db_encoding(&stringified) {
+            Utf8(s) => buffer.push_str(s),
+            Other(s) => buffer.push_bytes(s.to_bytes()),
+        }
+    }
+
+    fn input(input: &std::ffi::CStr) -> Self
+    where
+        Self: Sized,
+    {
+        use crate::serialization::str_from_db_encoding;
+
+        let utf8_str = str_from_db_encoding(input);
+        let val: ReadableDDSketch = ron::from_str(utf8_str).unwrap();
+        DDSketch::from(&val)
+    }
+}
+
+impl<'input> DDSketch<'input> {
+    fn collapse_mode(&self) -> CollapseMode {
+        if self.collapse_highest {
+            CollapseMode::Highest
+        } else {
+            CollapseMode::Lowest
+        }
+    }
+
+    fn keys(&self) -> impl Iterator<Item = SketchHashKey> + '_ {
+        decompress_keys(
+            self.negative_indexes.as_slice(),
+            self.zero_bucket_count != 0,
+            self.positive_indexes.as_slice(),
+        )
+    }
+
+    fn counts(&self) -> impl Iterator<Item = u64> + '_ {
+        decompress_counts(
+            self.negative_counts.as_slice(),
+            self.zero_bucket_count,
+            self.positive_counts.as_slice(),
+        )
+    }
+
+    fn to_ddsketch(&self) -> DDSketchInternal {
+        DDSketchInternal::new_from_data(
+            self.max_buckets as u64,
+            self.alpha,
+            self.collapse_mode(),
+            self.count,
+            self.sum,
+            self.keys(),
+            self.counts(),
+        )
+    }
+
+    fn from_internal(state: &DDSketchInternal) -> Self {
+        let CompressedBuckets {
+            negative_indexes,
+            negative_counts,
+            zero_bucket_count,
+            positive_indexes,
+            positive_counts,
+        } = compress_buckets(state.bucket_iter());
+
+        // we need to flatten the vector to a single buffer that contains
+        // both the size, the data, and the varlen header
+        unsafe {
+            flatten!(DDSketch {
+                alpha: state.max_error(),
+                max_buckets: state.max_allowed_buckets() as u32,
+                num_buckets: state.current_buckets_count() as u32,
+                count: state.count(),
+                sum: state.sum(),
+                zero_bucket_count,
+                collapse_highest: state.collapse_mode() == CollapseMode::Highest,
+                padding_2: [0; 3],
+                neg_indexes_bytes: negative_indexes.len() as u32,
+                neg_buckets_bytes: negative_counts.len() as u32,
+                pos_indexes_bytes: positive_indexes.len() as u32,
+                pos_buckets_bytes: positive_counts.len() as u32,
+                negative_indexes: negative_indexes.into(),
+                negative_counts: negative_counts.into(),
+                positive_indexes: positive_indexes.into(),
+                positive_counts: positive_counts.into(),
+            })
+        }
+    }
+}
+
+// PG function to generate a user-facing DDSketch object from a DDSketchInternal.
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+fn ddsketch_final(state: Internal, fcinfo: pg_sys::FunctionCallInfo) -> Option<DDSketch<'static>> {
+    unsafe { ddsketch_final_inner(state.to_inner(), fcinfo) }
+}
+fn ddsketch_final_inner(
+    state: Option<Inner<DDSketchInternal>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<DDSketch<'static>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let state = match state {
+                None => return None,
+                Some(state) => state,
+            };
+
+            DDSketch::from_internal(&state).into()
+        })
+    }
+}
+
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.ddsketch(\n\
+        size integer, max_error DOUBLE PRECISION, value DOUBLE PRECISION\n\
+    ) (\n\
+        sfunc = toolkit_experimental.ddsketch_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.ddsketch_final,\n\
+        combinefunc = toolkit_experimental.ddsketch_combine,\n\
+        serialfunc = toolkit_experimental.ddsketch_serialize,\n\
+        deserialfunc = toolkit_experimental.ddsketch_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+",
+    name = "ddsketch_agg",
+    requires = [
+        ddsketch_trans,
+        ddsketch_final,
+        ddsketch_combine,
+        ddsketch_serialize,
+        ddsketch_deserialize
+    ],
+);
+
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.ddsketch(\n\
+        size integer, max_error DOUBLE PRECISION, collapse TEXT, value DOUBLE PRECISION\n\
+    ) (\n\
+        sfunc = toolkit_experimental.ddsketch_collapse_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.ddsketch_final,\n\
+        combinefunc = toolkit_experimental.ddsketch_combine,\n\
+        serialfunc = toolkit_experimental.ddsketch_serialize,\n\
+        deserialfunc = toolkit_experimental.ddsketch_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+",
+    name = "ddsketch_collapse_agg",
+    requires = [
+        ddsketch_collapse_trans,
+        ddsketch_final,
+        ddsketch_combine,
+        ddsketch_serialize,
+        ddsketch_deserialize
+    ],
+);
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn ddsketch_compound_trans<'a>(
+    state: Internal,
+    value: Option<DDSketch<'a>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    unsafe { ddsketch_compound_trans_inner(state.to_inner(), value, fcinfo).internal() }
+}
+pub fn ddsketch_compound_trans_inner(
+    state: Option<Inner<DDSketchInternal>>,
+    value: Option<DDSketch>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<DDSketchInternal>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let value = match value {
+                None => return state,
+                Some(value) => value.to_ddsketch(),
+            };
+            let mut state = match state {
+                None => return Some(value.into()),
+                Some(state) => state,
+            };
+            merge_sketch(&mut state, &value);
+            state.into()
+        })
+    }
+}
+
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.rollup(\n\
+        sketch toolkit_experimental.ddsketch\n\
+    ) (\n\
+        sfunc = toolkit_experimental.ddsketch_compound_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.ddsketch_final,\n\
+        combinefunc = toolkit_experimental.ddsketch_combine,\n\
+        serialfunc = toolkit_experimental.ddsketch_serialize,\n\
+        deserialfunc = toolkit_experimental.ddsketch_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+",
+    name = "ddsketch_rollup",
+    requires = [
+        ddsketch_compound_trans,
+        ddsketch_final,
+        ddsketch_combine,
+        ddsketch_serialize,
+        ddsketch_deserialize
+    ],
+);
+
+//---- Available PG operations on the sketch
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_ddsketch_approx_percentile<'a>(
+    sketch: DDSketch<'a>,
+    accessor: AccessorApproxPercentile<'a>,
+) -> f64 {
+    ddsketch_approx_percentile(accessor.percentile, sketch)
+}
+
+// Approximate the value at the given approx_percentile (0.0-1.0)
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "approx_percentile",
+    schema = "toolkit_experimental"
+)]
+pub fn ddsketch_approx_percentile<'a>(percentile: f64, sketch: DDSketch<'a>) -> f64 {
+    uddsketch::estimate_quantile(
+        percentile,
+        sketch.alpha,
+        uddsketch::gamma(sketch.alpha),
+        sketch.count,
+        sketch.keys().zip(sketch.counts()),
+    )
+}
+
+#[pg_operator(immutable)]
+#[opname(->)]
+pub fn arrow_ddsketch_approx_percentile_array<'a>(
+    sketch: DDSketch<'a>,
+    percentiles: AccessorPercentileArray<'a>,
+) -> Vec<f64> {
+    approx_percentile_slice(percentiles.percentile.as_slice(), sketch)
+}
+
+// Approximate the value at the given approx_percentile (0.0-1.0) for each entry in an array
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "approx_percentile_array",
+    schema = "toolkit_experimental"
+)]
+pub fn ddsketch_approx_percentile_array<'a>(
+    percentiles: Vec<f64>,
+    sketch: DDSketch<'a>,
+) -> Vec<f64> {
+    approx_percentile_slice(&percentiles, sketch)
+}
+
+fn approx_percentile_slice<'a, 'b>(
+    percentiles: impl IntoIterator<Item = &'b f64>,
+    sketch: DDSketch<'a>,
+) -> Vec<f64> {
+    let mut results = Vec::new();
+    for percentile in percentiles {
+        results.push(uddsketch::estimate_quantile(
+            *percentile,
+            sketch.alpha,
+            uddsketch::gamma(sketch.alpha),
+            sketch.count,
+            sketch.keys().zip(sketch.counts()),
+        ))
+    }
+    results
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_ddsketch_approx_rank<'a>(
+    sketch: DDSketch<'a>,
+    accessor: AccessorApproxPercentileRank<'a>,
+) -> f64 {
+    ddsketch_approx_percentile_rank(accessor.value, sketch)
+}
+
+// Approximate the approx_percentile at the given value
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "approx_percentile_rank",
+    schema = "toolkit_experimental"
+)]
+pub fn ddsketch_approx_percentile_rank<'a>(value: f64, sketch: DDSketch<'a>) -> f64 {
+    uddsketch::estimate_quantile_at_value(
+        value,
+        uddsketch::gamma(sketch.alpha),
+        sketch.count,
+        sketch.keys().zip(sketch.counts()),
+    )
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_ddsketch_num_vals<'a>(sketch: DDSketch<'a>, _accessor: AccessorNumVals<'a>) -> f64 {
+    ddsketch_num_vals(sketch)
+}
+
+// Number of elements from which the sketch was built.
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "num_vals",
+    schema = "toolkit_experimental"
+)]
+pub fn ddsketch_num_vals<'a>(sketch: DDSketch<'a>) -> f64 {
+    sketch.count as f64
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_ddsketch_mean<'a>(sketch: DDSketch<'a>, _accessor: AccessorMean<'a>) -> f64 {
+    ddsketch_mean(sketch)
+}
+
+// Average of all the values entered in the sketch.
+// Note that this is not an approximation, though there may be loss of precision.
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "mean",
+    schema = "toolkit_experimental"
+)]
+pub fn ddsketch_mean<'a>(sketch: DDSketch<'a>) -> f64 {
+    if sketch.count > 0 {
+        sketch.sum / sketch.count as f64
+    } else {
+        0.0
+    }
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_ddsketch_error<'a>(sketch: DDSketch<'a>, _accessor: AccessorError<'a>) -> f64 {
+    ddsketch_error(sketch)
+}
+
+// The maximum error (relative to the true value) for any approx_percentile
+// estimate outside of the collapsed end of the sketch.
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "error",
+    schema = "toolkit_experimental"
+)]
+pub fn ddsketch_error<'a>(sketch: DDSketch<'a>) -> f64 {
+    sketch.alpha
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+
+    use pgx_macros::pg_test;
+
+    // Assert equality between two floats, within some fixed error range.
+    fn apx_eql(value: f64, expected: f64, error: f64) {
+        assert!(
+            (value - expected).abs() < error,
+            "Float value {} differs from expected {} by more than {}",
+            value,
+            expected,
+            error
+        );
+    }
+
+    // Assert equality between two floats, within an error expressed as a fraction of the expected value.
+    fn pct_eql(value: f64, expected: f64, pct_error: f64) {
+        apx_eql(value, expected, pct_error * expected);
+    }
+
+    #[pg_test]
+    fn test_ddsketch_aggregate() {
+        Spi::connect(|mut client| {
+            client
+                .update("CREATE TABLE test (data DOUBLE PRECISION)", None, None)
+                .unwrap();
+            client
+                .update(
+                    "INSERT INTO test SELECT generate_series(0.01, 100, 0.01)",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            client
+                .update(
+                    "CREATE VIEW sketch AS \
+                SELECT toolkit_experimental.ddsketch(200, 0.01, data) \
+                FROM test",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            let (mean, count, error) = client
+                .update(
+                    "SELECT \
+                    toolkit_experimental.mean(ddsketch), \
+                    toolkit_experimental.num_vals(ddsketch), \
+                    toolkit_experimental.error(ddsketch) \
+                    FROM sketch",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_three::<f64, f64, f64>()
+                .unwrap();
+
+            apx_eql(mean.unwrap(), 50.005, 0.0001);
+            apx_eql(count.unwrap(), 10000.0, 0.000001);
+            // unlike uddsketch the error never grows
+            apx_eql(error.unwrap(), 0.01, 0.000001);
+
+            let (mean2, count2, error2) = client
+                .update(
+                    "SELECT \
+                    ddsketch -> mean(), \
+                    ddsketch -> num_vals(), \
+                    ddsketch -> error() \
+                    FROM sketch",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_three::<f64, f64, f64>()
+                .unwrap();
+            assert_eq!(mean, mean2);
+            assert_eq!(count, count2);
+            assert_eq!(error, error2);
+
+            // 200 buckets at 1% error only cover values down to ~1.8, but
+            // everything above that must stay within the error bound
+            for i in 5..=100 {
+                let value = i as f64;
+                let approx_percentile = value / 100.0;
+
+                let (est_val, est_val2) = client
+                    .update(
+                        &format!(
+                            "SELECT \
+                                toolkit_experimental.approx_percentile({}, ddsketch), \
+                                ddsketch->approx_percentile({}) \
+                            FROM sketch",
+                            approx_percentile, approx_percentile
+                        ),
+                        None,
+                        None,
+                    )
+                    .unwrap()
+                    .first()
+                    .get_two::<f64, f64>()
+                    .unwrap();
+
+                // the true value at each percentile is the next number > value
+                pct_eql(est_val.unwrap(), value + 0.01, 0.0101);
+                assert_eq!(est_val, est_val2);
+            }
+
+            let (rank, rank2) = client
+                .update(
+                    "SELECT \
+                        toolkit_experimental.approx_percentile_rank(90, ddsketch), \
+                        ddsketch->approx_percentile_rank(90) \
+                    FROM sketch",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<f64, f64>()
+                .unwrap();
+            pct_eql(rank.unwrap(), 0.9, 0.02);
+            assert_eq!(rank, rank2);
+        });
+    }
+
+    #[pg_test]
+    fn test_ddsketch_collapse_modes() {
+        Spi::connect(|mut client| {
+            // values spread over 20 orders of magnitude, far more than 20 buckets can cover
+            client
+                .update(
+                    "CREATE TABLE collapse_test AS \
+                    SELECT (10 ^ (v / 10.0))::DOUBLE PRECISION AS value FROM generate_series(-100, 100) v",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            let (low_max, low_min) = client
+                .update(
+                    "SELECT \
+                        toolkit_experimental.approx_percentile(1.0, sketch), \
+                        toolkit_experimental.approx_percentile(0.0, sketch) \
+                    FROM (\
+                        SELECT toolkit_experimental.ddsketch(20, 0.01, 'lowest', value) AS sketch \
+                        FROM collapse_test) s",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<f64, f64>()
+                .unwrap();
+
+            pct_eql(low_max.unwrap(), 1e10, 0.01);
+            assert!(low_min.unwrap() > 1e-10 * 1.01);
+
+            let (high_max, high_min) = client
+                .update(
+                    "SELECT \
+                        toolkit_experimental.approx_percentile(1.0, sketch), \
+                        toolkit_experimental.approx_percentile(0.0, sketch) \
+                    FROM (\
+                        SELECT toolkit_experimental.ddsketch(20, 0.01, 'highest', value) AS sketch \
+                        FROM collapse_test) s",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<f64, f64>()
+                .unwrap();
+
+            assert!(high_max.unwrap() < 1e10 * 0.99);
+            pct_eql(high_min.unwrap(), 1e-10, 0.01);
+        });
+    }
+
+    #[pg_test]
+    fn test_ddsketch_rollup() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "CREATE TABLE new_test (device INTEGER, value DOUBLE PRECISION)",
+                    None,
+                    None,
+                )
+                .unwrap();
+            client.update("INSERT INTO new_test SELECT dev, dev - v FROM generate_series(1,10) dev, generate_series(0, 1.0, 0.01) v", None, None).unwrap();
+
+            client
+                .update(
+                    "CREATE VIEW sketches AS \
+                SELECT device, toolkit_experimental.ddsketch(20, 0.01, value) \
+                FROM new_test \
+                GROUP BY device",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            client
+                .update(
+                    "CREATE VIEW composite AS \
+                SELECT toolkit_experimental.rollup(ddsketch) as ddsketch \
+                FROM sketches",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            client
+                .update(
+                    "CREATE VIEW base AS \
+                SELECT toolkit_experimental.ddsketch(20, 0.01, value) \
+                FROM new_test",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            let (value, error) = client
+                .update(
+                    "SELECT \
+                    toolkit_experimental.approx_percentile(0.9, ddsketch), \
+                    toolkit_experimental.error(ddsketch) \
+                    FROM base",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<f64, f64>()
+                .unwrap();
+
+            let (test_value, test_error) = client
+                .update(
+                    "SELECT \
+                    toolkit_experimental.approx_percentile(0.9, ddsketch), \
+                    toolkit_experimental.error(ddsketch) \
+                    FROM composite",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<f64, f64>()
+                .unwrap();
+
+            apx_eql(test_value.unwrap(), value.unwrap(), 0.0001);
+            apx_eql(test_error.unwrap(), error.unwrap(), 0.000001);
+            pct_eql(test_value.unwrap(), 9.0, test_error.unwrap());
+        });
+    }
+
+    #[pg_test]
+    fn ddsketch_io_test() {
+        Spi::connect(|mut client| {
+            client
+                .update("CREATE TABLE io_test (value DOUBLE PRECISION)", None, None)
+                .unwrap();
+            client.update("INSERT INTO io_test VALUES (-1000), (-100), (-10), (-1), (-0.1), (-0.01), (-0.001), (0), (0.001), (0.01), (0.1), (1), (10), (100), (1000)", None, None).unwrap();
+
+            let sketch = client
+                .update(
+                    "SELECT toolkit_experimental.ddsketch(10, 0.01, value)::text FROM io_test",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+
+            let expected = "(\
+                version:1,\
+                alpha:0.01,\
+                max_buckets:10,\
+                num_buckets:10,\
+                collapse:Lowest,\
+                count:15,\
+                sum:0,\
+                buckets:[\
+                    (Negative(-230),6),\
+                    (Negative(-345),1),\
+                    (Zero,1),\
+                    (Positive(-345),1),\
+                    (Positive(-230),1),\
+                    (Positive(-115),1),\
+                    (Positive(0),1),\
+                    (Positive(116),1),\
+                    (Positive(231),1),\
+                    (Positive(346),1)\
+                    ]\
+                )";
+
+            assert_eq!(sketch, Some(expected.into()));
+
+            client
+                .update(
+                    "CREATE VIEW sketch AS SELECT toolkit_experimental.ddsketch(10, 0.01, value) FROM io_test",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            for cmd in [
+                "mean(",
+                "num_vals(",
+                "error(",
+                "approx_percentile(0.1,",
+                "approx_percentile(0.25,",
+                "approx_percentile(0.5,",
+                "approx_percentile(0.6,",
+                "approx_percentile(0.8,",
+            ] {
+                let sql1 = format!("SELECT toolkit_experimental.{}ddsketch) FROM sketch", cmd);
+                let sql2 = format!(
+                    "SELECT toolkit_experimental.{}'{}'::toolkit_experimental.ddsketch) FROM sketch",
+                    cmd, expected
+                );
+
+                let expected = client
+                    .update(&sql1, None, None)
+                    .unwrap()
+                    .first()
+                    .get_one::<f64>()
+                    .unwrap()
+                    .unwrap();
+                let test = client
+                    .update(&sql2, None, None)
+                    .unwrap()
+                    .first()
+                    .get_one::<f64>()
+                    .unwrap()
+                    .unwrap();
+
+                assert!((expected - test).abs() < f64::EPSILON);
+            }
+        });
+    }
+
+    #[pg_test]
+    fn ddsketch_byte_io_test() {
+        unsafe {
+            use std::ptr;
+            let lowest = CollapseMode::Lowest;
+            let state = ddsketch_trans_inner(None, 3, 0.005, lowest, Some(14.0), ptr::null_mut());
+            let state = ddsketch_trans_inner(state, 3, 0.005, lowest, Some(18.0), ptr::null_mut());
+            let state = ddsketch_trans_inner(state, 3, 0.005, lowest, Some(22.7), ptr::null_mut());
+            let state = ddsketch_trans_inner(state, 3, 0.005, lowest, Some(39.42), ptr::null_mut());
+            let state = ddsketch_trans_inner(state, 3, 0.005, lowest, Some(-43.0), ptr::null_mut());
+
+            let control = state.unwrap();
+            let buffer = ddsketch_serialize(Inner::from(control.clone()).internal().unwrap());
+            let new_state = ddsketch_deserialize_inner(buffer);
+            assert_eq!(&*new_state, &*control);
+        }
+    }
+
+    #[pg_test]
+    fn test_ddsketch_null_input_yields_null_output() {
+        Spi::connect(|mut client| {
+            let output = client
+                .update(
+                    "SELECT toolkit_experimental.ddsketch(20, 0.01, NULL)::TEXT",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert_eq!(output, None)
+        })
+    }
+
+    #[pg_test(error = "cannot merge ddsketches with different sizes, max errors or collapse modes")]
+    fn test_ddsketch_rollup_mismatched_parameters() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.rollup(sketch) FROM (\
+                        SELECT toolkit_experimental.ddsketch(20, 0.01, 1.0) AS sketch \
+                        UNION ALL \
+                        SELECT toolkit_experimental.ddsketch(20, 0.05, 1.0)\
+                    ) s",
+                    None,
+                    None,
+                )
+                .unwrap();
+        })
+    }
+}
@@ -0,0 +1,534 @@
+use pgx::{iter::TableIterator, *};
+
+use serde::{Deserialize, Serialize};
+
+use holt_winters::{FitError, HoltWinters as InternalHoltWinters};
+use statrs::distribution::{ContinuousCDF, Normal};
+use tspoint::TSPoint;
+
+use crate::{
+    aggregate_utils::in_aggregate_context,
+    build,
+    datum_utils::interval_to_ms,
+    palloc::{Inner, Internal, InternalAsValue, ToInternal},
+    pg_type,
+    raw::{bytea, Interval, TimestampTz},
+    ron_inout_funcs,
+    time_vector::{Timevector_TSTZ_F64, FLAG_IS_SORTED},
+};
+
+use toolkit_experimental::HoltWinters;
+
+#[pg_schema]
+pub mod toolkit_experimental {
+    use super::*;
+
+    pg_type! {
+        #[derive(Debug)]
+        struct HoltWinters<'input> {
+            alpha: f64,
+            beta: f64,
+            gamma: f64,
+            // the time between points the model assumes, the median of the
+            // input's
+            step: i64,
+            last_time: i64,
+            level: f64,
+            trend: f64,
+            sse: f64,
+            num_errors: u64,
+            period: u64,
+            seasonal: [f64; self.period],
+        }
+    }
+
+    ron_inout_funcs!(HoltWinters);
+}
+
+impl<'input> HoltWinters<'input> {
+    pub fn to_internal(&self) -> InternalHoltWinters {
+        InternalHoltWinters {
+            alpha: self.alpha,
+            beta: self.beta,
+            gamma: self.gamma,
+            level: self.level,
+            trend: self.trend,
+            seasonal: self.seasonal.as_slice().to_vec(),
+            sse: self.sse,
+            num_errors: self.num_errors,
+        }
+    }
+
+    pub fn from_internal(model: InternalHoltWinters, step: i64, last_time: i64) -> Self {
+        build!(HoltWinters {
+            alpha: model.alpha,
+            beta: model.beta,
+            gamma: model.gamma,
+            step,
+            last_time,
+            level: model.level,
+            trend: model.trend,
+            sse: model.sse,
+            num_errors: model.num_errors,
+            period: model.seasonal.len() as u64,
+            seasonal: model.seasonal.into(),
+        })
+    }
+}
+
+pub(crate) fn check_parameters(period: i32, alpha: f64, beta: f64, gamma: f64) -> usize {
+    if period < 0 || period == 1 {
+        pgx::error!("period must be 0 for no seasonality, or at least 2")
+    }
+    for param in [alpha, beta, gamma] {
+        if !(0.0..=1.0).contains(&param) {
+            pgx::error!("alpha, beta and gamma must be between 0 and 1")
+        }
+    }
+    period as usize
+}
+
+/// Fits a model to `points`, in any order, using the median time between them
+/// as the step.  The points are placed on a grid of that step starting at the
+/// first point: points in the same slot are averaged, and empty slots are
+/// linearly interpolated.  `None` if there are too few points.
+pub(crate) fn fit(
+    points: impl Iterator<Item = TSPoint>,
+    period: usize,
+    alpha: f64,
+    beta: f64,
+    gamma: f64,
+) -> Option<HoltWinters<'static>> {
+    let mut points: Vec<TSPoint> = points.collect();
+    points.sort_by_key(|p| p.ts);
+
+    let mut steps: Vec<i64> = points
+        .windows(2)
+        .map(|w| w[1].ts - w[0].ts)
+        .filter(|&step| step > 0)
+        .collect();
+    if steps.is_empty() {
+        return None;
+    }
+    steps.sort_unstable();
+    let step = steps[steps.len() / 2];
+
+    let first = points[0].ts;
+    let slot = |ts: i64| ((ts - first) as f64 / step as f64).round() as usize;
+    let num_slots = slot(points.last().unwrap().ts) + 1;
+    if num_slots > 16 * points.len() {
+        pgx::error!("the points are too irregularly spaced to fit a Holt-Winters model")
+    }
+    let mut sums = vec![(0.0, 0); num_slots];
+    for point in &points {
+        let (sum, count) = &mut sums[slot(point.ts)];
+        *sum += point.val;
+        *count += 1;
+    }
+    let mut values = Vec::with_capacity(num_slots);
+    let mut previous = 0;
+    for (i, &(sum, count)) in sums.iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+        let val = sum / count as f64;
+        // fill the gap since the previous occupied slot
+        for gap in previous + 1..i {
+            let start = values[previous];
+            let weight = (gap - previous) as f64 / (i - previous) as f64;
+            values.push(start + (val - start) * weight);
+        }
+        values.push(val);
+        previous = i;
+    }
+    let model = match InternalHoltWinters::fit(&values, period, alpha, beta, gamma) {
+        Ok(model) => model,
+        Err(FitError::TooFewValues) => return None,
+        Err(e) => pgx::error!("unable to fit Holt-Winters model: {:?}", e),
+    };
+    Some(HoltWinters::from_internal(
+        model,
+        step,
+        points.last().unwrap().ts,
+    ))
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HoltWintersTransState {
+    period: usize,
+    alpha: f64,
+    beta: f64,
+    gamma: f64,
+    points: Vec<TSPoint>,
+}
+
+#[pg_extern(immutable, parallel_safe, strict, schema = "toolkit_experimental")]
+pub fn holt_winters_serialize(state: Internal) -> bytea {
+    let ser: &HoltWintersTransState = unsafe { state.get().unwrap() };
+    crate::do_serialize!(ser)
+}
+
+#[pg_extern(immutable, parallel_safe, strict, schema = "toolkit_experimental")]
+pub fn holt_winters_deserialize(bytes: bytea, _internal: Internal) -> Option<Internal> {
+    holt_winters_deserialize_inner(bytes).internal()
+}
+pub fn holt_winters_deserialize_inner(bytes: bytea) -> Inner<HoltWintersTransState> {
+    let de: HoltWintersTransState = crate::do_deserialize!(bytes, HoltWintersTransState);
+    de.into()
+}
+
+#[allow(clippy::too_many_arguments)]
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn holt_winters_trans(
+    state: Internal,
+    ts: Option<TimestampTz>,
+    value: Option<f64>,
+    period: i32,
+    alpha: f64,
+    beta: f64,
+    gamma: f64,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    holt_winters_trans_inner(
+        unsafe { state.to_inner() },
+        ts,
+        value,
+        period,
+        alpha,
+        beta,
+        gamma,
+        fcinfo,
+    )
+    .internal()
+}
+#[allow(clippy::too_many_arguments)]
+pub fn holt_winters_trans_inner(
+    state: Option<Inner<HoltWintersTransState>>,
+    ts: Option<TimestampTz>,
+    value: Option<f64>,
+    period: i32,
+    alpha: f64,
+    beta: f64,
+    gamma: f64,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<HoltWintersTransState>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let mut state = state.unwrap_or_else(|| {
+                HoltWintersTransState {
+                    period: check_parameters(period, alpha, beta, gamma),
+                    alpha,
+                    beta,
+                    gamma,
+                    points: vec![],
+                }
+                .into()
+            });
+            if let (Some(ts), Some(val)) = (ts, value) {
+                state.points.push(TSPoint { ts: ts.into(), val });
+            }
+            Some(state)
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn holt_winters_combine(
+    state1: Internal,
+    state2: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    unsafe { holt_winters_combine_inner(state1.to_inner(), state2.to_inner(), fcinfo).internal() }
+}
+pub fn holt_winters_combine_inner(
+    state1: Option<Inner<HoltWintersTransState>>,
+    state2: Option<Inner<HoltWintersTransState>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<HoltWintersTransState>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || match (state1, state2) {
+            (None, None) => None,
+            (None, Some(state2)) => Some((*state2).clone().into()),
+            (Some(state1), None) => Some((*state1).clone().into()),
+            (Some(state1), Some(state2)) => {
+                // the points are sorted when the model is fit
+                let mut combined = (*state1).clone();
+                combined.points.extend_from_slice(&state2.points);
+                Some(combined.into())
+            }
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+fn holt_winters_final(
+    state: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<HoltWinters<'static>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let state: &HoltWintersTransState = state.get()?;
+            fit(
+                state.points.iter().copied(),
+                state.period,
+                state.alpha,
+                state.beta,
+                state.gamma,
+            )
+        })
+    }
+}
+
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.holt_winters_agg(\n\
+        ts TIMESTAMPTZ, value DOUBLE PRECISION, period INTEGER,\n\
+        alpha DOUBLE PRECISION, beta DOUBLE PRECISION, gamma DOUBLE PRECISION\n\
+    ) (\n\
+        sfunc = toolkit_experimental.holt_winters_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.holt_winters_final,\n\
+        combinefunc = toolkit_experimental.holt_winters_combine,\n\
+        serialfunc = toolkit_experimental.holt_winters_serialize,\n\
+        deserialfunc = toolkit_experimental.holt_winters_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+",
+    name = "holt_winters_agg",
+    requires = [
+        holt_winters_trans,
+        holt_winters_final,
+        holt_winters_combine,
+        holt_winters_serialize,
+        holt_winters_deserialize
+    ],
+);
+
+fn sorted_timevector(points: Vec<TSPoint>) -> Timevector_TSTZ_F64<'static> {
+    let nulls_len = (points.len() + 7) / 8;
+    build! {
+        Timevector_TSTZ_F64 {
+            num_points: points.len() as u32,
+            flags: FLAG_IS_SORTED,
+            internal_padding: [0; 3],
+            compressed_len: vec![].into(),
+            points: points.into(),
+            null_val: std::vec::from_elem(0_u8, nulls_len).into(),
+            compressed: vec![].into(),
+        }
+    }
+}
+
+/// Forecasts every `step` after the last point of the model, up to `horizon`
+/// after it, along with the bounds of the `confidence` prediction interval.
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn forecast<'a>(
+    model: HoltWinters<'a>,
+    horizon: Interval,
+    step: Interval,
+    confidence: default!(f64, 0.95),
+) -> TableIterator<
+    'static,
+    (
+        name!(forecast, Timevector_TSTZ_F64<'static>),
+        name!(lower, Timevector_TSTZ_F64<'static>),
+        name!(upper, Timevector_TSTZ_F64<'static>),
+    ),
+> {
+    let last_time = TimestampTz::from(model.last_time);
+    let horizon = interval_to_ms(&last_time, &horizon);
+    let step = interval_to_ms(&last_time, &step);
+    if horizon < 0 {
+        pgx::error!("forecast horizon must not be negative")
+    }
+    if step <= 0 {
+        pgx::error!("forecast step must be positive")
+    }
+    if !(confidence > 0.0 && confidence < 1.0) {
+        pgx::error!("confidence must be between 0 and 1")
+    }
+
+    let internal = model.to_internal();
+    let z = Normal::new(0.0, 1.0)
+        .unwrap()
+        .inverse_cdf((1.0 + confidence) / 2.0);
+    let mut forecast = vec![];
+    let mut lower = vec![];
+    let mut upper = vec![];
+    let mut offset = step;
+    while offset <= horizon {
+        let ts = model.last_time + offset;
+        let steps = offset as f64 / model.step as f64;
+        let val = internal.forecast(steps);
+        let error = z * internal.forecast_variance(steps).unwrap_or(f64::NAN).sqrt();
+        forecast.push(TSPoint { ts, val });
+        lower.push(TSPoint {
+            ts,
+            val: val - error,
+        });
+        upper.push(TSPoint {
+            ts,
+            val: val + error,
+        });
+        offset += step;
+    }
+    TableIterator::new(std::iter::once((
+        sorted_timevector(forecast),
+        sorted_timevector(lower),
+        sorted_timevector(upper),
+    )))
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use pgx::*;
+    use pgx_macros::pg_test;
+
+    #[pg_test]
+    fn test_holt_winters_agg() {
+        Spi::connect(|mut client| {
+            client.update("SET timezone TO 'UTC'", None, None).unwrap();
+            client
+                .update(
+                    "SET LOCAL search_path TO toolkit_experimental, public",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            // an hourly series with a linear trend, a daily pattern and some
+            // noise, out of order, with a NULL
+            client
+                .update(
+                    "CREATE TABLE disk(time TIMESTAMPTZ, used DOUBLE PRECISION)",
+                    None,
+                    None,
+                )
+                .unwrap();
+            client
+                .update(
+                    "INSERT INTO disk \
+                    SELECT '2020-01-01 UTC'::TIMESTAMPTZ + i * '1 hour'::INTERVAL, \
+                        CASE WHEN i = 50 THEN NULL \
+                        ELSE 100 + i + (ARRAY[10.0, 0.0, -10.0, 0.0])[i / 6 % 4 + 1] + sin(i) END \
+                    FROM generate_series(95, 0, -1) i",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            let model = client
+                .update(
+                    "SELECT holt_winters_agg(time, used, 24, 0.5, 0.1, 0.3)::TEXT FROM disk",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap()
+                .unwrap();
+            assert!(
+                model.starts_with(
+                    "(version:1,alpha:0.5,beta:0.1,gamma:0.3,step:3600000000,last_time:631494000000000,"
+                ),
+                "{}",
+                model
+            );
+
+            // the missing point is interpolated, so the forecast stays in phase
+            // with the pattern
+            let (forecast, lower) = client
+                .update(
+                    "SELECT \
+                        (SELECT array_agg(value) FROM unnest(forecast)), \
+                        (SELECT array_agg(value) FROM unnest(lower)) \
+                    FROM forecast(\
+                        (SELECT holt_winters_agg(time, used, 24, 0.5, 0.1, 0.3) FROM disk), \
+                        '1 day', '6 hours')",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<Vec<f64>, Vec<f64>>()
+                .unwrap();
+            let forecast = forecast.unwrap();
+            let lower = lower.unwrap();
+            assert_eq!(forecast.len(), 4);
+            for (i, val) in forecast.iter().enumerate() {
+                let step = 96 + 6 * i as i64 + 5;
+                let expected =
+                    100.0 + step as f64 + [10.0, 0.0, -10.0, 0.0][(step / 6 % 4) as usize];
+                assert!((val - expected).abs() < 3.0, "{:?}", forecast);
+                assert!(lower[i] < *val, "{:?} {:?}", lower, forecast);
+            }
+            // the intervals widen further out
+            assert!(forecast[3] - lower[3] > forecast[0] - lower[0]);
+
+            // without seasonality, the trend is extrapolated
+            let val = client
+                .update(
+                    "SELECT (SELECT array_agg(value) FROM unnest(forecast)) \
+                    FROM forecast(\
+                        (SELECT holt_winters_agg(time, value, 0, 0.5, 0.5, 0) \
+                        FROM (VALUES \
+                            ('2020-01-01 00:00 UTC'::TIMESTAMPTZ, 10.0), \
+                            ('2020-01-01 01:00 UTC'::TIMESTAMPTZ, 12.0), \
+                            ('2020-01-01 02:00 UTC'::TIMESTAMPTZ, 14.0)) v(time, value)), \
+                        '3 hours', '90 minutes')",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<Vec<f64>>()
+                .unwrap();
+            assert_eq!(val.unwrap(), vec![17.0, 20.0]);
+
+            // too few points
+            let val = client
+                .update(
+                    "SELECT holt_winters_agg(time, used, 24, 0.5, 0.1, 0.3)::TEXT \
+                    FROM disk WHERE time < '2020-01-02 UTC'",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert!(val.is_none());
+
+            // as a pipeline finalizer
+            let val = client
+                .update(
+                    "SELECT (\
+                        (SELECT timevector(time, used) FROM disk) \
+                        -> holt_winters_agg(24, 0.5, 0.1, 0.3))::TEXT \
+                    = (SELECT holt_winters_agg(time, used, 24, 0.5, 0.1, 0.3)::TEXT FROM disk)",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<bool>()
+                .unwrap();
+            assert_eq!(val, Some(true));
+        });
+    }
+
+    #[pg_test(error = "alpha, beta and gamma must be between 0 and 1")]
+    fn test_holt_winters_invalid_parameter() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.holt_winters_agg(now(), 1.0, 0, 1.5, 0.5, 0.5)",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+}
@@ -20,6 +20,8 @@ use crate::{
     serialization::{PgCollationId, ShortTypeId},
 };
 
+use toolkit_experimental::HyperLogLogDifferenceAccessor;
+
 use hyperloglogplusplus::{HyperLogLog as HLL, HyperLogLogStorage};
 
 // pgx doesn't implement Eq/Hash but it's okay here since we treat Datums as raw bytes
@@ -388,6 +390,113 @@ pub fn hyperloglog_error<'a>(hyperloglog: HyperLogLog<'a>) -> f64 {
     hyperloglogplusplus::error_for_precision(precision)
 }
 
+#[pg_schema]
+pub mod toolkit_experimental {
+    use super::*;
+
+    // carries the sketch to subtract so that `a -> difference_count(b)` works
+    pg_type! {
+        #[derive(Debug)]
+        struct HyperLogLogDifferenceAccessor<'input> {
+            #[flat_serialize::flatten]
+            log: Storage<'input>,
+        }
+    }
+
+    ron_inout_funcs!(HyperLogLogDifferenceAccessor);
+}
+
+#[pg_extern(
+    name = "difference_count",
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+pub fn hyperloglog_difference_accessor<'a>(
+    other: HyperLogLog<'a>,
+) -> HyperLogLogDifferenceAccessor<'static> {
+    unsafe {
+        flatten!(HyperLogLogDifferenceAccessor {
+            log: other.log.clone(),
+        })
+    }
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_hyperloglog_difference_count<'a>(
+    sketch: HyperLogLog<'a>,
+    accessor: HyperLogLogDifferenceAccessor<'a>,
+) -> i64 {
+    let (log, other) = unflatten_pair(&sketch.log, &accessor.log);
+    log.estimate_difference_count(&other) as i64
+}
+
+#[pg_extern(
+    name = "difference_count",
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+pub fn hyperloglog_difference_count<'a>(
+    hyperloglog: HyperLogLog<'a>,
+    other: HyperLogLog<'a>,
+) -> i64 {
+    let (log, other) = unflatten_pair(&hyperloglog.log, &other.log);
+    log.estimate_difference_count(&other) as i64
+}
+
+#[pg_extern(
+    name = "intersection_count",
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+pub fn hyperloglog_intersection_count<'a>(
+    hyperloglog: HyperLogLog<'a>,
+    other: HyperLogLog<'a>,
+) -> i64 {
+    let (log, other) = unflatten_pair(&hyperloglog.log, &other.log);
+    log.estimate_intersection_count(&other) as i64
+}
+
+#[pg_extern(
+    name = "jaccard",
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+pub fn hyperloglog_jaccard<'a>(hyperloglog: HyperLogLog<'a>, other: HyperLogLog<'a>) -> f64 {
+    let (log, other) = unflatten_pair(&hyperloglog.log, &other.log);
+    log.estimate_jaccard(&other)
+}
+
+// Set operations need both sketches to hash the same type into the same registers.
+fn unflatten_pair<'a, 'b>(
+    log: &Storage<'a>,
+    other: &Storage<'b>,
+) -> (
+    HLL<'a, HashableDatum, DatumHashBuilder>,
+    HLL<'b, HashableDatum, DatumHashBuilder>,
+) {
+    let precision = |log: &Storage| match log {
+        Storage::Sparse { precision, .. } => *precision,
+        Storage::Dense { precision, .. } => *precision,
+    };
+    if precision(log) != precision(other) {
+        error!(
+            "hyperloglogs must have the same number of buckets to be compared ({} vs {})",
+            1 << precision(log),
+            1 << precision(other)
+        )
+    }
+    let (log, other) = (unflatten_storage(log), unflatten_storage(other));
+    if log.buildhasher.type_id != other.buildhasher.type_id {
+        error!("mismatched types")
+    }
+    (log, other)
+}
+
 impl HyperLogLog<'_> {
     pub fn build_from(
         size: i32,
@@ -451,7 +560,11 @@ fn flatten_log(hyperloglog: &mut HLL<HashableDatum, DatumHashBuilder>) -> HyperL
 }
 
 fn unflatten_log(hyperloglog: HyperLogLog) -> HLL<HashableDatum, DatumHashBuilder> {
-    match &hyperloglog.log {
+    unflatten_storage(&hyperloglog.log)
+}
+
+fn unflatten_storage<'a>(log: &Storage<'a>) -> HLL<'a, HashableDatum, DatumHashBuilder> {
+    match log {
         Storage::Sparse {
             num_compressed,
             precision,
@@ -1059,5 +1172,147 @@ mod tests {
         })
     }
 
+    #[pg_test]
+    fn test_hll_set_operations() {
+        Spi::connect(|mut client| {
+            let sketches = "(SELECT \
+                    (SELECT hyperloglog(32768, v) FROM generate_series(1, 10000) v) a, \
+                    (SELECT hyperloglog(32768, v) FROM generate_series(5001, 15000) v) b \
+                ) sketches";
+            let (intersection, jaccard) = client
+                .update(
+                    &format!(
+                        "SELECT toolkit_experimental.intersection_count(a, b), \
+                            toolkit_experimental.jaccard(a, b) FROM {}",
+                        sketches
+                    ),
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<i64, f64>()
+                .unwrap();
+            assert!(
+                (intersection.unwrap() - 5000).abs() < 100,
+                "{:?}",
+                intersection
+            );
+            assert!((jaccard.unwrap() - 1.0 / 3.0).abs() < 0.01, "{:?}", jaccard);
+
+            let (difference, arrow_difference) = client
+                .update(
+                    &format!(
+                        "SELECT toolkit_experimental.difference_count(a, b), \
+                            a -> toolkit_experimental.difference_count(b) FROM {}",
+                        sketches
+                    ),
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<i64, i64>()
+                .unwrap();
+            assert!((difference.unwrap() - 5000).abs() < 100, "{:?}", difference);
+            assert_eq!(difference, arrow_difference);
+
+            // a sketch compared with itself
+            let (intersection, difference, jaccard) = client
+                .update(
+                    "SELECT toolkit_experimental.intersection_count(a, a), \
+                        toolkit_experimental.difference_count(a, a), \
+                        toolkit_experimental.jaccard(a, a) \
+                    FROM (SELECT hyperloglog(64, v) a FROM generate_series(1, 100) v) s",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_three::<i64, i64, f64>()
+                .unwrap();
+            let count = client
+                .update(
+                    "SELECT distinct_count(hyperloglog(64, v)) FROM generate_series(1, 100) v",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<i64>()
+                .unwrap();
+            assert_eq!(intersection, count);
+            assert_eq!(difference, Some(0));
+            assert_eq!(jaccard, Some(1.0));
+        });
+    }
+
+    #[pg_test]
+    fn test_hll_set_operations_dense() {
+        // the union no longer fits in the sparse representation, so the
+        // intersection is estimated jointly from the registers
+        Spi::connect(|mut client| {
+            let (intersection, difference, jaccard) = client
+                .update(
+                    "SELECT toolkit_experimental.intersection_count(a, b), \
+                        toolkit_experimental.difference_count(a, b), \
+                        toolkit_experimental.jaccard(a, b) \
+                    FROM (SELECT \
+                        (SELECT hyperloglog(1024, v) FROM generate_series(1, 10000) v) a, \
+                        (SELECT hyperloglog(1024, v) FROM generate_series(5001, 15000) v) b \
+                    ) sketches",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_three::<i64, i64, f64>()
+                .unwrap();
+            assert!(
+                (intersection.unwrap() - 5000).abs() < 1000,
+                "{:?}",
+                intersection
+            );
+            assert!(
+                (difference.unwrap() - 5000).abs() < 1000,
+                "{:?}",
+                difference
+            );
+            assert!((jaccard.unwrap() - 1.0 / 3.0).abs() < 0.06, "{:?}", jaccard);
+        });
+    }
+
+    #[pg_test(
+        error = "hyperloglogs must have the same number of buckets to be compared (32 vs 64)"
+    )]
+    fn test_hll_set_operations_mismatched_sizes() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.intersection_count(\
+                        (SELECT hyperloglog(32, v) FROM generate_series(1, 100) v), \
+                        (SELECT hyperloglog(64, v) FROM generate_series(1, 100) v))",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test(error = "mismatched types")]
+    fn test_hll_set_operations_mismatched_types() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.jaccard(\
+                        (SELECT hyperloglog(32, v) FROM generate_series(1, 100) v), \
+                        (SELECT hyperloglog(32, v::text) FROM generate_series(1, 100) v))",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
     //TODO test continuous aggregates
 }
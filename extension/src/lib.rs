@@ -16,11 +16,14 @@ pub mod asap;
 pub mod candlestick;
 pub mod counter_agg;
 pub mod countminsketch;
+pub mod ddsketch;
 pub mod frequency;
 pub mod gauge_agg;
 pub mod heartbeat_agg;
+pub mod holt_winters;
 pub mod hyperloglog;
 pub mod lttb;
+pub mod m4;
 pub mod nmost;
 pub mod range;
 pub mod saturation;
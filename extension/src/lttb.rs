@@ -88,8 +88,10 @@ pub fn lttb_final_inner(
                 num_points: downsampled.len() as u32,
                 flags: time_vector::FLAG_IS_SORTED,
                 internal_padding: [0; 3],
+                compressed_len: vec![].into(),
                 points: (&*downsampled).into(),
-                null_val: std::vec::from_elem(0_u8, (downsampled.len() + 7) / 8).into()
+                null_val: std::vec::from_elem(0_u8, (downsampled.len() + 7) / 8).into(),
+                compressed: vec![].into(),
             })
             .into()
         })
@@ -212,8 +214,43 @@ pub fn gap_preserving_lttb_final_inner(
                 num_points: downsampled.len() as u32,
                 flags: time_vector::FLAG_IS_SORTED,
                 internal_padding: [0; 3],
+                compressed_len: vec![].into(),
                 null_val: std::vec::from_elem(0_u8, (downsampled.len() + 7) / 8).into(),
                 points: downsampled.into(),
+                compressed: vec![].into(),
+            })
+            .into()
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn minmax_lttb_final(
+    state: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Timevector_TSTZ_F64<'static>> {
+    minmax_lttb_final_inner(unsafe { state.to_inner() }, fcinfo)
+}
+pub fn minmax_lttb_final_inner(
+    state: Option<Inner<LttbTrans>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Timevector_TSTZ_F64<'static>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let mut state = match state {
+                None => return None,
+                Some(state) => state,
+            };
+            state.series.sort_by_key(|point| point.ts);
+            let downsampled = minmax_lttb(&state.series[..], state.resolution);
+            flatten!(Timevector_TSTZ_F64 {
+                num_points: downsampled.len() as u32,
+                flags: time_vector::FLAG_IS_SORTED,
+                internal_padding: [0; 3],
+                compressed_len: vec![].into(),
+                points: (&*downsampled).into(),
+                null_val: std::vec::from_elem(0_u8, (downsampled.len() + 7) / 8).into(),
+                compressed: vec![].into(),
             })
             .into()
         })
@@ -254,6 +291,17 @@ name = "gp_lttb_agg_with_size",
 requires = [gp_lttb_trans, gp_lttb_final],
 );
 
+extension_sql!("\n\
+CREATE AGGREGATE toolkit_experimental.minmax_lttb(ts TIMESTAMPTZ, value DOUBLE PRECISION, resolution integer) (\n\
+    sfunc = lttb_trans,\n\
+    stype = internal,\n\
+    finalfunc = toolkit_experimental.minmax_lttb_final\n\
+);\n\
+",
+name = "minmax_lttb_agg",
+requires = [lttb_trans, minmax_lttb_final],
+);
+
 // based on https://github.com/jeromefroe/lttb-rs version 0.2.0
 pub fn lttb(data: &[TSPoint], threshold: usize) -> Cow<'_, [TSPoint]> {
     if threshold >= data.len() || threshold == 0 {
@@ -329,12 +377,50 @@ pub fn lttb(data: &[TSPoint], threshold: usize) -> Cow<'_, [TSPoint]> {
     Cow::Owned(sampled)
 }
 
+/// MinMaxLTTB, see "MinMaxLTTB: Leveraging MinMax-Preselection to Scale LTTB"
+/// (Van Der Donckt et al., 2023).
+///
+/// Runs LTTB on the first and last point plus the minimum and maximum of
+/// `2 * threshold` equal-sized buckets of the points in between, which picks
+/// nearly the same points as LTTB on all of `data` in a fraction of the time.
+pub fn minmax_lttb(data: &[TSPoint], threshold: usize) -> Cow<'_, [TSPoint]> {
+    // the number of points preselected for every point returned
+    const MINMAX_RATIO: usize = 4;
+    let num_buckets = threshold * MINMAX_RATIO / 2;
+    if threshold >= data.len() || threshold == 0 || 2 * num_buckets + 2 >= data.len() {
+        return lttb(data, threshold);
+    }
+
+    let inner = &data[1..data.len() - 1];
+    let mut preselected = Vec::with_capacity(2 * num_buckets + 2);
+    preselected.push(data[0]);
+    for i in 0..num_buckets {
+        let bucket = &inner[i * inner.len() / num_buckets..(i + 1) * inner.len() / num_buckets];
+        let (mut min, mut max) = (0, 0);
+        for (j, point) in bucket.iter().enumerate().skip(1) {
+            if point.val < bucket[min].val {
+                min = j;
+            }
+            if point.val > bucket[max].val {
+                max = j;
+            }
+        }
+        preselected.push(bucket[min.min(max)]);
+        if min != max {
+            preselected.push(bucket[min.max(max)]);
+        }
+    }
+    preselected.push(data[data.len() - 1]);
+
+    Cow::Owned(lttb(&preselected, threshold).into_owned())
+}
+
 #[pg_extern(name = "lttb", immutable, parallel_safe)]
 pub fn lttb_on_timevector(
     series: Timevector_TSTZ_F64<'static>,
     threshold: i32,
 ) -> Option<Timevector_TSTZ_F64<'static>> {
-    lttb_ts(series, threshold as usize).into()
+    lttb_ts(series.decompress(), threshold as usize).into()
 }
 
 // based on https://github.com/jeromefroe/lttb-rs version 0.2.0
@@ -421,8 +507,10 @@ pub fn lttb_ts(data: Timevector_TSTZ_F64, threshold: usize) -> Timevector_TSTZ_F
             num_points: sampled.len() as _,
             flags: time_vector::FLAG_IS_SORTED,
             internal_padding: [0; 3],
+            compressed_len: vec![].into(),
             points: sampled.into(),
             null_val: std::vec::from_elem(0_u8, nulls_len).into(),
+            compressed: vec![].into(),
         }
     }
 }
@@ -665,4 +753,59 @@ mod tests {
             assert!(result.next().is_none());
         })
     }
+
+    #[pg_test]
+    fn test_minmax_lttb() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "CREATE TABLE test(time TIMESTAMPTZ, value DOUBLE PRECISION);",
+                    None,
+                    None,
+                )
+                .unwrap();
+            client.update(
+                "INSERT INTO test
+                SELECT time, value
+                FROM toolkit_experimental.generate_periodic_normal_series('2020-01-01 UTC'::timestamptz, NULL);", None, None).unwrap();
+
+            // too few points to preselect from, this is plain LTTB
+            let delta = client
+                .update(
+                    "SELECT count(*) FROM \
+                        unnest((\
+                            SELECT lttb(time, value, 100) \
+                            FROM (SELECT * FROM test ORDER BY time LIMIT 300) t\
+                        )) r1 \
+                    FULL OUTER JOIN \
+                        unnest((\
+                            SELECT toolkit_experimental.minmax_lttb(time, value, 100) \
+                            FROM (SELECT * FROM test ORDER BY time LIMIT 300) t\
+                        )) r2 \
+                    ON r1 = r2 WHERE r1 IS NULL OR r2 IS NULL",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<i64>()
+                .unwrap();
+            assert_eq!(delta.unwrap(), 0);
+
+            let (count, first, last) = client
+                .update(
+                    "SELECT count(*), min(time) = (SELECT min(time) FROM test), max(time) = (SELECT max(time) FROM test) \
+                    FROM unnest((SELECT toolkit_experimental.minmax_lttb(time, value, 100) FROM test))",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_three::<i64, bool, bool>()
+                .unwrap();
+            assert_eq!(count.unwrap(), 100);
+            assert!(first.unwrap());
+            assert!(last.unwrap());
+        })
+    }
 }
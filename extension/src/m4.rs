@@ -0,0 +1,247 @@
+use pgx::*;
+
+use crate::{
+    aggregate_utils::in_aggregate_context,
+    flatten,
+    palloc::{Inner, Internal, InternalAsValue, ToInternal},
+    time_vector,
+};
+
+use tspoint::TSPoint;
+
+use crate::time_vector::{Timevector_TSTZ_F64, Timevector_TSTZ_F64Data};
+
+pub struct M4Trans {
+    series: Vec<TSPoint>,
+    width: usize,
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn m4_trans(
+    state: Internal,
+    time: crate::raw::TimestampTz,
+    val: Option<f64>,
+    width: i32,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    m4_trans_inner(unsafe { state.to_inner() }, time, val, width, fcinfo).internal()
+}
+pub fn m4_trans_inner(
+    state: Option<Inner<M4Trans>>,
+    time: crate::raw::TimestampTz,
+    val: Option<f64>,
+    width: i32,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<M4Trans>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let val = match val {
+                None => return state,
+                Some(val) => val,
+            };
+            let mut state = match state {
+                Some(state) => state,
+                None => {
+                    if width <= 0 {
+                        error!("width must be positive")
+                    }
+                    M4Trans {
+                        series: vec![],
+                        width: width as usize,
+                    }
+                    .into()
+                }
+            };
+
+            state.series.push(TSPoint {
+                ts: time.into(),
+                val,
+            });
+            Some(state)
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn m4_final(
+    state: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Timevector_TSTZ_F64<'static>> {
+    m4_final_inner(unsafe { state.to_inner() }, fcinfo)
+}
+pub fn m4_final_inner(
+    state: Option<Inner<M4Trans>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Timevector_TSTZ_F64<'static>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let mut state = match state {
+                None => return None,
+                Some(state) => state,
+            };
+            state.series.sort_by_key(|point| point.ts);
+            let downsampled = m4(&state.series[..], state.width);
+            flatten!(Timevector_TSTZ_F64 {
+                num_points: downsampled.len() as u32,
+                flags: time_vector::FLAG_IS_SORTED,
+                internal_padding: [0; 3],
+                compressed_len: vec![].into(),
+                null_val: std::vec::from_elem(0_u8, (downsampled.len() + 7) / 8).into(),
+                points: downsampled.into(),
+                compressed: vec![].into(),
+            })
+            .into()
+        })
+    }
+}
+
+extension_sql!(
+    "\n\
+CREATE AGGREGATE toolkit_experimental.m4(ts TIMESTAMPTZ, value DOUBLE PRECISION, width integer) (\n\
+    sfunc = toolkit_experimental.m4_trans,\n\
+    stype = internal,\n\
+    finalfunc = toolkit_experimental.m4_final\n\
+);\n\
+",
+    name = "m4_agg",
+    requires = [m4_trans, m4_final],
+);
+
+/// M4 downsampling, see "M4: A Visualization-Oriented Time Series Data
+/// Aggregation" (Jugel et al., 2014).
+///
+/// Splits the time range of `data` (sorted by time) into `width` equal
+/// columns, and keeps the first, last, minimum and maximum point of each.
+/// Drawn as a line chart `width` pixels wide this is indistinguishable from
+/// the full series, using at most `4 * width` points.
+pub fn m4(data: &[TSPoint], width: usize) -> Vec<TSPoint> {
+    let (start, end) = match (data.first(), data.last()) {
+        (Some(first), Some(last)) => (first.ts as i128, last.ts as i128),
+        _ => return vec![],
+    };
+    // one more than the range so that the last point is in the last column
+    let range = end - start + 1;
+    let column = |point: &TSPoint| (point.ts as i128 - start) * width as i128 / range;
+
+    let mut downsampled = vec![];
+    let mut rest = data;
+    while let Some(first) = rest.first() {
+        let col = column(first);
+        let len = rest
+            .iter()
+            .position(|point| column(point) != col)
+            .unwrap_or(rest.len());
+        let (points, remaining) = rest.split_at(len);
+
+        let (mut min, mut max) = (0, 0);
+        for (i, point) in points.iter().enumerate().skip(1) {
+            if point.val < points[min].val {
+                min = i;
+            }
+            if point.val > points[max].val {
+                max = i;
+            }
+        }
+        let mut picked = [0, min, max, len - 1];
+        picked.sort_unstable();
+        let mut previous = None;
+        for i in picked {
+            if previous != Some(i) {
+                downsampled.push(points[i]);
+                previous = Some(i);
+            }
+        }
+        rest = remaining;
+    }
+    downsampled
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use pgx::*;
+    use pgx_macros::pg_test;
+
+    #[pg_test]
+    fn test_m4_result() {
+        Spi::connect(|mut client| {
+            client.update("SET timezone TO 'UTC'", None, None).unwrap();
+            // 1 to 6 fall in the first column and 7 to 11 in the second
+            let mut result = client
+                .update(
+                    r#"SELECT unnest(toolkit_experimental.m4(ts, val, 2))::TEXT
+                FROM (VALUES
+                    ('2020-1-1'::timestamptz, 10),
+                    ('2020-1-2'::timestamptz, 21),
+                    ('2020-1-3'::timestamptz, 19),
+                    ('2020-1-4'::timestamptz, 32),
+                    ('2020-1-5'::timestamptz, 12),
+                    ('2020-1-6'::timestamptz, 14),
+                    ('2020-1-7'::timestamptz, 18),
+                    ('2020-1-8'::timestamptz, 29),
+                    ('2020-1-9'::timestamptz, 23),
+                    ('2020-1-10'::timestamptz, 27),
+                    ('2020-1-11'::timestamptz, 14),
+                    ('2020-1-12'::timestamptz, NULL)
+                ) AS v(ts, val)"#,
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            for expected in [
+                "(\"2020-01-01 00:00:00+00\",10)",
+                "(\"2020-01-04 00:00:00+00\",32)",
+                "(\"2020-01-06 00:00:00+00\",14)",
+                "(\"2020-01-07 00:00:00+00\",18)",
+                "(\"2020-01-08 00:00:00+00\",29)",
+                "(\"2020-01-11 00:00:00+00\",14)",
+            ] {
+                assert_eq!(result.next().unwrap()[1].value().unwrap(), Some(expected));
+            }
+            assert!(result.next().is_none());
+        })
+    }
+
+    #[pg_test]
+    fn test_m4_size() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "CREATE TABLE test(time TIMESTAMPTZ, value DOUBLE PRECISION);",
+                    None,
+                    None,
+                )
+                .unwrap();
+            client.update(
+                "INSERT INTO test
+                SELECT time, value
+                FROM toolkit_experimental.generate_periodic_normal_series('2020-01-01 UTC'::timestamptz, NULL);", None, None).unwrap();
+
+            // at most 4 points per column, and the extremes are always kept
+            let (count, matches) = client
+                .update(
+                    "SELECT count(*), \
+                        count(*) FILTER (WHERE value IN ((SELECT min(value) FROM test), (SELECT max(value) FROM test))) \
+                    FROM unnest((SELECT toolkit_experimental.m4(time, value, 50) FROM test))",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<i64, i64>()
+                .unwrap();
+            assert!(count.unwrap() <= 200);
+            assert!(matches.unwrap() >= 2);
+        })
+    }
+
+    #[pg_test(error = "width must be positive")]
+    fn test_m4_zero_width() {
+        Spi::connect(|mut client| {
+            client
+                .update("SELECT toolkit_experimental.m4(now(), 1.0, 0)", None, None)
+                .unwrap();
+        })
+    }
+}
@@ -23,6 +23,8 @@ use self::Method::*;
 
 use crate::raw::bytea;
 
+pub mod decay;
+
 type StatsSummary1DTF = InternalStatsSummary1D<TwoFloat>;
 type StatsSummary2DTF = InternalStatsSummary2D<TwoFloat>;
 
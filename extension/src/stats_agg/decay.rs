@@ -0,0 +1,876 @@
+use pgx::*;
+
+use crate::{
+    accessors::toolkit_experimental::{
+        AccessorEwmCovariance, AccessorEwmVariance, AccessorEwmVarianceX, AccessorEwmVarianceY,
+        AccessorEwma, AccessorEwmaX, AccessorEwmaY,
+    },
+    aggregate_utils::in_aggregate_context,
+    build,
+    palloc::{Inner, Internal, InternalAsValue, ToInternal},
+    pg_type,
+    raw::{bytea, Interval, TimestampTz},
+    ron_inout_funcs,
+};
+
+use stats_agg::decay::{DecayingSummary1D, DecayingSummary2D};
+use stats_agg::{StatsError, XYPair};
+
+use toolkit_experimental::{DecayingStatsSummary1D, DecayingStatsSummary2D};
+
+#[pg_schema]
+pub mod toolkit_experimental {
+    use super::*;
+
+    pg_type! {
+        #[derive(Debug, PartialEq)]
+        struct DecayingStatsSummary1D {
+            half_life: i64,
+            ref_time: i64,
+            n: u64,
+            w: f64,
+            mean: f64,
+            m2: f64,
+        }
+    }
+
+    pg_type! {
+        #[derive(Debug, PartialEq)]
+        struct DecayingStatsSummary2D {
+            half_life: i64,
+            ref_time: i64,
+            n: u64,
+            w: f64,
+            mean_x: f64,
+            mean_y: f64,
+            m2x: f64,
+            m2y: f64,
+            cxy: f64,
+        }
+    }
+
+    ron_inout_funcs!(DecayingStatsSummary1D);
+    ron_inout_funcs!(DecayingStatsSummary2D);
+}
+
+impl<'input> DecayingStatsSummary1D<'input> {
+    pub fn to_internal(&self) -> DecayingSummary1D {
+        DecayingSummary1D {
+            half_life: self.half_life,
+            ref_time: self.ref_time,
+            n: self.n,
+            w: self.w,
+            mean: self.mean,
+            m2: self.m2,
+        }
+    }
+    pub fn from_internal(st: DecayingSummary1D) -> Self {
+        build!(DecayingStatsSummary1D {
+            half_life: st.half_life,
+            ref_time: st.ref_time,
+            n: st.n,
+            w: st.w,
+            mean: st.mean,
+            m2: st.m2,
+        })
+    }
+}
+
+impl<'input> DecayingStatsSummary2D<'input> {
+    pub fn to_internal(&self) -> DecayingSummary2D {
+        DecayingSummary2D {
+            half_life: self.half_life,
+            ref_time: self.ref_time,
+            n: self.n,
+            w: self.w,
+            mean_x: self.mean_x,
+            mean_y: self.mean_y,
+            m2x: self.m2x,
+            m2y: self.m2y,
+            cxy: self.cxy,
+        }
+    }
+    pub fn from_internal(st: DecayingSummary2D) -> Self {
+        build!(DecayingStatsSummary2D {
+            half_life: st.half_life,
+            ref_time: st.ref_time,
+            n: st.n,
+            w: st.w,
+            mean_x: st.mean_x,
+            mean_y: st.mean_y,
+            m2x: st.m2x,
+            m2y: st.m2y,
+            cxy: st.cxy,
+        })
+    }
+}
+
+// Every partial must compute the same half-life for them to be combined, so
+// intervals with months are rejected.
+pub fn half_life_micros(half_life: Interval) -> i64 {
+    let micros = crate::datum_utils::interval_to_micros(half_life);
+    if micros <= 0 {
+        pgx::error!("half-life must be a positive interval")
+    }
+    micros
+}
+
+fn combine_error(error: StatsError) -> ! {
+    match error {
+        StatsError::MismatchedHalfLife => {
+            pgx::error!("cannot combine decaying summaries with different half-lives")
+        }
+        StatsError::DoubleOverflow => pgx::error!("double overflow in decaying summary"),
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, strict, schema = "toolkit_experimental")]
+pub fn decaying_stats1d_serialize(state: Internal) -> bytea {
+    let ser: &DecayingSummary1D = unsafe { state.get().unwrap() };
+    crate::do_serialize!(ser)
+}
+
+#[pg_extern(immutable, parallel_safe, strict, schema = "toolkit_experimental")]
+pub fn decaying_stats1d_deserialize(bytes: bytea, _internal: Internal) -> Option<Internal> {
+    decaying_stats1d_deserialize_inner(bytes).internal()
+}
+pub fn decaying_stats1d_deserialize_inner(bytes: bytea) -> Inner<DecayingSummary1D> {
+    let de: DecayingSummary1D = crate::do_deserialize!(bytes, DecayingSummary1D);
+    de.into()
+}
+
+#[pg_extern(immutable, parallel_safe, strict, schema = "toolkit_experimental")]
+pub fn decaying_stats2d_serialize(state: Internal) -> bytea {
+    let ser: &DecayingSummary2D = unsafe { state.get().unwrap() };
+    crate::do_serialize!(ser)
+}
+
+#[pg_extern(immutable, parallel_safe, strict, schema = "toolkit_experimental")]
+pub fn decaying_stats2d_deserialize(bytes: bytea, _internal: Internal) -> Option<Internal> {
+    decaying_stats2d_deserialize_inner(bytes).internal()
+}
+pub fn decaying_stats2d_deserialize_inner(bytes: bytea) -> Inner<DecayingSummary2D> {
+    let de: DecayingSummary2D = crate::do_deserialize!(bytes, DecayingSummary2D);
+    de.into()
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn decaying_stats1d_trans(
+    state: Internal,
+    half_life: Interval,
+    ts: Option<TimestampTz>,
+    val: Option<f64>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    decaying_stats1d_trans_inner(unsafe { state.to_inner() }, half_life, ts, val, fcinfo).internal()
+}
+pub fn decaying_stats1d_trans_inner(
+    state: Option<Inner<DecayingSummary1D>>,
+    half_life: Interval,
+    ts: Option<TimestampTz>,
+    val: Option<f64>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<DecayingSummary1D>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            // return an empty one from the trans function because otherwise it breaks in the window context
+            let mut state =
+                state.unwrap_or_else(|| DecayingSummary1D::new(half_life_micros(half_life)).into());
+            if let (Some(ts), Some(val)) = (ts, val) {
+                state.accum(ts.into(), val);
+            }
+            Some(state)
+        })
+    }
+}
+
+// as with stats_agg, if either the y or x value is missing we disregard the entire point
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn decaying_stats2d_trans(
+    state: Internal,
+    half_life: Interval,
+    ts: Option<TimestampTz>,
+    y: Option<f64>,
+    x: Option<f64>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    decaying_stats2d_trans_inner(unsafe { state.to_inner() }, half_life, ts, y, x, fcinfo)
+        .internal()
+}
+pub fn decaying_stats2d_trans_inner(
+    state: Option<Inner<DecayingSummary2D>>,
+    half_life: Interval,
+    ts: Option<TimestampTz>,
+    y: Option<f64>,
+    x: Option<f64>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<DecayingSummary2D>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let mut state =
+                state.unwrap_or_else(|| DecayingSummary2D::new(half_life_micros(half_life)).into());
+            if let (Some(ts), Some(y), Some(x)) = (ts, y, x) {
+                state.accum(ts.into(), XYPair { y, x });
+            }
+            Some(state)
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn decaying_stats1d_summary_trans<'a>(
+    state: Internal,
+    value: Option<DecayingStatsSummary1D<'a>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    decaying_stats1d_summary_trans_inner(unsafe { state.to_inner() }, value, fcinfo).internal()
+}
+pub fn decaying_stats1d_summary_trans_inner(
+    state: Option<Inner<DecayingSummary1D>>,
+    value: Option<DecayingStatsSummary1D>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<DecayingSummary1D>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || match (state, value) {
+            (state, None) => state,
+            (None, Some(value)) => Some(value.to_internal().into()),
+            (Some(mut state), Some(value)) => {
+                *state = state
+                    .combine(&value.to_internal())
+                    .unwrap_or_else(combine_error);
+                Some(state)
+            }
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn decaying_stats2d_summary_trans<'a>(
+    state: Internal,
+    value: Option<DecayingStatsSummary2D<'a>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    decaying_stats2d_summary_trans_inner(unsafe { state.to_inner() }, value, fcinfo).internal()
+}
+pub fn decaying_stats2d_summary_trans_inner(
+    state: Option<Inner<DecayingSummary2D>>,
+    value: Option<DecayingStatsSummary2D>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<DecayingSummary2D>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || match (state, value) {
+            (state, None) => state,
+            (None, Some(value)) => Some(value.to_internal().into()),
+            (Some(mut state), Some(value)) => {
+                *state = state
+                    .combine(&value.to_internal())
+                    .unwrap_or_else(combine_error);
+                Some(state)
+            }
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn decaying_stats1d_combine(
+    state1: Internal,
+    state2: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    unsafe {
+        decaying_stats1d_combine_inner(state1.to_inner(), state2.to_inner(), fcinfo).internal()
+    }
+}
+pub fn decaying_stats1d_combine_inner(
+    state1: Option<Inner<DecayingSummary1D>>,
+    state2: Option<Inner<DecayingSummary1D>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<DecayingSummary1D>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || match (state1, state2) {
+            (None, None) => None,
+            (None, Some(state2)) => Some((*state2).into()),
+            (Some(state1), None) => Some((*state1).into()),
+            (Some(state1), Some(state2)) => {
+                Some(state1.combine(&state2).unwrap_or_else(combine_error).into())
+            }
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn decaying_stats2d_combine(
+    state1: Internal,
+    state2: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    unsafe {
+        decaying_stats2d_combine_inner(state1.to_inner(), state2.to_inner(), fcinfo).internal()
+    }
+}
+pub fn decaying_stats2d_combine_inner(
+    state1: Option<Inner<DecayingSummary2D>>,
+    state2: Option<Inner<DecayingSummary2D>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<DecayingSummary2D>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || match (state1, state2) {
+            (None, None) => None,
+            (None, Some(state2)) => Some((*state2).into()),
+            (Some(state1), None) => Some((*state1).into()),
+            (Some(state1), Some(state2)) => {
+                Some(state1.combine(&state2).unwrap_or_else(combine_error).into())
+            }
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+fn decaying_stats1d_final(
+    state: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<DecayingStatsSummary1D<'static>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let state: &DecayingSummary1D = state.get()?;
+            Some(DecayingStatsSummary1D::from_internal(*state))
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+fn decaying_stats2d_final(
+    state: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<DecayingStatsSummary2D<'static>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let state: &DecayingSummary2D = state.get()?;
+            Some(DecayingStatsSummary2D::from_internal(*state))
+        })
+    }
+}
+
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.decaying_stats_agg(\n\
+        half_life INTERVAL, ts TIMESTAMPTZ, value DOUBLE PRECISION\n\
+    ) (\n\
+        sfunc = toolkit_experimental.decaying_stats1d_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.decaying_stats1d_final,\n\
+        combinefunc = toolkit_experimental.decaying_stats1d_combine,\n\
+        serialfunc = toolkit_experimental.decaying_stats1d_serialize,\n\
+        deserialfunc = toolkit_experimental.decaying_stats1d_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+",
+    name = "decaying_stats_agg_1d",
+    requires = [
+        decaying_stats1d_trans,
+        decaying_stats1d_final,
+        decaying_stats1d_combine,
+        decaying_stats1d_serialize,
+        decaying_stats1d_deserialize
+    ],
+);
+
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.decaying_stats_agg(\n\
+        half_life INTERVAL, ts TIMESTAMPTZ, y DOUBLE PRECISION, x DOUBLE PRECISION\n\
+    ) (\n\
+        sfunc = toolkit_experimental.decaying_stats2d_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.decaying_stats2d_final,\n\
+        combinefunc = toolkit_experimental.decaying_stats2d_combine,\n\
+        serialfunc = toolkit_experimental.decaying_stats2d_serialize,\n\
+        deserialfunc = toolkit_experimental.decaying_stats2d_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+",
+    name = "decaying_stats_agg_2d",
+    requires = [
+        decaying_stats2d_trans,
+        decaying_stats2d_final,
+        decaying_stats2d_combine,
+        decaying_stats2d_serialize,
+        decaying_stats2d_deserialize
+    ],
+);
+
+// the rollups re-weight each partial by the offset between its latest point and the latest point overall
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.rollup(ss toolkit_experimental.DecayingStatsSummary1D)\n\
+    (\n\
+        sfunc = toolkit_experimental.decaying_stats1d_summary_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.decaying_stats1d_final,\n\
+        combinefunc = toolkit_experimental.decaying_stats1d_combine,\n\
+        serialfunc = toolkit_experimental.decaying_stats1d_serialize,\n\
+        deserialfunc = toolkit_experimental.decaying_stats1d_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+",
+    name = "decaying_stats_1d_rollup",
+    requires = [
+        decaying_stats1d_summary_trans,
+        decaying_stats1d_final,
+        decaying_stats1d_combine,
+        decaying_stats1d_serialize,
+        decaying_stats1d_deserialize
+    ],
+);
+
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.rollup(ss toolkit_experimental.DecayingStatsSummary2D)\n\
+    (\n\
+        sfunc = toolkit_experimental.decaying_stats2d_summary_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.decaying_stats2d_final,\n\
+        combinefunc = toolkit_experimental.decaying_stats2d_combine,\n\
+        serialfunc = toolkit_experimental.decaying_stats2d_serialize,\n\
+        deserialfunc = toolkit_experimental.decaying_stats2d_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+",
+    name = "decaying_stats_2d_rollup",
+    requires = [
+        decaying_stats2d_summary_trans,
+        decaying_stats2d_final,
+        decaying_stats2d_combine,
+        decaying_stats2d_serialize,
+        decaying_stats2d_deserialize
+    ],
+);
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_decaying_stats1d_ewma<'a>(
+    summary: DecayingStatsSummary1D<'a>,
+    _accessor: AccessorEwma<'a>,
+) -> Option<f64> {
+    decaying_stats1d_ewma(summary)
+}
+
+#[pg_extern(
+    name = "ewma",
+    strict,
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+pub(crate) fn decaying_stats1d_ewma<'a>(summary: DecayingStatsSummary1D<'a>) -> Option<f64> {
+    summary.to_internal().ewma()
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_decaying_stats1d_ewm_variance<'a>(
+    summary: DecayingStatsSummary1D<'a>,
+    _accessor: AccessorEwmVariance<'a>,
+) -> Option<f64> {
+    decaying_stats1d_ewm_variance(summary)
+}
+
+#[pg_extern(
+    name = "ewm_variance",
+    strict,
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+pub(crate) fn decaying_stats1d_ewm_variance<'a>(
+    summary: DecayingStatsSummary1D<'a>,
+) -> Option<f64> {
+    summary.to_internal().ewm_variance()
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_decaying_stats1d_num_vals<'a>(
+    summary: DecayingStatsSummary1D<'a>,
+    _accessor: crate::accessors::AccessorNumVals<'a>,
+) -> i64 {
+    decaying_stats1d_num_vals(summary)
+}
+
+#[pg_extern(
+    name = "num_vals",
+    strict,
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+fn decaying_stats1d_num_vals<'a>(summary: DecayingStatsSummary1D<'a>) -> i64 {
+    summary.to_internal().count()
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_decaying_stats2d_ewma_x<'a>(
+    summary: DecayingStatsSummary2D<'a>,
+    _accessor: AccessorEwmaX<'a>,
+) -> Option<f64> {
+    decaying_stats2d_ewma_x(summary)
+}
+
+#[pg_extern(
+    name = "ewma_x",
+    strict,
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+fn decaying_stats2d_ewma_x<'a>(summary: DecayingStatsSummary2D<'a>) -> Option<f64> {
+    Some(summary.to_internal().ewma()?.x)
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_decaying_stats2d_ewma_y<'a>(
+    summary: DecayingStatsSummary2D<'a>,
+    _accessor: AccessorEwmaY<'a>,
+) -> Option<f64> {
+    decaying_stats2d_ewma_y(summary)
+}
+
+#[pg_extern(
+    name = "ewma_y",
+    strict,
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+fn decaying_stats2d_ewma_y<'a>(summary: DecayingStatsSummary2D<'a>) -> Option<f64> {
+    Some(summary.to_internal().ewma()?.y)
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_decaying_stats2d_ewm_variance_x<'a>(
+    summary: DecayingStatsSummary2D<'a>,
+    _accessor: AccessorEwmVarianceX<'a>,
+) -> Option<f64> {
+    decaying_stats2d_ewm_variance_x(summary)
+}
+
+#[pg_extern(
+    name = "ewm_variance_x",
+    strict,
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+fn decaying_stats2d_ewm_variance_x<'a>(summary: DecayingStatsSummary2D<'a>) -> Option<f64> {
+    Some(summary.to_internal().ewm_variance()?.x)
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_decaying_stats2d_ewm_variance_y<'a>(
+    summary: DecayingStatsSummary2D<'a>,
+    _accessor: AccessorEwmVarianceY<'a>,
+) -> Option<f64> {
+    decaying_stats2d_ewm_variance_y(summary)
+}
+
+#[pg_extern(
+    name = "ewm_variance_y",
+    strict,
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+fn decaying_stats2d_ewm_variance_y<'a>(summary: DecayingStatsSummary2D<'a>) -> Option<f64> {
+    Some(summary.to_internal().ewm_variance()?.y)
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_decaying_stats2d_ewm_covariance<'a>(
+    summary: DecayingStatsSummary2D<'a>,
+    _accessor: AccessorEwmCovariance<'a>,
+) -> Option<f64> {
+    decaying_stats2d_ewm_covariance(summary)
+}
+
+#[pg_extern(
+    name = "ewm_covariance",
+    strict,
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+fn decaying_stats2d_ewm_covariance<'a>(summary: DecayingStatsSummary2D<'a>) -> Option<f64> {
+    summary.to_internal().ewm_covariance()
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_decaying_stats2d_num_vals<'a>(
+    summary: DecayingStatsSummary2D<'a>,
+    _accessor: crate::accessors::AccessorNumVals<'a>,
+) -> i64 {
+    decaying_stats2d_num_vals(summary)
+}
+
+#[pg_extern(
+    name = "num_vals",
+    strict,
+    immutable,
+    parallel_safe,
+    schema = "toolkit_experimental"
+)]
+fn decaying_stats2d_num_vals<'a>(summary: DecayingStatsSummary2D<'a>) -> i64 {
+    summary.to_internal().count()
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use approx::assert_relative_eq;
+    use pgx::*;
+    use pgx_macros::pg_test;
+
+    // two points an hour apart with a one hour half-life: the first point has half the weight of the second
+    const SIMPLE: &str = "(VALUES ('2020-01-01 00:00 UTC'::TIMESTAMPTZ, 10.0, 1.0), \
+        ('2020-01-01 01:00 UTC'::TIMESTAMPTZ, 20.0, 4.0)) v(time, y, x)";
+
+    fn select_one<T: FromDatum + IntoDatum>(client: &mut pgx::spi::SpiClient, stmt: &str) -> T {
+        client
+            .update(stmt, None, None)
+            .unwrap()
+            .first()
+            .get_one::<T>()
+            .unwrap()
+            .unwrap()
+    }
+
+    #[pg_test]
+    fn test_decaying_stats_agg_1d() {
+        Spi::connect(|mut client| {
+            let summary = format!(
+                "SELECT toolkit_experimental.decaying_stats_agg('1 hour', time, y) AS s FROM {}",
+                SIMPLE
+            );
+
+            let ewma: f64 = select_one(
+                &mut client,
+                &format!("SELECT toolkit_experimental.ewma(s) FROM ({}) q", summary),
+            );
+            assert_relative_eq!(ewma, 25.0 / 1.5);
+
+            let variance: f64 = select_one(
+                &mut client,
+                &format!(
+                    "SELECT toolkit_experimental.ewm_variance(s) FROM ({}) q",
+                    summary
+                ),
+            );
+            assert_relative_eq!(variance, 200.0 / 9.0, max_relative = 1e-12);
+
+            let arrow: f64 = select_one(
+                &mut client,
+                &format!("SELECT s->toolkit_experimental.ewma() FROM ({}) q", summary),
+            );
+            assert_relative_eq!(arrow, ewma);
+
+            let count: i64 = select_one(
+                &mut client,
+                &format!(
+                    "SELECT toolkit_experimental.num_vals(s) FROM ({}) q",
+                    summary
+                ),
+            );
+            assert_eq!(count, 2);
+
+            // nulls are ignored
+            let ewma: f64 = select_one(
+                &mut client,
+                "SELECT toolkit_experimental.ewma(\
+                    toolkit_experimental.decaying_stats_agg('1 hour', time, value)) \
+                FROM (VALUES ('2020-01-01 00:00 UTC'::TIMESTAMPTZ, 5.0), \
+                    ('2020-01-01 01:00 UTC'::TIMESTAMPTZ, NULL), \
+                    (NULL, 7.0)) v(time, value)",
+            );
+            assert_relative_eq!(ewma, 5.0);
+        });
+    }
+
+    #[pg_test]
+    fn test_decaying_stats_agg_2d() {
+        Spi::connect(|mut client| {
+            let summary = format!(
+                "SELECT toolkit_experimental.decaying_stats_agg('1 hour', time, y, x) AS s FROM {}",
+                SIMPLE
+            );
+            let (ewma_y, ewma_x) = client
+                .update(
+                    &format!(
+                        "SELECT s->toolkit_experimental.ewma_y(), s->toolkit_experimental.ewma_x() \
+                        FROM ({}) q",
+                        summary
+                    ),
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<f64, f64>()
+                .unwrap();
+            let (var_x, covar) = client
+                .update(
+                    &format!(
+                        "SELECT s->toolkit_experimental.ewm_variance_x(), \
+                            s->toolkit_experimental.ewm_covariance() \
+                        FROM ({}) q",
+                        summary
+                    ),
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<f64, f64>()
+                .unwrap();
+            assert_relative_eq!(ewma_y.unwrap(), 25.0 / 1.5);
+            assert_relative_eq!(ewma_x.unwrap(), 4.5 / 1.5);
+            assert_relative_eq!(var_x.unwrap(), 2.0, max_relative = 1e-12);
+            assert_relative_eq!(covar.unwrap(), 20.0 / 3.0, max_relative = 1e-12);
+        });
+    }
+
+    #[pg_test]
+    fn test_decaying_stats_agg_rollup() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "CREATE TABLE decay_test(time TIMESTAMPTZ, value DOUBLE PRECISION)",
+                    None,
+                    None,
+                )
+                .unwrap();
+            client
+                .update(
+                    "INSERT INTO decay_test SELECT \
+                        '2020-01-01 UTC'::TIMESTAMPTZ + v * '10 minutes'::INTERVAL, \
+                        sin(v) * 10 + v \
+                    FROM generate_series(0, 199) v",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            let (direct_mean, direct_var) = client
+                .update(
+                    "SELECT toolkit_experimental.ewma(s), toolkit_experimental.ewm_variance(s) \
+                    FROM (SELECT toolkit_experimental.decaying_stats_agg('3 hours', time, value) s \
+                        FROM decay_test) q",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<f64, f64>()
+                .unwrap();
+
+            let (rollup_mean, rollup_var) = client
+                .update(
+                    "SELECT toolkit_experimental.ewma(s), toolkit_experimental.ewm_variance(s) \
+                    FROM (SELECT toolkit_experimental.rollup(partial) s \
+                        FROM (SELECT toolkit_experimental.decaying_stats_agg('3 hours', time, value) partial \
+                            FROM decay_test \
+                            GROUP BY date_trunc('day', time)) p) q",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<f64, f64>()
+                .unwrap();
+
+            assert_relative_eq!(
+                direct_mean.unwrap(),
+                rollup_mean.unwrap(),
+                max_relative = 1e-12
+            );
+            assert_relative_eq!(
+                direct_var.unwrap(),
+                rollup_var.unwrap(),
+                max_relative = 1e-12
+            );
+        });
+    }
+
+    #[pg_test]
+    fn test_decaying_stats_agg_io() {
+        Spi::connect(|mut client| {
+            let text: String = select_one(
+                &mut client,
+                &format!(
+                    "SELECT toolkit_experimental.decaying_stats_agg('1 hour', time, y)::TEXT FROM {}",
+                    SIMPLE
+                ),
+            );
+            assert_eq!(
+                text,
+                "(version:1,half_life:3600000000,ref_time:631155600000000,n:2,w:1.5,mean:16.666666666666668,m2:33.33333333333332)"
+            );
+
+            let ewma: f64 = select_one(
+                &mut client,
+                &format!(
+                    "SELECT toolkit_experimental.ewma('{}'::toolkit_experimental.DecayingStatsSummary1D)",
+                    text
+                ),
+            );
+            assert_relative_eq!(ewma, 25.0 / 1.5);
+        });
+    }
+
+    #[pg_test(error = "cannot combine decaying summaries with different half-lives")]
+    fn test_decaying_stats_agg_rollup_mismatched_half_life() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.rollup(s) FROM (\
+                        SELECT toolkit_experimental.decaying_stats_agg('1 hour', now(), 1.0) s \
+                        UNION ALL \
+                        SELECT toolkit_experimental.decaying_stats_agg('2 hours', now(), 2.0) s\
+                    ) q",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test(error = "intervals with months or years have no fixed length, use days instead")]
+    fn test_decaying_stats_agg_month_half_life() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.decaying_stats_agg('1 month', now(), 1.0)",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test(error = "half-life must be a positive interval")]
+    fn test_decaying_stats_agg_negative_half_life() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.decaying_stats_agg('-1 hour', now(), 1.0)",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+}
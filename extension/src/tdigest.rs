@@ -1,9 +1,13 @@
 use std::{convert::TryInto, ops::Deref};
 
-use pgx::*;
+use pgx::{iter::TableIterator, *};
 
 use crate::{
     accessors::{
+        toolkit_experimental::{
+            AccessorApproxIqr, AccessorApproxMad, AccessorApproxTrimmedMean,
+            AccessorApproxWinsorizedMean,
+        },
         AccessorApproxPercentile, AccessorApproxPercentileRank, AccessorMaxVal, AccessorMean,
         AccessorMinVal, AccessorNumVals,
     },
@@ -11,6 +15,7 @@ use crate::{
     flatten,
     palloc::{Inner, Internal, InternalAsValue, ToInternal},
     pg_type,
+    uddsketch::{check_histogram_bounds, check_trim_bounds},
 };
 
 use tdigest::{Centroid, TDigest as InternalTDigest};
@@ -55,6 +60,46 @@ pub fn tdigest_trans_inner(
     }
 }
 
+// PG function for adding pre-aggregated values to a digest, each value is
+// counted `weight` times.  Rows with a null value or weight are ignored.
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn tdigest_weighted_trans(
+    state: Internal,
+    size: i32,
+    value: Option<f64>,
+    weight: Option<i64>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    tdigest_weighted_trans_inner(unsafe { state.to_inner() }, size, value, weight, fcinfo)
+        .internal()
+}
+pub fn tdigest_weighted_trans_inner(
+    state: Option<Inner<tdigest::Builder>>,
+    size: i32,
+    value: Option<f64>,
+    weight: Option<i64>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<tdigest::Builder>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let (value, weight) = match (value, weight) {
+                // NaNs are nonsensical in the context of a percentile, so exclude them
+                (Some(value), Some(weight)) if !value.is_nan() => (value, weight),
+                _ => return state,
+            };
+            if weight < 0 {
+                pgx::error!("weight must not be negative, got {}", weight)
+            }
+            let mut state = match state {
+                None => tdigest::Builder::with_size(size.try_into().unwrap()).into(),
+                Some(state) => state,
+            };
+            state.push_weighted(value, weight as u64);
+            Some(state)
+        })
+    }
+}
+
 // PG function for merging digests.
 #[pg_extern(immutable, parallel_safe)]
 pub fn tdigest_combine(
@@ -217,6 +262,30 @@ extension_sql!(
     ],
 );
 
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.tdigest(\n\
+        size integer, value DOUBLE PRECISION, weight bigint\n\
+    ) (\n\
+        sfunc = toolkit_experimental.tdigest_weighted_trans,\n\
+        stype = internal,\n\
+        finalfunc = tdigest_final,\n\
+        combinefunc = tdigest_combine,\n\
+        serialfunc = tdigest_serialize,\n\
+        deserialfunc = tdigest_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+",
+    name = "tdigest_weighted_agg",
+    requires = [
+        tdigest_weighted_trans,
+        tdigest_final,
+        tdigest_combine,
+        tdigest_serialize,
+        tdigest_deserialize
+    ],
+);
+
 #[pg_extern(immutable, parallel_safe)]
 pub fn tdigest_compound_trans(
     state: Internal,
@@ -363,6 +432,129 @@ pub fn tdigest_quantile_at_value<'a>(value: f64, digest: TDigest<'a>) -> f64 {
         .estimate_quantile_at_value(value)
 }
 
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_tdigest_approx_trimmed_mean<'a>(
+    sketch: TDigest<'a>,
+    accessor: AccessorApproxTrimmedMean<'a>,
+) -> f64 {
+    tdigest_trimmed_mean(sketch, accessor.low, accessor.high)
+}
+
+// Approximate the mean of the values between the `low` and `high` quantiles.
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "approx_trimmed_mean",
+    schema = "toolkit_experimental"
+)]
+pub fn tdigest_trimmed_mean<'a>(digest: TDigest<'a>, low: f64, high: f64) -> f64 {
+    check_trim_bounds(low, high);
+    digest
+        .to_internal_tdigest()
+        .estimate_trimmed_mean(low, high)
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_tdigest_approx_winsorized_mean<'a>(
+    sketch: TDigest<'a>,
+    accessor: AccessorApproxWinsorizedMean<'a>,
+) -> f64 {
+    tdigest_winsorized_mean(sketch, accessor.low, accessor.high)
+}
+
+// Approximate the mean of the values, with those outside of the `low` and `high`
+// quantiles replaced by the value at the quantile.
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "approx_winsorized_mean",
+    schema = "toolkit_experimental"
+)]
+pub fn tdigest_winsorized_mean<'a>(digest: TDigest<'a>, low: f64, high: f64) -> f64 {
+    check_trim_bounds(low, high);
+    digest
+        .to_internal_tdigest()
+        .estimate_winsorized_mean(low, high)
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_tdigest_approx_iqr<'a>(sketch: TDigest<'a>, _accessor: AccessorApproxIqr<'a>) -> f64 {
+    tdigest_iqr(sketch)
+}
+
+// Approximate interquartile range, the difference between the 0.75 and 0.25 quantiles.
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "approx_iqr",
+    schema = "toolkit_experimental"
+)]
+pub fn tdigest_iqr<'a>(digest: TDigest<'a>) -> f64 {
+    let digest = digest.to_internal_tdigest();
+    digest.estimate_quantile(0.75) - digest.estimate_quantile(0.25)
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_tdigest_approx_mad<'a>(sketch: TDigest<'a>, _accessor: AccessorApproxMad<'a>) -> f64 {
+    tdigest_mad(sketch)
+}
+
+// Approximate median absolute deviation from the median.
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "approx_mad",
+    schema = "toolkit_experimental"
+)]
+pub fn tdigest_mad<'a>(digest: TDigest<'a>) -> f64 {
+    digest
+        .to_internal_tdigest()
+        .estimate_median_absolute_deviation()
+}
+
+// The digest's centroids, from lowest to highest mean.
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "centroids",
+    schema = "toolkit_experimental"
+)]
+pub fn tdigest_centroids<'a>(
+    digest: TDigest<'a>,
+) -> TableIterator<'static, (name!(mean, f64), name!(weight, i64))> {
+    let centroids: Vec<_> = digest
+        .centroids
+        .iter()
+        .map(|c| (c.mean(), c.weight() as i64))
+        .collect();
+    TableIterator::new(centroids.into_iter())
+}
+
+// Estimated number of values in each bin (`bounds[i]`, `bounds[i + 1]`].
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "approx_histogram",
+    schema = "toolkit_experimental"
+)]
+pub fn tdigest_approx_histogram<'a>(digest: TDigest<'a>, bounds: Vec<f64>) -> Vec<f64> {
+    check_histogram_bounds(&bounds);
+    let count = digest.count as f64;
+    let digest = digest.to_internal_tdigest();
+    let ranks: Vec<f64> = bounds
+        .iter()
+        .map(|b| digest.estimate_quantile_at_value(*b))
+        .collect();
+    ranks
+        .windows(2)
+        .map(|w| (w[1] - w[0]).max(0.0) * count)
+        .collect()
+}
+
 #[pg_operator(immutable, parallel_safe)]
 #[opname(->)]
 pub fn arrow_tdigest_num_vals<'a>(sketch: TDigest<'a>, _accessor: AccessorNumVals<'a>) -> f64 {
@@ -576,6 +768,241 @@ mod tests {
         });
     }
 
+    #[pg_test]
+    fn test_tdigest_weighted() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "CREATE TABLE weighted_test (value DOUBLE PRECISION, weight BIGINT)",
+                    None,
+                    None,
+                )
+                .unwrap();
+            // value v is seen v times
+            client
+                .update(
+                    "INSERT INTO weighted_test \
+                    SELECT v, v::bigint FROM generate_series(1, 1000) v",
+                    None,
+                    None,
+                )
+                .unwrap();
+            client
+                .update(
+                    "INSERT INTO weighted_test VALUES (NULL, 3), (-5, NULL), ('NaN', 2), (-1, 0)",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            client
+                .update(
+                    "CREATE VIEW weighted_digest AS \
+                    SELECT toolkit_experimental.tdigest(100, value, weight) AS digest \
+                    FROM weighted_test",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            let (min, max, count) = client
+                .update(
+                    "SELECT \
+                    min_val(digest), \
+                    max_val(digest), \
+                    num_vals(digest) \
+                    FROM weighted_digest",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_three::<f64, f64, f64>()
+                .unwrap();
+            assert_eq!(min, Some(1.0));
+            assert_eq!(max, Some(1000.0));
+            assert_eq!(count, Some(500500.0));
+
+            let mean = client
+                .update("SELECT digest->mean() FROM weighted_digest", None, None)
+                .unwrap()
+                .first()
+                .get_one::<f64>()
+                .unwrap();
+            // sum(v^2) / sum(v)
+            apx_eql(mean.unwrap(), 667.0, 0.000001);
+
+            // the q percentile is the v where v(v+1)/2 = q * 500500
+            for quantile in [0.1, 0.5, 0.9, 0.99] {
+                let estimate = client
+                    .update(
+                        &format!(
+                            "SELECT approx_percentile({}, digest) FROM weighted_digest",
+                            quantile
+                        ),
+                        None,
+                        None,
+                    )
+                    .unwrap()
+                    .first()
+                    .get_one::<f64>()
+                    .unwrap();
+                pct_eql(estimate.unwrap(), (quantile * 1001000.0_f64).sqrt(), 0.01);
+            }
+
+            let count = client
+                .update(
+                    "SELECT num_vals(rollup(digest)) FROM ( \
+                        SELECT digest FROM weighted_digest \
+                        UNION ALL \
+                        SELECT tdigest(100, value) FROM weighted_test \
+                    ) d",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<f64>()
+                .unwrap();
+            // the unweighted digest also counts the -5 and -1 rows
+            assert_eq!(count, Some(500500.0 + 1002.0));
+        });
+    }
+
+    #[pg_test(error = "weight must not be negative, got -2")]
+    fn test_tdigest_negative_weight() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.tdigest(100, 1.0, -2)",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test]
+    fn test_tdigest_robust_statistics() {
+        Spi::connect(|mut client| {
+            // 1 to 1000 and a few outliers
+            client
+                .update(
+                    "CREATE VIEW robust_test AS \
+                    SELECT tdigest(100, v) AS sketch FROM ( \
+                        SELECT generate_series(1, 1000)::float8 AS v \
+                        UNION ALL SELECT 1e9 FROM generate_series(1, 10) \
+                    ) data",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            for (function, arrow, expected) in [
+                (
+                    "toolkit_experimental.approx_trimmed_mean(sketch, 0.1, 0.9)",
+                    "sketch->toolkit_experimental.approx_trimmed_mean(0.1, 0.9)",
+                    505.0,
+                ),
+                (
+                    "toolkit_experimental.approx_winsorized_mean(sketch, 0.1, 0.9)",
+                    "sketch->toolkit_experimental.approx_winsorized_mean(0.1, 0.9)",
+                    505.0,
+                ),
+                (
+                    "toolkit_experimental.approx_iqr(sketch)",
+                    "sketch->toolkit_experimental.approx_iqr()",
+                    505.0,
+                ),
+                (
+                    "toolkit_experimental.approx_mad(sketch)",
+                    "sketch->toolkit_experimental.approx_mad()",
+                    252.5,
+                ),
+            ] {
+                let (value, arrow_value) = client
+                    .update(
+                        &format!("SELECT {}, {} FROM robust_test", function, arrow),
+                        None,
+                        None,
+                    )
+                    .unwrap()
+                    .first()
+                    .get_two::<f64, f64>()
+                    .unwrap();
+                assert_eq!(value, arrow_value);
+                pct_eql(value.unwrap(), expected, 0.02);
+            }
+        });
+    }
+
+    #[pg_test(error = "the bounds must satisfy 0 <= low < high <= 1, got low 0.9 and high 0.1")]
+    fn test_tdigest_robust_statistics_bounds() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.approx_trimmed_mean(tdigest(100, v::float8), 0.9, 0.1) \
+                    FROM generate_series(1, 100) v",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test]
+    fn test_tdigest_centroids_and_histogram() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "CREATE VIEW centroid_test AS \
+                    SELECT tdigest(50, v) AS digest \
+                    FROM generate_series(1, 1000) v",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            let (centroids, weight, unordered) = client
+                .update(
+                    "SELECT count(*), sum(weight), \
+                        count(*) FILTER (WHERE mean < prev) \
+                    FROM ( \
+                        SELECT mean, weight, lag(mean) OVER () AS prev \
+                        FROM centroid_test, toolkit_experimental.centroids(digest) \
+                    ) c",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_three::<i64, i64, i64>()
+                .unwrap();
+            assert!(centroids.unwrap() <= 50);
+            assert_eq!(weight, Some(1000));
+            assert_eq!(unordered, Some(0));
+
+            let histogram = client
+                .update(
+                    "SELECT toolkit_experimental.approx_histogram(\
+                        digest, '{-Infinity, 250, 500, 750, Infinity}') \
+                    FROM centroid_test",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<Vec<f64>>()
+                .unwrap()
+                .unwrap();
+            assert_eq!(histogram.len(), 4);
+            for estimate in &histogram {
+                pct_eql(*estimate, 250.0, 0.05);
+            }
+            apx_eql(histogram.iter().sum(), 1000.0, 0.000001);
+        });
+    }
+
     #[pg_test]
     fn test_tdigest_small_count() {
         Spi::connect(|mut client| {
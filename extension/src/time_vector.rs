@@ -3,6 +3,7 @@
 use crate::pg_sys::timestamptz_to_str;
 use core::str::Utf8Error;
 use pgx::{iter::TableIterator, *};
+use serde::{Deserialize, Serialize};
 use std::ffi::CStr;
 use tera::{Context, Tera};
 
@@ -19,8 +20,10 @@ pub use iter::Iter;
 
 use flat_serialize::*;
 
+mod compression;
 mod iter;
 mod pipeline;
+mod typed;
 
 use crate::raw::bytea;
 
@@ -28,6 +31,8 @@ use crate::raw::bytea;
 pub const FLAG_IS_SORTED: u8 = 0x01;
 pub const FLAG_HAS_NULLS: u8 = 0x01 << 1;
 
+// Timevectors with a version greater than 1 are compressed, their points are
+// stored in `compressed` instead of `points`, see the `compression` module.
 pg_type! {
     #[derive(Debug)]
     #[allow(non_camel_case_types)]
@@ -35,12 +40,67 @@ pg_type! {
         num_points: u32,
         flags: u8,         // extra information about the stored data
         internal_padding: [u8; 3],  // required to be aligned
-        points: [TSPoint; self.num_points],
-        null_val: [u8; (self.num_points + 7)/ 8], // bit vector, must be last element for alignment purposes
+        // the sizes of the compressed timestamps and values, if compressed
+        compressed_len: [u64; if self.version > 1 { 2 } else { 0 }],
+        points: [TSPoint; if self.version > 1 { 0 } else { self.num_points }],
+        null_val: [u8; (self.num_points + 7)/ 8], // bit vector, must come after the points for alignment purposes
+        compressed: [u8; self.compressed_len.iter().sum::<u64>()],
     }
 }
 
-ron_inout_funcs!(Timevector_TSTZ_F64);
+// The text format is always that of an uncompressed timevector, compression
+// only affects how timevectors are stored.
+#[derive(Serialize, Deserialize)]
+struct TimevectorText {
+    version: u8,
+    num_points: u32,
+    flags: u8,
+    internal_padding: [u8; 3],
+    points: Vec<TSPoint>,
+    null_val: Vec<u8>,
+}
+
+impl<'input> InOutFuncs for Timevector_TSTZ_F64<'input> {
+    fn output(&self, buffer: &mut StringInfo) {
+        use crate::serialization::{str_to_db_encoding, EncodedStr::*};
+
+        let text = TimevectorText {
+            version: 1,
+            num_points: self.num_points,
+            flags: self.flags,
+            internal_padding: self.internal_padding,
+            points: self.iter().collect(),
+            null_val: self.null_val.as_slice().to_vec(),
+        };
+        let stringified = ron::to_string(&text).unwrap();
+        match str_to_db_encoding(&stringified) {
+            Utf8(s) => buffer.push_str(s),
+            Other(s) => buffer.push_bytes(s.to_bytes()),
+        }
+    }
+
+    fn input(input: &std::ffi::CStr) -> Timevector_TSTZ_F64<'input>
+    where
+        Self: Sized,
+    {
+        use crate::serialization::str_from_db_encoding;
+
+        let input = str_from_db_encoding(input);
+        let text: TimevectorText = ron::from_str(input).unwrap();
+        let series = build! {
+            Timevector_TSTZ_F64 {
+                num_points: text.num_points,
+                flags: text.flags,
+                internal_padding: text.internal_padding,
+                compressed_len: vec![].into(),
+                points: text.points.into(),
+                null_val: text.null_val.into(),
+                compressed: vec![].into(),
+            }
+        };
+        unsafe { series.flatten() }
+    }
+}
 
 impl<'input> Timevector_TSTZ_F64<'input> {
     pub fn num_points(&self) -> usize {
@@ -54,6 +114,10 @@ impl<'input> Timevector_TSTZ_F64<'input> {
             return None;
         }
 
+        if self.is_compressed() {
+            return self.iter().nth(index);
+        }
+
         Some(self.points.as_slice()[index])
     }
 
@@ -67,6 +131,11 @@ impl<'input> Timevector_TSTZ_F64<'input> {
         self.flags & FLAG_HAS_NULLS != 0
     }
 
+    #[inline]
+    pub fn is_compressed(&self) -> bool {
+        self.version > 1
+    }
+
     pub fn is_null_val(&self, index: usize) -> bool {
         assert!(index < self.num_points()); // should we handle this better
 
@@ -79,10 +148,68 @@ impl<'input> Timevector_TSTZ_F64<'input> {
     fn clone_owned(&self) -> Timevector_TSTZ_F64<'static> {
         Timevector_TSTZ_F64Data::clone(self).into_owned().into()
     }
+
+    /// Returns the timevector in the compressed encoding, or uncompressed if
+    /// compressing it wouldn't save any space.
+    pub fn compress(&self) -> Timevector_TSTZ_F64<'static> {
+        if self.is_compressed() {
+            return self.clone_owned();
+        }
+
+        let (times, values) = compression::compress(self.iter());
+        let compressed_len = [times.len() as u64, values.len() as u64];
+        if times.len() + values.len() + std::mem::size_of_val(&compressed_len)
+            >= self.num_points() * std::mem::size_of::<TSPoint>()
+        {
+            return self.clone_owned();
+        }
+
+        Timevector_TSTZ_F64Data {
+            header: 0,
+            version: 2,
+            padding: [0; 3],
+            num_points: self.num_points,
+            flags: self.flags,
+            internal_padding: [0; 3],
+            compressed_len: compressed_len.to_vec().into(),
+            points: vec![].into(),
+            null_val: self.null_val.as_slice().to_vec().into(),
+            compressed: [times, values].concat().into(),
+        }
+        .into()
+    }
+
+    /// Returns the timevector with its points stored uncompressed, needed by
+    /// anything that works on `points` directly.
+    pub fn decompress(self) -> Timevector_TSTZ_F64<'input> {
+        if !self.is_compressed() {
+            return self;
+        }
+
+        let points: Vec<TSPoint> = self.iter().collect();
+        build! {
+            Timevector_TSTZ_F64 {
+                num_points: self.num_points,
+                flags: self.flags,
+                internal_padding: [0; 3],
+                compressed_len: vec![].into(),
+                points: points.into(),
+                null_val: self.null_val.clone(),
+                compressed: vec![].into(),
+            }
+        }
+    }
 }
 
 impl<'a> Timevector_TSTZ_F64<'a> {
     pub fn iter(&self) -> Iter<'_> {
+        if self.is_compressed() {
+            let len = self.compressed_len.as_slice();
+            let (times, values) = self.compressed.as_slice().split_at(len[0] as usize);
+            return Iter::Compressed {
+                iter: compression::decompress(times, values, self.num_points()),
+            };
+        }
         Iter::Slice {
             iter: self.points.iter(),
         }
@@ -98,6 +225,12 @@ impl<'a> IntoIterator for Timevector_TSTZ_F64<'a> {
     type IntoIter = Iter<'a>;
 
     fn into_iter(self) -> Self::IntoIter {
+        if self.is_compressed() {
+            let points: Vec<TSPoint> = self.iter().collect();
+            return Iter::Slice {
+                iter: Slice::from(points).into_iter(),
+            };
+        }
         #[allow(clippy::unnecessary_to_owned)] // Pretty sure clippy's wrong about this
         Iter::Slice {
             iter: self.points.to_owned().into_iter(),
@@ -228,8 +361,10 @@ pub fn timevector_trans_inner(
                         num_points: 0,
                         flags: FLAG_IS_SORTED,
                         internal_padding: [0; 3],
+                        compressed_len: vec![].into(),
                         points: vec![].into(),
                         null_val: vec![].into(),
+                        compressed: vec![].into(),
                     }
                 }),
                 Some(state) => state,
@@ -324,8 +459,7 @@ pub fn combine(
 
     let is_sorted = first.is_sorted()
         && second.is_sorted()
-        && first.points.as_slice().last().unwrap().ts
-            <= second.points.as_slice().first().unwrap().ts;
+        && first.get(first.num_points() - 1).unwrap().ts <= second.get(0).unwrap().ts;
     let points: Vec<_> = first.iter().chain(second.iter()).collect();
 
     let mut flags = (first.flags & FLAG_HAS_NULLS) | (second.flags & FLAG_HAS_NULLS);
@@ -356,8 +490,10 @@ pub fn combine(
             num_points: points.len() as _,
             flags,
             internal_padding: [0; 3],
+            compressed_len: vec![].into(),
             points: points.into(),
             null_val: null_val.into(),
+            compressed: vec![].into(),
         }
     }
 }
@@ -380,7 +516,9 @@ pub fn timevector_final_inner<'a>(
                 None => return None,
                 Some(state) => state,
             };
-            Some(state.in_current_context())
+            // the results of the aggregates are what gets stored, so this is
+            // where they're compressed
+            Some(state.compress().in_current_context())
         })
     }
 }
@@ -960,6 +1098,115 @@ mod tests {
         })
     }
 
+    #[pg_test]
+    fn test_compressed_timevector() {
+        Spi::connect(|mut client| {
+            client.update("SET timezone TO 'UTC'", None, None).unwrap();
+            client
+                .update(
+                    "CREATE TABLE data(time TIMESTAMPTZ, value DOUBLE PRECISION)",
+                    None,
+                    None,
+                )
+                .unwrap();
+            client
+                .update(
+                    "INSERT INTO data \
+                    SELECT '2020-01-01 UTC'::TIMESTAMPTZ + i * '1 minute'::INTERVAL, \
+                        CASE WHEN i % 100 = 7 THEN NULL ELSE (i / 10)::FLOAT END \
+                    FROM generate_series(0, 999) i",
+                    None,
+                    None,
+                )
+                .unwrap();
+            client
+                .update(
+                    "CREATE TABLE tvecs AS \
+                    SELECT time < '2020-01-01 12:00 UTC' AS first_half, timevector(time, value) AS vector \
+                    FROM data GROUP BY 1",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            // uncompressed this would be 16 bytes per point
+            let size = client
+                .update(
+                    "SELECT pg_column_size(timevector(time, value)) FROM data",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<i32>()
+                .unwrap()
+                .unwrap();
+            assert!(size < 4000, "size {}", size);
+
+            // NULLs are NaN in the unnested output
+            let differences = |client: &mut pgx::spi::SpiClient, vector: &str| {
+                client
+                    .update(
+                        &format!(
+                            "SELECT count(*) FROM (\
+                                (SELECT time, coalesce(value, 'NaN') FROM data \
+                                EXCEPT ALL SELECT * FROM unnest(({0}))) \
+                                UNION ALL \
+                                (SELECT * FROM unnest(({0})) \
+                                EXCEPT ALL SELECT time, coalesce(value, 'NaN') FROM data) \
+                            ) d",
+                            vector
+                        ),
+                        None,
+                        None,
+                    )
+                    .unwrap()
+                    .first()
+                    .get_one::<i64>()
+                    .unwrap()
+                    .unwrap()
+            };
+            assert_eq!(
+                differences(&mut client, "SELECT timevector(time, value) FROM data"),
+                0
+            );
+            assert_eq!(
+                differences(&mut client, "SELECT rollup(vector) FROM tvecs"),
+                0
+            );
+
+            // the text format doesn't change
+            let (compressed, uncompressed) = client
+                .update(
+                    "SELECT vector::TEXT, (vector -> toolkit_experimental.sort())::TEXT \
+                    FROM tvecs WHERE first_half",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<String, String>()
+                .unwrap();
+            assert_eq!(compressed, uncompressed);
+
+            let (num_vals, expected) = client
+                .update(
+                    "SELECT \
+                        (SELECT rollup(vector) FROM tvecs) \
+                            -> toolkit_experimental.filter($$ $value >= 50 $$) \
+                            -> num_vals(), \
+                        (SELECT count(*) FROM data WHERE value >= 50)",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<i64, i64>()
+                .unwrap();
+            assert_eq!(num_vals, expected);
+        })
+    }
+
     #[pg_test]
     fn test_asof_join() {
         Spi::connect(|mut client| {
@@ -0,0 +1,70 @@
+//! The compressed (version 2) encoding of timevectors.
+//!
+//! Timestamps are stored as zigzagged delta-of-deltas in prefix varints, so a
+//! regularly spaced series needs a single byte per timestamp.  Values use the
+//! Gorilla XOR encoding from `encodings::gorilla`, which stores NaNs,
+//! including the ones standing in for NULLs, bit-for-bit.
+
+use encodings::{
+    delta,
+    gorilla::{self, F64Compressor, F64Decompressor},
+    prefix_varint, zigzag,
+};
+
+use tspoint::TSPoint;
+
+/// Returns the encoded timestamps and values of `points`.
+pub fn compress(points: impl Iterator<Item = TSPoint>) -> (Vec<u8>, Vec<u8>) {
+    let mut delta = delta::i64_encoder();
+    let mut delta_of_delta = delta::i64_encoder();
+    let mut times = prefix_varint::I64Compressor::with(move |ts| delta_of_delta(delta(ts)));
+    let mut values = F64Compressor::new();
+    for point in points {
+        times.push(point.ts);
+        values.push(point.val);
+    }
+    (times.finish(), values.finish())
+}
+
+pub fn decompress<'a>(times: &'a [u8], values: &'a [u8], num_points: usize) -> Decompressor<'a> {
+    Decompressor {
+        times,
+        ts: 0,
+        delta: 0,
+        values: gorilla::f64_decompressor(values),
+        remaining: num_points,
+    }
+}
+
+pub struct Decompressor<'a> {
+    times: &'a [u8],
+    ts: i64,
+    delta: i64,
+    values: F64Decompressor<'a>,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Decompressor<'a> {
+    type Item = TSPoint;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        let (delta_of_delta, len) = prefix_varint::read_from_slice(self.times);
+        self.times = &self.times[len..];
+        self.delta = self.delta.wrapping_add(zigzag::decode(delta_of_delta));
+        self.ts = self.ts.wrapping_add(self.delta);
+
+        Some(TSPoint {
+            ts: self.ts,
+            val: self.values.next()?,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
@@ -1,12 +1,20 @@
 mod aggregation;
+mod anomaly;
 mod arithmetic;
+mod combine;
 mod delta;
+mod downsample;
 mod expansion;
+mod fill_holes;
 mod fill_to;
 mod filter;
 mod lambda;
 mod map;
+mod resample;
+mod rolling;
+mod seasonal;
 mod sort;
+mod typed;
 
 use std::convert::TryInto;
 
@@ -14,13 +22,26 @@ use pgx::*;
 
 use super::*;
 
-use crate::{flatten, pg_type, ron_inout_funcs};
+use crate::{datum_utils::interval_to_micros, flatten, pg_type, ron_inout_funcs};
 
+use anomaly::mad_outliers;
+use combine::{combine, CombineJoin};
+use downsample::downsample;
+use fill_holes::{fill_holes, FillHolesMethod};
 use fill_to::{fill_to, FillToMethod};
+use resample::{resample, ResampleMethod};
+use rolling::{rolling, RollingFunction};
+use seasonal::{seasonal, SeasonalFunction};
+
+pub(crate) use typed::run_typed_pipeline_elements;
 
 use delta::timevector_delta;
 use sort::sort_timevector;
 
+// The functions building pipeline elements, and the `->` joining them into a
+// pipeline, only depend on their arguments, so they are all `immutable` and
+// `parallel_safe`.
+
 pub use self::toolkit_experimental::*;
 use crate::serialization::PgProcId;
 
@@ -49,7 +70,8 @@ pub mod toolkit_experimental {
                 resolution: u64,
             },
             // 2 was for resample_to_rate
-            // 3 was for fill_holes
+            // 3 was for the fill_holes of 1.1, since removed, FillHoles is a
+            // new element with a different layout
             Sort: 4 {
             },
             Delta: 5 {
@@ -76,6 +98,40 @@ pub mod toolkit_experimental {
                 interval: i64,
                 fill_method: FillToMethod,
             },
+            Resample: 12 {
+                interval: i64,
+                method: ResampleMethod,
+            },
+            FillHoles: 13 {
+                interval: i64,
+                method: FillHolesMethod,
+            },
+            Rolling: 14 {
+                window: i64,
+                function: RollingFunction,
+            },
+            Combine: 15 {
+                join: CombineJoin,
+                num_points: u64,
+                points: [TSPoint; self.num_points],
+                lambda: LambdaData<'input>,
+            },
+            Seasonal: 16 {
+                period: i64,
+                // a negative tolerance interpolates the earlier value instead
+                tolerance: i64,
+                function: SeasonalFunction,
+            },
+            MadOutliers: 17 {
+                window: i64,
+                threshold: f64,
+            },
+            M4: 18 {
+                width: u64,
+            },
+            MinMaxLTTB: 19 {
+                resolution: u64,
+            },
         }
     }
 
@@ -120,9 +176,11 @@ pub fn arrow_run_pipeline<'a>(
 }
 
 pub fn run_pipeline_elements<'s, 'j, 'i>(
-    mut timevector: Timevector_TSTZ_F64<'s>,
+    timevector: Timevector_TSTZ_F64<'s>,
     pipeline: impl Iterator<Item = Element<'j>> + 'i,
 ) -> Timevector_TSTZ_F64<'s> {
+    // the elements work on the points directly
+    let mut timevector = timevector.decompress();
     for element in pipeline {
         timevector = execute_pipeline_element(timevector, &element);
     }
@@ -143,10 +201,30 @@ pub fn execute_pipeline_element<'s>(
         Element::FilterLambda { lambda } => filter::apply_lambda_to(timevector, lambda),
         Element::Arithmetic { function, rhs } => arithmetic::apply(timevector, *function, *rhs),
         Element::FillTo { .. } => fill_to(timevector, element),
+        Element::Resample { interval, method } => resample(timevector, *interval, *method),
+        Element::FillHoles { interval, method } => fill_holes(timevector, *interval, *method),
+        Element::Rolling { window, function } => rolling(timevector, *window, *function),
+        Element::Combine {
+            join,
+            points,
+            lambda,
+            ..
+        } => combine(timevector, *join, points.as_slice(), lambda),
+        Element::Seasonal {
+            period,
+            tolerance,
+            function,
+        } => seasonal(timevector, *period, *tolerance, *function),
+        Element::MadOutliers { window, threshold } => mad_outliers(timevector, *window, *threshold),
+        Element::M4 { width } => {
+            downsample(timevector, |points| crate::m4::m4(points, *width as _))
+        }
+        Element::MinMaxLTTB { resolution } => downsample(timevector, |points| {
+            crate::lttb::minmax_lttb(points, *resolution as _).into_owned()
+        }),
     }
 }
 
-// TODO is (immutable, parallel_safe) correct?
 #[pg_operator(immutable, parallel_safe)]
 #[opname(->)]
 pub fn arrow_add_unstable_element<'p>(
@@ -298,7 +376,6 @@ ALTER FUNCTION "arrow_add_unstable_element" SUPPORT toolkit_experimental.toolkit
     requires = [pipeline_support],
 );
 
-// TODO is (immutable, parallel_safe) correct?
 #[pg_extern(
     immutable,
     parallel_safe,
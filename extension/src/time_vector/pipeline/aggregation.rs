@@ -10,18 +10,24 @@ use crate::{
     accessors::{AccessorAverage, AccessorNumVals, AccessorSum},
     build,
     counter_agg::CounterSummary,
+    holt_winters::{self, toolkit_experimental::HoltWinters},
     hyperloglog::HyperLogLog,
     pg_type, ron_inout_funcs,
-    stats_agg::{self, InternalStatsSummary1D, StatsSummary1D},
+    stats_agg::{
+        self,
+        decay::{half_life_micros, toolkit_experimental::DecayingStatsSummary1D},
+        InternalStatsSummary1D, StatsSummary1D,
+    },
     uddsketch::UddSketch,
 };
 
 use self::toolkit_experimental::{
     PipelineThenAverage, PipelineThenAverageData, PipelineThenCounterAgg,
-    PipelineThenCounterAggData, PipelineThenHyperLogLog, PipelineThenHyperLogLogData,
-    PipelineThenNumVals, PipelineThenNumValsData, PipelineThenPercentileAgg,
-    PipelineThenPercentileAggData, PipelineThenStatsAgg, PipelineThenStatsAggData, PipelineThenSum,
-    PipelineThenSumData,
+    PipelineThenCounterAggData, PipelineThenDecayingStatsAgg, PipelineThenDecayingStatsAggData,
+    PipelineThenHoltWinters, PipelineThenHoltWintersData, PipelineThenHyperLogLog,
+    PipelineThenHyperLogLogData, PipelineThenNumVals, PipelineThenNumValsData,
+    PipelineThenPercentileAgg, PipelineThenPercentileAggData, PipelineThenStatsAgg,
+    PipelineThenStatsAggData, PipelineThenSum, PipelineThenSumData,
 };
 
 #[pg_schema]
@@ -99,6 +105,31 @@ pub mod toolkit_experimental {
     }
 
     ron_inout_funcs!(PipelineThenPercentileAgg);
+
+    pg_type! {
+        #[derive(Debug)]
+        struct PipelineThenDecayingStatsAgg<'input> {
+            half_life: i64,
+            num_elements: u64,
+            elements: [Element<'input>; self.num_elements],
+        }
+    }
+
+    ron_inout_funcs!(PipelineThenDecayingStatsAgg);
+
+    pg_type! {
+        #[derive(Debug)]
+        struct PipelineThenHoltWinters<'input> {
+            period: u64,
+            alpha: f64,
+            beta: f64,
+            gamma: f64,
+            num_elements: u64,
+            elements: [Element<'input>; self.num_elements],
+        }
+    }
+
+    ron_inout_funcs!(PipelineThenHoltWinters);
 }
 
 #[pg_operator(immutable, parallel_safe)]
@@ -714,6 +745,204 @@ ALTER FUNCTION "arrow_run_pipeline_then_percentile_agg" SUPPORT toolkit_experime
     requires = [pipeline_percentile_agg_support],
 );
 
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_run_pipeline_then_decaying_stats_agg<'a>(
+    mut timevector: Timevector_TSTZ_F64<'a>,
+    pipeline: toolkit_experimental::PipelineThenDecayingStatsAgg<'a>,
+) -> DecayingStatsSummary1D<'static> {
+    if timevector.has_nulls() {
+        panic!("Unable to compute stats aggregate over timevector containing nulls");
+    }
+    timevector = run_pipeline_elements(timevector, pipeline.elements.iter());
+    let mut stats = stats_agg::decay::DecayingSummary1D::new(pipeline.half_life);
+    for TSPoint { ts, val } in timevector.iter() {
+        stats.accum(ts, val);
+    }
+    DecayingStatsSummary1D::from_internal(stats)
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn finalize_with_decaying_stats_agg<'e>(
+    mut pipeline: toolkit_experimental::UnstableTimevectorPipeline<'e>,
+    then_stats_agg: toolkit_experimental::PipelineThenDecayingStatsAgg<'e>,
+) -> toolkit_experimental::PipelineThenDecayingStatsAgg<'e> {
+    if then_stats_agg.num_elements == 0 {
+        // flatten immediately so we don't need a temporary allocation for elements
+        return unsafe {
+            flatten! {
+                PipelineThenDecayingStatsAgg {
+                    half_life: then_stats_agg.half_life,
+                    num_elements: pipeline.0.num_elements,
+                    elements: pipeline.0.elements,
+                }
+            }
+        };
+    }
+
+    let mut elements = take(pipeline.elements.as_owned());
+    elements.extend(then_stats_agg.elements.iter());
+    build! {
+        PipelineThenDecayingStatsAgg {
+            half_life: then_stats_agg.half_life,
+            num_elements: elements.len().try_into().unwrap(),
+            elements: elements.into(),
+        }
+    }
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "decaying_stats_agg",
+    schema = "toolkit_experimental"
+)]
+pub fn pipeline_decaying_stats_agg(
+    half_life: crate::raw::Interval,
+) -> toolkit_experimental::PipelineThenDecayingStatsAgg<'static> {
+    build! {
+        PipelineThenDecayingStatsAgg {
+            half_life: half_life_micros(half_life),
+            num_elements: 0,
+            elements: vec![].into(),
+        }
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub unsafe fn pipeline_decaying_stats_agg_support(input: Internal) -> Internal {
+    pipeline_support_helper(input, |old_pipeline, new_element| {
+        let new_element = PipelineThenDecayingStatsAgg::from_polymorphic_datum(
+            new_element,
+            false,
+            pg_sys::Oid::INVALID,
+        )
+        .unwrap();
+        finalize_with_decaying_stats_agg(old_pipeline, new_element)
+            .into_datum()
+            .unwrap()
+    })
+}
+
+// using this instead of pg_operator since the latter doesn't support schemas yet
+// FIXME there is no CREATE OR REPLACE OPERATOR need to update post-install.rs
+//       need to ensure this works with out unstable warning
+extension_sql!(
+    r#"
+ALTER FUNCTION "arrow_run_pipeline_then_decaying_stats_agg" SUPPORT toolkit_experimental.pipeline_decaying_stats_agg_support;
+"#,
+    name = "pipe_then_decaying_stats_agg",
+    requires = [pipeline_decaying_stats_agg_support],
+);
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_run_pipeline_then_holt_winters<'a>(
+    timevector: Timevector_TSTZ_F64<'a>,
+    pipeline: toolkit_experimental::PipelineThenHoltWinters<'a>,
+) -> Option<HoltWinters<'static>> {
+    let timevector = run_pipeline_elements(timevector, pipeline.elements.iter());
+    // NULLs are ignored, same as in the aggregate
+    let points = timevector
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !(timevector.has_nulls() && timevector.is_null_val(*i)))
+        .map(|(_, point)| point);
+    holt_winters::fit(
+        points,
+        pipeline.period as usize,
+        pipeline.alpha,
+        pipeline.beta,
+        pipeline.gamma,
+    )
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn finalize_with_holt_winters<'e>(
+    mut pipeline: toolkit_experimental::UnstableTimevectorPipeline<'e>,
+    then_holt_winters: toolkit_experimental::PipelineThenHoltWinters<'e>,
+) -> toolkit_experimental::PipelineThenHoltWinters<'e> {
+    if then_holt_winters.num_elements == 0 {
+        // flatten immediately so we don't need a temporary allocation for elements
+        return unsafe {
+            flatten! {
+                PipelineThenHoltWinters {
+                    period: then_holt_winters.period,
+                    alpha: then_holt_winters.alpha,
+                    beta: then_holt_winters.beta,
+                    gamma: then_holt_winters.gamma,
+                    num_elements: pipeline.0.num_elements,
+                    elements: pipeline.0.elements,
+                }
+            }
+        };
+    }
+
+    let mut elements = take(pipeline.elements.as_owned());
+    elements.extend(then_holt_winters.elements.iter());
+    build! {
+        PipelineThenHoltWinters {
+            period: then_holt_winters.period,
+            alpha: then_holt_winters.alpha,
+            beta: then_holt_winters.beta,
+            gamma: then_holt_winters.gamma,
+            num_elements: elements.len().try_into().unwrap(),
+            elements: elements.into(),
+        }
+    }
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "holt_winters_agg",
+    schema = "toolkit_experimental"
+)]
+pub fn pipeline_holt_winters(
+    period: i32,
+    alpha: f64,
+    beta: f64,
+    gamma: f64,
+) -> toolkit_experimental::PipelineThenHoltWinters<'static> {
+    let period = holt_winters::check_parameters(period, alpha, beta, gamma);
+    build! {
+        PipelineThenHoltWinters {
+            period: period as u64,
+            alpha,
+            beta,
+            gamma,
+            num_elements: 0,
+            elements: vec![].into(),
+        }
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub unsafe fn pipeline_holt_winters_support(input: Internal) -> Internal {
+    pipeline_support_helper(input, |old_pipeline, new_element| {
+        let new_element = PipelineThenHoltWinters::from_polymorphic_datum(
+            new_element,
+            false,
+            pg_sys::Oid::INVALID,
+        )
+        .unwrap();
+        finalize_with_holt_winters(old_pipeline, new_element)
+            .into_datum()
+            .unwrap()
+    })
+}
+
+// using this instead of pg_operator since the latter doesn't support schemas yet
+// FIXME there is no CREATE OR REPLACE OPERATOR need to update post-install.rs
+//       need to ensure this works with out unstable warning
+extension_sql!(
+    r#"
+ALTER FUNCTION "arrow_run_pipeline_then_holt_winters" SUPPORT toolkit_experimental.pipeline_holt_winters_support;
+"#,
+    name = "pipe_then_holt_winters",
+    requires = [pipeline_holt_winters_support],
+);
+
 #[cfg(any(test, feature = "pg_test"))]
 #[pg_schema]
 mod tests {
@@ -1392,4 +1621,77 @@ mod tests {
                 )");
         });
     }
+
+    #[pg_test]
+    fn test_decaying_stats_agg_finalizer() {
+        Spi::connect(|mut client| {
+            client.update("SET timezone TO 'UTC'", None, None).unwrap();
+            // using the search path trick for this test b/c the operator is
+            // difficult to spot otherwise.
+            let sp = client
+                .update(
+                    "SELECT format(' %s, toolkit_experimental',current_setting('search_path'))",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap()
+                .unwrap();
+            client
+                .update(&format!("SET LOCAL search_path TO {}", sp), None, None)
+                .unwrap();
+
+            // we use a subselect to guarantee order
+            let create_series = "SELECT timevector(time, value) as series FROM \
+                (VALUES ('2020-01-01 01:00 UTC'::TIMESTAMPTZ, 20.0), \
+                    ('2020-01-01 00:00 UTC'::TIMESTAMPTZ, 10.0)) as v(time, value)";
+
+            let val = client
+                .update(
+                    &format!(
+                        "SELECT (series -> decaying_stats_agg('1 hour'))::TEXT FROM ({}) s",
+                        create_series
+                    ),
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert_eq!(
+                val.unwrap(),
+                "(version:1,half_life:3600000000,ref_time:631155600000000,n:2,w:1.5,mean:16.666666666666668,m2:33.33333333333332)"
+            );
+
+            let output = client
+                .update(
+                    "EXPLAIN (verbose) SELECT \
+                timevector('1930-04-05'::timestamptz, 123.0) \
+                -> ceil() -> abs() -> floor() \
+                -> decaying_stats_agg('1 hour') -> ewma();",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .nth(1)
+                .unwrap()
+                .get_datum_by_ordinal(1)
+                .unwrap()
+                .value::<String>()
+                .unwrap()
+                .unwrap();
+            assert_eq!(output.trim(), "Output: (\
+                arrow_run_pipeline_then_decaying_stats_agg(\
+                    timevector('1930-04-05 00:00:00+00'::timestamp with time zone, '123'::double precision), \
+                    '(version:1,half_life:3600000000,num_elements:3,elements:[\
+                        Arithmetic(function:Ceil,rhs:0),\
+                        Arithmetic(function:Abs,rhs:0),\
+                        Arithmetic(function:Floor,rhs:0)\
+                    ])'::pipelinethendecayingstatsagg\
+                ) -> '(version:1)'::accessorewma)");
+        });
+    }
 }
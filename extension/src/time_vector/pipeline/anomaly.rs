@@ -0,0 +1,514 @@
+use pgx::{iter::TableIterator, *};
+
+use super::*;
+
+use super::rolling::{rolling_element, RollingFunction};
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "zscore",
+    schema = "toolkit_experimental"
+)]
+pub fn zscore_pipeline_element<'e>(
+    window: crate::raw::Interval,
+) -> toolkit_experimental::UnstableTimevectorPipeline<'e> {
+    rolling_element(window, RollingFunction::ZScore)
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "mad_outliers",
+    schema = "toolkit_experimental"
+)]
+pub fn mad_outliers_pipeline_element<'e>(
+    window: crate::raw::Interval,
+    threshold: f64,
+) -> toolkit_experimental::UnstableTimevectorPipeline<'e> {
+    let window = interval_to_micros(window);
+    if window < 0 {
+        pgx::error!("mad_outliers window must not be negative")
+    }
+    if threshold < 0.0 || threshold.is_nan() {
+        pgx::error!("mad_outliers threshold must not be negative")
+    }
+    Element::MadOutliers { window, threshold }.flatten()
+}
+
+// Scales the median absolute deviation to estimate the standard deviation of
+// normally distributed values.
+const MAD_SCALE: f64 = 1.4826;
+
+fn median(sorted: &[f64]) -> f64 {
+    let mid = sorted.len() / 2;
+    if sorted.len() % 2 == 0 {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
+
+/// Keeps the points whose value is more than `threshold` scaled median
+/// absolute deviations away from the median of the time window ending at the
+/// point, `[ts - window, ts]` as for the rolling elements.  Where the MAD is
+/// zero every value that differs from the median is an outlier.
+///
+/// The input is sorted first if needed.  NULL values are left out of the
+/// windows and are never outliers.
+pub fn mad_outliers<'s>(
+    series: Timevector_TSTZ_F64<'s>,
+    window: i64,
+    threshold: f64,
+) -> Timevector_TSTZ_F64<'s> {
+    let series = sort_timevector(series);
+    let points: Vec<(i64, Option<f64>)> = series
+        .iter()
+        .enumerate()
+        .map(|(i, point)| {
+            if series.has_nulls() && series.is_null_val(i) {
+                (point.ts, None)
+            } else {
+                (point.ts, Some(point.val))
+            }
+        })
+        .collect();
+
+    // the non-NULL values in the window, sorted
+    let mut values: Vec<f64> = vec![];
+    let mut deviations = vec![];
+    let mut start = 0;
+    let mut end = 0;
+    let mut outliers = vec![];
+    for &(ts, val) in &points {
+        while end < points.len() && points[end].0 <= ts {
+            if let Some(val) = points[end].1 {
+                let idx = values.partition_point(|v| v.total_cmp(&val).is_lt());
+                values.insert(idx, val);
+            }
+            end += 1;
+        }
+        while points[start].0 < ts.saturating_sub(window) {
+            if let Some(val) = points[start].1 {
+                let idx = values.partition_point(|v| v.total_cmp(&val).is_lt());
+                values.remove(idx);
+            }
+            start += 1;
+        }
+
+        let val = match val {
+            Some(val) => val,
+            None => continue,
+        };
+        let median = median(&values);
+        deviations.clear();
+        deviations.extend(values.iter().map(|v| (v - median).abs()));
+        deviations.sort_by(f64::total_cmp);
+        let mad = MAD_SCALE * self::median(&deviations);
+        let is_outlier = if mad == 0.0 {
+            val != median
+        } else {
+            (val - median).abs() / mad > threshold
+        };
+        if is_outlier {
+            outliers.push(TSPoint { ts, val });
+        }
+    }
+
+    let nulls_len = (outliers.len() + 7) / 8;
+    build! {
+        Timevector_TSTZ_F64 {
+            num_points: outliers.len() as _,
+            flags: FLAG_IS_SORTED,
+            internal_padding: [0; 3],
+            compressed_len: vec![].into(),
+            points: outliers.into(),
+            null_val: std::vec::from_elem(0_u8, nulls_len).into(),
+            compressed: vec![].into(),
+        }
+    }
+}
+
+/// Splits a regularly spaced series into trend, seasonal and residual
+/// components with `period` points per season, using the robust STL procedure
+/// from Cleveland et al., "STL: A Seasonal-Trend Decomposition Procedure Based
+/// on Loess" (1990).  The three timevectors have the timestamps of the sorted
+/// input and their values add up to the input's.
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn stl<'a>(
+    series: Timevector_TSTZ_F64<'a>,
+    period: i32,
+) -> TableIterator<
+    'static,
+    (
+        name!(trend, Timevector_TSTZ_F64<'static>),
+        name!(seasonal, Timevector_TSTZ_F64<'static>),
+        name!(residual, Timevector_TSTZ_F64<'static>),
+    ),
+> {
+    if series.has_nulls() {
+        pgx::error!("stl requires a timevector without NULL values")
+    }
+    if period < 2 {
+        pgx::error!("stl period must be at least 2")
+    }
+    let period = period as usize;
+    if series.num_points() < 2 * period {
+        pgx::error!("stl requires at least two periods of points")
+    }
+
+    let mut points: Vec<TSPoint> = series.iter().collect();
+    points.sort_by_key(|p| p.ts);
+    let values: Vec<f64> = points.iter().map(|p| p.val).collect();
+
+    let (trend, seasonal) = decomposition::decompose(&values, period);
+    let residual: Vec<f64> = values
+        .iter()
+        .zip(&trend)
+        .zip(&seasonal)
+        .map(|((v, t), s)| v - t - s)
+        .collect();
+
+    let component = |vals: &[f64]| {
+        let points: Vec<TSPoint> = points
+            .iter()
+            .zip(vals)
+            .map(|(p, &val)| TSPoint { ts: p.ts, val })
+            .collect();
+        let nulls_len = (points.len() + 7) / 8;
+        build! {
+            Timevector_TSTZ_F64 {
+                num_points: points.len() as _,
+                flags: FLAG_IS_SORTED,
+                internal_padding: [0; 3],
+                compressed_len: vec![].into(),
+                points: points.into(),
+                null_val: std::vec::from_elem(0_u8, nulls_len).into(),
+                compressed: vec![].into(),
+            }
+        }
+    };
+    TableIterator::new(std::iter::once((
+        component(&trend),
+        component(&seasonal),
+        component(&residual),
+    )))
+}
+
+mod decomposition {
+    // Parameters suggested by the paper for a robust decomposition.
+    const SEASONAL_SMOOTHER: usize = 7;
+    const INNER_ITERATIONS: usize = 1;
+    const OUTER_ITERATIONS: usize = 5;
+
+    fn next_odd(x: f64) -> usize {
+        let x = x.ceil() as usize;
+        if x % 2 == 0 {
+            x + 1
+        } else {
+            x
+        }
+    }
+
+    /// Returns the trend and seasonal components of `ys`.
+    pub fn decompose(ys: &[f64], period: usize) -> (Vec<f64>, Vec<f64>) {
+        let n = ys.len();
+        let low_pass_len = next_odd(period as f64);
+        let seasonal_smoother = SEASONAL_SMOOTHER as f64;
+        let trend_len = next_odd(1.5 * period as f64 / (1.0 - 1.5 / seasonal_smoother));
+
+        let mut weights = vec![1.0; n];
+        let mut trend = vec![0.0; n];
+        let mut seasonal = vec![0.0; n];
+        for outer in 0..=OUTER_ITERATIONS {
+            for _ in 0..INNER_ITERATIONS {
+                let detrended: Vec<f64> = ys.iter().zip(&trend).map(|(y, t)| y - t).collect();
+                let cycles = smooth_cycle_subseries(&detrended, &weights, period);
+                let low_pass = moving_average(&cycles, period);
+                let low_pass = moving_average(&low_pass, period);
+                let low_pass = moving_average(&low_pass, 3);
+                let low_pass = loess(&low_pass, None, low_pass_len);
+                for i in 0..n {
+                    seasonal[i] = cycles[period + i] - low_pass[i];
+                }
+                let deseasonalized: Vec<f64> =
+                    ys.iter().zip(&seasonal).map(|(y, s)| y - s).collect();
+                trend = loess(&deseasonalized, Some(&weights), trend_len);
+            }
+            if outer < OUTER_ITERATIONS {
+                weights = robustness_weights(ys, &trend, &seasonal);
+            }
+        }
+        (trend, seasonal)
+    }
+
+    // Smooths each cycle-subseries (the values at the same position in every
+    // period), extending it by one point at either end.  The result has
+    // `period` more points on each side than `ys`.
+    fn smooth_cycle_subseries(ys: &[f64], weights: &[f64], period: usize) -> Vec<f64> {
+        let mut cycles = vec![0.0; ys.len() + 2 * period];
+        for phase in 0..period {
+            let sub: Vec<f64> = ys.iter().skip(phase).step_by(period).copied().collect();
+            let sub_weights: Vec<f64> = weights
+                .iter()
+                .skip(phase)
+                .step_by(period)
+                .copied()
+                .collect();
+            for j in 0..sub.len() + 2 {
+                let x = j as f64 - 1.0;
+                let fallback = sub[j.saturating_sub(1).min(sub.len() - 1)];
+                let val =
+                    loess_at(&sub, Some(&sub_weights), SEASONAL_SMOOTHER, x).unwrap_or(fallback);
+                cycles[j * period + phase] = val;
+            }
+        }
+        cycles
+    }
+
+    fn moving_average(ys: &[f64], len: usize) -> Vec<f64> {
+        ys.windows(len)
+            .map(|window| window.iter().sum::<f64>() / len as f64)
+            .collect()
+    }
+
+    fn loess(ys: &[f64], weights: Option<&[f64]>, q: usize) -> Vec<f64> {
+        (0..ys.len())
+            .map(|i| loess_at(ys, weights, q, i as f64).unwrap_or(ys[i]))
+            .collect()
+    }
+
+    // Local linear regression of `ys`, at positions `0..ys.len()`, evaluated
+    // at `x` using the `q` nearest positions with tricube weights (multiplied
+    // by the robustness `weights`).  Follows the netlib STL implementation.
+    fn loess_at(ys: &[f64], weights: Option<&[f64]>, q: usize, x: f64) -> Option<f64> {
+        let n = ys.len();
+        let len = q.min(n);
+        // the window of `len` positions closest to `x`
+        let mut left = (x.round().max(0.0) as usize)
+            .saturating_sub(len / 2)
+            .min(n - len);
+        while left + len < n && x - left as f64 > (left + len) as f64 - x {
+            left += 1;
+        }
+        while left > 0 && (left + len - 1) as f64 - x > x - (left - 1) as f64 {
+            left -= 1;
+        }
+        let right = left + len - 1;
+
+        let mut h = (x - left as f64).max(right as f64 - x);
+        if q > n {
+            h += ((q - n) / 2) as f64;
+        }
+
+        let mut w = vec![0.0; len];
+        let mut total = 0.0;
+        for (i, w) in (left..=right).zip(&mut w) {
+            let r = (i as f64 - x).abs();
+            let tricube = if r <= 0.001 * h {
+                1.0
+            } else if r <= 0.999 * h {
+                (1.0 - (r / h).powi(3)).powi(3)
+            } else {
+                0.0
+            };
+            *w = tricube * weights.map_or(1.0, |weights| weights[i]);
+            total += *w;
+        }
+        if total <= 0.0 {
+            return None;
+        }
+        w.iter_mut().for_each(|w| *w /= total);
+
+        if h > 0.0 {
+            let center: f64 = (left..=right).zip(&w).map(|(i, w)| w * i as f64).sum();
+            let spread: f64 = (left..=right)
+                .zip(&w)
+                .map(|(i, w)| w * (i as f64 - center).powi(2))
+                .sum();
+            if spread.sqrt() > 0.001 * (n - 1) as f64 {
+                let slope = (x - center) / spread;
+                for (i, w) in (left..=right).zip(&mut w) {
+                    *w *= slope * (i as f64 - center) + 1.0;
+                }
+            }
+        }
+        Some((left..=right).zip(&w).map(|(i, w)| w * ys[i]).sum())
+    }
+
+    // Bisquare weights of the residuals, scaled by six times their median
+    // absolute value, so that outliers don't distort the components.
+    fn robustness_weights(ys: &[f64], trend: &[f64], seasonal: &[f64]) -> Vec<f64> {
+        let residuals: Vec<f64> = ys
+            .iter()
+            .zip(trend)
+            .zip(seasonal)
+            .map(|((y, t), s)| (y - t - s).abs())
+            .collect();
+        let mut sorted = residuals.clone();
+        sorted.sort_by(f64::total_cmp);
+        let h = 6.0 * super::median(&sorted);
+        residuals
+            .iter()
+            .map(|r| {
+                let u = r / h;
+                if u <= 0.001 {
+                    1.0
+                } else if u <= 0.999 {
+                    (1.0 - u * u).powi(2)
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use pgx::*;
+    use pgx_macros::pg_test;
+
+    #[pg_test]
+    fn test_pipeline_anomalies() {
+        Spi::connect(|mut client| {
+            client.update("SET timezone TO 'UTC'", None, None).unwrap();
+            // using the search path trick for this test b/c the operator is
+            // difficult to spot otherwise.
+            let sp = client
+                .update(
+                    "SELECT format(' %s, toolkit_experimental',current_setting('search_path'))",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap()
+                .unwrap();
+            client
+                .update(&format!("SET LOCAL search_path TO {}", sp), None, None)
+                .unwrap();
+
+            client
+                .update(
+                    "CREATE TABLE series(time timestamptz, value double precision)",
+                    None,
+                    None,
+                )
+                .unwrap();
+            client
+                .update(
+                    "INSERT INTO series \
+                    VALUES \
+                    ('2020-01-01 00:00 UTC'::TIMESTAMPTZ, 1.0), \
+                    ('2020-01-01 00:10 UTC'::TIMESTAMPTZ, 3.0), \
+                    ('2020-01-01 00:20 UTC'::TIMESTAMPTZ, 2.0), \
+                    ('2020-01-01 00:30 UTC'::TIMESTAMPTZ, 2.0), \
+                    ('2020-01-01 00:40 UTC'::TIMESTAMPTZ, NULL), \
+                    ('2020-01-01 00:50 UTC'::TIMESTAMPTZ, 20.0)",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            // the windows at 00:00 and 00:50 have a single value and the one at
+            // 00:30 has a stddev of zero
+            let val = client
+                .update(
+                    "SELECT (timevector(time, value) -> zscore('10 minutes'))::TEXT FROM series",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert_eq!(
+                val.unwrap(),
+                "(version:1,num_points:6,flags:3,internal_padding:(0,0,0),points:[\
+                    (ts:\"2020-01-01 00:00:00+00\",val:NaN),\
+                    (ts:\"2020-01-01 00:10:00+00\",val:0.7071067811865475),\
+                    (ts:\"2020-01-01 00:20:00+00\",val:-0.7071067811865475),\
+                    (ts:\"2020-01-01 00:30:00+00\",val:NaN),\
+                    (ts:\"2020-01-01 00:40:00+00\",val:NaN),\
+                    (ts:\"2020-01-01 00:50:00+00\",val:NaN)\
+                ],null_val:[57])"
+            );
+
+            // over the last hour the median is 2 and the MAD 1.4826, so 1
+            // and 3 are 0.67 MADs away while 20 is over 12 away
+            let val = client
+                .update(
+                    "SELECT (timevector(time, value) -> mad_outliers('1 hour', 3))::TEXT FROM series",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert_eq!(
+                val.unwrap(),
+                "(version:1,num_points:1,flags:1,internal_padding:(0,0,0),points:[\
+                    (ts:\"2020-01-01 00:50:00+00\",val:20)\
+                ],null_val:[0])"
+            );
+
+            // a linear trend plus a period 4 pattern and some noise, with one spike
+            client
+                .update(
+                    "CREATE TABLE seasonal AS \
+                    SELECT '2020-01-01 UTC'::TIMESTAMPTZ + i * '1 hour'::INTERVAL AS time, \
+                        (i * 0.5 + (ARRAY[3.0, -1.0, -3.0, 1.0])[i % 4 + 1] + sin(i * 7) * 0.3 \
+                            + CASE WHEN i = 13 THEN 10 ELSE 0 END)::DOUBLE PRECISION AS value \
+                    FROM generate_series(0, 31) i",
+                    None,
+                    None,
+                )
+                .unwrap();
+            let (seasonal, residual) = client
+                .update(
+                    "SELECT \
+                        (SELECT array_agg(value) FROM unnest(seasonal)), \
+                        (SELECT array_agg(value) FROM unnest(residual)) \
+                    FROM stl((SELECT timevector(time, value) FROM seasonal), 4)",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<Vec<f64>, Vec<f64>>()
+                .unwrap();
+            let seasonal = seasonal.unwrap();
+            let residual = residual.unwrap();
+            let pattern = [3.0, -1.0, -3.0, 1.0];
+            for (i, s) in seasonal.iter().enumerate() {
+                assert!((s - pattern[i % 4]).abs() < 0.5, "{} {:?}", i, seasonal);
+            }
+            // the spike ends up in the residual
+            let spike = residual
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+                .unwrap();
+            assert_eq!(spike.0, 13, "{:?}", residual);
+            assert!(*spike.1 > 8.0, "{:?}", residual);
+        });
+    }
+
+    #[pg_test(error = "stl requires at least two periods of points")]
+    fn test_stl_too_short() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT * FROM toolkit_experimental.stl(\
+                        timevector(now(), 1.0), 2)",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+}
@@ -0,0 +1,376 @@
+use pgx::*;
+
+use flat_serialize_macro::FlatSerializable;
+
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug, FlatSerializable)]
+#[repr(u64)]
+pub enum CombineJoin {
+    Inner,
+    Left,
+    Outer,
+    AsOf,
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "combine",
+    schema = "toolkit_experimental"
+)]
+pub fn combine_pipeline_element<'o, 'l, 'e>(
+    other: Timevector_TSTZ_F64<'o>,
+    lambda: toolkit_experimental::Lambda<'l>,
+    join: default!(&str, "'inner'"),
+) -> toolkit_experimental::UnstableTimevectorPipeline<'e> {
+    let expression = lambda.parse_combining();
+    if expression.ty() != &lambda::Type::Double {
+        panic!("invalid lambda type: the lambda must return a DOUBLE PRECISION")
+    }
+
+    let join = match join.to_lowercase().as_str() {
+        "inner" => CombineJoin::Inner,
+        "left" => CombineJoin::Left,
+        "outer" | "full" => CombineJoin::Outer,
+        "asof" | "as of" => CombineJoin::AsOf,
+        _ => pgx::error!(
+            "invalid join '{}', expected one of inner, left, outer or asof",
+            join
+        ),
+    };
+
+    // NULL values are treated as missing points, they'll be interpolated over
+    // like any other gap
+    let mut points: Vec<TSPoint> = other
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !(other.has_nulls() && other.is_null_val(*i)))
+        .map(|(_, point)| point)
+        .collect();
+    points.sort_by_key(|p| p.ts);
+
+    Element::Combine {
+        join,
+        num_points: points.len() as _,
+        points: points.into(),
+        lambda: lambda.into_data(),
+    }
+    .flatten()
+}
+
+// The value of `points` (sorted, without NULLs) at `ts`, linearly
+// interpolated between the neighboring points if there is no point at `ts`.
+pub(super) fn interpolated_value(points: &[TSPoint], ts: i64) -> Option<f64> {
+    let idx = points.partition_point(|p| p.ts < ts);
+    let rhs = points.get(idx)?;
+    if rhs.ts == ts {
+        return Some(rhs.val);
+    }
+    let lhs = points.get(idx.checked_sub(1)?)?;
+    let weight = (ts - lhs.ts) as f64 / (rhs.ts - lhs.ts) as f64;
+    Some(lhs.val + (rhs.val - lhs.val) * weight)
+}
+
+fn exact_value(points: &[TSPoint], ts: i64) -> Option<f64> {
+    let idx = points.partition_point(|p| p.ts < ts);
+    points.get(idx).filter(|p| p.ts == ts).map(|p| p.val)
+}
+
+// The value of the latest point at or before `ts`.
+fn as_of_value(points: &[TSPoint], ts: i64) -> Option<f64> {
+    let idx = points.partition_point(|p| p.ts <= ts);
+    points.get(idx.checked_sub(1)?).map(|p| p.val)
+}
+
+/// Aligns `series` with the timevector stored in the element, and evaluates
+/// `lambda` with `$a` bound to the value from `series` and `$b` bound to the
+/// value from the other timevector.
+///
+/// - `inner` keeps only the timestamps present in both timevectors.
+/// - `left` keeps every point of `series`, interpolating the other timevector
+///   at those timestamps.
+/// - `outer` keeps the timestamps of both, interpolating whichever side is
+///   missing a point.
+/// - `asof` keeps every point of `series`, pairing it with the latest point of
+///   the other timevector at or before it.
+///
+/// Points where one side has no value (NULL, or outside the range that can be
+/// interpolated) are NULL in the result, except for `inner` which drops them.
+pub fn combine<'s>(
+    series: Timevector_TSTZ_F64<'s>,
+    join: CombineJoin,
+    rhs: &[TSPoint],
+    lambda: &lambda::LambdaData<'_>,
+) -> Timevector_TSTZ_F64<'s> {
+    let expression = lambda.parse_combining();
+    if expression.ty() != &lambda::Type::Double {
+        panic!("invalid lambda type: the lambda must return a DOUBLE PRECISION")
+    }
+    let mut executor = lambda::ExpressionExecutor::new(&expression);
+
+    let series = sort_timevector(series);
+    let lhs: Vec<(i64, Option<f64>)> = series
+        .iter()
+        .enumerate()
+        .map(|(i, point)| {
+            if series.has_nulls() && series.is_null_val(i) {
+                (point.ts, None)
+            } else {
+                (point.ts, Some(point.val))
+            }
+        })
+        .collect();
+
+    let mut aligned: Vec<(i64, Option<f64>, Option<f64>)> = match join {
+        CombineJoin::Inner => lhs
+            .iter()
+            .filter_map(|&(ts, val)| Some((ts, Some(val?), Some(exact_value(rhs, ts)?))))
+            .collect(),
+        CombineJoin::Left => lhs
+            .iter()
+            .map(|&(ts, val)| (ts, val, interpolated_value(rhs, ts)))
+            .collect(),
+        CombineJoin::AsOf => lhs
+            .iter()
+            .map(|&(ts, val)| (ts, val, as_of_value(rhs, ts)))
+            .collect(),
+        CombineJoin::Outer => {
+            let known: Vec<TSPoint> = lhs
+                .iter()
+                .filter_map(|&(ts, val)| Some(TSPoint { ts, val: val? }))
+                .collect();
+            let mut aligned: Vec<_> = lhs
+                .iter()
+                .map(|&(ts, val)| (ts, val, interpolated_value(rhs, ts)))
+                .collect();
+            for point in rhs {
+                let present = lhs.binary_search_by_key(&point.ts, |&(ts, _)| ts).is_ok();
+                if !present {
+                    let val = interpolated_value(&known, point.ts);
+                    aligned.push((point.ts, val, Some(point.val)));
+                }
+            }
+            aligned
+        }
+    };
+    // stable, so points from `series` stay in their original order
+    aligned.sort_by_key(|&(ts, _, _)| ts);
+
+    let mut flags = FLAG_IS_SORTED;
+    let mut null_val = std::vec::from_elem(0_u8, (aligned.len() + 7) / 8);
+    let points: Vec<TSPoint> = aligned
+        .into_iter()
+        .enumerate()
+        .map(|(i, (ts, a, b))| match (a, b) {
+            (Some(a), Some(b)) => {
+                executor.reset();
+                let val = executor.exec_combine(a, b, ts).float();
+                TSPoint { ts, val }
+            }
+            _ => {
+                flags |= FLAG_HAS_NULLS;
+                null_val[i / 8] |= 1 << (i % 8);
+                TSPoint { ts, val: f64::NAN }
+            }
+        })
+        .collect();
+
+    build! {
+        Timevector_TSTZ_F64 {
+            num_points: points.len() as _,
+            flags,
+            internal_padding: [0; 3],
+            compressed_len: vec![].into(),
+            points: points.into(),
+            null_val: null_val.into(),
+            compressed: vec![].into(),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use pgx::*;
+    use pgx_macros::pg_test;
+
+    #[pg_test]
+    fn test_pipeline_combine() {
+        Spi::connect(|mut client| {
+            client.update("SET timezone TO 'UTC'", None, None).unwrap();
+            // using the search path trick for this test b/c the operator is
+            // difficult to spot otherwise.
+            let sp = client
+                .update(
+                    "SELECT format(' %s, toolkit_experimental',current_setting('search_path'))",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap()
+                .unwrap();
+            client
+                .update(&format!("SET LOCAL search_path TO {}", sp), None, None)
+                .unwrap();
+
+            client
+                .update(
+                    "CREATE TABLE requests(time timestamptz, value double precision)",
+                    None,
+                    None,
+                )
+                .unwrap();
+            client
+                .update(
+                    "INSERT INTO requests \
+                    VALUES \
+                    ('2020-01-01 00:00 UTC'::TIMESTAMPTZ, 100.0), \
+                    ('2020-01-01 00:10 UTC'::TIMESTAMPTZ, 200.0), \
+                    ('2020-01-01 00:20 UTC'::TIMESTAMPTZ, 400.0), \
+                    ('2020-01-01 00:30 UTC'::TIMESTAMPTZ, 400.0)",
+                    None,
+                    None,
+                )
+                .unwrap();
+            client
+                .update(
+                    "CREATE TABLE errors(time timestamptz, value double precision)",
+                    None,
+                    None,
+                )
+                .unwrap();
+            client
+                .update(
+                    "INSERT INTO errors \
+                    VALUES \
+                    ('2020-01-01 00:20 UTC'::TIMESTAMPTZ, 20.0), \
+                    ('2020-01-01 00:05 UTC'::TIMESTAMPTZ, 5.0), \
+                    ('2020-01-01 00:10 UTC'::TIMESTAMPTZ, 10.0)",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            let mut combine = |join: &str| {
+                client
+                    .update(
+                        &format!(
+                            "SELECT (\
+                                (SELECT timevector(time, value) FROM errors) \
+                                -> combine((SELECT timevector(time, value) FROM requests), '$a / $b', '{}') \
+                            )::TEXT",
+                            join
+                        ),
+                        None,
+                        None,
+                    )
+                    .unwrap()
+                    .first()
+                    .get_one::<String>()
+                    .unwrap()
+                    .unwrap()
+            };
+
+            assert_eq!(
+                combine("inner"),
+                "(version:1,num_points:2,flags:1,internal_padding:(0,0,0),points:[\
+                    (ts:\"2020-01-01 00:10:00+00\",val:0.05),\
+                    (ts:\"2020-01-01 00:20:00+00\",val:0.05)\
+                ],null_val:[0])"
+            );
+            // requests is interpolated to 150 at 00:05
+            assert_eq!(
+                combine("left"),
+                "(version:1,num_points:3,flags:1,internal_padding:(0,0,0),points:[\
+                    (ts:\"2020-01-01 00:05:00+00\",val:0.03333333333333333),\
+                    (ts:\"2020-01-01 00:10:00+00\",val:0.05),\
+                    (ts:\"2020-01-01 00:20:00+00\",val:0.05)\
+                ],null_val:[0])"
+            );
+            // the last request count at or before 00:05 is 100
+            assert_eq!(
+                combine("asof"),
+                "(version:1,num_points:3,flags:1,internal_padding:(0,0,0),points:[\
+                    (ts:\"2020-01-01 00:05:00+00\",val:0.05),\
+                    (ts:\"2020-01-01 00:10:00+00\",val:0.05),\
+                    (ts:\"2020-01-01 00:20:00+00\",val:0.05)\
+                ],null_val:[0])"
+            );
+            // errors can't be interpolated at 00:00 or 00:30
+            assert_eq!(
+                combine("outer"),
+                "(version:1,num_points:5,flags:3,internal_padding:(0,0,0),points:[\
+                    (ts:\"2020-01-01 00:00:00+00\",val:NaN),\
+                    (ts:\"2020-01-01 00:05:00+00\",val:0.03333333333333333),\
+                    (ts:\"2020-01-01 00:10:00+00\",val:0.05),\
+                    (ts:\"2020-01-01 00:20:00+00\",val:0.05),\
+                    (ts:\"2020-01-01 00:30:00+00\",val:NaN)\
+                ],null_val:[17])"
+            );
+
+            let val = client
+                .update(
+                    "SELECT (timevector('2020-01-01 UTC'::TIMESTAMPTZ, 1.0) \
+                        -> combine(timevector('2020-01-01 UTC'::TIMESTAMPTZ, 2.0), '$a + $b * 10') \
+                        -> combine(timevector('2020-01-01 UTC'::TIMESTAMPTZ, 100.0), '$value + $b'))::TEXT",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert_eq!(
+                val.unwrap(),
+                "(version:1,num_points:1,flags:1,internal_padding:(0,0,0),points:[\
+                    (ts:\"2020-01-01 00:00:00+00\",val:121)\
+                ],null_val:[0])"
+            );
+        });
+    }
+
+    #[pg_test(error = "$b is only available in lambdas passed to combine()")]
+    fn test_pipeline_rhs_value_in_filter() {
+        Spi::connect(|mut client| {
+            // rejected when the element is built, before there are any points
+            client
+                .update(
+                    "SELECT toolkit_experimental.filter($$ let $x = $b; $x > 0 $$)",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test(error = "$a is reserved and cannot be bound with let")]
+    fn test_pipeline_let_reserved_variable() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.f64_lambda('let $a = 5; $a', now(), 1.0)",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test(error = "$b is only available in lambdas passed to combine()")]
+    fn test_pipeline_rhs_value_outside_combine() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.f64_lambda('$a + $b', now(), 1.0)",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+}
@@ -4,7 +4,6 @@ use super::*;
 
 use crate::accessors::AccessorDelta;
 
-// TODO is (immutable, parallel_safe) correct?
 #[pg_extern(
     immutable,
     parallel_safe,
@@ -53,8 +52,10 @@ pub fn timevector_delta<'s>(series: &Timevector_TSTZ_F64<'s>) -> Timevector_TSTZ
         num_points: delta_points.len() as u32,
         flags: series.flags,
         internal_padding: [0; 3],
+        compressed_len: vec![].into(),
         points: delta_points.into(),
         null_val: std::vec::from_elem(0_u8, nulls_len).into(),
+        compressed: vec![].into(),
     })
 }
 
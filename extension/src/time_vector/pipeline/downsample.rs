@@ -0,0 +1,185 @@
+use pgx::*;
+
+use super::*;
+
+#[pg_extern(immutable, parallel_safe, name = "m4", schema = "toolkit_experimental")]
+pub fn m4_pipeline_element<'e>(width: i32) -> toolkit_experimental::UnstableTimevectorPipeline<'e> {
+    if width <= 0 {
+        pgx::error!("width must be positive")
+    }
+    Element::M4 {
+        width: width as u64,
+    }
+    .flatten()
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "minmax_lttb",
+    schema = "toolkit_experimental"
+)]
+pub fn minmax_lttb_pipeline_element<'e>(
+    resolution: i32,
+) -> toolkit_experimental::UnstableTimevectorPipeline<'e> {
+    if resolution <= 2 {
+        pgx::error!("resolution must be greater than 2")
+    }
+    Element::MinMaxLTTB {
+        resolution: resolution as u64,
+    }
+    .flatten()
+}
+
+/// Downsamples `series` with `downsample`, which gets the points sorted by
+/// time and without NULLs.
+pub fn downsample<'s>(
+    series: Timevector_TSTZ_F64<'s>,
+    downsample: impl FnOnce(&[TSPoint]) -> Vec<TSPoint>,
+) -> Timevector_TSTZ_F64<'s> {
+    let series = sort_timevector(series);
+    let points: Vec<TSPoint> = series
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !(series.has_nulls() && series.is_null_val(*i)))
+        .map(|(_, point)| point)
+        .collect();
+    let points = downsample(&points);
+
+    let nulls_len = (points.len() + 7) / 8;
+    build! {
+        Timevector_TSTZ_F64 {
+            num_points: points.len() as _,
+            flags: FLAG_IS_SORTED,
+            internal_padding: [0; 3],
+            compressed_len: vec![].into(),
+            points: points.into(),
+            null_val: std::vec::from_elem(0_u8, nulls_len).into(),
+            compressed: vec![].into(),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use pgx::*;
+    use pgx_macros::pg_test;
+
+    #[pg_test]
+    fn test_pipeline_downsample() {
+        Spi::connect(|mut client| {
+            client.update("SET timezone TO 'UTC'", None, None).unwrap();
+            // using the search path trick for this test b/c the operator is
+            // difficult to spot otherwise.
+            let sp = client
+                .update(
+                    "SELECT format(' %s, toolkit_experimental',current_setting('search_path'))",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap()
+                .unwrap();
+            client
+                .update(&format!("SET LOCAL search_path TO {}", sp), None, None)
+                .unwrap();
+
+            client
+                .update(
+                    "CREATE TABLE series(time timestamptz, value double precision)",
+                    None,
+                    None,
+                )
+                .unwrap();
+            // out of order, with a NULL
+            client
+                .update(
+                    "INSERT INTO series \
+                    VALUES \
+                    ('2020-01-04 UTC'::TIMESTAMPTZ, 32.0), \
+                    ('2020-01-01 UTC'::TIMESTAMPTZ, 10.0), \
+                    ('2020-01-02 UTC'::TIMESTAMPTZ, 21.0), \
+                    ('2020-01-03 UTC'::TIMESTAMPTZ, 19.0), \
+                    ('2020-01-05 UTC'::TIMESTAMPTZ, NULL), \
+                    ('2020-01-06 UTC'::TIMESTAMPTZ, 14.0), \
+                    ('2020-01-07 UTC'::TIMESTAMPTZ, 18.0), \
+                    ('2020-01-08 UTC'::TIMESTAMPTZ, 29.0), \
+                    ('2020-01-09 UTC'::TIMESTAMPTZ, 23.0), \
+                    ('2020-01-10 UTC'::TIMESTAMPTZ, 27.0), \
+                    ('2020-01-11 UTC'::TIMESTAMPTZ, 14.0)",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            let val = client
+                .update(
+                    "SELECT (timevector(time, value) -> m4(2))::TEXT FROM series",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert_eq!(
+                val.unwrap(),
+                "(version:1,num_points:6,flags:1,internal_padding:(0,0,0),points:[\
+                    (ts:\"2020-01-01 00:00:00+00\",val:10),\
+                    (ts:\"2020-01-04 00:00:00+00\",val:32),\
+                    (ts:\"2020-01-06 00:00:00+00\",val:14),\
+                    (ts:\"2020-01-07 00:00:00+00\",val:18),\
+                    (ts:\"2020-01-08 00:00:00+00\",val:29),\
+                    (ts:\"2020-01-11 00:00:00+00\",val:14)\
+                ],null_val:[0])"
+            );
+
+            // the pipeline elements match the aggregates
+            for (element, aggregate) in [
+                ("m4(3)", "m4(time, value, 3)"),
+                ("minmax_lttb(4)", "minmax_lttb(time, value, 4)"),
+            ] {
+                let val = client
+                    .update(
+                        &format!(
+                            "SELECT (timevector(time, value) -> {})::TEXT = {}::TEXT FROM series",
+                            element, aggregate
+                        ),
+                        None,
+                        None,
+                    )
+                    .unwrap()
+                    .first()
+                    .get_one::<bool>()
+                    .unwrap();
+                assert!(val.unwrap(), "{}", element);
+            }
+
+            let val = client
+                .update("SELECT (m4(100) -> minmax_lttb(50))::TEXT", None, None)
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert_eq!(
+                val.unwrap(),
+                "(version:1,num_elements:2,elements:[\
+                    M4(width:100),\
+                    MinMaxLTTB(resolution:50)\
+                ])"
+            );
+        });
+    }
+
+    #[pg_test(error = "resolution must be greater than 2")]
+    fn test_pipeline_minmax_lttb_resolution() {
+        Spi::connect(|mut client| {
+            client
+                .update("SELECT toolkit_experimental.minmax_lttb(2)", None, None)
+                .unwrap();
+        });
+    }
+}
@@ -0,0 +1,401 @@
+use pgx::*;
+
+use flat_serialize_macro::FlatSerializable;
+
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug, FlatSerializable)]
+#[repr(u64)]
+pub enum FillHolesMethod {
+    Locf,
+    Linear,
+    Null,
+}
+
+// The interval of the grid can be left out, in which case it is the smallest
+// gap between two points, as for a series that was already resampled.  A single
+// pair of points close together would then make for an enormous grid, so when
+// inferring the interval fill_holes refuses to create more than this many points.
+const MAX_INFERRED_POINTS: i64 = 1_000_000;
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "fill_holes",
+    schema = "toolkit_experimental"
+)]
+pub fn fill_holes_pipeline_element<'e>(
+    interval: crate::raw::Interval,
+    method: String,
+) -> toolkit_experimental::UnstableTimevectorPipeline<'e> {
+    let interval = interval_to_micros(interval);
+    if interval <= 0 {
+        pgx::error!("fill_holes requires a positive interval")
+    }
+
+    Element::FillHoles {
+        interval,
+        method: parse_method(&method),
+    }
+    .flatten()
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "fill_holes",
+    schema = "toolkit_experimental"
+)]
+pub fn fill_holes_inferred_pipeline_element<'e>(
+    method: String,
+) -> toolkit_experimental::UnstableTimevectorPipeline<'e> {
+    // an interval of 0 is inferred from the points
+    Element::FillHoles {
+        interval: 0,
+        method: parse_method(&method),
+    }
+    .flatten()
+}
+
+fn parse_method(method: &str) -> FillHolesMethod {
+    match method.to_lowercase().as_str() {
+        "locf" => FillHolesMethod::Locf,
+        "linear" | "interpolate" => FillHolesMethod::Linear,
+        "null" => FillHolesMethod::Null,
+        _ => pgx::error!(
+            "invalid fill_holes method '{}', expected one of locf, linear or null",
+            method
+        ),
+    }
+}
+
+/// Makes the timevector regular: like `fill_to`, a point is inserted every
+/// `interval` after each existing point, up to the next one.  The inserted
+/// points, and any existing NULL values, are then filled according to
+/// `method`.  Values that can't be filled (those before the first non-NULL
+/// value, and for linear fill after the last one) are left NULL.
+pub fn fill_holes<'s>(
+    series: Timevector_TSTZ_F64<'s>,
+    interval: i64,
+    method: FillHolesMethod,
+) -> Timevector_TSTZ_F64<'s> {
+    if !series.is_sorted() {
+        panic!("Timevector must be sorted prior to passing to fill_holes")
+    }
+
+    let interval = if interval > 0 {
+        Some(interval)
+    } else {
+        let gaps = series.iter().zip(series.iter().skip(1));
+        // None if there is at most one distinct time, then there are no holes to fill
+        let smallest = gaps.map(|(a, b)| b.ts - a.ts).filter(|gap| *gap > 0).min();
+        if let Some(interval) = smallest {
+            let span = series.iter().last().unwrap().ts - series.iter().next().unwrap().ts;
+            if span / interval >= MAX_INFERRED_POINTS {
+                pgx::error!(
+                    "fill_holes would create more than {} points, pass the interval of the grid explicitly",
+                    MAX_INFERRED_POINTS
+                )
+            }
+        }
+        smallest
+    };
+
+    let mut times = vec![];
+    let mut values: Vec<Option<f64>> = vec![];
+    for (i, point) in series.iter().enumerate() {
+        if let (Some(&prev), Some(interval)) = (times.last(), interval) {
+            let mut target = prev + interval;
+            while target < point.ts {
+                times.push(target);
+                values.push(None);
+                target += interval;
+            }
+        }
+        times.push(point.ts);
+        if series.has_nulls() && series.is_null_val(i) {
+            values.push(None);
+        } else {
+            values.push(Some(point.val));
+        }
+    }
+
+    match method {
+        FillHolesMethod::Locf => {
+            let mut last = None;
+            for value in values.iter_mut() {
+                match value {
+                    Some(v) => last = Some(*v),
+                    None => *value = last,
+                }
+            }
+        }
+        FillHolesMethod::Linear => {
+            let known: Vec<usize> = (0..values.len()).filter(|&i| values[i].is_some()).collect();
+            for pair in known.windows(2) {
+                let (p, n) = (pair[0], pair[1]);
+                let (lhs, rhs) = (values[p].unwrap(), values[n].unwrap());
+                let width = (times[n] - times[p]) as f64;
+                for (ts, value) in times[p + 1..n].iter().zip(&mut values[p + 1..n]) {
+                    let weight = (ts - times[p]) as f64 / width;
+                    *value = Some(lhs + (rhs - lhs) * weight);
+                }
+            }
+        }
+        FillHolesMethod::Null => (),
+    }
+
+    let mut flags = FLAG_IS_SORTED;
+    let mut null_val = std::vec::from_elem(0_u8, (values.len() + 7) / 8);
+    let points: Vec<TSPoint> = times
+        .into_iter()
+        .zip(values)
+        .enumerate()
+        .map(|(i, (ts, val))| {
+            let val = val.unwrap_or_else(|| {
+                flags |= FLAG_HAS_NULLS;
+                null_val[i / 8] |= 1 << (i % 8);
+                f64::NAN
+            });
+            TSPoint { ts, val }
+        })
+        .collect();
+
+    build! {
+        Timevector_TSTZ_F64 {
+            num_points: points.len() as _,
+            flags,
+            internal_padding: [0; 3],
+            compressed_len: vec![].into(),
+            points: points.into(),
+            null_val: null_val.into(),
+            compressed: vec![].into(),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use pgx::*;
+    use pgx_macros::pg_test;
+
+    #[pg_test]
+    fn test_pipeline_fill_holes() {
+        Spi::connect(|mut client| {
+            client.update("SET timezone TO 'UTC'", None, None).unwrap();
+            // using the search path trick for this test b/c the operator is
+            // difficult to spot otherwise.
+            let sp = client
+                .update(
+                    "SELECT format(' %s, toolkit_experimental',current_setting('search_path'))",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap()
+                .unwrap();
+            client
+                .update(&format!("SET LOCAL search_path TO {}", sp), None, None)
+                .unwrap();
+
+            client
+                .update(
+                    "CREATE TABLE series(time timestamptz, value double precision)",
+                    None,
+                    None,
+                )
+                .unwrap();
+            // a leading NULL, a NULL value and a missing hour
+            client
+                .update(
+                    "INSERT INTO series \
+                    VALUES \
+                    ('2020-01-01 00:00 UTC'::TIMESTAMPTZ, NULL), \
+                    ('2020-01-01 01:00 UTC'::TIMESTAMPTZ, 10.0), \
+                    ('2020-01-01 02:00 UTC'::TIMESTAMPTZ, NULL), \
+                    ('2020-01-01 04:00 UTC'::TIMESTAMPTZ, 40.0)",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            for (method, flags, null_val, filled) in [
+                ("locf", 3, 1, ["10", "10", "40"]),
+                ("linear", 3, 1, ["20", "30", "40"]),
+                ("null", 3, 13, ["NaN", "NaN", "40"]),
+            ] {
+                let val = client
+                    .update(
+                        &format!(
+                            "SELECT (timevector(time, value) -> sort() -> fill_holes('1 hour', '{}'))::TEXT \
+                            FROM series",
+                            method
+                        ),
+                        None,
+                        None,
+                    )
+                    .unwrap()
+                    .first()
+                    .get_one::<String>()
+                    .unwrap();
+                assert_eq!(
+                    val.unwrap(),
+                    format!(
+                        "(version:1,num_points:5,flags:{},internal_padding:(0,0,0),points:[\
+                        (ts:\"2020-01-01 00:00:00+00\",val:NaN),\
+                        (ts:\"2020-01-01 01:00:00+00\",val:10),\
+                        (ts:\"2020-01-01 02:00:00+00\",val:{}),\
+                        (ts:\"2020-01-01 03:00:00+00\",val:{}),\
+                        (ts:\"2020-01-01 04:00:00+00\",val:{})\
+                    ],null_val:[{}])",
+                        flags, filled[0], filled[1], filled[2], null_val
+                    )
+                );
+            }
+
+            // resample then fill the empty buckets
+            let val = client
+                .update(
+                    "SELECT (timevector(time, value) -> resample('1 hour', 'max') -> fill_holes('1 hour', 'interpolate'))::TEXT \
+                    FROM series",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert_eq!(
+                val.unwrap(),
+                "(version:1,num_points:4,flags:1,internal_padding:(0,0,0),points:[\
+                    (ts:\"2020-01-01 01:00:00+00\",val:10),\
+                    (ts:\"2020-01-01 02:00:00+00\",val:20),\
+                    (ts:\"2020-01-01 03:00:00+00\",val:30),\
+                    (ts:\"2020-01-01 04:00:00+00\",val:40)\
+                ],null_val:[0])"
+            );
+
+            let val = client
+                .update(
+                    "SELECT (resample('1 hour', 'last') -> fill_holes('1 hour', 'LOCF'))::TEXT",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert_eq!(
+                val.unwrap(),
+                "(version:1,num_elements:2,elements:[\
+                    Resample(interval:3600000000,method:Last),\
+                    FillHoles(interval:3600000000,method:Locf)\
+                ])"
+            );
+        });
+    }
+
+    #[pg_test]
+    fn test_pipeline_fill_holes_inferred_interval() {
+        Spi::connect(|mut client| {
+            client.update("SET timezone TO 'UTC'", None, None).unwrap();
+            // the smallest gap is 30 minutes
+            let val = client
+                .update(
+                    "SELECT (toolkit_experimental.timevector(time, value) \
+                        OPERATOR(toolkit_experimental.->) toolkit_experimental.fill_holes('linear'))::TEXT \
+                    FROM (VALUES \
+                        ('2020-01-01 00:00 UTC'::TIMESTAMPTZ, 10.0), \
+                        ('2020-01-01 00:30 UTC'::TIMESTAMPTZ, 20.0), \
+                        ('2020-01-01 02:00 UTC'::TIMESTAMPTZ, 50.0) \
+                    ) v(time, value)",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert_eq!(
+                val.unwrap(),
+                "(version:1,num_points:5,flags:1,internal_padding:(0,0,0),points:[\
+                    (ts:\"2020-01-01 00:00:00+00\",val:10),\
+                    (ts:\"2020-01-01 00:30:00+00\",val:20),\
+                    (ts:\"2020-01-01 01:00:00+00\",val:30),\
+                    (ts:\"2020-01-01 01:30:00+00\",val:40),\
+                    (ts:\"2020-01-01 02:00:00+00\",val:50)\
+                ],null_val:[0])"
+            );
+
+            let val = client
+                .update(
+                    "SELECT toolkit_experimental.fill_holes('locf')::TEXT",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert_eq!(
+                val.unwrap(),
+                "(version:1,num_elements:1,elements:[\
+                    FillHoles(interval:0,method:Locf)\
+                ])"
+            );
+        });
+    }
+
+    #[pg_test(
+        error = "fill_holes would create more than 1000000 points, pass the interval of the grid explicitly"
+    )]
+    fn test_pipeline_fill_holes_inferred_interval_too_small() {
+        Spi::connect(|mut client| {
+            // two points a microsecond apart in a day long series
+            client
+                .update(
+                    "SELECT toolkit_experimental.timevector(time, value) \
+                        OPERATOR(toolkit_experimental.->) toolkit_experimental.fill_holes('locf') \
+                    FROM (VALUES \
+                        ('2020-01-01 00:00 UTC'::TIMESTAMPTZ, 10.0), \
+                        ('2020-01-01 00:00:00.000001 UTC'::TIMESTAMPTZ, 20.0), \
+                        ('2020-01-02 00:00 UTC'::TIMESTAMPTZ, 50.0) \
+                    ) v(time, value)",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test(error = "intervals with months or years have no fixed length, use days instead")]
+    fn test_pipeline_fill_holes_month() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.fill_holes('1 month', 'locf')",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test(error = "invalid fill_holes method 'nearest', expected one of locf, linear or null")]
+    fn test_pipeline_fill_holes_unknown_method() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.fill_holes('1 hour', 'nearest')",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+}
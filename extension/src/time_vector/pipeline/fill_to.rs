@@ -48,7 +48,6 @@ impl FillToMethod {
     }
 }
 
-// TODO is (immutable, parallel_safe) correct?
 #[pg_extern(
     immutable,
     parallel_safe,
@@ -132,8 +131,10 @@ pub fn fill_to<'s>(
             num_points: result.len() as _,
             flags: series.flags,
             internal_padding: [0; 3],
+            compressed_len: vec![].into(),
             points: result.into(),
             null_val: std::vec::from_elem(0_u8, nulls_len).into(),
+            compressed: vec![].into(),
         }
     }
 }
@@ -286,6 +287,20 @@ mod tests {
                 (ts:\"2020-01-09 00:00:00+00\",val:40)\
             ],null_val:[0,0,0])"
             );
+
+            // fill_to counts a month as 30 days
+            let val = client
+                .update("SELECT fill_to('1 month', 'locf')::TEXT", None, None)
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert_eq!(
+                val.unwrap(),
+                "(version:1,num_elements:1,elements:[\
+                    FillTo(interval:2592000000000,fill_method:Locf)\
+                ])"
+            );
         });
     }
 }
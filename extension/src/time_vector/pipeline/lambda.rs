@@ -64,6 +64,15 @@ impl<'input> InOutFuncs for Lambda<'input> {
 
 impl<'a> LambdaData<'a> {
     pub fn parse(&self) -> Expression {
+        let expression = self.parse_combining();
+        if expression.uses_rhs_value() {
+            panic!("$b is only available in lambdas passed to combine()")
+        }
+        expression
+    }
+
+    /// Parses a lambda for `combine()`, the only place `$b` is bound.
+    pub fn parse_combining(&self) -> Expression {
         parser::parse_expression(std::str::from_utf8(self.string.as_slice()).unwrap())
     }
 }
@@ -188,6 +197,8 @@ pub struct Expression {
 #[derive(Clone, Debug)]
 pub enum ExpressionSegment {
     ValueVar,
+    // the value from the right-hand timevector, only set by `combine()`
+    RhsValueVar,
     TimeVar,
     DoubleConstant(f64),
     TimeConstant(i64),
@@ -279,6 +290,14 @@ impl Expression {
     pub fn ty_is_ts_point(&self) -> bool {
         self.expr.ty_is_ts_point()
     }
+
+    pub fn uses_value(&self) -> bool {
+        self.expr.uses_value() || self.variables.iter().any(|v| v.uses_value())
+    }
+
+    pub fn uses_rhs_value(&self) -> bool {
+        self.expr.uses_rhs_value() || self.variables.iter().any(|v| v.uses_rhs_value())
+    }
 }
 
 impl ExpressionSegment {
@@ -287,6 +306,7 @@ impl ExpressionSegment {
         use Type::*;
         match self {
             ValueVar => &Double,
+            RhsValueVar => &Double,
             TimeVar => &Time,
             DoubleConstant(_) => &Double,
             TimeConstant(_) => &Time,
@@ -308,10 +328,45 @@ impl ExpressionSegment {
         matches!(&**columns, [Type::Time, Type::Double])
     }
 
+    // does evaluating this segment read `$value`?
+    pub fn uses_value(&self) -> bool {
+        use ExpressionSegment::*;
+        match self {
+            ValueVar => true,
+            RhsValueVar
+            | TimeVar
+            | DoubleConstant(_)
+            | TimeConstant(_)
+            | IntervalConstant(_)
+            | UserVar(_, _) => false,
+            Unary(_, expr, _) => expr.uses_value(),
+            Binary(_, left, right, _) => left.uses_value() || right.uses_value(),
+            FunctionCall(_, args) | BuildTuple(args, _) => args.iter().any(|a| a.uses_value()),
+        }
+    }
+
+    // does evaluating this segment read `$b`?
+    pub fn uses_rhs_value(&self) -> bool {
+        use ExpressionSegment::*;
+        match self {
+            RhsValueVar => true,
+            ValueVar
+            | TimeVar
+            | DoubleConstant(_)
+            | TimeConstant(_)
+            | IntervalConstant(_)
+            | UserVar(_, _) => false,
+            Unary(_, expr, _) => expr.uses_rhs_value(),
+            Binary(_, left, right, _) => left.uses_rhs_value() || right.uses_rhs_value(),
+            FunctionCall(_, args) | BuildTuple(args, _) => args.iter().any(|a| a.uses_rhs_value()),
+        }
+    }
+
     pub fn name(&self) -> Cow<'static, str> {
         use ExpressionSegment::*;
         match self {
             ValueVar => "$value".into(),
+            RhsValueVar => "$b".into(),
             TimeVar => "$time".into(),
             DoubleConstant(_) => "f64 const".into(),
             TimeConstant(_) => "time const".into(),
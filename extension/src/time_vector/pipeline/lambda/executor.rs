@@ -5,6 +5,7 @@ use super::*;
 pub struct ExpressionExecutor<'e, T> {
     exprs: &'e Expression,
     var_vals: Vec<Option<Value>>,
+    rhs_value: Option<f64>,
     tracer: T,
 }
 
@@ -28,6 +29,7 @@ where
     pub fn with_tracer(exprs: &'e Expression, tracer: T) -> Self {
         Self {
             var_vals: vec![None; exprs.variables.len()],
+            rhs_value: None,
             exprs,
             tracer,
         }
@@ -43,6 +45,15 @@ where
         self.exec_expression(&self.exprs.expr, value, time)
     }
 
+    /// Executes the expression with `$a` (or `$value`) bound to `value` and
+    /// `$b` bound to `rhs`.
+    pub fn exec_combine(&mut self, value: f64, rhs: f64, time: i64) -> Value {
+        self.rhs_value = Some(rhs);
+        let res = self.exec(value, time);
+        self.rhs_value = None;
+        res
+    }
+
     fn exec_expression(
         &mut self,
         expr: &ExpressionSegment,
@@ -53,6 +64,10 @@ where
         use ExpressionSegment::*;
         let res = match expr {
             ValueVar => Value::Double(value),
+            RhsValueVar => match self.rhs_value {
+                Some(rhs) => Value::Double(rhs),
+                None => panic!("$b is only available in lambdas passed to combine()"),
+            },
             TimeVar => Value::Time(time),
             DoubleConstant(f) => Value::Double(*f),
             TimeConstant(t) => Value::Time(*t),
@@ -86,6 +86,8 @@ fn parse_primary<'a>(
 
         val_var => ValueVar,
         time_var => TimeVar,
+        a_var => ValueVar,
+        b_var => RhsValueVar,
 
         time => {
             let s = pair.as_str();
@@ -166,6 +168,13 @@ fn parse_primary<'a>(
                 let var_value = parse_primary(var_value, var_expressions, known_vars);
 
                 let var_name = var_name_or_expr.as_str();
+                // these would be read as the builtin variables instead
+                if ["$value", "$time", "$a", "$b"]
+                    .iter()
+                    .any(|reserved| var_name.eq_ignore_ascii_case(reserved))
+                {
+                    panic!("{} is reserved and cannot be bound with let", var_name)
+                }
                 known_vars
                     .entry(var_name)
                     .and_modify(|_| panic!("duplicate var {}", var_name))
@@ -140,8 +140,11 @@ pub fn apply_to_series(
             //        and the sub-function will allocate the returned timevector
             series.cached_datum_or_flatten(),
         );
+        // the result may come from an aggregate, which compresses it, while
+        // the following elements work on the points directly
         Timevector_TSTZ_F64::from_polymorphic_datum(res, false, pg_sys::InvalidOid)
             .expect("unexpected NULL in timevector mapping function")
+            .decompress()
     }
 }
 
@@ -618,6 +621,51 @@ mod tests {
         });
     }
 
+    #[pg_test]
+    fn test_pipeline_map_series_compressed() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "CREATE TABLE series AS \
+                    SELECT '2020-01-01 UTC'::TIMESTAMPTZ + i * '1 minute'::interval AS time, \
+                        i::DOUBLE PRECISION AS value \
+                    FROM generate_series(1, 1000) i",
+                    None,
+                    None,
+                )
+                .unwrap();
+            // the regular points the aggregate returns are stored compressed
+            client
+                .update(
+                    "CREATE FUNCTION doubled(timevector_tstz_f64) RETURNS timevector_tstz_f64 AS $$\
+                        SELECT timevector(time, value * 2) \
+                        FROM (SELECT (unnest($1)).*) a;\
+                    $$ LANGUAGE SQL",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            let (count, sum) = client
+                .update(
+                    "SELECT count(*), sum(value) FROM unnest(( \
+                        SELECT timevector(time, value) \
+                            -> toolkit_experimental.map_series('doubled') \
+                            -> toolkit_experimental.filter($$ $value > 1000 $$) \
+                        FROM series \
+                    ))",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<i64, f64>()
+                .unwrap();
+            assert_eq!(count, Some(500));
+            assert_eq!(sum, Some(2.0 * (501..=1000).sum::<i64>() as f64));
+        });
+    }
+
     #[pg_test]
     #[should_panic = "division by zero"]
     fn test_pipeline_map_series_failure() {
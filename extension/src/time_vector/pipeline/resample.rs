@@ -0,0 +1,262 @@
+use std::collections::BTreeMap;
+
+use pgx::*;
+
+use flat_serialize_macro::FlatSerializable;
+
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug, FlatSerializable)]
+#[repr(u64)]
+pub enum ResampleMethod {
+    Average,
+    Sum,
+    First,
+    Last,
+    Min,
+    Max,
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "resample",
+    schema = "toolkit_experimental"
+)]
+pub fn resample_pipeline_element<'e>(
+    interval: crate::raw::Interval,
+    method: String,
+) -> toolkit_experimental::UnstableTimevectorPipeline<'e> {
+    let interval = interval_to_micros(interval);
+    if interval <= 0 {
+        pgx::error!("resample requires a positive interval")
+    }
+
+    let method = match method.to_lowercase().as_str() {
+        "avg" | "average" => ResampleMethod::Average,
+        "sum" => ResampleMethod::Sum,
+        "first" => ResampleMethod::First,
+        "last" => ResampleMethod::Last,
+        "min" => ResampleMethod::Min,
+        "max" => ResampleMethod::Max,
+        _ => pgx::error!(
+            "invalid resample method '{}', expected one of avg, sum, first, last, min or max",
+            method
+        ),
+    };
+
+    Element::Resample { interval, method }.flatten()
+}
+
+// The points that fell into one grid cell, reduced as they're added.
+struct Bucket {
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+    first: TSPoint,
+    last: TSPoint,
+}
+
+impl Bucket {
+    fn new(point: TSPoint) -> Self {
+        Bucket {
+            count: 1,
+            sum: point.val,
+            min: point.val,
+            max: point.val,
+            first: point,
+            last: point,
+        }
+    }
+
+    fn add(&mut self, point: TSPoint) {
+        self.count += 1;
+        self.sum += point.val;
+        self.min = self.min.min(point.val);
+        self.max = self.max.max(point.val);
+        // on ties the earliest input is first and the latest is last
+        if point.ts < self.first.ts {
+            self.first = point;
+        }
+        if point.ts >= self.last.ts {
+            self.last = point;
+        }
+    }
+
+    fn value(&self, method: ResampleMethod) -> f64 {
+        match method {
+            ResampleMethod::Average => self.sum / self.count as f64,
+            ResampleMethod::Sum => self.sum,
+            ResampleMethod::First => self.first.val,
+            ResampleMethod::Last => self.last.val,
+            ResampleMethod::Min => self.min,
+            ResampleMethod::Max => self.max,
+        }
+    }
+}
+
+/// Groups the points into `interval`-wide buckets aligned to the postgres
+/// epoch, and replaces each bucket with a single point at the start of the
+/// bucket.  The input doesn't need to be sorted, NULL values are ignored, and
+/// buckets without any points are left out; see `fill_holes` to fill them in.
+pub fn resample<'s>(
+    series: Timevector_TSTZ_F64<'s>,
+    interval: i64,
+    method: ResampleMethod,
+) -> Timevector_TSTZ_F64<'s> {
+    let mut buckets: BTreeMap<i64, Bucket> = BTreeMap::new();
+    for (i, point) in series.iter().enumerate() {
+        if series.has_nulls() && series.is_null_val(i) {
+            continue;
+        }
+        let start = point.ts - point.ts.rem_euclid(interval);
+        buckets
+            .entry(start)
+            .and_modify(|b| b.add(point))
+            .or_insert_with(|| Bucket::new(point));
+    }
+
+    let points: Vec<TSPoint> = buckets
+        .into_iter()
+        .map(|(ts, bucket)| TSPoint {
+            ts,
+            val: bucket.value(method),
+        })
+        .collect();
+
+    let nulls_len = (points.len() + 7) / 8;
+    build! {
+        Timevector_TSTZ_F64 {
+            num_points: points.len() as _,
+            flags: FLAG_IS_SORTED,
+            internal_padding: [0; 3],
+            compressed_len: vec![].into(),
+            points: points.into(),
+            null_val: std::vec::from_elem(0_u8, nulls_len).into(),
+            compressed: vec![].into(),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use pgx::*;
+    use pgx_macros::pg_test;
+
+    #[pg_test]
+    fn test_pipeline_resample() {
+        Spi::connect(|mut client| {
+            client.update("SET timezone TO 'UTC'", None, None).unwrap();
+            // using the search path trick for this test b/c the operator is
+            // difficult to spot otherwise.
+            let sp = client
+                .update(
+                    "SELECT format(' %s, toolkit_experimental',current_setting('search_path'))",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap()
+                .unwrap();
+            client
+                .update(&format!("SET LOCAL search_path TO {}", sp), None, None)
+                .unwrap();
+
+            client
+                .update(
+                    "CREATE TABLE series(time timestamptz, value double precision)",
+                    None,
+                    None,
+                )
+                .unwrap();
+            // deliberately out of order, with a NULL and an empty hour
+            client
+                .update(
+                    "INSERT INTO series \
+                    VALUES \
+                    ('2020-01-01 00:40 UTC'::TIMESTAMPTZ, 30.0), \
+                    ('2020-01-01 00:10 UTC'::TIMESTAMPTZ, 10.0), \
+                    ('2020-01-01 00:20 UTC'::TIMESTAMPTZ, 20.0), \
+                    ('2020-01-01 01:30 UTC'::TIMESTAMPTZ, NULL), \
+                    ('2020-01-01 03:00 UTC'::TIMESTAMPTZ, 5.0), \
+                    ('2020-01-01 03:59 UTC'::TIMESTAMPTZ, 7.0)",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            for (method, first, second) in [
+                ("avg", "20", "6"),
+                ("sum", "60", "12"),
+                ("first", "10", "5"),
+                ("last", "30", "7"),
+                ("min", "10", "5"),
+                ("max", "30", "7"),
+            ] {
+                let val = client
+                    .update(
+                        &format!(
+                            "SELECT (timevector(time, value) -> resample('1 hour', '{}'))::TEXT \
+                            FROM series",
+                            method
+                        ),
+                        None,
+                        None,
+                    )
+                    .unwrap()
+                    .first()
+                    .get_one::<String>()
+                    .unwrap();
+                assert_eq!(
+                    val.unwrap(),
+                    format!(
+                        "(version:1,num_points:2,flags:1,internal_padding:(0,0,0),points:[\
+                        (ts:\"2020-01-01 00:00:00+00\",val:{}),\
+                        (ts:\"2020-01-01 03:00:00+00\",val:{})\
+                    ],null_val:[0])",
+                        first, second
+                    )
+                );
+            }
+
+            let val = client
+                .update(
+                    "SELECT (resample('1 hour', 'AVG') -> resample('1 day', 'max'))::TEXT",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert_eq!(
+                val.unwrap(),
+                "(version:1,num_elements:2,elements:[\
+                    Resample(interval:3600000000,method:Average),\
+                    Resample(interval:86400000000,method:Max)\
+                ])"
+            );
+        });
+    }
+
+    #[pg_test(
+        error = "invalid resample method 'median', expected one of avg, sum, first, last, min or max"
+    )]
+    fn test_pipeline_resample_unknown_method() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.resample('1 hour', 'median')",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+}
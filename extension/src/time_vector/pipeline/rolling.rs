@@ -0,0 +1,409 @@
+use std::collections::VecDeque;
+
+use pgx::*;
+
+use flat_serialize_macro::FlatSerializable;
+
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+use crate::stats_agg::InternalStatsSummary1D;
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug, FlatSerializable)]
+#[repr(u64)]
+pub enum RollingFunction {
+    Average,
+    Sum,
+    Min,
+    Max,
+    Stddev,
+    ZScore,
+}
+
+pub(super) fn rolling_element<'e>(
+    window: crate::raw::Interval,
+    function: RollingFunction,
+) -> toolkit_experimental::UnstableTimevectorPipeline<'e> {
+    let window = interval_to_micros(window);
+    if window < 0 {
+        pgx::error!("rolling window must not be negative")
+    }
+    Element::Rolling { window, function }.flatten()
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "rolling_avg",
+    schema = "toolkit_experimental"
+)]
+pub fn rolling_avg_pipeline_element<'e>(
+    window: crate::raw::Interval,
+) -> toolkit_experimental::UnstableTimevectorPipeline<'e> {
+    rolling_element(window, RollingFunction::Average)
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "rolling_sum",
+    schema = "toolkit_experimental"
+)]
+pub fn rolling_sum_pipeline_element<'e>(
+    window: crate::raw::Interval,
+) -> toolkit_experimental::UnstableTimevectorPipeline<'e> {
+    rolling_element(window, RollingFunction::Sum)
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "rolling_min",
+    schema = "toolkit_experimental"
+)]
+pub fn rolling_min_pipeline_element<'e>(
+    window: crate::raw::Interval,
+) -> toolkit_experimental::UnstableTimevectorPipeline<'e> {
+    rolling_element(window, RollingFunction::Min)
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "rolling_max",
+    schema = "toolkit_experimental"
+)]
+pub fn rolling_max_pipeline_element<'e>(
+    window: crate::raw::Interval,
+) -> toolkit_experimental::UnstableTimevectorPipeline<'e> {
+    rolling_element(window, RollingFunction::Max)
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "rolling_stddev",
+    schema = "toolkit_experimental"
+)]
+pub fn rolling_stddev_pipeline_element<'e>(
+    window: crate::raw::Interval,
+) -> toolkit_experimental::UnstableTimevectorPipeline<'e> {
+    rolling_element(window, RollingFunction::Stddev)
+}
+
+// Sliding state for the functions that can be maintained by adding and
+// removing values as the window moves.
+struct Window {
+    stats: InternalStatsSummary1D<f64>,
+    // (index, value) with increasing index and monotonic value, the front is
+    // the min or max of the window
+    extremes: VecDeque<(usize, f64)>,
+    function: RollingFunction,
+}
+
+impl Window {
+    fn add(&mut self, index: usize, val: f64) {
+        match self.function {
+            RollingFunction::Min | RollingFunction::Max => {
+                let keep_min = self.function == RollingFunction::Min;
+                while let Some(&(_, back)) = self.extremes.back() {
+                    let dominated = if keep_min { back >= val } else { back <= val };
+                    if !dominated {
+                        break;
+                    }
+                    self.extremes.pop_back();
+                }
+                self.extremes.push_back((index, val));
+            }
+            _ => self
+                .stats
+                .accum(val)
+                .expect("error while computing rolling statistics"),
+        }
+    }
+
+    // `window` holds the values that remain in the window after `val` is
+    // removed, used to recompute from scratch when removal isn't precise
+    fn remove(&mut self, index: usize, val: f64, window: impl Iterator<Item = f64>) {
+        match self.function {
+            RollingFunction::Min | RollingFunction::Max => {
+                if let Some(&(front, _)) = self.extremes.front() {
+                    if front == index {
+                        self.extremes.pop_front();
+                    }
+                }
+            }
+            _ => {
+                self.stats = match self.stats.remove(val) {
+                    Some(stats) => stats,
+                    None => {
+                        let mut stats = InternalStatsSummary1D::new();
+                        for val in window {
+                            stats
+                                .accum(val)
+                                .expect("error while computing rolling statistics");
+                        }
+                        stats
+                    }
+                }
+            }
+        }
+    }
+
+    // `current` is the value of the point the window ends at
+    fn value(&self, current: Option<f64>) -> Option<f64> {
+        match self.function {
+            RollingFunction::Min | RollingFunction::Max => {
+                self.extremes.front().map(|&(_, val)| val)
+            }
+            _ if self.stats.count() == 0 => None,
+            RollingFunction::Average => self.stats.avg(),
+            RollingFunction::Sum => self.stats.sum(),
+            RollingFunction::Stddev if self.stats.count() < 2 => None,
+            RollingFunction::Stddev => self.stats.stddev_samp(),
+            RollingFunction::ZScore if self.stats.count() < 2 => None,
+            RollingFunction::ZScore => {
+                let stddev = self.stats.stddev_samp()?;
+                if stddev == 0.0 {
+                    return None;
+                }
+                Some((current? - self.stats.avg()?) / stddev)
+            }
+        }
+    }
+}
+
+/// Replaces every point with `function` applied to the values in the time
+/// window ending at that point.  The window for a point at `ts` covers
+/// `[ts - window, ts]`, both ends inclusive, so it includes every point that
+/// shares the timestamp `ts`; this matches the SQL window frame
+/// `RANGE BETWEEN window PRECEDING AND CURRENT ROW`.
+///
+/// The z-score is the point's own value standardized by the mean and sample
+/// standard deviation of its window.
+///
+/// The input is sorted first if needed and the output has one point per input
+/// point.  NULL values are left out of the windows, and the result is NULL
+/// where a window has no values (or fewer than two for the stddev and
+/// z-score).  The z-score is also NULL for NULL points and where the standard
+/// deviation is zero.
+pub fn rolling<'s>(
+    series: Timevector_TSTZ_F64<'s>,
+    window: i64,
+    function: RollingFunction,
+) -> Timevector_TSTZ_F64<'s> {
+    let series = sort_timevector(series);
+    let points: Vec<(i64, Option<f64>)> = series
+        .iter()
+        .enumerate()
+        .map(|(i, point)| {
+            if series.has_nulls() && series.is_null_val(i) {
+                (point.ts, None)
+            } else {
+                (point.ts, Some(point.val))
+            }
+        })
+        .collect();
+
+    let mut state = Window {
+        stats: InternalStatsSummary1D::new(),
+        extremes: VecDeque::new(),
+        function,
+    };
+    let mut start = 0;
+    let mut end = 0;
+    let mut results = Vec::with_capacity(points.len());
+    for &(ts, val) in &points {
+        while end < points.len() && points[end].0 <= ts {
+            if let Some(val) = points[end].1 {
+                state.add(end, val);
+            }
+            end += 1;
+        }
+        while points[start].0 < ts.saturating_sub(window) {
+            if let Some(val) = points[start].1 {
+                let remaining = points[start + 1..end].iter().filter_map(|(_, v)| *v);
+                state.remove(start, val, remaining);
+            }
+            start += 1;
+        }
+        results.push((ts, state.value(val)));
+    }
+
+    let mut flags = FLAG_IS_SORTED;
+    let mut null_val = std::vec::from_elem(0_u8, (results.len() + 7) / 8);
+    let points: Vec<TSPoint> = results
+        .into_iter()
+        .enumerate()
+        .map(|(i, (ts, val))| {
+            let val = val.unwrap_or_else(|| {
+                flags |= FLAG_HAS_NULLS;
+                null_val[i / 8] |= 1 << (i % 8);
+                f64::NAN
+            });
+            TSPoint { ts, val }
+        })
+        .collect();
+
+    build! {
+        Timevector_TSTZ_F64 {
+            num_points: points.len() as _,
+            flags,
+            internal_padding: [0; 3],
+            compressed_len: vec![].into(),
+            points: points.into(),
+            null_val: null_val.into(),
+            compressed: vec![].into(),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use pgx::*;
+    use pgx_macros::pg_test;
+
+    #[pg_test]
+    fn test_pipeline_rolling() {
+        Spi::connect(|mut client| {
+            client.update("SET timezone TO 'UTC'", None, None).unwrap();
+            // using the search path trick for this test b/c the operator is
+            // difficult to spot otherwise.
+            let sp = client
+                .update(
+                    "SELECT format(' %s, toolkit_experimental',current_setting('search_path'))",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap()
+                .unwrap();
+            client
+                .update(&format!("SET LOCAL search_path TO {}", sp), None, None)
+                .unwrap();
+
+            client
+                .update(
+                    "CREATE TABLE series(time timestamptz, value double precision)",
+                    None,
+                    None,
+                )
+                .unwrap();
+            // out of order, with a NULL and a gap longer than the window
+            client
+                .update(
+                    "INSERT INTO series \
+                    VALUES \
+                    ('2020-01-01 00:20 UTC'::TIMESTAMPTZ, 4.0), \
+                    ('2020-01-01 00:00 UTC'::TIMESTAMPTZ, 2.0), \
+                    ('2020-01-01 00:10 UTC'::TIMESTAMPTZ, 6.0), \
+                    ('2020-01-01 00:30 UTC'::TIMESTAMPTZ, NULL), \
+                    ('2020-01-01 02:00 UTC'::TIMESTAMPTZ, 1.0)",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            // the window is [ts - 20 minutes, ts]
+            for (function, vals, null_val) in [
+                ("avg", ["2", "4", "4", "5", "1"], 0),
+                ("sum", ["2", "8", "12", "10", "1"], 0),
+                ("min", ["2", "2", "2", "4", "1"], 0),
+                ("max", ["2", "6", "6", "6", "1"], 0),
+                (
+                    "stddev",
+                    [
+                        "NaN",
+                        "2.8284271247461903",
+                        "2",
+                        "1.4142135623730951",
+                        "NaN",
+                    ],
+                    17,
+                ),
+            ] {
+                let val = client
+                    .update(
+                        &format!(
+                            "SELECT (timevector(time, value) -> rolling_{}('20 minutes'))::TEXT \
+                            FROM series",
+                            function
+                        ),
+                        None,
+                        None,
+                    )
+                    .unwrap()
+                    .first()
+                    .get_one::<String>()
+                    .unwrap();
+                assert_eq!(
+                    val.unwrap(),
+                    format!(
+                        "(version:1,num_points:5,flags:{},internal_padding:(0,0,0),points:[\
+                        (ts:\"2020-01-01 00:00:00+00\",val:{}),\
+                        (ts:\"2020-01-01 00:10:00+00\",val:{}),\
+                        (ts:\"2020-01-01 00:20:00+00\",val:{}),\
+                        (ts:\"2020-01-01 00:30:00+00\",val:{}),\
+                        (ts:\"2020-01-01 02:00:00+00\",val:{})\
+                    ],null_val:[{}])",
+                        if null_val == 0 { 1 } else { 3 },
+                        vals[0],
+                        vals[1],
+                        vals[2],
+                        vals[3],
+                        vals[4],
+                        null_val
+                    ),
+                    "rolling_{}",
+                    function
+                );
+            }
+
+            let val = client
+                .update(
+                    "SELECT (rolling_avg('1 hour') -> rolling_stddev('1 day'))::TEXT",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert_eq!(
+                val.unwrap(),
+                "(version:1,num_elements:2,elements:[\
+                    Rolling(window:3600000000,function:Average),\
+                    Rolling(window:86400000000,function:Stddev)\
+                ])"
+            );
+
+            // every point sharing a timestamp sees the same window
+            let val = client
+                .update(
+                    "SELECT (timevector(time, value) -> rolling_sum('0 seconds'))::TEXT \
+                    FROM (VALUES \
+                        ('2020-01-01 00:00 UTC'::TIMESTAMPTZ, 1.0), \
+                        ('2020-01-01 00:00 UTC'::TIMESTAMPTZ, 2.0), \
+                        ('2020-01-01 00:01 UTC'::TIMESTAMPTZ, 4.0)) v(time, value)",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert_eq!(
+                val.unwrap(),
+                "(version:1,num_points:3,flags:1,internal_padding:(0,0,0),points:[\
+                    (ts:\"2020-01-01 00:00:00+00\",val:3),\
+                    (ts:\"2020-01-01 00:00:00+00\",val:3),\
+                    (ts:\"2020-01-01 00:01:00+00\",val:4)\
+                ],null_val:[0])"
+            );
+        });
+    }
+}
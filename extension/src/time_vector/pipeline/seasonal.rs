@@ -0,0 +1,288 @@
+use pgx::*;
+
+use flat_serialize_macro::FlatSerializable;
+
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+use super::combine::interpolated_value;
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug, FlatSerializable)]
+#[repr(u64)]
+pub enum SeasonalFunction {
+    Delta,
+    PercentChange,
+}
+
+fn seasonal_element<'e>(
+    period: crate::raw::Interval,
+    tolerance: Option<crate::raw::Interval>,
+    function: SeasonalFunction,
+) -> toolkit_experimental::UnstableTimevectorPipeline<'e> {
+    let period = interval_to_micros(period);
+    if period <= 0 {
+        pgx::error!("the period must be positive")
+    }
+    let tolerance = match tolerance {
+        None => -1,
+        Some(tolerance) => {
+            let tolerance = interval_to_micros(tolerance);
+            if tolerance < 0 {
+                pgx::error!("the tolerance must not be negative")
+            }
+            tolerance
+        }
+    };
+    Element::Seasonal {
+        period,
+        tolerance,
+        function,
+    }
+    .flatten()
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "seasonal_delta",
+    schema = "toolkit_experimental"
+)]
+pub fn seasonal_delta_pipeline_element<'e>(
+    period: crate::raw::Interval,
+    tolerance: default!(Option<crate::raw::Interval>, "NULL"),
+) -> toolkit_experimental::UnstableTimevectorPipeline<'e> {
+    seasonal_element(period, tolerance, SeasonalFunction::Delta)
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "percent_change",
+    schema = "toolkit_experimental"
+)]
+pub fn percent_change_pipeline_element<'e>(
+    period: crate::raw::Interval,
+    tolerance: default!(Option<crate::raw::Interval>, "NULL"),
+) -> toolkit_experimental::UnstableTimevectorPipeline<'e> {
+    seasonal_element(period, tolerance, SeasonalFunction::PercentChange)
+}
+
+// The value of the point of `points` (sorted, without NULLs) closest to
+// `ts`, if it is at most `tolerance` away.  Ties go to the earlier point.
+fn nearest_value(points: &[TSPoint], ts: i64, tolerance: i64) -> Option<f64> {
+    let idx = points.partition_point(|p| p.ts < ts);
+    let before = idx.checked_sub(1).and_then(|i| points.get(i));
+    let after = points.get(idx);
+    let nearest = match (before, after) {
+        (Some(before), Some(after)) if after.ts - ts < ts - before.ts => after,
+        (Some(before), _) => before,
+        (None, Some(after)) => after,
+        (None, None) => return None,
+    };
+    if (nearest.ts - ts).abs() <= tolerance {
+        Some(nearest.val)
+    } else {
+        None
+    }
+}
+
+/// Compares every point with the value of the series one `period` earlier:
+/// `seasonal_delta` returns `value - earlier` and `percent_change` returns
+/// `(value - earlier) / earlier * 100`.
+///
+/// With a `tolerance` the earlier value is the one of the point closest to
+/// `ts - period`, as long as it's at most `tolerance` away.  Without one
+/// (negative `tolerance`) it's linearly interpolated between the points around
+/// `ts - period`, or taken from a point exactly at that time.
+///
+/// The input is sorted first if needed and the output has one point per input
+/// point.  NULL values are never used as the earlier value, and the result is
+/// NULL where the point's value is NULL, there's no earlier value, or (for
+/// `percent_change`) the earlier value is zero.
+pub fn seasonal<'s>(
+    series: Timevector_TSTZ_F64<'s>,
+    period: i64,
+    tolerance: i64,
+    function: SeasonalFunction,
+) -> Timevector_TSTZ_F64<'s> {
+    let series = sort_timevector(series);
+    let known: Vec<TSPoint> = series
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !(series.has_nulls() && series.is_null_val(*i)))
+        .map(|(_, point)| point)
+        .collect();
+
+    let mut flags = FLAG_IS_SORTED;
+    let mut null_val = std::vec::from_elem(0_u8, (series.num_points() + 7) / 8);
+    let points: Vec<TSPoint> = series
+        .iter()
+        .enumerate()
+        .map(|(i, point)| {
+            let is_null = series.has_nulls() && series.is_null_val(i);
+            let target = point.ts.saturating_sub(period);
+            let earlier = if tolerance < 0 {
+                interpolated_value(&known, target)
+            } else {
+                nearest_value(&known, target, tolerance)
+            };
+            let val = match (is_null, earlier, function) {
+                (true, _, _) | (_, None, _) => None,
+                (_, Some(earlier), SeasonalFunction::Delta) => Some(point.val - earlier),
+                (_, Some(earlier), SeasonalFunction::PercentChange) if earlier == 0.0 => None,
+                (_, Some(earlier), SeasonalFunction::PercentChange) => {
+                    Some((point.val - earlier) / earlier * 100.0)
+                }
+            };
+            let val = val.unwrap_or_else(|| {
+                flags |= FLAG_HAS_NULLS;
+                null_val[i / 8] |= 1 << (i % 8);
+                f64::NAN
+            });
+            TSPoint { ts: point.ts, val }
+        })
+        .collect();
+
+    build! {
+        Timevector_TSTZ_F64 {
+            num_points: points.len() as _,
+            flags,
+            internal_padding: [0; 3],
+            compressed_len: vec![].into(),
+            points: points.into(),
+            null_val: null_val.into(),
+            compressed: vec![].into(),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use pgx::*;
+    use pgx_macros::pg_test;
+
+    #[pg_test]
+    fn test_pipeline_seasonal() {
+        Spi::connect(|mut client| {
+            client.update("SET timezone TO 'UTC'", None, None).unwrap();
+            // using the search path trick for this test b/c the operator is
+            // difficult to spot otherwise.
+            let sp = client
+                .update(
+                    "SELECT format(' %s, toolkit_experimental',current_setting('search_path'))",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap()
+                .unwrap();
+            client
+                .update(&format!("SET LOCAL search_path TO {}", sp), None, None)
+                .unwrap();
+
+            client
+                .update(
+                    "CREATE TABLE series(time timestamptz, value double precision)",
+                    None,
+                    None,
+                )
+                .unwrap();
+            // out of order, and missing 2020-01-04
+            client
+                .update(
+                    "INSERT INTO series \
+                    VALUES \
+                    ('2020-01-03 UTC'::TIMESTAMPTZ, 30.0), \
+                    ('2020-01-01 UTC'::TIMESTAMPTZ, 10.0), \
+                    ('2020-01-02 UTC'::TIMESTAMPTZ, 20.0), \
+                    ('2020-01-05 UTC'::TIMESTAMPTZ, 50.0), \
+                    ('2020-01-06 UTC'::TIMESTAMPTZ, 40.0)",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            // 2020-01-04 is interpolated to 40 without a tolerance, it's
+            // missing with a tolerance of 0, and with a tolerance of 1 day
+            // the earlier of the two closest points is used
+            for (element, vals, null_val) in [
+                ("seasonal_delta('2 days')", ["20", "20", "0"], 3),
+                (
+                    "percent_change('2 days')",
+                    ["200", "66.66666666666666", "0"],
+                    3,
+                ),
+                ("seasonal_delta('2 days', '0')", ["20", "20", "NaN"], 19),
+                (
+                    "percent_change('2 days', '1 day')",
+                    ["200", "66.66666666666666", "33.33333333333333"],
+                    3,
+                ),
+            ] {
+                let val = client
+                    .update(
+                        &format!(
+                            "SELECT (timevector(time, value) -> {})::TEXT FROM series",
+                            element
+                        ),
+                        None,
+                        None,
+                    )
+                    .unwrap()
+                    .first()
+                    .get_one::<String>()
+                    .unwrap();
+                assert_eq!(
+                    val.unwrap(),
+                    format!(
+                        "(version:1,num_points:5,flags:3,internal_padding:(0,0,0),points:[\
+                        (ts:\"2020-01-01 00:00:00+00\",val:NaN),\
+                        (ts:\"2020-01-02 00:00:00+00\",val:NaN),\
+                        (ts:\"2020-01-03 00:00:00+00\",val:{}),\
+                        (ts:\"2020-01-05 00:00:00+00\",val:{}),\
+                        (ts:\"2020-01-06 00:00:00+00\",val:{})\
+                    ],null_val:[{}])",
+                        vals[0], vals[1], vals[2], null_val
+                    ),
+                    "{}",
+                    element
+                );
+            }
+
+            let val = client
+                .update(
+                    "SELECT (seasonal_delta('1 week') -> percent_change('1 day', '1 hour'))::TEXT",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert_eq!(
+                val.unwrap(),
+                "(version:1,num_elements:2,elements:[\
+                    Seasonal(period:604800000000,tolerance:-1,function:Delta),\
+                    Seasonal(period:86400000000,tolerance:3600000000,function:PercentChange)\
+                ])"
+            );
+        });
+    }
+
+    #[pg_test(error = "the period must be positive")]
+    fn test_pipeline_seasonal_zero_period() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.seasonal_delta('0 days')",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+}
@@ -2,7 +2,6 @@ use pgx::*;
 
 use super::*;
 
-// TODO is (immutable, parallel_safe) correct?
 #[pg_extern(
     immutable,
     parallel_safe,
@@ -51,8 +50,10 @@ pub fn sort_timevector(mut series: Timevector_TSTZ_F64<'_>) -> Timevector_TSTZ_F
         num_points: points.len() as u32,
         flags: series.flags | FLAG_IS_SORTED,
         internal_padding: [0; 3],
+        compressed_len: vec![].into(),
         points: points.into(),
         null_val: null_val.into(),
+        compressed: vec![].into(),
     }
     .into()
 }
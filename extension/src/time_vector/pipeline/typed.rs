@@ -0,0 +1,106 @@
+//! Pipeline execution for the timevectors whose values aren't `DOUBLE
+//! PRECISION`.  Only the elements that don't need to do arithmetic on the
+//! values are supported.
+
+use super::*;
+
+use crate::time_vector::typed::{TypedPoints, TypedValue};
+
+pub fn run_typed_pipeline_elements<'j, T: TypedValue>(
+    mut points: TypedPoints<T>,
+    pipeline: impl Iterator<Item = Element<'j>>,
+) -> TypedPoints<T> {
+    for element in pipeline {
+        points = match &element {
+            Element::Sort { .. } => sort(points),
+            Element::FilterLambda { lambda } => filter(points, lambda),
+            Element::FillTo {
+                interval,
+                fill_method,
+            } => fill_to(points, *interval, fill_method),
+            _ => pgx::error!(
+                "timevectors of {} only support the sort, filter and fill_to pipeline elements",
+                T::TYPE_NAME
+            ),
+        }
+    }
+    points
+}
+
+fn sort<T: TypedValue>(points: TypedPoints<T>) -> TypedPoints<T> {
+    if points.is_sorted() {
+        return points;
+    }
+    let mut pairs: Vec<_> = points.times.into_iter().zip(points.values).collect();
+    pairs.sort_by_key(|(ts, _)| *ts);
+    let (times, values) = pairs.into_iter().unzip();
+    TypedPoints { times, values }
+}
+
+fn filter<T: TypedValue>(
+    points: TypedPoints<T>,
+    lambda: &lambda::LambdaData<'_>,
+) -> TypedPoints<T> {
+    let expression = lambda.parse();
+    if expression.ty() != &lambda::Type::Bool {
+        panic!("invalid lambda type: the lambda must return a BOOLEAN")
+    }
+    if !T::IN_LAMBDAS && expression.uses_value() {
+        panic!(
+            "$value is not available for timevectors of {}, only $time is",
+            T::TYPE_NAME
+        )
+    }
+
+    let mut executor = lambda::ExpressionExecutor::new(&expression);
+    let mut result = TypedPoints::default();
+    for (ts, value) in points.times.into_iter().zip(points.values) {
+        // NULLs are passed to lambdas as NaN, same as for DOUBLE PRECISION timevectors
+        let lambda_value = value.as_ref().map_or(f64::NAN, |v| v.lambda_value());
+        executor.reset();
+        if executor.exec(lambda_value, ts).bool() {
+            result.push(ts, value);
+        }
+    }
+    result
+}
+
+fn fill_to<T: TypedValue>(
+    points: TypedPoints<T>,
+    interval: i64,
+    method: &FillToMethod,
+) -> TypedPoints<T> {
+    if *method == FillToMethod::Interpolate {
+        pgx::error!(
+            "fill_to can't interpolate timevectors of {}, use locf or nearest",
+            T::TYPE_NAME
+        )
+    }
+
+    if !points.is_sorted() {
+        panic!("Timevector must be sorted prior to passing to fill_to")
+    }
+
+    if points.values.iter().any(Option::is_none) {
+        panic!("Fill_to requires a timevector to not have NULL values")
+    }
+
+    let mut result = TypedPoints::default();
+    for (i, &ts) in points.times.iter().enumerate() {
+        result.push(ts, points.values[i].clone());
+        let next = match points.times.get(i + 1) {
+            Some(next) => *next,
+            None => break,
+        };
+        let mut target = ts + interval;
+        while next - ts > interval && target < next {
+            let source = match method {
+                FillToMethod::Nearest if next - target < target - ts => i + 1,
+                _ => i,
+            };
+            result.push(target, points.values[source].clone());
+            target += interval;
+        }
+    }
+    result
+}
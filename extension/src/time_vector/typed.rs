@@ -0,0 +1,834 @@
+//! Timevectors of `BIGINT`, `TEXT` and `BOOLEAN` values.
+//!
+//! These share the `timevector(ts, value)` aggregate name with
+//! `Timevector_TSTZ_F64`, but only support the pipeline elements that don't do
+//! arithmetic on the values: `sort()`, `filter()` and `fill_to()` with `locf` or
+//! `nearest`.  `BIGINT` values are seen by lambdas as `DOUBLE PRECISION` and
+//! `BOOLEAN`s as 1 or 0; lambdas over `TEXT` values can only use `$time`.
+
+use pgx::{iter::TableIterator, *};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    aggregate_utils::in_aggregate_context,
+    build,
+    palloc::{Inner, Internal, InternalAsValue, ToInternal},
+    pg_type,
+    raw::bytea,
+    ron_inout_funcs,
+};
+
+use super::{
+    pipeline::{run_typed_pipeline_elements, UnstableTimevectorPipeline},
+    FLAG_HAS_NULLS, FLAG_IS_SORTED,
+};
+
+use self::toolkit_experimental::{
+    Timevector_TSTZ_Bool, Timevector_TSTZ_BoolData, Timevector_TSTZ_Int8, Timevector_TSTZ_Int8Data,
+    Timevector_TSTZ_Text, Timevector_TSTZ_TextData,
+};
+
+#[pg_schema]
+pub mod toolkit_experimental {
+    use super::*;
+
+    pg_type! {
+        #[derive(Debug)]
+        #[allow(non_camel_case_types)]
+        struct Timevector_TSTZ_Int8<'input> {
+            num_points: u32,
+            flags: u8,
+            internal_padding: [u8; 3],
+            times: [i64; self.num_points],
+            values: [i64; self.num_points],
+            null_val: [u8; (self.num_points + 7) / 8],
+        }
+    }
+
+    ron_inout_funcs!(Timevector_TSTZ_Int8);
+
+    pg_type! {
+        #[derive(Debug)]
+        #[allow(non_camel_case_types)]
+        struct Timevector_TSTZ_Bool<'input> {
+            num_points: u32,
+            flags: u8,
+            internal_padding: [u8; 3],
+            times: [i64; self.num_points],
+            values: [u8; self.num_points],
+            null_val: [u8; (self.num_points + 7) / 8],
+        }
+    }
+
+    ron_inout_funcs!(Timevector_TSTZ_Bool);
+
+    pg_type! {
+        #[derive(Debug)]
+        #[allow(non_camel_case_types)]
+        struct Timevector_TSTZ_Text<'input> {
+            num_points: u32,
+            flags: u8,
+            internal_padding: [u8; 3],
+            text_len: u64,
+            times: [i64; self.num_points],
+            // the end offset of each value in `text`
+            text_ends: [u32; self.num_points],
+            text: [u8; self.text_len],
+            null_val: [u8; (self.num_points + 7) / 8],
+        }
+    }
+
+    ron_inout_funcs!(Timevector_TSTZ_Text);
+}
+
+/// The points of a timevector with values of type `T`, NULL values are `None`.
+/// This is the transition state of the aggregates, and what the pipeline
+/// elements operate on.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TypedPoints<T> {
+    pub times: Vec<i64>,
+    pub values: Vec<Option<T>>,
+}
+
+impl<T> TypedPoints<T> {
+    pub fn push(&mut self, ts: i64, value: Option<T>) {
+        self.times.push(ts);
+        self.values.push(value);
+    }
+
+    pub fn is_sorted(&self) -> bool {
+        self.times.windows(2).all(|w| w[0] <= w[1])
+    }
+
+    fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.is_sorted() {
+            flags |= FLAG_IS_SORTED;
+        }
+        if self.values.iter().any(Option::is_none) {
+            flags |= FLAG_HAS_NULLS;
+        }
+        flags
+    }
+
+    fn null_val(&self) -> Vec<u8> {
+        let mut null_val = vec![0; (self.values.len() + 7) / 8];
+        for (i, value) in self.values.iter().enumerate() {
+            if value.is_none() {
+                null_val[i / 8] |= 1 << (i % 8);
+            }
+        }
+        null_val
+    }
+}
+
+fn is_null(flags: u8, null_val: &[u8], index: usize) -> bool {
+    flags & FLAG_HAS_NULLS != 0 && null_val[index / 8] & (1 << (index % 8)) != 0
+}
+
+pub trait TypedValue: Clone + Serialize + DeserializeOwned {
+    const TYPE_NAME: &'static str;
+    // can lambdas use `$value` with this type?
+    const IN_LAMBDAS: bool;
+
+    fn lambda_value(&self) -> f64;
+}
+
+impl TypedValue for i64 {
+    const TYPE_NAME: &'static str = "BIGINT";
+    const IN_LAMBDAS: bool = true;
+
+    fn lambda_value(&self) -> f64 {
+        *self as f64
+    }
+}
+
+impl TypedValue for bool {
+    const TYPE_NAME: &'static str = "BOOLEAN";
+    const IN_LAMBDAS: bool = true;
+
+    fn lambda_value(&self) -> f64 {
+        if *self {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+impl TypedValue for String {
+    const TYPE_NAME: &'static str = "TEXT";
+    const IN_LAMBDAS: bool = false;
+
+    fn lambda_value(&self) -> f64 {
+        f64::NAN
+    }
+}
+
+impl<'a> From<&Timevector_TSTZ_Int8<'a>> for TypedPoints<i64> {
+    fn from(series: &Timevector_TSTZ_Int8<'a>) -> Self {
+        let null_val = series.null_val.as_slice();
+        TypedPoints {
+            times: series.times.as_slice().to_vec(),
+            values: series
+                .values
+                .iter()
+                .enumerate()
+                .map(|(i, val)| (!is_null(series.flags, null_val, i)).then_some(val))
+                .collect(),
+        }
+    }
+}
+
+impl From<TypedPoints<i64>> for Timevector_TSTZ_Int8<'static> {
+    fn from(points: TypedPoints<i64>) -> Self {
+        let (flags, null_val) = (points.flags(), points.null_val());
+        let values: Vec<i64> = points.values.iter().map(|v| v.unwrap_or(0)).collect();
+        build! {
+            Timevector_TSTZ_Int8 {
+                num_points: points.times.len() as _,
+                flags,
+                internal_padding: [0; 3],
+                times: points.times.into(),
+                values: values.into(),
+                null_val: null_val.into(),
+            }
+        }
+    }
+}
+
+impl<'a> From<&Timevector_TSTZ_Bool<'a>> for TypedPoints<bool> {
+    fn from(series: &Timevector_TSTZ_Bool<'a>) -> Self {
+        let null_val = series.null_val.as_slice();
+        TypedPoints {
+            times: series.times.as_slice().to_vec(),
+            values: series
+                .values
+                .iter()
+                .enumerate()
+                .map(|(i, val)| (!is_null(series.flags, null_val, i)).then_some(val != 0))
+                .collect(),
+        }
+    }
+}
+
+impl From<TypedPoints<bool>> for Timevector_TSTZ_Bool<'static> {
+    fn from(points: TypedPoints<bool>) -> Self {
+        let (flags, null_val) = (points.flags(), points.null_val());
+        let values: Vec<u8> = points
+            .values
+            .iter()
+            .map(|v| v.unwrap_or(false) as u8)
+            .collect();
+        build! {
+            Timevector_TSTZ_Bool {
+                num_points: points.times.len() as _,
+                flags,
+                internal_padding: [0; 3],
+                times: points.times.into(),
+                values: values.into(),
+                null_val: null_val.into(),
+            }
+        }
+    }
+}
+
+impl<'a> From<&Timevector_TSTZ_Text<'a>> for TypedPoints<String> {
+    fn from(series: &Timevector_TSTZ_Text<'a>) -> Self {
+        let null_val = series.null_val.as_slice();
+        let text = series.text.as_slice();
+        let mut start = 0;
+        let values = series
+            .text_ends
+            .iter()
+            .enumerate()
+            .map(|(i, end)| {
+                let value = std::str::from_utf8(&text[start..end as usize]).unwrap();
+                start = end as usize;
+                (!is_null(series.flags, null_val, i)).then(|| value.to_string())
+            })
+            .collect();
+        TypedPoints {
+            times: series.times.as_slice().to_vec(),
+            values,
+        }
+    }
+}
+
+impl From<TypedPoints<String>> for Timevector_TSTZ_Text<'static> {
+    fn from(points: TypedPoints<String>) -> Self {
+        let (flags, null_val) = (points.flags(), points.null_val());
+        let mut text = vec![];
+        let mut text_ends = Vec::with_capacity(points.values.len());
+        for value in &points.values {
+            if let Some(value) = value {
+                text.extend_from_slice(value.as_bytes());
+            }
+            let end: u32 = text
+                .len()
+                .try_into()
+                .unwrap_or_else(|_| pgx::error!("timevector text values are too large"));
+            text_ends.push(end);
+        }
+        build! {
+            Timevector_TSTZ_Text {
+                num_points: points.times.len() as _,
+                flags,
+                internal_padding: [0; 3],
+                text_len: text.len() as _,
+                times: points.times.into(),
+                text_ends: text_ends.into(),
+                text: text.into(),
+                null_val: null_val.into(),
+            }
+        }
+    }
+}
+
+//
+// aggregates
+//
+
+fn typed_trans<T: TypedValue>(
+    state: Option<Inner<TypedPoints<T>>>,
+    time: Option<crate::raw::TimestampTz>,
+    value: Option<T>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<TypedPoints<T>>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let time: pg_sys::TimestampTz = match time {
+                None => return state,
+                Some(time) => time.into(),
+            };
+            let mut state = state.unwrap_or_else(|| TypedPoints::default().into());
+            state.push(time, value);
+            Some(state)
+        })
+    }
+}
+
+fn typed_combine<T: TypedValue>(
+    state1: Option<Inner<TypedPoints<T>>>,
+    state2: Option<Inner<TypedPoints<T>>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<TypedPoints<T>>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || match (state1, state2) {
+            (None, None) => None,
+            (None, Some(state)) | (Some(state), None) => Some((*state).clone().into()),
+            (Some(state1), Some(state2)) => {
+                let mut points = (*state1).clone();
+                points.times.extend_from_slice(&state2.times);
+                points.values.extend_from_slice(&state2.values);
+                Some(points.into())
+            }
+        })
+    }
+}
+
+fn typed_final<T: TypedValue, V: From<TypedPoints<T>>>(
+    state: Option<Inner<TypedPoints<T>>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<V> {
+    unsafe { in_aggregate_context(fcinfo, || state.map(|state| (*state).clone().into())) }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn timevector_tstz_int8_trans(
+    state: Internal,
+    time: Option<crate::raw::TimestampTz>,
+    value: Option<i64>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    typed_trans(unsafe { state.to_inner() }, time, value, fcinfo).internal()
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn timevector_tstz_int8_combine(
+    state1: Internal,
+    state2: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    unsafe { typed_combine::<i64>(state1.to_inner(), state2.to_inner(), fcinfo).internal() }
+}
+
+#[pg_extern(immutable, parallel_safe, strict, schema = "toolkit_experimental")]
+pub fn timevector_tstz_int8_serialize(state: Internal) -> bytea {
+    let state: &TypedPoints<i64> = unsafe { state.get().unwrap() };
+    crate::do_serialize!(state)
+}
+
+#[pg_extern(strict, immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn timevector_tstz_int8_deserialize(bytes: bytea, _internal: Internal) -> Option<Internal> {
+    let points: TypedPoints<i64> = crate::do_deserialize!(bytes, TypedPoints<i64>);
+    Inner::from(points).internal()
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn timevector_tstz_int8_final(
+    state: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Timevector_TSTZ_Int8<'static>> {
+    typed_final(unsafe { state.to_inner() }, fcinfo)
+}
+
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.timevector(ts TIMESTAMPTZ, value BIGINT) (\n\
+        sfunc = toolkit_experimental.timevector_tstz_int8_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.timevector_tstz_int8_final,\n\
+        combinefunc = toolkit_experimental.timevector_tstz_int8_combine,\n\
+        serialfunc = toolkit_experimental.timevector_tstz_int8_serialize,\n\
+        deserialfunc = toolkit_experimental.timevector_tstz_int8_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+",
+    name = "timevector_tstz_int8_agg",
+    requires = [
+        timevector_tstz_int8_trans,
+        timevector_tstz_int8_final,
+        timevector_tstz_int8_combine,
+        timevector_tstz_int8_serialize,
+        timevector_tstz_int8_deserialize
+    ],
+);
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn timevector_tstz_bool_trans(
+    state: Internal,
+    time: Option<crate::raw::TimestampTz>,
+    value: Option<bool>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    typed_trans(unsafe { state.to_inner() }, time, value, fcinfo).internal()
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn timevector_tstz_bool_combine(
+    state1: Internal,
+    state2: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    unsafe { typed_combine::<bool>(state1.to_inner(), state2.to_inner(), fcinfo).internal() }
+}
+
+#[pg_extern(immutable, parallel_safe, strict, schema = "toolkit_experimental")]
+pub fn timevector_tstz_bool_serialize(state: Internal) -> bytea {
+    let state: &TypedPoints<bool> = unsafe { state.get().unwrap() };
+    crate::do_serialize!(state)
+}
+
+#[pg_extern(strict, immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn timevector_tstz_bool_deserialize(bytes: bytea, _internal: Internal) -> Option<Internal> {
+    let points: TypedPoints<bool> = crate::do_deserialize!(bytes, TypedPoints<bool>);
+    Inner::from(points).internal()
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn timevector_tstz_bool_final(
+    state: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Timevector_TSTZ_Bool<'static>> {
+    typed_final(unsafe { state.to_inner() }, fcinfo)
+}
+
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.timevector(ts TIMESTAMPTZ, value BOOLEAN) (\n\
+        sfunc = toolkit_experimental.timevector_tstz_bool_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.timevector_tstz_bool_final,\n\
+        combinefunc = toolkit_experimental.timevector_tstz_bool_combine,\n\
+        serialfunc = toolkit_experimental.timevector_tstz_bool_serialize,\n\
+        deserialfunc = toolkit_experimental.timevector_tstz_bool_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+",
+    name = "timevector_tstz_bool_agg",
+    requires = [
+        timevector_tstz_bool_trans,
+        timevector_tstz_bool_final,
+        timevector_tstz_bool_combine,
+        timevector_tstz_bool_serialize,
+        timevector_tstz_bool_deserialize
+    ],
+);
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn timevector_tstz_text_trans(
+    state: Internal,
+    time: Option<crate::raw::TimestampTz>,
+    value: Option<String>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    typed_trans(unsafe { state.to_inner() }, time, value, fcinfo).internal()
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn timevector_tstz_text_combine(
+    state1: Internal,
+    state2: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    unsafe { typed_combine::<String>(state1.to_inner(), state2.to_inner(), fcinfo).internal() }
+}
+
+#[pg_extern(immutable, parallel_safe, strict, schema = "toolkit_experimental")]
+pub fn timevector_tstz_text_serialize(state: Internal) -> bytea {
+    let state: &TypedPoints<String> = unsafe { state.get().unwrap() };
+    crate::do_serialize!(state)
+}
+
+#[pg_extern(strict, immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn timevector_tstz_text_deserialize(bytes: bytea, _internal: Internal) -> Option<Internal> {
+    let points: TypedPoints<String> = crate::do_deserialize!(bytes, TypedPoints<String>);
+    Inner::from(points).internal()
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn timevector_tstz_text_final(
+    state: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Timevector_TSTZ_Text<'static>> {
+    typed_final(unsafe { state.to_inner() }, fcinfo)
+}
+
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.timevector(ts TIMESTAMPTZ, value TEXT) (\n\
+        sfunc = toolkit_experimental.timevector_tstz_text_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.timevector_tstz_text_final,\n\
+        combinefunc = toolkit_experimental.timevector_tstz_text_combine,\n\
+        serialfunc = toolkit_experimental.timevector_tstz_text_serialize,\n\
+        deserialfunc = toolkit_experimental.timevector_tstz_text_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+",
+    name = "timevector_tstz_text_agg",
+    requires = [
+        timevector_tstz_text_trans,
+        timevector_tstz_text_final,
+        timevector_tstz_text_combine,
+        timevector_tstz_text_serialize,
+        timevector_tstz_text_deserialize
+    ],
+);
+
+//
+// unnest
+//
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "unnest",
+    schema = "toolkit_experimental"
+)]
+pub fn unnest_int8<'a>(
+    series: Timevector_TSTZ_Int8<'a>,
+) -> TableIterator<
+    'static,
+    (
+        name!(time, crate::raw::TimestampTz),
+        name!(value, Option<i64>),
+    ),
+> {
+    let points = TypedPoints::from(&series);
+    TableIterator::new(
+        points
+            .times
+            .into_iter()
+            .zip(points.values)
+            .map(|(ts, val)| (ts.into(), val)),
+    )
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "unnest",
+    schema = "toolkit_experimental"
+)]
+pub fn unnest_bool<'a>(
+    series: Timevector_TSTZ_Bool<'a>,
+) -> TableIterator<
+    'static,
+    (
+        name!(time, crate::raw::TimestampTz),
+        name!(value, Option<bool>),
+    ),
+> {
+    let points = TypedPoints::from(&series);
+    TableIterator::new(
+        points
+            .times
+            .into_iter()
+            .zip(points.values)
+            .map(|(ts, val)| (ts.into(), val)),
+    )
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "unnest",
+    schema = "toolkit_experimental"
+)]
+pub fn unnest_text<'a>(
+    series: Timevector_TSTZ_Text<'a>,
+) -> TableIterator<
+    'static,
+    (
+        name!(time, crate::raw::TimestampTz),
+        name!(value, Option<String>),
+    ),
+> {
+    let points = TypedPoints::from(&series);
+    TableIterator::new(
+        points
+            .times
+            .into_iter()
+            .zip(points.values)
+            .map(|(ts, val)| (ts.into(), val)),
+    )
+}
+
+//
+// pipelines
+//
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_run_pipeline_int8<'a>(
+    timevector: Timevector_TSTZ_Int8<'a>,
+    pipeline: UnstableTimevectorPipeline<'a>,
+) -> Timevector_TSTZ_Int8<'static> {
+    run_typed_pipeline_elements(TypedPoints::from(&timevector), pipeline.elements.iter()).into()
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_run_pipeline_bool<'a>(
+    timevector: Timevector_TSTZ_Bool<'a>,
+    pipeline: UnstableTimevectorPipeline<'a>,
+) -> Timevector_TSTZ_Bool<'static> {
+    run_typed_pipeline_elements(TypedPoints::from(&timevector), pipeline.elements.iter()).into()
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_run_pipeline_text<'a>(
+    timevector: Timevector_TSTZ_Text<'a>,
+    pipeline: UnstableTimevectorPipeline<'a>,
+) -> Timevector_TSTZ_Text<'static> {
+    run_typed_pipeline_elements(TypedPoints::from(&timevector), pipeline.elements.iter()).into()
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use pgx::*;
+    use pgx_macros::pg_test;
+
+    fn setup(client: &mut pgx::spi::SpiClient) {
+        client.update("SET timezone TO 'UTC'", None, None).unwrap();
+        // using the search path trick for this test b/c the operator is
+        // difficult to spot otherwise.
+        let sp = client
+            .update(
+                "SELECT format(' %s, toolkit_experimental',current_setting('search_path'))",
+                None,
+                None,
+            )
+            .unwrap()
+            .first()
+            .get_one::<String>()
+            .unwrap()
+            .unwrap();
+        client
+            .update(&format!("SET LOCAL search_path TO {}", sp), None, None)
+            .unwrap();
+        client
+            .update(
+                "CREATE TABLE states(time timestamptz, code bigint, status text, up boolean)",
+                None,
+                None,
+            )
+            .unwrap();
+        client
+            .update(
+                "INSERT INTO states \
+                VALUES \
+                ('2020-01-01 00:20 UTC'::TIMESTAMPTZ, 503, 'error', false), \
+                ('2020-01-01 00:00 UTC'::TIMESTAMPTZ, 200, 'ok', true), \
+                ('2020-01-01 00:10 UTC'::TIMESTAMPTZ, NULL, NULL, NULL), \
+                ('2020-01-01 00:40 UTC'::TIMESTAMPTZ, 9007199254740993, 'recovered', true)",
+                None,
+                None,
+            )
+            .unwrap();
+    }
+
+    fn unnest(client: &mut pgx::spi::SpiClient, query: &str) -> Vec<String> {
+        client
+            .update(
+                &format!("SELECT unnest::TEXT FROM unnest(({}))", query),
+                None,
+                None,
+            )
+            .unwrap()
+            .map(|row| row.get::<String>(1).unwrap().unwrap())
+            .collect()
+    }
+
+    #[pg_test]
+    fn test_typed_timevector_aggregates() {
+        Spi::connect(|mut client| {
+            setup(&mut client);
+
+            assert_eq!(
+                unnest(&mut client, "SELECT timevector(time, code) FROM states"),
+                [
+                    "(\"2020-01-01 00:20:00+00\",503)",
+                    "(\"2020-01-01 00:00:00+00\",200)",
+                    "(\"2020-01-01 00:10:00+00\",)",
+                    // BIGINTs are kept exactly
+                    "(\"2020-01-01 00:40:00+00\",9007199254740993)",
+                ]
+            );
+            assert_eq!(
+                unnest(&mut client, "SELECT timevector(time, status) FROM states"),
+                [
+                    "(\"2020-01-01 00:20:00+00\",error)",
+                    "(\"2020-01-01 00:00:00+00\",ok)",
+                    "(\"2020-01-01 00:10:00+00\",)",
+                    "(\"2020-01-01 00:40:00+00\",recovered)",
+                ]
+            );
+            assert_eq!(
+                unnest(&mut client, "SELECT timevector(time, up) FROM states"),
+                [
+                    "(\"2020-01-01 00:20:00+00\",f)",
+                    "(\"2020-01-01 00:00:00+00\",t)",
+                    "(\"2020-01-01 00:10:00+00\",)",
+                    "(\"2020-01-01 00:40:00+00\",t)",
+                ]
+            );
+
+            // text round trip
+            let val = client
+                .update(
+                    "SELECT timevector(time, status)::TEXT::timevector_tstz_text::TEXT \
+                    FROM states WHERE status IS NOT NULL",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            let expected = client
+                .update(
+                    "SELECT timevector(time, status)::TEXT FROM states WHERE status IS NOT NULL",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert_eq!(val, expected);
+        });
+    }
+
+    #[pg_test]
+    fn test_typed_timevector_pipelines() {
+        Spi::connect(|mut client| {
+            setup(&mut client);
+
+            assert_eq!(
+                unnest(
+                    &mut client,
+                    "SELECT timevector(time, code) -> sort() -> filter($$ $value != 200 $$) \
+                    FROM states"
+                ),
+                [
+                    // NULLs are NaN in lambdas, and NaN != 200
+                    "(\"2020-01-01 00:10:00+00\",)",
+                    "(\"2020-01-01 00:20:00+00\",503)",
+                    "(\"2020-01-01 00:40:00+00\",9007199254740993)",
+                ]
+            );
+            assert_eq!(
+                unnest(
+                    &mut client,
+                    "SELECT timevector(time, up) -> sort() -> filter($$ $value = 1 $$) FROM states"
+                ),
+                [
+                    "(\"2020-01-01 00:00:00+00\",t)",
+                    "(\"2020-01-01 00:40:00+00\",t)",
+                ]
+            );
+            assert_eq!(
+                unnest(
+                    &mut client,
+                    "SELECT timevector(time, status) -> sort() -> fill_to('10 minutes', 'locf') \
+                    FROM states WHERE status IS NOT NULL"
+                ),
+                [
+                    "(\"2020-01-01 00:00:00+00\",ok)",
+                    "(\"2020-01-01 00:10:00+00\",ok)",
+                    "(\"2020-01-01 00:20:00+00\",error)",
+                    "(\"2020-01-01 00:30:00+00\",error)",
+                    "(\"2020-01-01 00:40:00+00\",recovered)",
+                ]
+            );
+            assert_eq!(
+                unnest(
+                    &mut client,
+                    "SELECT timevector(time, status) -> sort() \
+                        -> filter($$ $time >= '2020-01-01 00:20:00+00't $$) \
+                    FROM states"
+                ),
+                [
+                    "(\"2020-01-01 00:20:00+00\",error)",
+                    "(\"2020-01-01 00:40:00+00\",recovered)",
+                ]
+            );
+        });
+    }
+
+    #[pg_test(error = "$value is not available for timevectors of TEXT, only $time is")]
+    fn test_typed_timevector_text_lambda_value() {
+        Spi::connect(|mut client| {
+            setup(&mut client);
+            client
+                .update(
+                    "SELECT timevector(time, status) -> filter($$ $value > 0 $$) FROM states",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test(
+        error = "timevectors of BIGINT only support the sort, filter and fill_to pipeline elements"
+    )]
+    fn test_typed_timevector_unsupported_element() {
+        Spi::connect(|mut client| {
+            setup(&mut client);
+            client
+                .update(
+                    "SELECT timevector(time, code) -> delta() FROM states",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+}
@@ -10,9 +10,8 @@ use crate::{
     },
     aggregate_utils::in_aggregate_context,
     duration::DurationUnit,
-    flatten,
     palloc::{Inner, Internal, InternalAsValue, ToInternal},
-    pg_type, ron_inout_funcs,
+    pg_type,
 };
 
 use tspoint::TSPoint;
@@ -24,36 +23,132 @@ use time_weighted_average::{
 use crate::raw::bytea;
 
 mod accessors;
+mod stats;
 
 use accessors::{TimeWeightInterpolatedAverageAccessor, TimeWeightInterpolatedIntegralAccessor};
 
+// Summaries with a `max_gap` have a version greater than 1 and store it and
+// their `gap_duration` as the bytes of two i64s after the method, older ones
+// predate the gaps and have neither.
 pg_type! {
     #[derive(Debug)]
-    struct TimeWeightSummary {
+    struct TimeWeightSummary<'input> {
         first: TSPoint,
         last: TSPoint,
         weighted_sum: f64,
         method: TimeWeightMethod,
+        gaps: [u8; if self.version > 1 { 16 } else { 0 }],
+    }
+}
+
+// The text format leaves out the gaps of summaries without a `max_gap`.
+#[derive(Serialize, Deserialize)]
+struct TimeWeightSummaryText {
+    version: u8,
+    first: TSPoint,
+    last: TSPoint,
+    weighted_sum: f64,
+    method: TimeWeightMethod,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_gap: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gap_duration: Option<i64>,
+}
+
+impl<'input> InOutFuncs for TimeWeightSummary<'input> {
+    fn output(&self, buffer: &mut StringInfo) {
+        use crate::serialization::{str_to_db_encoding, EncodedStr::*};
+
+        let summary = self.internal();
+        let text = TimeWeightSummaryText {
+            version: self.version,
+            first: summary.first,
+            last: summary.last,
+            weighted_sum: summary.w_sum,
+            method: summary.method,
+            max_gap: summary.max_gap,
+            gap_duration: summary.max_gap.map(|_| summary.gap_duration),
+        };
+        let stringified = ron::to_string(&text).unwrap();
+        match str_to_db_encoding(&stringified) {
+            Utf8(s) => buffer.push_str(s),
+            Other(s) => buffer.push_bytes(s.to_bytes()),
+        }
+    }
+
+    fn input(input: &std::ffi::CStr) -> TimeWeightSummary<'input>
+    where
+        Self: Sized,
+    {
+        use crate::serialization::str_from_db_encoding;
+
+        let input = str_from_db_encoding(input);
+        let text: TimeWeightSummaryText = ron::from_str(input).unwrap();
+        TimeWeightSummary::from_internal(&TimeWeightSummaryInternal {
+            method: text.method,
+            first: text.first,
+            last: text.last,
+            w_sum: text.weighted_sum,
+            max_gap: text.max_gap,
+            gap_duration: text.gap_duration.unwrap_or(0),
+        })
     }
 }
-ron_inout_funcs!(TimeWeightSummary);
 
 impl<'input> TimeWeightSummary<'input> {
     fn internal(&self) -> TimeWeightSummaryInternal {
+        let (max_gap, gap_duration) = if self.version > 1 {
+            let gaps: Vec<u8> = self.gaps.iter().collect();
+            let (max_gap, gap_duration) = gaps.split_at(8);
+            (
+                Some(i64::from_ne_bytes(max_gap.try_into().unwrap())),
+                i64::from_ne_bytes(gap_duration.try_into().unwrap()),
+            )
+        } else {
+            (None, 0)
+        };
         TimeWeightSummaryInternal {
             method: self.method,
             first: self.first,
             last: self.last,
             w_sum: self.weighted_sum,
+            max_gap,
+            gap_duration,
+        }
+    }
+
+    fn from_internal(summary: &TimeWeightSummaryInternal) -> TimeWeightSummary<'static> {
+        let (version, gaps) = match summary.max_gap {
+            None => (1, vec![]),
+            Some(max_gap) => {
+                let mut gaps = max_gap.to_ne_bytes().to_vec();
+                gaps.extend_from_slice(&summary.gap_duration.to_ne_bytes());
+                (2, gaps)
+            }
+        };
+        unsafe {
+            TimeWeightSummaryData {
+                header: 0,
+                version,
+                padding: [0; 3],
+                first: summary.first,
+                last: summary.last,
+                weighted_sum: summary.w_sum,
+                method: summary.method,
+                gaps: gaps.into(),
+            }
+            .flatten()
         }
     }
 
+    // `prev` is the last point before the summary and `next` the first one
+    // after it, the time between them and the summary is missing if it's a gap
     pub(super) fn interpolate(
         &self,
         interval_start: i64,
         interval_len: i64,
-        prev: Option<TimeWeightSummary>,
-        next: Option<TimeWeightSummary>,
+        prev: Option<TSPoint>,
+        next: Option<TSPoint>,
     ) -> TimeWeightSummary<'static> {
         assert!(
             interval_start <= self.first.ts,
@@ -68,52 +163,53 @@ impl<'input> TimeWeightSummary<'input> {
             end,
             self.last.ts
         );
-        let mut new_sum = self.weighted_sum;
-        let new_start = match prev {
-            Some(prev) if interval_start < self.first.ts => {
-                let new_start = self
-                    .method
-                    .interpolate(prev.last, Some(self.first), interval_start)
-                    .expect("unable to interpolate start of interval");
-                new_sum += self.method.weighted_sum(new_start, self.first);
-                new_start
+        let mut new = self.internal();
+        if let Some(prev) = prev.filter(|_| interval_start < self.first.ts) {
+            let new_start = self
+                .method
+                .interpolate(prev, Some(self.first), interval_start)
+                .expect("unable to interpolate start of interval");
+            if new.is_gap(prev, self.first) {
+                new.gap_duration += self.first.ts - new_start.ts;
+            } else {
+                new.w_sum += self.method.weighted_sum(new_start, self.first);
             }
-            _ => self.first,
-        };
-        let new_end = match (self.method, next) {
+            new.first = new_start;
+        }
+        match (self.method, next) {
             (_, Some(next)) => {
                 let new_end = self
                     .method
-                    .interpolate(self.last, Some(next.first), end)
+                    .interpolate(self.last, Some(next), end)
                     .expect("unable to interpolate end of interval");
-                new_sum += self.method.weighted_sum(self.last, new_end);
-                new_end
+                if new.is_gap(self.last, next) {
+                    new.gap_duration += new_end.ts - self.last.ts;
+                } else {
+                    new.w_sum += self.method.weighted_sum(self.last, new_end);
+                }
+                new.last = new_end;
             }
             (TimeWeightMethod::LOCF, None) => {
                 let new_end = self
                     .method
                     .interpolate(self.last, None, end)
                     .expect("unable to interpolate end of interval");
-                new_sum += self.method.weighted_sum(self.last, new_end);
-                new_end
+                new.w_sum += self.method.weighted_sum(self.last, new_end);
+                new.last = new_end;
             }
-            _ => self.last,
-        };
-
-        unsafe {
-            crate::flatten!(TimeWeightSummary {
-                first: new_start,
-                last: new_end,
-                weighted_sum: new_sum,
-                method: self.method,
-            })
+            _ => (),
         }
+
+        TimeWeightSummary::from_internal(&new)
     }
 }
 
+// The points are kept uncombined until the final function: in a parallel
+// aggregate each worker sees rows from anywhere in time, so the partial states
+// overlap and the weighted sum of a partial is only known once its neighboring
+// points from the other partials are.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct TimeWeightTransState {
-    #[serde(skip)]
     point_buffer: Vec<TSPoint>,
     method: TimeWeightMethod,
     summary_buffer: Vec<TimeWeightSummaryInternal>,
@@ -143,6 +239,11 @@ impl TimeWeightTransState {
         }
     }
 
+    fn merge(&mut self, other: &TimeWeightTransState) {
+        self.point_buffer.extend_from_slice(&other.point_buffer);
+        self.push_summary(other);
+    }
+
     fn combine_summaries(&mut self) {
         self.combine_points();
         if self.summary_buffer.len() <= 1 {
@@ -156,8 +257,7 @@ impl TimeWeightTransState {
 
 #[pg_extern(immutable, parallel_safe, strict)]
 pub fn time_weight_trans_serialize(state: Internal) -> bytea {
-    let mut state: Inner<TimeWeightTransState> = unsafe { state.to_inner().unwrap() };
-    state.combine_summaries();
+    let state: Inner<TimeWeightTransState> = unsafe { state.to_inner().unwrap() };
     crate::do_serialize!(state)
 }
 
@@ -170,7 +270,17 @@ pub fn time_weight_trans_deserialize_inner(bytes: bytea) -> Inner<TimeWeightTran
     t.into()
 }
 
-// these are technically parallel_safe (as in they can be called in a parallel context) even though the aggregate itself is parallel restricted.
+fn parse_method(method: &str) -> TimeWeightMethod {
+    // TODO technically not portable to ASCII-compatible charsets
+    match method.trim().to_lowercase().as_str() {
+        "linear" | "trapezoidal" => TimeWeightMethod::Linear,
+        "locf" => TimeWeightMethod::LOCF,
+        "nocb" => TimeWeightMethod::NOCB,
+        "linear_with_gaps" | "trapezoidal_with_gaps" => TimeWeightMethod::LinearWithGaps,
+        _ => panic!("unknown method"),
+    }
+}
+
 #[pg_extern(immutable, parallel_safe)]
 pub fn time_weight_trans(
     state: Internal,
@@ -199,14 +309,13 @@ pub fn time_weight_trans_inner(
 
             match state {
                 None => {
+                    let method = parse_method(&method);
+                    if method == TimeWeightMethod::LinearWithGaps {
+                        pgx::error!("the linear_with_gaps method requires a max_gap")
+                    }
                     let mut s = TimeWeightTransState {
                         point_buffer: vec![],
-                        // TODO technically not portable to ASCII-compatible charsets
-                        method: match method.trim().to_lowercase().as_str() {
-                            "linear" | "trapezoidal" => TimeWeightMethod::Linear,
-                            "locf" => TimeWeightMethod::LOCF,
-                            _ => panic!("unknown method"),
-                        },
+                        method,
                         summary_buffer: vec![],
                     };
                     s.push_point(p);
@@ -275,27 +384,14 @@ pub fn time_weight_combine_inner(
     fcinfo: pg_sys::FunctionCallInfo,
 ) -> Option<Inner<TimeWeightTransState>> {
     unsafe {
-        in_aggregate_context(fcinfo, || {
-            match (state1, state2) {
-                (None, None) => None,
-                (None, Some(state2)) => {
-                    let mut s = state2.clone();
-                    s.combine_points();
-                    Some(s.into())
-                }
-                (Some(state1), None) => {
-                    let mut s = state1.clone();
-                    s.combine_points();
-                    Some(s.into())
-                }
-                (Some(state1), Some(state2)) => {
-                    let mut s1 = state1.clone(); // is there a way to avoid if it doesn't need it?
-                    s1.combine_points();
-                    let mut s2 = state2.clone();
-                    s2.combine_points();
-                    s2.push_summary(&s1);
-                    Some(s2.into())
-                }
+        in_aggregate_context(fcinfo, || match (state1, state2) {
+            (None, None) => None,
+            (None, Some(state2)) => Some(state2.clone().into()),
+            (Some(state1), None) => Some(state1.clone().into()),
+            (Some(state1), Some(state2)) => {
+                let mut s2 = state2.clone();
+                s2.merge(&state1);
+                Some(s2.into())
             }
         })
     }
@@ -321,14 +417,10 @@ fn time_weight_final_inner(
             };
             state.combine_summaries();
             debug_assert!(state.summary_buffer.len() <= 1);
-            state.summary_buffer.pop().map(|st| {
-                flatten!(TimeWeightSummary {
-                    method: st.method,
-                    first: st.first,
-                    last: st.last,
-                    weighted_sum: st.w_sum,
-                })
-            })
+            state
+                .summary_buffer
+                .pop()
+                .map(|st| TimeWeightSummary::from_internal(&st))
         })
     }
 }
@@ -399,7 +491,7 @@ extension_sql!(
         combinefunc = time_weight_combine,\n\
         serialfunc = time_weight_trans_serialize,\n\
         deserialfunc = time_weight_trans_deserialize,\n\
-        parallel = restricted\n\
+        parallel = safe\n\
     );\n\
 \n\
     CREATE AGGREGATE rollup(tws TimeWeightSummary)\n\
@@ -410,7 +502,7 @@ extension_sql!(
         combinefunc = time_weight_combine,\n\
         serialfunc = time_weight_trans_serialize,\n\
         deserialfunc = time_weight_trans_deserialize,\n\
-        parallel = restricted\n\
+        parallel = safe\n\
     );\n\
 ",
     name = "time_weight_agg",
@@ -483,8 +575,8 @@ fn interpolate<'a>(
     tws: Option<TimeWeightSummary>,
     start: crate::raw::TimestampTz,
     duration: crate::raw::Interval,
-    prev: Option<TimeWeightSummary>,
-    next: Option<TimeWeightSummary>,
+    prev: Option<TSPoint>,
+    next: Option<TSPoint>,
 ) -> Option<TimeWeightSummary<'a>> {
     match tws {
         None => None,
@@ -503,7 +595,13 @@ pub fn time_weighted_average_interpolated_average<'a>(
     prev: default!(Option<TimeWeightSummary<'a>>, "NULL"),
     next: default!(Option<TimeWeightSummary<'a>>, "NULL"),
 ) -> Option<f64> {
-    let target = interpolate(tws, start, duration, prev, next);
+    let target = interpolate(
+        tws,
+        start,
+        duration,
+        prev.map(|prev| prev.last),
+        next.map(|next| next.first),
+    );
     time_weighted_average_average(target)
 }
 
@@ -513,24 +611,17 @@ pub fn arrow_time_weighted_average_interpolated_average<'a>(
     tws: Option<TimeWeightSummary<'a>>,
     accessor: TimeWeightInterpolatedAverageAccessor<'a>,
 ) -> Option<f64> {
-    let prev = if accessor.flags & 1 == 1 {
-        Some(accessor.prev.clone().into())
-    } else {
-        None
-    };
-    let next = if accessor.flags & 2 == 2 {
-        Some(accessor.next.clone().into())
-    } else {
-        None
-    };
+    let prev = (accessor.flags & 1 == 1).then_some(accessor.prev.last);
+    let next = (accessor.flags & 2 == 2).then_some(accessor.next.first);
 
-    time_weighted_average_interpolated_average(
+    let target = interpolate(
         tws,
         accessor.timestamp.into(),
         accessor.interval.into(),
         prev,
         next,
-    )
+    );
+    time_weighted_average_average(target)
 }
 
 #[pg_extern(immutable, parallel_safe, name = "interpolated_integral")]
@@ -542,7 +633,13 @@ pub fn time_weighted_average_interpolated_integral<'a>(
     next: default!(Option<TimeWeightSummary<'a>>, "NULL"),
     unit: default!(String, "'second'"),
 ) -> Option<f64> {
-    let target = interpolate(tws, start, interval, prev, next);
+    let target = interpolate(
+        tws,
+        start,
+        interval,
+        prev.map(|prev| prev.last),
+        next.map(|next| next.first),
+    );
     time_weighted_average_integral(target, unit)
 }
 
@@ -552,16 +649,8 @@ pub fn arrow_time_weighted_average_interpolated_integral<'a>(
     tws: Option<TimeWeightSummary<'a>>,
     accessor: TimeWeightInterpolatedIntegralAccessor<'a>,
 ) -> Option<f64> {
-    let prev = if accessor.flags & 1 == 1 {
-        Some(accessor.prev.clone().into())
-    } else {
-        None
-    };
-    let next = if accessor.flags & 2 == 2 {
-        Some(accessor.next.clone().into())
-    } else {
-        None
-    };
+    let prev = (accessor.flags & 1 == 1).then_some(accessor.prev.last);
+    let next = (accessor.flags & 2 == 2).then_some(accessor.next.first);
 
     // Convert from num of milliseconds to DurationUnit and then to string
     let unit = match accessor.unit {
@@ -574,14 +663,14 @@ pub fn arrow_time_weighted_average_interpolated_integral<'a>(
     }
     .to_string();
 
-    time_weighted_average_interpolated_integral(
+    let target = interpolate(
         tws,
         accessor.start.into(),
         accessor.interval.into(),
         prev,
         next,
-        unit,
-    )
+    );
+    time_weighted_average_integral(target, unit)
 }
 
 #[cfg(any(test, feature = "pg_test"))]
@@ -626,6 +715,8 @@ mod tests {
             assert!((select_one!(client, stmt, f64) - 15.0).abs() < f64::EPSILON);
             let stmt = "SELECT average(time_weight('LOCF', ts, val)) FROM test";
             assert!((select_one!(client, stmt, f64) - 10.0).abs() < f64::EPSILON);
+            let stmt = "SELECT average(time_weight('NOCB', ts, val)) FROM test";
+            assert!((select_one!(client, stmt, f64) - 20.0).abs() < f64::EPSILON);
 
             let stmt = "SELECT first_val(time_weight('LOCF', ts, val)) FROM test";
             assert!((select_one!(client, stmt, f64) - 10.0).abs() < f64::EPSILON);
@@ -662,6 +753,8 @@ mod tests {
             assert!((select_one!(client, stmt, f64) - 60.0).abs() < f64::EPSILON);
             let stmt = "SELECT integral(time_weight('LOCF', ts, val), 'hour') FROM test";
             assert!((select_one!(client, stmt, f64) - 1.0).abs() < f64::EPSILON);
+            let stmt = "SELECT integral(time_weight('NOCB', ts, val), 'mins') FROM test";
+            assert!((select_one!(client, stmt, f64) - 60.0).abs() < f64::EPSILON);
 
             //non-evenly spaced values
             let stmt = "INSERT INTO test VALUES('2020-01-01 00:08:00+00', 30.0), ('2020-01-01 00:10:00+00', 10.0), ('2020-01-01 00:10:30+00', 20.0), ('2020-01-01 00:20:00+00', 30.0)";
@@ -695,11 +788,17 @@ mod tests {
             let stmt = "SELECT integral(time_weight('LOCF', ts, val), 'milliseconds') FROM test";
             assert!((select_one!(client, stmt, f64) - 21300000.0).abs() < f64::EPSILON);
 
+            let stmt = "SELECT average(time_weight('NOCB', ts, val)) FROM test";
+            // expected = (20 + 10 + 20 + 10 + 30*4 + 10*2 + 20*.5 + 30*9.5) / 20 = 24.75 using next value and carrying back for each point
+            assert!((select_one!(client, stmt, f64) - 24.75).abs() < f64::EPSILON);
+
             //make sure this works with whatever ordering we throw at it
             let stmt = "SELECT average(time_weight('Linear', ts, val ORDER BY random())) FROM test";
             assert!((select_one!(client, stmt, f64) - 21.25).abs() < f64::EPSILON);
             let stmt = "SELECT average(time_weight('LOCF', ts, val ORDER BY random())) FROM test";
             assert!((select_one!(client, stmt, f64) - 17.75).abs() < f64::EPSILON);
+            let stmt = "SELECT average(time_weight('NOCB', ts, val ORDER BY random())) FROM test";
+            assert!((select_one!(client, stmt, f64) - 24.75).abs() < f64::EPSILON);
 
             let stmt = "SELECT integral(time_weight('Linear', ts, val ORDER BY random()), 'seconds') FROM test";
             assert!((select_one!(client, stmt, f64) - 25500.0).abs() < f64::EPSILON);
@@ -711,6 +810,8 @@ mod tests {
             assert!((select_one!(client, stmt, f64) - 21.25).abs() < f64::EPSILON);
             let stmt = "WITH t AS (SELECT date_trunc('minute', ts), time_weight('LOCF', ts, val) AS tws FROM test GROUP BY 1) SELECT average(rollup(tws)) FROM t";
             assert!((select_one!(client, stmt, f64) - 17.75).abs() < f64::EPSILON);
+            let stmt = "WITH t AS (SELECT date_trunc('minute', ts), time_weight('NOCB', ts, val) AS tws FROM test GROUP BY 1) SELECT average(rollup(tws)) FROM t";
+            assert!((select_one!(client, stmt, f64) - 24.75).abs() < f64::EPSILON);
         });
     }
 
@@ -750,6 +851,17 @@ mod tests {
             assert_eq!(select_one!(client, locf_time_weight, String), expected);
             assert!((select_one!(client, &*avg(expected), f64) - 10.0).abs() < f64::EPSILON);
 
+            let expected = "(\
+                version:1,\
+                first:(ts:\"2020-01-01 00:00:00+00\",val:10),\
+                last:(ts:\"2020-01-01 00:01:00+00\",val:20),\
+                weighted_sum:1200000000,\
+                method:NOCB\
+            )";
+            let stmt = "SELECT time_weight('NOCB', ts, val)::TEXT FROM test";
+            assert_eq!(select_one!(client, stmt, String), expected);
+            assert!((select_one!(client, &*avg(expected), f64) - 20.0).abs() < f64::EPSILON);
+
             // more values evenly spaced
             let stmt = "INSERT INTO test VALUES('2020-01-01 00:02:00+00', 10.0), ('2020-01-01 00:03:00+00', 20.0), ('2020-01-01 00:04:00+00', 10.0)";
             client.update(stmt, None, None).unwrap();
@@ -847,15 +959,18 @@ mod tests {
                 ptr::null_mut(),
             );
 
-            let mut control = state.unwrap();
+            let control = state.unwrap();
             let buffer =
                 time_weight_trans_serialize(Inner::from(control.clone()).internal().unwrap());
             let buffer = pgx::varlena::varlena_to_byte_slice(buffer.0.cast_mut_ptr());
 
+            // the points are serialized as they are, not combined
             let expected = [
-                1, 1, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 96, 194, 134, 7, 62, 2, 0,
-                0, 0, 0, 0, 0, 0, 36, 64, 0, 3, 164, 152, 7, 62, 2, 0, 0, 0, 0, 0, 0, 0, 62, 64, 0,
-                0, 0, 192, 11, 90, 246, 65,
+                1, 1, 6, 0, 0, 0, 0, 0, 0, 0, 0, 96, 194, 134, 7, 62, 2, 0, 0, 0, 0, 0, 0, 0, 36,
+                64, 0, 231, 85, 138, 7, 62, 2, 0, 0, 0, 0, 0, 0, 0, 52, 64, 0, 110, 233, 141, 7,
+                62, 2, 0, 0, 0, 0, 0, 0, 0, 62, 64, 0, 245, 124, 145, 7, 62, 2, 0, 0, 0, 0, 0, 0,
+                0, 36, 64, 0, 124, 16, 149, 7, 62, 2, 0, 0, 0, 0, 0, 0, 0, 52, 64, 0, 3, 164, 152,
+                7, 62, 2, 0, 0, 0, 0, 0, 0, 0, 62, 64, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
             ];
             assert_eq!(buffer, expected);
 
@@ -863,11 +978,160 @@ mod tests {
             let new_state =
                 time_weight_trans_deserialize_inner(bytea(pg_sys::Datum::from(expected.as_ptr())));
 
-            control.combine_summaries(); // Serialized form is always combined
             assert_eq!(&*new_state, &*control);
         }
     }
 
+    #[pg_test]
+    fn test_time_weight_parallel() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "CREATE TABLE test(ts timestamptz, val DOUBLE PRECISION); \
+                    INSERT INTO test \
+                        SELECT '2020-01-01'::timestamptz + i * '1 minute'::interval, i % 17 \
+                        FROM generate_series(0, 9999) i; \
+                    CREATE TABLE shuffled AS SELECT * FROM test ORDER BY random()",
+                    None,
+                    None,
+                )
+                .unwrap();
+            let stmt = "SELECT average(time_weight('Linear', ts, val))::TEXT \
+                    || ' ' || integral(time_weight('LOCF', ts, val))::TEXT \
+                FROM test";
+            let shuffled_stmt = stmt.replace("FROM test", "FROM shuffled");
+            let serial = select_one!(client, stmt, String);
+            assert_eq!(select_one!(client, &shuffled_stmt, String), serial);
+
+            // the workers are handed blocks of rows, so with the rows in
+            // random order every worker sees rows from the whole time range
+            client
+                .update(
+                    "SET parallel_setup_cost = 0; \
+                    SET parallel_tuple_cost = 0; \
+                    SET min_parallel_table_scan_size = 0; \
+                    SET max_parallel_workers_per_gather = 4; \
+                    SET parallel_leader_participation = off",
+                    None,
+                    None,
+                )
+                .unwrap();
+            let plan = client
+                .update(&format!("EXPLAIN {}", stmt), None, None)
+                .unwrap()
+                .map(|row| row[1].value::<String>().unwrap().unwrap())
+                .collect::<Vec<_>>()
+                .join("\n");
+            assert!(plan.contains("Partial Aggregate"), "{}", plan);
+            assert_eq!(select_one!(client, stmt, String), serial);
+            assert_eq!(select_one!(client, &shuffled_stmt, String), serial);
+        });
+    }
+
+    #[pg_test]
+    fn test_time_weight_linear_with_gaps() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SET TIME ZONE 'UTC'; \
+                    CREATE TABLE test(ts timestamptz, val DOUBLE PRECISION); \
+                    INSERT INTO test VALUES \
+                        ('2020-01-01 00:00:00+00', 10.0), \
+                        ('2020-01-01 00:01:00+00', 20.0), \
+                        ('2020-01-01 00:02:00+00', 10.0), \
+                        ('2020-01-01 00:03:00+00', 20.0), \
+                        ('2020-01-01 00:04:00+00', 10.0), \
+                        ('2020-01-01 00:10:00+00', 30.0), \
+                        ('2020-01-01 00:11:00+00', 10.0)",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            // the 6 minutes between 00:04 and 00:10 are missing, leaving
+            // (15*4 + 20) / 5
+            let stmt = "SELECT average(tw), integral(tw, 'minute') FROM (\
+                    SELECT toolkit_experimental.time_weight('linear_with_gaps', ts, val, '2 minutes') tw \
+                    FROM test\
+                ) t";
+            let (average, integral) = client
+                .update(stmt, None, None)
+                .unwrap()
+                .first()
+                .get_two::<f64, f64>()
+                .unwrap();
+            assert_eq!(average, Some(16.0));
+            assert_eq!(integral, Some(80.0));
+
+            let expected = "(\
+                version:2,\
+                first:(ts:\"2020-01-01 00:00:00+00\",val:10),\
+                last:(ts:\"2020-01-01 00:11:00+00\",val:10),\
+                weighted_sum:4800000000,\
+                method:LinearWithGaps,\
+                max_gap:120000000,\
+                gap_duration:360000000\
+            )";
+            let stmt = "SELECT toolkit_experimental.time_weight('Trapezoidal_With_Gaps', ts, val, '2 minutes')::TEXT \
+                FROM test";
+            assert_eq!(select_one!(client, stmt, String), expected);
+            let stmt = format!("SELECT average('{}'::TimeWeightSummary)", expected);
+            assert_eq!(select_one!(client, &stmt, f64), 16.0);
+
+            let stmt = "WITH t AS (\
+                    SELECT date_trunc('minute', ts), \
+                        toolkit_experimental.time_weight('linear_with_gaps', ts, val, '2 minutes') AS tw \
+                    FROM test GROUP BY 1\
+                ) SELECT average(rollup(tw)) FROM t";
+            assert_eq!(select_one!(client, stmt, f64), 16.0);
+
+            // the ends of the 3 minute buckets next to the gap are missing as
+            // well, the middle bucket only covers 00:03 to 00:04 and the last
+            // one 00:10 to 00:11
+            let stmt = "SELECT \
+                    interpolated_average(tw, bucket, '3 minutes', \
+                        LAG(tw) OVER (ORDER BY bucket), LEAD(tw) OVER (ORDER BY bucket)), \
+                    tw -> interpolated_average(bucket, '3 minutes', \
+                        LAG(tw) OVER (ORDER BY bucket), LEAD(tw) OVER (ORDER BY bucket)) \
+                FROM (\
+                    SELECT to_timestamp(floor(extract(epoch FROM ts) / 180) * 180) AS bucket, \
+                        toolkit_experimental.time_weight('linear_with_gaps', ts, val, '2 minutes') AS tw \
+                    FROM test GROUP BY 1\
+                ) s ORDER BY bucket";
+            let averages = client
+                .update(stmt, None, None)
+                .unwrap()
+                .map(|row| {
+                    (
+                        row[1].value::<f64>().unwrap(),
+                        row[2].value::<f64>().unwrap(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                averages,
+                vec![
+                    (Some(15.0), Some(15.0)),
+                    (Some(15.0), Some(15.0)),
+                    (Some(20.0), Some(20.0)),
+                ]
+            );
+        });
+    }
+
+    #[pg_test(error = "the linear_with_gaps method requires a max_gap")]
+    fn test_time_weight_linear_with_gaps_without_max_gap() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT time_weight('linear_with_gaps', '2020-01-01 00:00:00+00', 10.0)",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
     #[pg_test]
     fn test_time_weight_interpolation() {
         Spi::connect(|mut client| {
@@ -3,20 +3,66 @@ use pgx::*;
 use crate::time_weighted_average::DurationUnit;
 use crate::{
     datum_utils::interval_to_ms,
-    flatten, pg_type, ron_inout_funcs,
-    time_weighted_average::{TimeWeightMethod, TimeWeightSummary, TimeWeightSummaryData},
+    pg_type, ron_inout_funcs,
+    time_weighted_average::{TimeWeightMethod, TimeWeightSummary},
 };
 
 use tspoint::TSPoint;
 
+// The summaries before and after the one being interpolated, in the layout
+// of a `TimeWeightSummary` without gaps. Only their points are used, so the
+// gaps of a summary with a `max_gap` are left out.
+flat_serialize_macro::flat_serialize! {
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    struct TimeWeightNeighbor {
+        #[serde(skip, default="crate::serialization::serde_reference_adaptor::default_header")]
+        header: u32,
+        version: u8,
+        #[serde(skip, default="crate::serialization::serde_reference_adaptor::default_padding")]
+        padding: [u8; 3],
+        first: TSPoint,
+        last: TSPoint,
+        weighted_sum: f64,
+        method: TimeWeightMethod,
+    }
+}
+
+impl TimeWeightNeighbor {
+    fn new(summary: Option<TimeWeightSummary>) -> Self {
+        let (first, last, weighted_sum, method) = match summary {
+            Some(summary) => (
+                summary.first,
+                summary.last,
+                summary.weighted_sum,
+                summary.method,
+            ),
+            None => (
+                TSPoint { ts: 0, val: 0.0 },
+                TSPoint { ts: 0, val: 0.0 },
+                0.0,
+                TimeWeightMethod::LOCF,
+            ),
+        };
+        TimeWeightNeighbor {
+            header: 0,
+            version: 1,
+            padding: [0; 3],
+            first,
+            last,
+            weighted_sum,
+            method,
+        }
+    }
+}
+
 pg_type! {
     struct TimeWeightInterpolatedAverageAccessor {
         timestamp : i64,
         interval : i64,
-        prev : TimeWeightSummaryData,
+        prev : TimeWeightNeighbor,
         pad : [u8;3],
         flags : u32,
-        next : TimeWeightSummaryData,
+        next : TimeWeightNeighbor,
     }
 }
 
@@ -29,20 +75,9 @@ fn time_weight_interpolated_average_accessor<'a>(
     prev: default!(Option<TimeWeightSummary<'a>>, "NULL"),
     next: default!(Option<TimeWeightSummary<'a>>, "NULL"),
 ) -> TimeWeightInterpolatedAverageAccessor<'static> {
-    fn empty_summary<'b>() -> Option<TimeWeightSummary<'b>> {
-        Some(unsafe {
-            flatten!(TimeWeightSummary {
-                first: TSPoint { ts: 0, val: 0.0 },
-                last: TSPoint { ts: 0, val: 0.0 },
-                weighted_sum: 0.0,
-                method: TimeWeightMethod::LOCF,
-            })
-        })
-    }
-
     let flags = u32::from(prev.is_some()) + if next.is_some() { 2 } else { 0 };
-    let prev = prev.or_else(empty_summary).unwrap().0;
-    let next = next.or_else(empty_summary).unwrap().0;
+    let prev = TimeWeightNeighbor::new(prev);
+    let next = TimeWeightNeighbor::new(next);
     let interval = interval_to_ms(&start, &duration);
     crate::build! {
         TimeWeightInterpolatedAverageAccessor {
@@ -61,11 +96,11 @@ pg_type! {
     struct TimeWeightInterpolatedIntegralAccessor {
         start : i64,
         interval : i64,
-        prev : TimeWeightSummaryData,
+        prev : TimeWeightNeighbor,
         pad : [u8;3],
         unit : u32,
         flags: u64,
-        next : TimeWeightSummaryData,
+        next : TimeWeightNeighbor,
     }
 }
 
@@ -79,17 +114,6 @@ fn time_weight_interpolated_integral_accessor<'a>(
     next: default!(Option<TimeWeightSummary<'a>>, "NULL"),
     unit: default!(String, "'second'"),
 ) -> TimeWeightInterpolatedIntegralAccessor<'static> {
-    fn empty_summary<'b>() -> Option<TimeWeightSummary<'b>> {
-        Some(unsafe {
-            flatten!(TimeWeightSummary {
-                first: TSPoint { ts: 0, val: 0.0 },
-                last: TSPoint { ts: 0, val: 0.0 },
-                weighted_sum: 0.0,
-                method: TimeWeightMethod::LOCF,
-            })
-        })
-    }
-
     let unit = match DurationUnit::from_str(&unit) {
         Some(unit) => unit.microseconds(),
         None => pgx::error!(
@@ -98,8 +122,8 @@ fn time_weight_interpolated_integral_accessor<'a>(
         ),
     };
     let flags = u64::from(prev.is_some()) + if next.is_some() { 2 } else { 0 };
-    let prev = prev.or_else(empty_summary).unwrap().0;
-    let next = next.or_else(empty_summary).unwrap().0;
+    let prev = TimeWeightNeighbor::new(prev);
+    let next = TimeWeightNeighbor::new(next);
     let interval = interval_to_ms(&start, &interval);
     crate::build! {
         TimeWeightInterpolatedIntegralAccessor {
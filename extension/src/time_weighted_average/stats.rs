@@ -0,0 +1,1018 @@
+use pgx::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aggregate_utils::in_aggregate_context,
+    datum_utils::interval_to_ms,
+    duration::DurationUnit,
+    flatten,
+    palloc::{Inner, Internal, InternalAsValue, ToInternal},
+    pg_type,
+    raw::bytea,
+    ron_inout_funcs,
+    uddsketch::{UddSketch, UddSketchData},
+};
+
+use tspoint::TSPoint;
+use uddsketch::UDDSketch as UddSketchInternal;
+
+use time_weighted_average::{
+    TimeWeightError, TimeWeightMethod, TimeWeightStats as TimeWeightStatsInternal,
+    TimeWeightSummary as TimeWeightSummaryInternal,
+};
+
+use super::{parse_method, TimeWeightSummary};
+
+use toolkit_experimental::TimeWeightStatsSummary;
+
+#[pg_schema]
+pub mod toolkit_experimental {
+    use super::*;
+
+    pg_type! {
+        #[derive(Debug)]
+        struct TimeWeightStatsSummary<'input> {
+            first: TSPoint,
+            last: TSPoint,
+            weighted_sum: f64,
+            m2: f64,
+            min: f64,
+            max: f64,
+            // 0 when there is none
+            max_gap: i64,
+            gap_duration: i64,
+            method: TimeWeightMethod,
+            has_sketch: bool,
+            internal_padding: [u8; 6],
+            // empty when `has_sketch` is false
+            sketch: UddSketchData<'input>,
+        }
+    }
+
+    ron_inout_funcs!(TimeWeightStatsSummary);
+}
+
+impl<'input> TimeWeightStatsSummary<'input> {
+    fn internal(&self) -> TimeWeightStatsInternal {
+        TimeWeightStatsInternal {
+            summary: self.summary_internal(),
+            m2: self.m2,
+            min: self.min,
+            max: self.max,
+            sketch: self.sketch().map(|sketch| sketch.to_uddsketch()),
+        }
+    }
+
+    fn summary_internal(&self) -> TimeWeightSummaryInternal {
+        TimeWeightSummaryInternal {
+            method: self.method,
+            first: self.first,
+            last: self.last,
+            w_sum: self.weighted_sum,
+            max_gap: (self.max_gap > 0).then_some(self.max_gap),
+            gap_duration: self.gap_duration,
+        }
+    }
+
+    fn sketch(&self) -> Option<UddSketch<'static>> {
+        if !self.has_sketch {
+            return None;
+        }
+        Some(unsafe { self.sketch.flatten() })
+    }
+
+    fn from_internal(stats: &TimeWeightStatsInternal) -> TimeWeightStatsSummary<'static> {
+        let sketch = match &stats.sketch {
+            Some(sketch) => UddSketch::from_internal(sketch),
+            None => UddSketch::from_internal(&UddSketchInternal::new(1, 0.5)),
+        };
+        unsafe {
+            flatten!(TimeWeightStatsSummary {
+                first: stats.summary.first,
+                last: stats.summary.last,
+                weighted_sum: stats.summary.w_sum,
+                m2: stats.m2,
+                min: stats.min,
+                max: stats.max,
+                max_gap: stats.summary.max_gap.unwrap_or(0),
+                gap_duration: stats.summary.gap_duration,
+                method: stats.summary.method,
+                has_sketch: stats.sketch.is_some(),
+                internal_padding: [0; 6],
+                sketch: sketch.0,
+            })
+        }
+    }
+}
+
+// like `TimeWeightTransState` the points are only combined in the final
+// function, so that the partial states of a parallel aggregate can overlap
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TimeWeightStatsTransState {
+    point_buffer: Vec<TSPoint>,
+    method: TimeWeightMethod,
+    max_gap: Option<i64>,
+    // the empty sketch each summary starts from, if they keep one
+    sketch: Option<UddSketchInternal>,
+    summary_buffer: Vec<TimeWeightStatsInternal>,
+}
+
+impl TimeWeightStatsTransState {
+    fn combine_points(&mut self) {
+        if self.point_buffer.is_empty() {
+            return;
+        }
+        self.point_buffer.sort_unstable_by_key(|p| p.ts);
+        self.summary_buffer.push(
+            TimeWeightStatsInternal::new_from_sorted_iter(
+                &self.point_buffer,
+                self.method,
+                self.max_gap,
+                self.sketch.clone(),
+            )
+            .unwrap(),
+        );
+        self.point_buffer.clear();
+    }
+
+    fn combine_summaries(&mut self) {
+        self.combine_points();
+        if self.summary_buffer.len() <= 1 {
+            return;
+        }
+        self.summary_buffer
+            .sort_unstable_by_key(|s| s.summary.first.ts);
+        self.summary_buffer =
+            vec![TimeWeightStatsInternal::combine_sorted_iter(&self.summary_buffer).unwrap()];
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, strict, schema = "toolkit_experimental")]
+pub fn time_weight_stats_trans_serialize(state: Internal) -> bytea {
+    let state: Inner<TimeWeightStatsTransState> = unsafe { state.to_inner().unwrap() };
+    crate::do_serialize!(state)
+}
+
+#[pg_extern(strict, immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn time_weight_stats_trans_deserialize(bytes: bytea, _internal: Internal) -> Option<Internal> {
+    time_weight_stats_trans_deserialize_inner(bytes).internal()
+}
+pub fn time_weight_stats_trans_deserialize_inner(bytes: bytea) -> Inner<TimeWeightStatsTransState> {
+    let t: TimeWeightStatsTransState = crate::do_deserialize!(bytes, TimeWeightStatsTransState);
+    t.into()
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn time_weight_stats_trans(
+    state: Internal,
+    method: String,
+    ts: Option<crate::raw::TimestampTz>,
+    val: Option<f64>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    unsafe {
+        time_weight_stats_trans_inner(state.to_inner(), None, method, ts, val, None, fcinfo)
+            .internal()
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn time_weight_stats_gap_trans(
+    state: Internal,
+    method: String,
+    ts: Option<crate::raw::TimestampTz>,
+    val: Option<f64>,
+    max_gap: crate::raw::Interval,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    unsafe {
+        time_weight_stats_trans_inner(
+            state.to_inner(),
+            None,
+            method,
+            ts,
+            val,
+            Some(max_gap),
+            fcinfo,
+        )
+        .internal()
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn time_weight_stats_sketch_trans(
+    state: Internal,
+    size: i32,
+    max_error: f64,
+    method: String,
+    ts: Option<crate::raw::TimestampTz>,
+    val: Option<f64>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    unsafe {
+        time_weight_stats_trans_inner(
+            state.to_inner(),
+            Some((size, max_error)),
+            method,
+            ts,
+            val,
+            None,
+            fcinfo,
+        )
+        .internal()
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn time_weight_stats_sketch_gap_trans(
+    state: Internal,
+    size: i32,
+    max_error: f64,
+    method: String,
+    ts: Option<crate::raw::TimestampTz>,
+    val: Option<f64>,
+    max_gap: crate::raw::Interval,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    unsafe {
+        time_weight_stats_trans_inner(
+            state.to_inner(),
+            Some((size, max_error)),
+            method,
+            ts,
+            val,
+            Some(max_gap),
+            fcinfo,
+        )
+        .internal()
+    }
+}
+
+pub fn time_weight_stats_trans_inner(
+    state: Option<Inner<TimeWeightStatsTransState>>,
+    sketch: Option<(i32, f64)>,
+    method: String,
+    ts: Option<crate::raw::TimestampTz>,
+    val: Option<f64>,
+    max_gap: Option<crate::raw::Interval>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<TimeWeightStatsTransState>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let (ts, p) = match (ts, val) {
+                (_, None) => return state,
+                (None, _) => return state,
+                (Some(ts), Some(val)) => (ts, TSPoint { ts: ts.into(), val }),
+            };
+
+            let mut state = match state {
+                Some(state) => state,
+                None => {
+                    let method = parse_method(&method);
+                    let max_gap = max_gap.map(|max_gap| interval_to_ms(&ts, &max_gap));
+                    match (method, max_gap) {
+                        (TimeWeightMethod::LinearWithGaps, None) => {
+                            pgx::error!("the linear_with_gaps method requires a max_gap")
+                        }
+                        (_, Some(max_gap)) if max_gap <= 0 => {
+                            pgx::error!("max_gap must be positive")
+                        }
+                        _ => (),
+                    }
+                    TimeWeightStatsTransState {
+                        point_buffer: vec![],
+                        method,
+                        max_gap,
+                        sketch: sketch.map(|(size, max_error)| {
+                            UddSketchInternal::new(size as u64, max_error)
+                        }),
+                        summary_buffer: vec![],
+                    }
+                    .into()
+                }
+            };
+            state.point_buffer.push(p);
+            Some(state)
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn time_weight_stats_summary_trans<'a>(
+    state: Internal,
+    next: Option<TimeWeightStatsSummary<'a>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    time_weight_stats_summary_trans_inner(unsafe { state.to_inner() }, next, fcinfo).internal()
+}
+
+pub fn time_weight_stats_summary_trans_inner(
+    state: Option<Inner<TimeWeightStatsTransState>>,
+    next: Option<TimeWeightStatsSummary>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<TimeWeightStatsTransState>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || match (state, next) {
+            (None, None) => None,
+            (None, Some(next)) => {
+                let next = next.internal();
+                Some(
+                    TimeWeightStatsTransState {
+                        point_buffer: vec![],
+                        method: next.summary.method,
+                        max_gap: next.summary.max_gap,
+                        sketch: None,
+                        summary_buffer: vec![next],
+                    }
+                    .into(),
+                )
+            }
+            (Some(state), None) => Some(state),
+            (Some(mut state), Some(next)) => {
+                state.summary_buffer.push(next.internal());
+                Some(state)
+            }
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn time_weight_stats_combine(
+    state1: Internal,
+    state2: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    unsafe {
+        time_weight_stats_combine_inner(state1.to_inner(), state2.to_inner(), fcinfo).internal()
+    }
+}
+
+pub fn time_weight_stats_combine_inner(
+    state1: Option<Inner<TimeWeightStatsTransState>>,
+    state2: Option<Inner<TimeWeightStatsTransState>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<TimeWeightStatsTransState>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || match (state1, state2) {
+            (None, None) => None,
+            (None, Some(only)) | (Some(only), None) => Some(only.clone().into()),
+            (Some(state1), Some(state2)) => {
+                let mut s2 = state2.clone();
+                s2.point_buffer.extend_from_slice(&state1.point_buffer);
+                s2.summary_buffer.extend_from_slice(&state1.summary_buffer);
+                Some(s2.into())
+            }
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+fn time_weight_stats_final(
+    state: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<TimeWeightStatsSummary<'static>> {
+    time_weight_stats_final_inner(unsafe { state.to_inner() }, fcinfo)
+}
+
+fn time_weight_stats_final_inner(
+    state: Option<Inner<TimeWeightStatsTransState>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<TimeWeightStatsSummary<'static>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let mut state = match state {
+                None => return None,
+                Some(state) => state.clone(),
+            };
+            state.combine_summaries();
+            debug_assert!(state.summary_buffer.len() <= 1);
+            state
+                .summary_buffer
+                .pop()
+                .map(|stats| TimeWeightStatsSummary::from_internal(&stats))
+        })
+    }
+}
+
+// the four argument `time_weight` shares the transition functions of
+// `time_weight_stats` and returns the summary without the statistics
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+fn time_weight_gap_final(
+    state: Internal,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<TimeWeightSummary<'static>> {
+    time_weight_gap_final_inner(unsafe { state.to_inner() }, fcinfo)
+}
+
+fn time_weight_gap_final_inner(
+    state: Option<Inner<TimeWeightStatsTransState>>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<TimeWeightSummary<'static>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let mut state = match state {
+                None => return None,
+                Some(state) => state.clone(),
+            };
+            state.combine_summaries();
+            debug_assert!(state.summary_buffer.len() <= 1);
+            state
+                .summary_buffer
+                .pop()
+                .map(|stats| TimeWeightSummary::from_internal(&stats.summary))
+        })
+    }
+}
+
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.time_weight_stats(method text, ts timestamptz, value DOUBLE PRECISION)\n\
+    (\n\
+        sfunc = toolkit_experimental.time_weight_stats_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.time_weight_stats_final,\n\
+        combinefunc = toolkit_experimental.time_weight_stats_combine,\n\
+        serialfunc = toolkit_experimental.time_weight_stats_trans_serialize,\n\
+        deserialfunc = toolkit_experimental.time_weight_stats_trans_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+\n\
+    CREATE AGGREGATE toolkit_experimental.time_weight_stats(\n\
+        size INTEGER, max_error DOUBLE PRECISION, method text, ts timestamptz, value DOUBLE PRECISION\n\
+    ) (\n\
+        sfunc = toolkit_experimental.time_weight_stats_sketch_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.time_weight_stats_final,\n\
+        combinefunc = toolkit_experimental.time_weight_stats_combine,\n\
+        serialfunc = toolkit_experimental.time_weight_stats_trans_serialize,\n\
+        deserialfunc = toolkit_experimental.time_weight_stats_trans_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+\n\
+    CREATE AGGREGATE toolkit_experimental.time_weight_stats(\n\
+        method text, ts timestamptz, value DOUBLE PRECISION, max_gap interval\n\
+    ) (\n\
+        sfunc = toolkit_experimental.time_weight_stats_gap_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.time_weight_stats_final,\n\
+        combinefunc = toolkit_experimental.time_weight_stats_combine,\n\
+        serialfunc = toolkit_experimental.time_weight_stats_trans_serialize,\n\
+        deserialfunc = toolkit_experimental.time_weight_stats_trans_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+\n\
+    CREATE AGGREGATE toolkit_experimental.time_weight_stats(\n\
+        size INTEGER, max_error DOUBLE PRECISION, method text, ts timestamptz, value DOUBLE PRECISION, max_gap interval\n\
+    ) (\n\
+        sfunc = toolkit_experimental.time_weight_stats_sketch_gap_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.time_weight_stats_final,\n\
+        combinefunc = toolkit_experimental.time_weight_stats_combine,\n\
+        serialfunc = toolkit_experimental.time_weight_stats_trans_serialize,\n\
+        deserialfunc = toolkit_experimental.time_weight_stats_trans_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+\n\
+    CREATE AGGREGATE toolkit_experimental.time_weight(\n\
+        method text, ts timestamptz, value DOUBLE PRECISION, max_gap interval\n\
+    ) (\n\
+        sfunc = toolkit_experimental.time_weight_stats_gap_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.time_weight_gap_final,\n\
+        combinefunc = toolkit_experimental.time_weight_stats_combine,\n\
+        serialfunc = toolkit_experimental.time_weight_stats_trans_serialize,\n\
+        deserialfunc = toolkit_experimental.time_weight_stats_trans_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+\n\
+    CREATE AGGREGATE toolkit_experimental.rollup(stats toolkit_experimental.TimeWeightStatsSummary)\n\
+    (\n\
+        sfunc = toolkit_experimental.time_weight_stats_summary_trans,\n\
+        stype = internal,\n\
+        finalfunc = toolkit_experimental.time_weight_stats_final,\n\
+        combinefunc = toolkit_experimental.time_weight_stats_combine,\n\
+        serialfunc = toolkit_experimental.time_weight_stats_trans_serialize,\n\
+        deserialfunc = toolkit_experimental.time_weight_stats_trans_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+",
+    name = "time_weight_stats_agg",
+    requires = [
+        time_weight_stats_trans,
+        time_weight_stats_sketch_trans,
+        time_weight_stats_gap_trans,
+        time_weight_stats_sketch_gap_trans,
+        time_weight_stats_final,
+        time_weight_gap_final,
+        time_weight_stats_combine,
+        time_weight_stats_trans_serialize,
+        time_weight_stats_trans_deserialize,
+        time_weight_stats_summary_trans
+    ],
+);
+
+// without bounds the statistics of a single value are undefined, like
+// `average` we return NULL for them instead of erroring
+fn zero_duration_to_none(result: Result<f64, TimeWeightError>) -> Option<f64> {
+    match result {
+        Ok(value) => Some(value),
+        Err(TimeWeightError::ZeroDuration) => None,
+        Err(e) => Err(e).unwrap(),
+    }
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "time_weight",
+    schema = "toolkit_experimental"
+)]
+pub fn time_weight_stats_time_weight<'a>(
+    stats: TimeWeightStatsSummary<'a>,
+) -> TimeWeightSummary<'static> {
+    TimeWeightSummary::from_internal(&stats.summary_internal())
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "average",
+    schema = "toolkit_experimental"
+)]
+pub fn time_weight_stats_average<'a>(stats: Option<TimeWeightStatsSummary<'a>>) -> Option<f64> {
+    zero_duration_to_none(stats?.summary_internal().time_weighted_average())
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "integral",
+    schema = "toolkit_experimental"
+)]
+pub fn time_weight_stats_integral<'a>(
+    stats: Option<TimeWeightStatsSummary<'a>>,
+    unit: default!(String, "'second'"),
+) -> Option<f64> {
+    let unit = match DurationUnit::from_str(&unit) {
+        Some(unit) => unit,
+        None => pgx::error!(
+            "Unrecognized duration unit: {}. Valid units are: usecond, msecond, second, minute, hour",
+            unit,
+        ),
+    };
+    let integral_microsecs = stats?.summary_internal().time_weighted_integral();
+    Some(DurationUnit::Microsec.convert_unit(integral_microsecs, unit))
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "variance",
+    schema = "toolkit_experimental"
+)]
+pub fn time_weight_stats_variance<'a>(stats: Option<TimeWeightStatsSummary<'a>>) -> Option<f64> {
+    zero_duration_to_none(stats?.internal().time_weighted_variance())
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "stddev",
+    schema = "toolkit_experimental"
+)]
+pub fn time_weight_stats_stddev<'a>(stats: Option<TimeWeightStatsSummary<'a>>) -> Option<f64> {
+    zero_duration_to_none(stats?.internal().time_weighted_stddev())
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "min_val",
+    schema = "toolkit_experimental"
+)]
+pub fn time_weight_stats_min_val<'a>(stats: TimeWeightStatsSummary<'a>) -> f64 {
+    stats.min
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "max_val",
+    schema = "toolkit_experimental"
+)]
+pub fn time_weight_stats_max_val<'a>(stats: TimeWeightStatsSummary<'a>) -> f64 {
+    stats.max
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "covered_duration",
+    schema = "toolkit_experimental"
+)]
+pub fn time_weight_stats_covered_duration<'a>(
+    stats: TimeWeightStatsSummary<'a>,
+) -> crate::raw::Interval {
+    stats.summary_internal().covered_duration().into()
+}
+
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "covered_duration",
+    schema = "toolkit_experimental"
+)]
+pub fn time_weight_covered_duration<'a>(tws: TimeWeightSummary<'a>) -> crate::raw::Interval {
+    tws.internal().covered_duration().into()
+}
+
+fn sketch_or_error(stats: &TimeWeightStatsSummary) -> UddSketch<'static> {
+    match stats.sketch() {
+        Some(sketch) => sketch,
+        None => pgx::error!(
+            "time_weight_stats was computed without a sketch, pass a size and max_error to it to compute percentiles"
+        ),
+    }
+}
+
+/// The value the series was at or below for `percentile` of the time.
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "approx_percentile",
+    schema = "toolkit_experimental"
+)]
+pub fn time_weight_stats_approx_percentile<'a>(
+    percentile: f64,
+    stats: TimeWeightStatsSummary<'a>,
+) -> f64 {
+    crate::uddsketch::uddsketch_approx_percentile(percentile, sketch_or_error(&stats))
+}
+
+/// The fraction of the time the series was at or below `value`.
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "approx_percentile_rank",
+    schema = "toolkit_experimental"
+)]
+pub fn time_weight_stats_approx_percentile_rank<'a>(
+    value: f64,
+    stats: TimeWeightStatsSummary<'a>,
+) -> f64 {
+    crate::uddsketch::uddsketch_approx_percentile_rank(value, sketch_or_error(&stats))
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use pgx::*;
+    use pgx_macros::pg_test;
+
+    macro_rules! select_one {
+        ($client:expr, $stmt:expr, $type:ty) => {
+            $client
+                .update($stmt, None, None)
+                .unwrap()
+                .first()
+                .get_one::<$type>()
+                .unwrap()
+                .unwrap()
+        };
+    }
+
+    fn setup(client: &mut pgx::spi::SpiClient) {
+        client.update("SET timezone TO 'UTC'", None, None).unwrap();
+        // using the search path trick for this test b/c the overloads are
+        // difficult to spot otherwise.
+        let sp = client
+            .update(
+                "SELECT format(' %s, toolkit_experimental',current_setting('search_path'))",
+                None,
+                None,
+            )
+            .unwrap()
+            .first()
+            .get_one::<String>()
+            .unwrap()
+            .unwrap();
+        client
+            .update(&format!("SET LOCAL search_path TO {}", sp), None, None)
+            .unwrap();
+        client
+            .update(
+                "CREATE TABLE test(ts timestamptz, val DOUBLE PRECISION)",
+                None,
+                None,
+            )
+            .unwrap();
+        client
+            .update(
+                "INSERT INTO test VALUES \
+                ('2020-01-01 00:00:00+00', 10.0), \
+                ('2020-01-01 00:01:00+00', 20.0), \
+                ('2020-01-01 00:02:00+00', 10.0), \
+                ('2020-01-01 00:03:00+00', 20.0), \
+                ('2020-01-01 00:04:00+00', 10.0)",
+                None,
+                None,
+            )
+            .unwrap();
+    }
+
+    #[pg_test]
+    fn test_time_weight_stats() {
+        Spi::connect(|mut client| {
+            setup(&mut client);
+
+            let stmt = "SELECT average(s), integral(s, 'minute'), variance(s) \
+                FROM (SELECT time_weight_stats('LOCF', ts, val) s FROM test) s";
+            let (average, integral, variance) = client
+                .update(stmt, None, None)
+                .unwrap()
+                .first()
+                .get_three::<f64, f64, f64>()
+                .unwrap();
+            assert_eq!(average, Some(15.0));
+            assert_eq!(integral, Some(60.0));
+            assert_eq!(variance, Some(25.0));
+
+            let stmt = "SELECT stddev(s), min_val(s), max_val(s) \
+                FROM (SELECT time_weight_stats('LOCF', ts, val ORDER BY random()) s FROM test) s";
+            let (stddev, min, max) = client
+                .update(stmt, None, None)
+                .unwrap()
+                .first()
+                .get_three::<f64, f64, f64>()
+                .unwrap();
+            assert_eq!(stddev, Some(5.0));
+            assert_eq!(min, Some(10.0));
+            assert_eq!(max, Some(20.0));
+
+            let stmt = "SELECT variance(time_weight_stats('Linear', ts, val)) FROM test";
+            assert!((select_one!(client, stmt, f64) - 100.0 / 12.0).abs() < 1e-9);
+
+            // the stable accessors work on the embedded summary
+            let stmt = "SELECT time_weight(time_weight_stats('Linear', ts, val))::TEXT \
+                = time_weight('Linear', ts, val)::TEXT FROM test";
+            assert!(select_one!(client, stmt, bool));
+
+            // a single point has no duration
+            let stmt = "SELECT variance(time_weight_stats('LOCF', ts, val)) FROM test \
+                WHERE ts = '2020-01-01 00:00:00+00'";
+            assert_eq!(
+                client
+                    .update(stmt, None, None)
+                    .unwrap()
+                    .first()
+                    .get_one::<f64>()
+                    .unwrap(),
+                None
+            );
+        });
+    }
+
+    #[pg_test]
+    fn test_time_weight_stats_percentiles() {
+        Spi::connect(|mut client| {
+            setup(&mut client);
+
+            for method in ["LOCF", "Linear"] {
+                let stmt = format!(
+                    "SELECT approx_percentile_rank(15, s), approx_percentile(0.25, s) \
+                    FROM (SELECT time_weight_stats(100, 0.001, '{}', ts, val) s FROM test) s",
+                    method
+                );
+                let (rank, percentile) = client
+                    .update(&stmt, None, None)
+                    .unwrap()
+                    .first()
+                    .get_two::<f64, f64>()
+                    .unwrap();
+                assert!((rank.unwrap() - 0.5).abs() < 1e-9, "{}", method);
+                // LOCF spends half of the time at 10, linear a quarter of the
+                // time below 12.5
+                let expected = if method == "LOCF" { 10.0 } else { 12.5 };
+                assert!(
+                    (percentile.unwrap() - expected).abs() < 0.5,
+                    "{} {:?}",
+                    method,
+                    percentile
+                );
+            }
+        });
+    }
+
+    #[pg_test]
+    fn test_time_weight_stats_rollup() {
+        Spi::connect(|mut client| {
+            setup(&mut client);
+
+            for method in ["LOCF", "Linear"] {
+                let stmt = format!(
+                    "WITH t AS (\
+                        SELECT date_trunc('hour', ts) + (extract(minute FROM ts)::int / 2) * '2 min'::interval, \
+                            time_weight_stats(100, 0.001, '{0}', ts, val) AS s \
+                        FROM test GROUP BY 1\
+                    ), rolled AS (SELECT rollup(s) AS s FROM t), \
+                    direct AS (SELECT time_weight_stats(100, 0.001, '{0}', ts, val) AS s FROM test) \
+                    SELECT average(rolled.s) = average(direct.s), \
+                        abs(variance(rolled.s) - variance(direct.s)) < 1e-9, \
+                        approx_percentile_rank(15, rolled.s) = approx_percentile_rank(15, direct.s) \
+                    FROM rolled, direct",
+                    method
+                );
+                let (average, variance, rank) = client
+                    .update(&stmt, None, None)
+                    .unwrap()
+                    .first()
+                    .get_three::<bool, bool, bool>()
+                    .unwrap();
+                assert_eq!(
+                    (average, variance, rank),
+                    (Some(true), Some(true), Some(true)),
+                    "{}",
+                    method
+                );
+            }
+        });
+    }
+
+    #[pg_test(
+        error = "time_weight_stats was computed without a sketch, pass a size and max_error to it to compute percentiles"
+    )]
+    fn test_time_weight_stats_no_sketch() {
+        Spi::connect(|mut client| {
+            setup(&mut client);
+            client
+                .update(
+                    "SELECT approx_percentile(0.5, time_weight_stats('LOCF', ts, val)) FROM test",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test]
+    fn test_time_weight_stats_gaps() {
+        Spi::connect(|mut client| {
+            setup(&mut client);
+            client
+                .update(
+                    "INSERT INTO test VALUES \
+                    ('2020-01-01 00:10:00+00', 30.0), \
+                    ('2020-01-01 00:11:00+00', 10.0)",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            // the 6 minutes between 00:04 and 00:10 are missing, leaving
+            // (15*4 + 20) / 5
+            let stmt = "SELECT average(s), integral(s, 'minute') \
+                FROM (SELECT time_weight_stats('linear_with_gaps', ts, val, '2 minutes') s FROM test) s";
+            let (average, integral) = client
+                .update(stmt, None, None)
+                .unwrap()
+                .first()
+                .get_two::<f64, f64>()
+                .unwrap();
+            assert_eq!(average, Some(16.0));
+            assert_eq!(integral, Some(80.0));
+
+            // without the gap it's interpolated across
+            let stmt = "SELECT average(time_weight_stats('linear', ts, val, '10 minutes')) \
+                = average(time_weight('linear', ts, val)) FROM test";
+            assert!(select_one!(client, stmt, bool));
+
+            // below 20 for all of the first 4 minutes and half of the last one
+            let stmt = "SELECT approx_percentile_rank(20, \
+                time_weight_stats(100, 0.001, 'linear', ts, val, '2 minutes' ORDER BY random())) \
+                FROM test";
+            assert!((select_one!(client, stmt, f64) - 0.9).abs() < 1e-9);
+
+            let stmt = "WITH t AS (\
+                    SELECT date_trunc('minute', ts), \
+                        time_weight_stats('linear', ts, val, '2 minutes') AS s \
+                    FROM test GROUP BY 1\
+                ) SELECT average(rollup(s ORDER BY random())) FROM t";
+            assert_eq!(select_one!(client, stmt, f64), 16.0);
+        });
+    }
+
+    #[pg_test]
+    fn test_time_weight_max_gap() {
+        Spi::connect(|mut client| {
+            setup(&mut client);
+            client
+                .update(
+                    "INSERT INTO test VALUES \
+                    ('2020-01-01 00:10:00+00', 30.0), \
+                    ('2020-01-01 00:11:00+00', 10.0)",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            // (15*4 + 30) / 5 with the 6 minute gap left out
+            let stmt = "SELECT average(tw), integral(tw, 'minute'), covered_duration(tw)::TEXT \
+                FROM (SELECT time_weight('LOCF', ts, val, '2 minutes') tw FROM test) t";
+            let (average, integral, covered) = client
+                .update(stmt, None, None)
+                .unwrap()
+                .first()
+                .get_three::<f64, f64, String>()
+                .unwrap();
+            assert_eq!(average, Some(18.0));
+            assert_eq!(integral, Some(90.0));
+            assert_eq!(covered.as_deref(), Some("00:05:00"));
+
+            // (15*4 + 10) / 5
+            let stmt =
+                "SELECT average(time_weight('NOCB', ts, val, '2 minutes' ORDER BY random())) \
+                FROM test";
+            assert_eq!(select_one!(client, stmt, f64), 14.0);
+
+            let stmt =
+                "SELECT covered_duration(time_weight_stats('LOCF', ts, val))::TEXT FROM test";
+            assert_eq!(select_one!(client, stmt, &str), "00:11:00");
+
+            let stmt = "WITH t AS (\
+                    SELECT date_trunc('minute', ts), \
+                        time_weight('LOCF', ts, val, '2 minutes') AS tw \
+                    FROM test GROUP BY 1\
+                ) SELECT covered_duration(rollup(tw))::TEXT FROM t";
+            assert_eq!(select_one!(client, stmt, &str), "00:05:00");
+        });
+    }
+
+    #[pg_test]
+    fn test_time_weight_max_gap_rollup_across_buckets() {
+        Spi::connect(|mut client| {
+            setup(&mut client);
+            client
+                .update(
+                    "INSERT INTO test VALUES \
+                    ('2020-01-01 00:10:00+00', 30.0), \
+                    ('2020-01-01 00:11:00+00', 10.0)",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            // the 00:04 to 00:10 segment crosses the bounds of the 5 minute
+            // buckets, the rollup decides whether it's a gap same as the
+            // aggregate over all of the points
+            for (max_gap, average, covered) in [
+                ("2 minutes", 18.0, "00:05:00"),
+                ("10 minutes", 150.0 / 11.0, "00:11:00"),
+            ] {
+                let stmt = format!(
+                    "WITH t AS (\
+                        SELECT to_timestamp(floor(extract(epoch FROM ts) / 300) * 300), \
+                            time_weight('LOCF', ts, val, '{max_gap}') AS tw \
+                        FROM test GROUP BY 1\
+                    ) SELECT average(rollup(tw ORDER BY random())), \
+                        covered_duration(rollup(tw))::TEXT, \
+                        (SELECT average(time_weight('LOCF', ts, val, '{max_gap}')) FROM test) \
+                    FROM t",
+                    max_gap = max_gap
+                );
+                let (rolled_up, rolled_up_covered, direct) = client
+                    .update(&stmt, None, None)
+                    .unwrap()
+                    .first()
+                    .get_three::<f64, String, f64>()
+                    .unwrap();
+                assert!((rolled_up.unwrap() - average).abs() < 1e-9);
+                assert_eq!(rolled_up, direct);
+                assert_eq!(rolled_up_covered.as_deref(), Some(covered));
+            }
+        });
+    }
+
+    #[pg_test]
+    fn test_time_weight_max_gap_to_summary() {
+        Spi::connect(|mut client| {
+            setup(&mut client);
+            let stmt = "SELECT time_weight(time_weight_stats('LOCF', ts, val, '2 minutes'))::TEXT \
+                = time_weight('LOCF', ts, val, '2 minutes')::TEXT FROM test";
+            assert!(select_one!(client, stmt, bool));
+        });
+    }
+
+    #[pg_test(error = "the linear_with_gaps method requires a max_gap")]
+    fn test_time_weight_stats_gaps_without_max_gap() {
+        Spi::connect(|mut client| {
+            setup(&mut client);
+            client
+                .update(
+                    "SELECT time_weight_stats('linear_with_gaps', ts, val) FROM test",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+}
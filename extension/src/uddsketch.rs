@@ -1,4 +1,4 @@
-use pgx::*;
+use pgx::{iter::TableIterator, *};
 
 use encodings::{delta, prefix_varint};
 
@@ -6,6 +6,10 @@ use uddsketch::{SketchHashKey, UDDSketch as UddSketchInternal};
 
 use crate::{
     accessors::{
+        toolkit_experimental::{
+            AccessorApproxIqr, AccessorApproxMad, AccessorApproxTrimmedMean,
+            AccessorApproxWinsorizedMean,
+        },
         AccessorApproxPercentile, AccessorApproxPercentileRank, AccessorError, AccessorMean,
         AccessorNumVals, AccessorPercentileArray,
     },
@@ -74,6 +78,73 @@ pub fn percentile_agg_trans_inner(
     let default_max_error = PERCENTILE_AGG_DEFAULT_ERROR;
     uddsketch_trans_inner(state, default_size as _, default_max_error, value, fcinfo)
 }
+// PG function for adding pre-aggregated values to a sketch, each value is
+// counted `weight` times.  Rows with a null value or weight are ignored.
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn uddsketch_weighted_trans(
+    state: Internal,
+    size: i32,
+    max_error: f64,
+    value: Option<f64>,
+    weight: Option<i64>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    uddsketch_weighted_trans_inner(
+        unsafe { state.to_inner() },
+        size,
+        max_error,
+        value,
+        weight,
+        fcinfo,
+    )
+    .internal()
+}
+
+pub fn uddsketch_weighted_trans_inner(
+    state: Option<Inner<UddSketchInternal>>,
+    size: i32,
+    max_error: f64,
+    value: Option<f64>,
+    weight: Option<i64>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Inner<UddSketchInternal>> {
+    unsafe {
+        in_aggregate_context(fcinfo, || {
+            let (value, weight) = match (value, weight) {
+                (Some(value), Some(weight)) => (value, weight),
+                _ => return state,
+            };
+            if weight < 0 {
+                pgx::error!("weight must not be negative, got {}", weight)
+            }
+            let mut state = match state {
+                None => UddSketchInternal::new(size as u64, max_error).into(),
+                Some(state) => state,
+            };
+            state.add_weighted_value(value, weight as u64);
+            Some(state)
+        })
+    }
+}
+
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn percentile_agg_weighted_trans(
+    state: Internal,
+    value: Option<f64>,
+    weight: Option<i64>,
+    fcinfo: pg_sys::FunctionCallInfo,
+) -> Option<Internal> {
+    uddsketch_weighted_trans_inner(
+        unsafe { state.to_inner() },
+        PERCENTILE_AGG_DEFAULT_SIZE as _,
+        PERCENTILE_AGG_DEFAULT_ERROR,
+        value,
+        weight,
+        fcinfo,
+    )
+    .internal()
+}
+
 // PG function for merging sketches.
 #[pg_extern(immutable, parallel_safe)]
 pub fn uddsketch_combine(
@@ -304,7 +375,7 @@ impl<'input> UddSketch<'input> {
         )
     }
 
-    fn to_uddsketch(&self) -> UddSketchInternal {
+    pub(crate) fn to_uddsketch(&self) -> UddSketchInternal {
         UddSketchInternal::new_from_data(
             self.max_buckets as u64,
             self.alpha,
@@ -316,7 +387,7 @@ impl<'input> UddSketch<'input> {
         )
     }
 
-    fn from_internal(state: &UddSketchInternal) -> Self {
+    pub(crate) fn from_internal(state: &UddSketchInternal) -> Self {
         let CompressedBuckets {
             negative_indexes,
             negative_counts,
@@ -387,15 +458,17 @@ fn uddsketch_final_inner(
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
-struct CompressedBuckets {
-    negative_indexes: Vec<u8>,
-    negative_counts: Vec<u8>,
-    zero_bucket_count: u64,
-    positive_indexes: Vec<u8>,
-    positive_counts: Vec<u8>,
+pub(crate) struct CompressedBuckets {
+    pub(crate) negative_indexes: Vec<u8>,
+    pub(crate) negative_counts: Vec<u8>,
+    pub(crate) zero_bucket_count: u64,
+    pub(crate) positive_indexes: Vec<u8>,
+    pub(crate) positive_counts: Vec<u8>,
 }
 
-fn compress_buckets(buckets: impl Iterator<Item = (SketchHashKey, u64)>) -> CompressedBuckets {
+pub(crate) fn compress_buckets(
+    buckets: impl Iterator<Item = (SketchHashKey, u64)>,
+) -> CompressedBuckets {
     let mut negative_indexes = prefix_varint::I64Compressor::with(delta::i64_encoder());
     let mut negative_counts = prefix_varint::U64Compressor::with(delta::u64_encoder());
     let mut zero_bucket_count = 0;
@@ -428,7 +501,7 @@ fn compress_buckets(buckets: impl Iterator<Item = (SketchHashKey, u64)>) -> Comp
     }
 }
 
-fn decompress_keys<'i>(
+pub(crate) fn decompress_keys<'i>(
     negative_indexes: &'i [u8],
     zero_bucket: bool,
     positive_indexes: &'i [u8],
@@ -446,7 +519,7 @@ fn decompress_keys<'i>(
     negatives.chain(zero).chain(positives)
 }
 
-fn decompress_counts<'b>(
+pub(crate) fn decompress_counts<'b>(
     negative_buckets: &'b [u8],
     zero_bucket: u64,
     positive_buckets: &'b [u8],
@@ -505,6 +578,53 @@ extension_sql!(
     ],
 );
 
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.uddsketch(\n\
+        size integer, max_error DOUBLE PRECISION, value DOUBLE PRECISION, weight bigint\n\
+    ) (\n\
+        sfunc = toolkit_experimental.uddsketch_weighted_trans,\n\
+        stype = internal,\n\
+        finalfunc = uddsketch_final,\n\
+        combinefunc = uddsketch_combine,\n\
+        serialfunc = uddsketch_serialize,\n\
+        deserialfunc = uddsketch_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+",
+    name = "udd_weighted_agg",
+    requires = [
+        uddsketch_weighted_trans,
+        uddsketch_final,
+        uddsketch_combine,
+        uddsketch_serialize,
+        uddsketch_deserialize
+    ],
+);
+
+extension_sql!(
+    "\n\
+    CREATE AGGREGATE toolkit_experimental.percentile_agg(value DOUBLE PRECISION, weight bigint)\n\
+    (\n\
+        sfunc = toolkit_experimental.percentile_agg_weighted_trans,\n\
+        stype = internal,\n\
+        finalfunc = uddsketch_final,\n\
+        combinefunc = uddsketch_combine,\n\
+        serialfunc = uddsketch_serialize,\n\
+        deserialfunc = uddsketch_deserialize,\n\
+        parallel = safe\n\
+    );\n\
+",
+    name = "percentile_agg_weighted",
+    requires = [
+        percentile_agg_weighted_trans,
+        uddsketch_final,
+        uddsketch_combine,
+        uddsketch_serialize,
+        uddsketch_deserialize
+    ],
+);
+
 #[pg_extern(immutable, parallel_safe)]
 pub fn uddsketch_compound_trans<'a>(
     state: Internal,
@@ -558,6 +678,68 @@ extension_sql!(
     ],
 );
 
+// Builds a sketch from an explicit-bucket histogram, `counts[i]` values lie in
+// (`bounds[i]`, `bounds[i + 1]`].  Returns NULL if there are no values at all.
+// The sketch is compacted until each bucket fits into one of its own, so the
+// error guarantee holds for the values of every bucket, except for:
+// - a bucket containing zero, its values are counted as zero, like those of the
+//   zero bucket of a Prometheus native histogram.
+// - the first bound may be `-Infinity` and the last `Infinity`, as in Prometheus
+//   histograms.  The values of such an unbounded bucket are counted at its
+//   finite bound, as Prometheus' `histogram_quantile` does.
+#[pg_extern(immutable, parallel_safe, schema = "toolkit_experimental")]
+pub fn uddsketch_from_buckets(
+    bounds: Vec<f64>,
+    counts: Vec<i64>,
+    max_buckets: i32,
+    max_error: f64,
+) -> Option<UddSketch<'static>> {
+    if bounds.len() != counts.len() + 1 {
+        pgx::error!(
+            "uddsketch_from_buckets requires one more bound than counts, got {} bounds and {} counts",
+            bounds.len(),
+            counts.len()
+        )
+    }
+    if bounds.iter().any(|b| b.is_nan()) {
+        pgx::error!("uddsketch_from_buckets requires bounds that are not NaN")
+    }
+    if bounds.windows(2).any(|w| w[0] >= w[1]) {
+        pgx::error!("uddsketch_from_buckets requires strictly increasing bounds")
+    }
+    // bounds are strictly increasing so only a lone bucket can be unbounded on both sides
+    if bounds
+        .windows(2)
+        .any(|w| w[0].is_infinite() && w[1].is_infinite())
+    {
+        pgx::error!("uddsketch_from_buckets requires a finite bound for each bucket")
+    }
+    if let Some(count) = counts.iter().find(|c| **c < 0) {
+        pgx::error!("bucket counts must not be negative, got {}", count)
+    }
+    if max_buckets <= 0 {
+        pgx::error!(
+            "uddsketch_from_buckets requires a positive max_buckets, got {}",
+            max_buckets
+        )
+    }
+    if !(1e-12..1.0).contains(&max_error) {
+        pgx::error!(
+            "uddsketch_from_buckets requires a max_error between 0 and 1, got {}",
+            max_error
+        )
+    }
+
+    let mut sketch = UddSketchInternal::new(max_buckets as u64, max_error);
+    for (edges, count) in bounds.windows(2).zip(counts) {
+        sketch.add_bucket(edges[0], edges[1], count as u64);
+    }
+    if sketch.count() == 0 {
+        return None;
+    }
+    Some(UddSketch::from_internal(&sketch))
+}
+
 //---- Available PG operations on the sketch
 
 #[pg_operator(immutable, parallel_safe)]
@@ -636,6 +818,168 @@ pub fn uddsketch_approx_percentile_rank<'a>(value: f64, sketch: UddSketch<'a>) -
     )
 }
 
+// The bounds of the trimmed and winsorized means are quantiles, with at least some values between them.
+pub(crate) fn check_trim_bounds(low: f64, high: f64) {
+    if !(0.0 <= low && low < high && high <= 1.0) {
+        pgx::error!(
+            "the bounds must satisfy 0 <= low < high <= 1, got low {} and high {}",
+            low,
+            high
+        )
+    }
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_uddsketch_approx_trimmed_mean<'a>(
+    sketch: UddSketch<'a>,
+    accessor: AccessorApproxTrimmedMean<'a>,
+) -> f64 {
+    uddsketch_approx_trimmed_mean(sketch, accessor.low, accessor.high)
+}
+
+// Approximate the mean of the values between the `low` and `high` percentiles.
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "approx_trimmed_mean",
+    schema = "toolkit_experimental"
+)]
+pub fn uddsketch_approx_trimmed_mean<'a>(sketch: UddSketch<'a>, low: f64, high: f64) -> f64 {
+    check_trim_bounds(low, high);
+    uddsketch::estimate_trimmed_mean(
+        low,
+        high,
+        sketch.alpha,
+        uddsketch::gamma(sketch.alpha),
+        sketch.count,
+        sketch.keys().zip(sketch.counts()),
+    )
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_uddsketch_approx_winsorized_mean<'a>(
+    sketch: UddSketch<'a>,
+    accessor: AccessorApproxWinsorizedMean<'a>,
+) -> f64 {
+    uddsketch_approx_winsorized_mean(sketch, accessor.low, accessor.high)
+}
+
+// Approximate the mean of the values, with those outside of the `low` and `high`
+// percentiles replaced by the value at the percentile.
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "approx_winsorized_mean",
+    schema = "toolkit_experimental"
+)]
+pub fn uddsketch_approx_winsorized_mean<'a>(sketch: UddSketch<'a>, low: f64, high: f64) -> f64 {
+    check_trim_bounds(low, high);
+    uddsketch::estimate_winsorized_mean(
+        low,
+        high,
+        sketch.alpha,
+        uddsketch::gamma(sketch.alpha),
+        sketch.count,
+        sketch.keys().zip(sketch.counts()),
+    )
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_uddsketch_approx_iqr<'a>(
+    sketch: UddSketch<'a>,
+    _accessor: AccessorApproxIqr<'a>,
+) -> f64 {
+    uddsketch_approx_iqr(sketch)
+}
+
+// Approximate interquartile range, the difference between the 75th and 25th percentiles.
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "approx_iqr",
+    schema = "toolkit_experimental"
+)]
+pub fn uddsketch_approx_iqr<'a>(sketch: UddSketch<'a>) -> f64 {
+    let quartiles = approx_percentile_slice(&[0.25, 0.75], sketch);
+    quartiles[1] - quartiles[0]
+}
+
+#[pg_operator(immutable, parallel_safe)]
+#[opname(->)]
+pub fn arrow_uddsketch_approx_mad<'a>(
+    sketch: UddSketch<'a>,
+    _accessor: AccessorApproxMad<'a>,
+) -> f64 {
+    uddsketch_approx_mad(sketch)
+}
+
+// Approximate median absolute deviation from the median.
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "approx_mad",
+    schema = "toolkit_experimental"
+)]
+pub fn uddsketch_approx_mad<'a>(sketch: UddSketch<'a>) -> f64 {
+    uddsketch::estimate_median_absolute_deviation(
+        sketch.alpha,
+        uddsketch::gamma(sketch.alpha),
+        sketch.count,
+        sketch.keys().zip(sketch.counts()),
+    )
+}
+
+// Bins for `approx_histogram` are (`bounds[i]`, `bounds[i + 1]`], so there must be at least two bounds.
+pub(crate) fn check_histogram_bounds(bounds: &[f64]) {
+    if bounds.len() < 2 {
+        pgx::error!("approx_histogram requires at least two bounds")
+    }
+    if bounds.iter().any(|b| b.is_nan()) || bounds.windows(2).any(|w| w[0] >= w[1]) {
+        pgx::error!("approx_histogram requires strictly increasing bounds")
+    }
+}
+
+// The range of values and count of each of the sketch's buckets, from lowest to highest.
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "buckets",
+    schema = "toolkit_experimental"
+)]
+pub fn uddsketch_buckets<'a>(
+    sketch: UddSketch<'a>,
+) -> TableIterator<'static, (name!(lower, f64), name!(upper, f64), name!(count, i64))> {
+    let gamma = uddsketch::gamma(sketch.alpha);
+    let buckets: Vec<_> = sketch
+        .keys()
+        .zip(sketch.counts())
+        .map(|(key, count)| {
+            let (lower, upper) = uddsketch::bucket_bounds(gamma, key);
+            (lower, upper, count as i64)
+        })
+        .collect();
+    TableIterator::new(buckets.into_iter())
+}
+
+// Estimated number of values in each bin (`bounds[i]`, `bounds[i + 1]`].
+#[pg_extern(
+    immutable,
+    parallel_safe,
+    name = "approx_histogram",
+    schema = "toolkit_experimental"
+)]
+pub fn uddsketch_approx_histogram<'a>(sketch: UddSketch<'a>, bounds: Vec<f64>) -> Vec<f64> {
+    check_histogram_bounds(&bounds);
+    uddsketch::estimate_histogram(
+        &bounds,
+        uddsketch::gamma(sketch.alpha),
+        sketch.keys().zip(sketch.counts()),
+    )
+}
+
 #[pg_operator(immutable, parallel_safe)]
 #[opname(->)]
 pub fn arrow_uddsketch_num_vals<'a>(sketch: UddSketch<'a>, _accessor: AccessorNumVals<'a>) -> f64 {
@@ -1286,6 +1630,431 @@ mod tests {
         }
     }
 
+    #[pg_test]
+    fn test_weighted_aggregates() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "CREATE TABLE weighted_test (value DOUBLE PRECISION, weight BIGINT)",
+                    None,
+                    None,
+                )
+                .unwrap();
+            client
+                .update(
+                    "INSERT INTO weighted_test \
+                    SELECT v, v::bigint % 7 FROM generate_series(1, 100) v",
+                    None,
+                    None,
+                )
+                .unwrap();
+            client
+                .update(
+                    "INSERT INTO weighted_test VALUES (NULL, 3), (1000, NULL)",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            // the same data, one row per observation
+            client
+                .update(
+                    "CREATE VIEW exploded_test AS \
+                    SELECT value FROM weighted_test, generate_series(1, weight)",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            for (weighted, exploded) in [
+                (
+                    "toolkit_experimental.uddsketch(100, 0.01, value, weight)",
+                    "uddsketch(100, 0.01, value)",
+                ),
+                (
+                    "toolkit_experimental.percentile_agg(value, weight)",
+                    "percentile_agg(value)",
+                ),
+            ] {
+                let (count, expected_count) = client
+                    .update(
+                        &format!(
+                            "SELECT \
+                            (SELECT num_vals({}) FROM weighted_test), \
+                            (SELECT num_vals({}) FROM exploded_test)",
+                            weighted, exploded
+                        ),
+                        None,
+                        None,
+                    )
+                    .unwrap()
+                    .first()
+                    .get_two::<f64, f64>()
+                    .unwrap();
+                assert_eq!(count, expected_count);
+
+                for percentile in [0.01, 0.25, 0.5, 0.9, 0.99] {
+                    let (value, expected) = client
+                        .update(
+                            &format!(
+                                "SELECT \
+                                (SELECT approx_percentile({p}, {}) FROM weighted_test), \
+                                (SELECT approx_percentile({p}, {}) FROM exploded_test)",
+                                weighted,
+                                exploded,
+                                p = percentile
+                            ),
+                            None,
+                            None,
+                        )
+                        .unwrap()
+                        .first()
+                        .get_two::<f64, f64>()
+                        .unwrap();
+                    assert_eq!(value, expected);
+                }
+            }
+
+            // the result is a regular uddsketch, so it can be rolled up with unweighted sketches
+            let (count, mean) = client
+                .update(
+                    "SELECT num_vals(rollup(sketch)), mean(rollup(sketch)) FROM ( \
+                        SELECT toolkit_experimental.uddsketch(100, 0.01, value, weight) AS sketch \
+                        FROM weighted_test \
+                        UNION ALL \
+                        SELECT uddsketch(100, 0.01, value) FROM exploded_test \
+                    ) s",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<f64, f64>()
+                .unwrap();
+            let expected_mean = client
+                .update("SELECT avg(value) FROM exploded_test", None, None)
+                .unwrap()
+                .first()
+                .get_one::<f64>()
+                .unwrap();
+            // sum(v % 7) for v in 1..=100 is 297, counted once per sketch
+            assert_eq!(count, Some(594.0));
+            apx_eql(mean.unwrap(), expected_mean.unwrap(), 0.000001);
+        });
+    }
+
+    #[pg_test(error = "weight must not be negative, got -1")]
+    fn test_negative_weight() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.percentile_agg(1.0, -1)",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test]
+    fn test_uddsketch_from_buckets() {
+        Spi::connect(|mut client| {
+            let (count, error, median) = client
+                .update(
+                    "SELECT num_vals(s), error(s), approx_percentile(0.5, s) FROM ( \
+                        SELECT toolkit_experimental.uddsketch_from_buckets(\
+                            '{-4, -2, 0.5, 1, 2, 4}', '{1, 2, 3, 4, 5}', 100, 0.001) s \
+                    ) q",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_three::<f64, f64, f64>()
+                .unwrap();
+            assert_eq!(count, Some(15.0));
+            // the buckets are a lot wider than 0.1%, so the sketch had to be compacted
+            assert!(error.unwrap() > 0.3);
+            // the median lies in (1, 2], the (-2, 0.5] bucket is counted as zero
+            let (median, error) = (median.unwrap(), error.unwrap());
+            assert!(1.0 * (1.0 - error) <= median && median <= 2.0 * (1.0 + error));
+
+            // the result rolls up with sketches built from individual values
+            let count = client
+                .update(
+                    "SELECT num_vals(rollup(s)) FROM ( \
+                        SELECT toolkit_experimental.uddsketch_from_buckets(\
+                            '{1, 2, 4}', '{10, 20}', 100, 0.001) s \
+                        UNION ALL \
+                        SELECT uddsketch(100, 0.001, v) FROM generate_series(1, 5) v \
+                    ) q",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<f64>()
+                .unwrap();
+            assert_eq!(count, Some(35.0));
+
+            let empty = client
+                .update(
+                    "SELECT toolkit_experimental.uddsketch_from_buckets(\
+                        '{1, 2}', '{0}', 100, 0.001)::text",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap();
+            assert_eq!(empty, None);
+        });
+    }
+
+    #[pg_test]
+    fn test_uddsketch_from_unbounded_buckets() {
+        Spi::connect(|mut client| {
+            // Prometheus style, the last bucket has no upper bound
+            let (count, min, max) = client
+                .update(
+                    "SELECT num_vals(s), approx_percentile(0.0, s), approx_percentile(1.0, s) FROM ( \
+                        SELECT toolkit_experimental.uddsketch_from_buckets(\
+                            '{-Infinity, -1, 1, 10, Infinity}', '{2, 4, 3, 1}', 100, 0.001) s \
+                    ) q",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_three::<f64, f64, f64>()
+                .unwrap();
+            assert_eq!(count, Some(10.0));
+            // the unbounded buckets are counted at their finite bounds
+            let (min, max) = (min.unwrap(), max.unwrap());
+            assert!((min + 1.0).abs() < 0.1, "{}", min);
+            assert!((max - 10.0).abs() < 1.0, "{}", max);
+
+            // (-1, 1] straddles zero, its values are counted as zero
+            let (below, above) = client
+                .update(
+                    "SELECT approx_percentile(0.25, s), approx_percentile(0.75, s) FROM ( \
+                        SELECT toolkit_experimental.uddsketch_from_buckets(\
+                            '{-1, 1}', '{4}', 100, 0.001) s \
+                    ) q",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<f64, f64>()
+                .unwrap();
+            assert_eq!(below, Some(0.0));
+            assert_eq!(above, Some(0.0));
+        });
+    }
+
+    #[pg_test(error = "uddsketch_from_buckets requires a finite bound for each bucket")]
+    fn test_uddsketch_from_fully_unbounded_bucket() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.uddsketch_from_buckets(\
+                        '{-Infinity, Infinity}', '{1}', 100, 0.001)",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test(error = "uddsketch_from_buckets requires a positive max_buckets, got 0")]
+    fn test_uddsketch_from_buckets_no_max_buckets() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.uddsketch_from_buckets(\
+                        '{1, 2}', '{1}', 0, 0.001)",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test(error = "uddsketch_from_buckets requires a max_error between 0 and 1, got 1.5")]
+    fn test_uddsketch_from_buckets_bad_max_error() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.uddsketch_from_buckets(\
+                        '{1, 2}', '{1}', 100, 1.5)",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test(error = "uddsketch_from_buckets requires strictly increasing bounds")]
+    fn test_uddsketch_from_unsorted_buckets() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.uddsketch_from_buckets(\
+                        '{1, 4, 2}', '{1, 1}', 100, 0.001)",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test]
+    fn test_uddsketch_robust_statistics() {
+        Spi::connect(|mut client| {
+            // 1 to 1000 and a few outliers
+            client
+                .update(
+                    "CREATE VIEW robust_test AS \
+                    SELECT uddsketch(200, 0.001, v) AS sketch FROM ( \
+                        SELECT generate_series(1, 1000)::float8 AS v \
+                        UNION ALL SELECT 1e9 FROM generate_series(1, 10) \
+                    ) data",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            for (function, arrow, expected) in [
+                (
+                    "toolkit_experimental.approx_trimmed_mean(sketch, 0.1, 0.9)",
+                    "sketch->toolkit_experimental.approx_trimmed_mean(0.1, 0.9)",
+                    505.0,
+                ),
+                (
+                    "toolkit_experimental.approx_winsorized_mean(sketch, 0.1, 0.9)",
+                    "sketch->toolkit_experimental.approx_winsorized_mean(0.1, 0.9)",
+                    505.0,
+                ),
+                (
+                    "toolkit_experimental.approx_iqr(sketch)",
+                    "sketch->toolkit_experimental.approx_iqr()",
+                    505.0,
+                ),
+                (
+                    "toolkit_experimental.approx_mad(sketch)",
+                    "sketch->toolkit_experimental.approx_mad()",
+                    252.5,
+                ),
+            ] {
+                let (value, arrow_value) = client
+                    .update(
+                        &format!("SELECT {}, {} FROM robust_test", function, arrow),
+                        None,
+                        None,
+                    )
+                    .unwrap()
+                    .first()
+                    .get_two::<f64, f64>()
+                    .unwrap();
+                assert_eq!(value, arrow_value);
+                pct_eql(value.unwrap(), expected, 0.02);
+            }
+        });
+    }
+
+    #[pg_test(error = "the bounds must satisfy 0 <= low < high <= 1, got low 0.9 and high 0.1")]
+    fn test_uddsketch_robust_statistics_bounds() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.approx_trimmed_mean(uddsketch(200, 0.001, v::float8), 0.9, 0.1) \
+                    FROM generate_series(1, 100) v",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
+    #[pg_test]
+    fn test_uddsketch_buckets_and_histogram() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "CREATE VIEW bucket_test AS \
+                    SELECT uddsketch(100, 0.01, v) AS sketch \
+                    FROM generate_series(-100, 100) v",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            let (buckets, values, bad_buckets) = client
+                .update(
+                    "SELECT count(*), sum(count), count(*) FILTER (WHERE lower > upper) \
+                    FROM bucket_test, toolkit_experimental.buckets(sketch)",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_three::<i64, i64, i64>()
+                .unwrap();
+            assert!(buckets.unwrap() <= 100);
+            assert_eq!(values, Some(201));
+            assert_eq!(bad_buckets, Some(0));
+
+            let zero = client
+                .update(
+                    "SELECT count FROM bucket_test, toolkit_experimental.buckets(sketch) \
+                    WHERE lower = 0 AND upper = 0",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<i64>()
+                .unwrap();
+            assert_eq!(zero, Some(1));
+
+            let histogram = client
+                .update(
+                    "SELECT toolkit_experimental.approx_histogram(\
+                        sketch, '{-Infinity, -50, 0, 50, 100, 200}') \
+                    FROM bucket_test",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<Vec<f64>>()
+                .unwrap()
+                .unwrap();
+            let expected = [51.0, 50.0, 50.0, 50.0, 0.0];
+            assert_eq!(histogram.len(), expected.len());
+            for (estimate, expected) in histogram.iter().zip(expected) {
+                apx_eql(*estimate, expected, 2.0);
+            }
+        });
+    }
+
+    #[pg_test(error = "approx_histogram requires strictly increasing bounds")]
+    fn test_uddsketch_histogram_unsorted_bounds() {
+        Spi::connect(|mut client| {
+            client
+                .update(
+                    "SELECT toolkit_experimental.approx_histogram(\
+                        uddsketch(100, 0.01, v), '{0, 10, 5}') \
+                    FROM generate_series(1, 10) v",
+                    None,
+                    None,
+                )
+                .unwrap();
+        });
+    }
+
     #[pg_test]
     fn test_udd_null_input_yields_null_output() {
         Spi::connect(|mut client| {